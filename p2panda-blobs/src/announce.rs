@@ -0,0 +1,324 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use futures_util::{stream, Stream};
+use p2panda_core::cbor::{decode_cbor, encode_cbor};
+use p2panda_core::{Hash, PublicKey};
+use p2panda_net::{FromNetwork, Network, ToNetwork, TopicId};
+use p2panda_sync::TopicQuery;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex};
+use tracing::{debug, warn};
+
+/// Notification that a blob announced on a topic is available for download.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobAnnouncement {
+    /// Hash of the announced blob.
+    pub hash: Hash,
+    /// Peer which announced the blob and is able to provide it.
+    pub delivered_from: PublicKey,
+}
+
+/// Availability record broadcast into the gossip overlay of a topic.
+#[derive(Debug, Serialize, Deserialize)]
+enum AnnouncementMessage {
+    BlobAvailable(Hash),
+}
+
+/// Subscription to the gossip overlay of a topic used for blob announcements.
+#[derive(Debug)]
+struct AnnouncementTopic {
+    /// Hashes which were already announced by us on this topic.
+    announced: HashSet<Hash>,
+    announce_tx: mpsc::Sender<Hash>,
+    events_tx: broadcast::Sender<BlobAnnouncement>,
+}
+
+/// Publishes and receives availability records of blobs on `p2panda-net` topics.
+#[derive(Debug)]
+pub(crate) struct Announcer<T> {
+    network: Network<T>,
+    topics: Mutex<HashMap<[u8; 32], AnnouncementTopic>>,
+}
+
+impl<T> Announcer<T>
+where
+    T: TopicQuery + TopicId + 'static,
+{
+    pub(crate) fn new(network: Network<T>) -> Self {
+        Self {
+            network,
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Announces the given blob on the topic.
+    ///
+    /// Returns `false` if the blob was already announced on this topic before, in this case
+    /// nothing is published again.
+    pub(crate) async fn announce(&self, hash: Hash, topic: T) -> Result<bool> {
+        let mut topics = self.topics.lock().await;
+        let announcement_topic = self.get_or_subscribe(&mut topics, topic).await?;
+        if !announcement_topic.announced.insert(hash) {
+            return Ok(false);
+        }
+        announcement_topic.announce_tx.send(hash).await?;
+        Ok(true)
+    }
+
+    /// Returns a stream of blobs announced by other peers on the topic.
+    pub(crate) async fn announcements(
+        &self,
+        topic: T,
+    ) -> Result<impl Stream<Item = BlobAnnouncement>> {
+        let mut topics = self.topics.lock().await;
+        let announcement_topic = self.get_or_subscribe(&mut topics, topic).await?;
+        let events_rx = announcement_topic.events_tx.subscribe();
+
+        Ok(stream::unfold(events_rx, |mut events_rx| async move {
+            loop {
+                match events_rx.recv().await {
+                    Ok(event) => return Some((event, events_rx)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("missed {skipped} blob announcements");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }))
+    }
+
+    async fn get_or_subscribe<'a>(
+        &self,
+        topics: &'a mut HashMap<[u8; 32], AnnouncementTopic>,
+        topic: T,
+    ) -> Result<&'a mut AnnouncementTopic> {
+        let vacant = match topics.entry(topic.id()) {
+            Entry::Occupied(entry) => return Ok(entry.into_mut()),
+            Entry::Vacant(entry) => entry,
+        };
+
+        let (to_network_tx, from_network_rx, gossip_ready_rx) =
+            self.network.subscribe(topic).await?;
+        let (announce_tx, announce_rx) = mpsc::channel(128);
+        let (events_tx, _) = broadcast::channel(128);
+
+        tokio::task::spawn(run_topic(
+            to_network_tx,
+            from_network_rx,
+            gossip_ready_rx,
+            announce_rx,
+            events_tx.clone(),
+        ));
+
+        Ok(vacant.insert(AnnouncementTopic {
+            announced: HashSet::new(),
+            announce_tx,
+            events_tx,
+        }))
+    }
+}
+
+/// Forwards our announcements into the gossip overlay and informs subscribers about the ones we
+/// received from other peers.
+///
+/// Messages sent before the gossip overlay was joined are dropped by the network, this is why
+/// announcements are held back until then.
+async fn run_topic(
+    to_network_tx: mpsc::Sender<ToNetwork>,
+    mut from_network_rx: mpsc::Receiver<FromNetwork>,
+    mut gossip_ready_rx: oneshot::Receiver<()>,
+    mut announce_rx: mpsc::Receiver<Hash>,
+    events_tx: broadcast::Sender<BlobAnnouncement>,
+) {
+    let mut gossip_ready = false;
+    let mut queued: Vec<Hash> = Vec::new();
+
+    loop {
+        tokio::select! {
+            result = &mut gossip_ready_rx, if !gossip_ready => {
+                // The engine drops the signal when it stops, we can't wait for it any longer then.
+                if result.is_err() {
+                    debug!("no gossip ready signal for blob announcements");
+                }
+                gossip_ready = true;
+                for hash in queued.drain(..) {
+                    if send_announcement(&to_network_tx, hash).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            Some(hash) = announce_rx.recv() => {
+                if !gossip_ready {
+                    queued.push(hash);
+                } else if send_announcement(&to_network_tx, hash).await.is_err() {
+                    break;
+                }
+            }
+            message = from_network_rx.recv() => {
+                // The stream ends when the network shuts down.
+                let Some(message) = message else {
+                    break;
+                };
+
+                // Other messages might be sent on the same topic, we ignore them.
+                let FromNetwork::GossipMessage { bytes, delivered_from } = message else {
+                    continue;
+                };
+                let Ok(AnnouncementMessage::BlobAvailable(hash)) = decode_cbor(&bytes[..]) else {
+                    continue;
+                };
+
+                // Sending only fails if there's no subscriber.
+                events_tx.send(BlobAnnouncement { hash, delivered_from }).ok();
+            }
+        }
+    }
+}
+
+async fn send_announcement(to_network_tx: &mpsc::Sender<ToNetwork>, hash: Hash) -> Result<()> {
+    let bytes = encode_cbor(&AnnouncementMessage::BlobAvailable(hash))?;
+    to_network_tx.send(ToNetwork::Message { bytes }).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures_lite::StreamExt;
+    use p2panda_net::{NetworkBuilder, TopicId};
+    use p2panda_sync::TopicQuery;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{Blobs, ImportBlobEvent, MemoryStore};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct TestTopic;
+
+    impl TopicQuery for TestTopic {}
+
+    impl TopicId for TestTopic {
+        fn id(&self) -> [u8; 32] {
+            [7; 32]
+        }
+    }
+
+    #[tokio::test]
+    async fn announce_blob() {
+        let network_id = [1; 32];
+
+        let (network_a, blobs_a) = Blobs::from_builder(
+            NetworkBuilder::<TestTopic>::new(network_id),
+            MemoryStore::new(),
+        )
+        .await
+        .unwrap();
+        let (network_b, blobs_b) = Blobs::from_builder(
+            NetworkBuilder::<TestTopic>::new(network_id),
+            MemoryStore::new(),
+        )
+        .await
+        .unwrap();
+
+        network_a.add_peer(network_b.node_address()).await.unwrap();
+        network_b.add_peer(network_a.node_address()).await.unwrap();
+
+        let mut announcements = pin!(blobs_b.announcements(TestTopic).await.unwrap());
+
+        // Peer A imports a blob and announces it.
+        let mut events = pin!(
+            blobs_a
+                .import_blob_from_stream(futures_lite::stream::once(Ok(Bytes::from(
+                    "Hello, Penguin!"
+                ))))
+                .await
+        );
+        let hash = loop {
+            match events.next().await.unwrap() {
+                ImportBlobEvent::Done(hash) => break hash,
+                ImportBlobEvent::Abort(err) => panic!("import failed: {err}"),
+                ImportBlobEvent::Progress { .. } => (),
+            }
+        };
+        assert!(blobs_a.announce(hash, TestTopic).await.unwrap());
+
+        // Announcing the same blob again does not publish anything.
+        assert!(!blobs_a.announce(hash, TestTopic).await.unwrap());
+
+        // Peer B learns that the blob is available.
+        let announcement = tokio::time::timeout(Duration::from_secs(30), announcements.next())
+            .await
+            .expect("announcement within timeout")
+            .unwrap();
+        assert_eq!(announcement.hash, hash);
+        assert_eq!(announcement.delivered_from, network_a.node_id());
+
+        network_a.shutdown().await.unwrap();
+        network_b.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn announce_on_joined_topic() {
+        let network_id = [2; 32];
+
+        let (network_a, blobs_a) = Blobs::from_builder(
+            NetworkBuilder::<TestTopic>::new(network_id),
+            MemoryStore::new(),
+        )
+        .await
+        .unwrap();
+        let (network_b, blobs_b) = Blobs::from_builder(
+            NetworkBuilder::<TestTopic>::new(network_id),
+            MemoryStore::new(),
+        )
+        .await
+        .unwrap();
+
+        network_a.add_peer(network_b.node_address()).await.unwrap();
+        network_b.add_peer(network_a.node_address()).await.unwrap();
+
+        let mut announcements = pin!(blobs_b.announcements(TestTopic).await.unwrap());
+
+        // The application on peer A subscribed to the same topic and joined its gossip overlay
+        // before announcing anything.
+        let (_to_network_tx, _from_network_rx, gossip_ready_rx) =
+            network_a.subscribe(TestTopic).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(30), gossip_ready_rx)
+            .await
+            .expect("gossip overlay joined within timeout")
+            .unwrap();
+
+        let mut events = pin!(
+            blobs_a
+                .import_blob_from_stream(futures_lite::stream::once(Ok(Bytes::from(
+                    "Hello, Walrus!"
+                ))))
+                .await
+        );
+        let hash = loop {
+            match events.next().await.unwrap() {
+                ImportBlobEvent::Done(hash) => break hash,
+                ImportBlobEvent::Abort(err) => panic!("import failed: {err}"),
+                ImportBlobEvent::Progress { .. } => (),
+            }
+        };
+        assert!(blobs_a.announce(hash, TestTopic).await.unwrap());
+
+        let announcement = tokio::time::timeout(Duration::from_secs(30), announcements.next())
+            .await
+            .expect("announcement within timeout")
+            .unwrap();
+        assert_eq!(announcement.hash, hash);
+        assert_eq!(announcement.delivered_from, network_a.node_id());
+
+        network_a.shutdown().await.unwrap();
+        network_b.shutdown().await.unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::collections::HashSet;
 use std::io;
 use std::path::PathBuf;
 
@@ -11,14 +12,17 @@ use iroh_blobs::store::{Map, Store};
 use iroh_blobs::util::local_pool::{Config as LocalPoolConfig, LocalPool};
 use iroh_blobs::Hash as IrohHash;
 use p2panda_core::Hash;
-use p2panda_net::{Network, NetworkBuilder, TopicId};
+use p2panda_net::{Network, NetworkBuilder, NodeAddress, TopicId};
 use p2panda_sync::TopicQuery;
 
+use crate::announce::{Announcer, BlobAnnouncement};
 use crate::config::Config;
-use crate::download::download_blob;
+use crate::download::{download_blob, download_blob_resumable};
 use crate::export::export_blob;
+use crate::gc::{gc_blobs, GcReport};
 use crate::import::{import_blob, import_blob_from_stream, ImportBlobEvent};
 use crate::protocol::{BlobsProtocol, BLOBS_ALPN};
+use crate::verify::{verify_all_blobs, verify_blob};
 use crate::DownloadBlobEvent;
 
 /// Blobs service offering storage, retrieval and synchronisation of content-addressed data.
@@ -27,6 +31,7 @@ pub struct Blobs<T, S>
 where
     S: Store,
 {
+    announcer: Announcer<T>,
     downloader: Downloader,
     network: Network<T>,
     rt: LocalPool,
@@ -75,6 +80,7 @@ where
         );
 
         let blobs = Self {
+            announcer: Announcer::new(network.clone()),
             downloader,
             network: network.clone(),
             rt: local_pool,
@@ -117,9 +123,122 @@ where
         .await
     }
 
+    /// Download a blob from the given network peer, resuming an earlier interrupted download.
+    ///
+    /// Already verified parts of the blob are not fetched again. The first event reports from
+    /// which byte offset the download was resumed. Partial blobs are only kept by the
+    /// `FilesystemStore`.
+    pub async fn download_resumable(
+        &self,
+        hash: Hash,
+        node_addr: NodeAddress,
+    ) -> impl Stream<Item = DownloadBlobEvent> {
+        download_blob_resumable(
+            self.store.clone(),
+            self.downloader.clone(),
+            self.rt.handle().clone(),
+            hash,
+            node_addr,
+        )
+        .await
+    }
+
+    /// Announce to all peers interested in the given topic that a blob is available for download.
+    ///
+    /// A small availability record is published over the gossip overlay of the topic, it is sent
+    /// as soon as the overlay was joined. Only peers which are subscribed to the announcements of
+    /// that topic at that point will learn about the blob.
+    ///
+    /// Applications subscribed to the same topic receive the records as regular gossip messages
+    /// as well, use a topic dedicated to announcements to keep them apart.
+    ///
+    /// Returns `false` if the blob was already announced on this topic, announcing it again does
+    /// not publish anything.
+    pub async fn announce(&self, hash: Hash, topic: T) -> Result<bool> {
+        self.announcer.announce(hash, topic).await
+    }
+
+    /// Subscribe to blobs announced by other peers on the given topic.
+    pub async fn announcements(&self, topic: T) -> Result<impl Stream<Item = BlobAnnouncement>> {
+        self.announcer.announcements(topic).await
+    }
+
+    /// Remove all blobs from the store which are not in the given `keep` set.
+    ///
+    /// This allows reclaiming storage for blobs which are not referenced by the application
+    /// anymore. Blobs which are currently being downloaded or imported are never removed.
+    pub async fn gc(&self, keep: &HashSet<Hash>) -> Result<GcReport> {
+        gc_blobs(&self.store, keep).await
+    }
+
+    /// Re-read a blob from the store and check if its BLAKE3 hash still matches.
+    ///
+    /// This allows detecting corrupted data, for example caused by bit-rot or partial writes in
+    /// the `FilesystemStore`. Returns `false` if the blob is corrupted or not complete, and an
+    /// error if it is not in the store.
+    pub async fn verify(&self, hash: Hash) -> Result<bool> {
+        verify_blob(&self.store, hash).await
+    }
+
+    /// Verify all complete blobs in the store.
+    ///
+    /// Each blob is re-hashed only when the stream is polled. Blobs which could not be read are
+    /// reported as corrupted.
+    pub async fn verify_all(&self) -> Result<impl Stream<Item = (Hash, bool)>> {
+        verify_all_blobs(self.store.clone()).await
+    }
+
     /// Export a blob to the given filesystem path.
     pub async fn export_blob(&self, hash: Hash, path: &PathBuf) -> Result<()> {
         export_blob(&self.store, hash, path).await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_lite::future::Boxed as BoxedFuture;
+    use iroh::endpoint::Connecting;
+    use p2panda_net::{NetworkBuilder, ProtocolError, ProtocolHandler, TopicId};
+    use p2panda_sync::TopicQuery;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{Blobs, MemoryStore, BLOBS_ALPN};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct TestTopic;
+
+    impl TopicQuery for TestTopic {}
+
+    impl TopicId for TestTopic {
+        fn id(&self) -> [u8; 32] {
+            [0; 32]
+        }
+    }
+
+    #[derive(Debug)]
+    struct DummyHandler;
+
+    impl ProtocolHandler for DummyHandler {
+        fn accept(self: Arc<Self>, _conn: Connecting) -> BoxedFuture<anyhow::Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_reserved_blobs_alpn() {
+        let builder = NetworkBuilder::<TestTopic>::new([1; 32]).protocol(BLOBS_ALPN, DummyHandler);
+
+        let result = Blobs::from_builder(builder, MemoryStore::new()).await;
+        let err = result.expect_err("conflicting ALPN should fail the build");
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::DuplicateAlpn(
+                String::from_utf8_lossy(BLOBS_ALPN).to_string()
+            ))
+        );
+        assert!(err.to_string().contains("/iroh-bytes/"));
+    }
+}
@@ -4,13 +4,14 @@ use anyhow::{ensure, Result};
 use futures_lite::{Stream, StreamExt};
 use iroh::NodeAddr;
 use iroh_blobs::downloader::{DownloadRequest, Downloader};
-use iroh_blobs::get::db::DownloadProgress;
+use iroh_blobs::get::db::{valid_ranges, DownloadProgress};
 use iroh_blobs::get::Stats;
+use iroh_blobs::store::{EntryStatus, MapEntry, Store};
 use iroh_blobs::util::local_pool::LocalPoolHandle;
 use iroh_blobs::util::progress::{AsyncChannelProgressSender, ProgressSender};
 use iroh_blobs::{BlobFormat, Hash as IrohHash, HashAndFormat};
 use p2panda_core::Hash;
-use p2panda_net::{Network, TopicId};
+use p2panda_net::{Network, NodeAddress, TopicId};
 use p2panda_sync::TopicQuery;
 use serde::{Deserialize, Serialize};
 use serde_error::Error as RpcError;
@@ -20,6 +21,15 @@ use crate::from_node_addr;
 /// Status of a blob download attempt.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DownloadBlobEvent {
+    /// Download started, resuming after the given number of already verified bytes.
+    Started {
+        resumed_from: u64,
+    },
+    /// Number of bytes of the blob transferred so far and its total size, if already known.
+    Progress {
+        offset: u64,
+        total: Option<u64>,
+    },
     Done,
     Abort(RpcError),
 }
@@ -51,7 +61,76 @@ pub(crate) async fn download_blob<T: TopicQuery + TopicId + 'static>(
         }
     });
 
-    receiver.filter_map(|event| match event {
+    into_events(receiver)
+}
+
+/// Download a blob from the given node, resuming from already verified data in the store.
+///
+/// Partially downloaded blobs are kept in the store, already verified chunks will not be fetched
+/// again. Only the filesystem store keeps partial blobs, with the in-memory store downloads always
+/// start from the beginning.
+pub(crate) async fn download_blob_resumable<S: Store>(
+    store: S,
+    downloader: Downloader,
+    pool_handle: LocalPoolHandle,
+    hash: Hash,
+    node_addr: NodeAddress,
+) -> impl Stream<Item = DownloadBlobEvent> {
+    let (sender, receiver) = async_channel::bounded(1024);
+    let progress = AsyncChannelProgressSender::new(sender);
+    let hash_and_format = HashAndFormat {
+        hash: IrohHash::from_bytes(*hash.as_bytes()),
+        format: BlobFormat::Raw,
+    };
+
+    // If the local state of the blob can't be determined we're starting from zero.
+    let resumed_from = verified_prefix(&store, &hash_and_format.hash)
+        .await
+        .unwrap_or_default();
+
+    pool_handle.spawn_detached(move || async move {
+        let req = DownloadRequest::new(hash_and_format, vec![from_node_addr(node_addr)])
+            .progress_sender(progress.clone());
+        let handle = downloader.queue(req).await;
+        match handle.await {
+            Ok(stats) => {
+                progress.send(DownloadProgress::AllDone(stats)).await.ok();
+            }
+            Err(err) => {
+                progress
+                    .send(DownloadProgress::Abort(RpcError::new(&err)))
+                    .await
+                    .ok();
+            }
+        }
+    });
+
+    futures_lite::stream::once(DownloadBlobEvent::Started { resumed_from })
+        .chain(into_events(receiver))
+}
+
+fn into_events(
+    receiver: async_channel::Receiver<DownloadProgress>,
+) -> impl Stream<Item = DownloadBlobEvent> {
+    // Size of the blob, learned as soon as we found it locally or on the remote node.
+    let mut total = None;
+
+    receiver.filter_map(move |event| match event {
+        DownloadProgress::FoundLocal { size, .. } => {
+            total = Some(size.value());
+            None
+        }
+        DownloadProgress::Found { size, .. } => {
+            total = Some(size);
+            None
+        }
+        DownloadProgress::Progress { offset, .. } => {
+            Some(DownloadBlobEvent::Progress { offset, total })
+        }
+        DownloadProgress::Done { .. } => total.map(|total| DownloadBlobEvent::Progress {
+            offset: total,
+            total: Some(total),
+        }),
         DownloadProgress::AllDone(_) => Some(DownloadBlobEvent::Done),
         // @TODO: Use own error type here
         DownloadProgress::Abort(err) => Some(DownloadBlobEvent::Abort(err)),
@@ -62,6 +141,32 @@ pub(crate) async fn download_blob<T: TopicQuery + TopicId + 'static>(
     })
 }
 
+/// Returns the number of bytes at the beginning of a blob which are already present and verified
+/// in the store.
+async fn verified_prefix<S: Store>(store: &S, hash: &IrohHash) -> Result<u64> {
+    if store.entry_status(hash).await? == EntryStatus::NotFound {
+        return Ok(0);
+    }
+
+    let Some(entry) = store.get_mut(hash).await? else {
+        return Ok(0);
+    };
+
+    if entry.is_complete() {
+        return Ok(entry.size().value());
+    }
+
+    // Chunk ranges are represented by their sorted boundaries, a range starting at the first
+    // chunk is our verified prefix.
+    let ranges = valid_ranges::<S>(&entry).await?;
+    let prefix = match ranges.boundaries() {
+        [start, end, ..] if start.0 == 0 => end.to_bytes(),
+        [start] if start.0 == 0 => entry.size().value(),
+        _ => 0,
+    };
+    Ok(prefix)
+}
+
 async fn download_queued<T: TopicQuery + TopicId + 'static>(
     network: Network<T>,
     downloader: &Downloader,
@@ -81,3 +186,130 @@ async fn download_queued<T: TopicQuery + TopicId + 'static>(
     let stats = handle.await?;
     Ok(stats)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use bytes::Bytes;
+    use futures_lite::StreamExt;
+    use iroh_blobs::get::fsm::{self, ConnectedNext};
+    use iroh_blobs::protocol::{GetRequest, RangeSpecSeq};
+    use iroh_blobs::store::bao_tree::{ChunkNum, ChunkRanges};
+    use iroh_blobs::store::{BaoBatchWriter, MapEntry, MapEntryMut, MapMut};
+    use iroh_blobs::Hash as IrohHash;
+    use iroh_io::AsyncSliceReaderExt;
+    use p2panda_net::{NetworkBuilder, NodeAddress, TopicId};
+    use p2panda_sync::TopicQuery;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_node_addr, Blobs, FilesystemStore, ImportBlobEvent, MemoryStore, BLOBS_ALPN};
+
+    use super::DownloadBlobEvent;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct TestTopic;
+
+    impl TopicQuery for TestTopic {}
+
+    impl TopicId for TestTopic {
+        fn id(&self) -> [u8; 32] {
+            [0; 32]
+        }
+    }
+
+    const BLOB_SIZE: u64 = 256 * 1024;
+
+    #[tokio::test]
+    async fn resume_partial_download() {
+        let network_id = [1; 32];
+        let data: Vec<u8> = (0..BLOB_SIZE).map(|i| (i % 251) as u8).collect();
+
+        // The provider imports the blob.
+        let (provider_network, provider) = Blobs::from_builder(
+            NetworkBuilder::<TestTopic>::new(network_id),
+            MemoryStore::new(),
+        )
+        .await
+        .unwrap();
+        let mut events = pin!(
+            provider
+                .import_blob_from_stream(futures_lite::stream::once(Ok(Bytes::from(data.clone()))))
+                .await
+        );
+        let hash = loop {
+            match events.next().await.unwrap() {
+                ImportBlobEvent::Done(hash) => break hash,
+                ImportBlobEvent::Abort(err) => panic!("import failed: {err}"),
+                ImportBlobEvent::Progress { .. } => (),
+            }
+        };
+        let provider_addr = NodeAddress {
+            public_key: provider_network.node_id(),
+            direct_addresses: provider_network.direct_addresses().await.unwrap(),
+            relay_url: None,
+        };
+
+        // Only the filesystem store keeps partial blobs.
+        let dir = tempfile::tempdir().unwrap();
+        let store = FilesystemStore::load(dir.path()).await.unwrap();
+        let (network, blobs) =
+            Blobs::from_builder(NetworkBuilder::<TestTopic>::new(network_id), store.clone())
+                .await
+                .unwrap();
+
+        // Download only the first half of the blob and abort the transfer afterwards.
+        let half = BLOB_SIZE / 2;
+        let iroh_hash = IrohHash::from_bytes(*hash.as_bytes());
+        let connection = network
+            .endpoint()
+            .connect(from_node_addr(provider_addr.clone()), BLOBS_ALPN)
+            .await
+            .unwrap();
+        let request = GetRequest::new(
+            iroh_hash,
+            RangeSpecSeq::from_ranges([ChunkRanges::from(..ChunkNum::chunks(half))]),
+        );
+        let connected = fsm::start(connection, request).next().await.unwrap();
+        let ConnectedNext::StartRoot(start) = connected.next().await.unwrap() else {
+            panic!("expected blob in response");
+        };
+        let (content, size) = start.next().next().await.unwrap();
+        let entry = store.get_or_create(iroh_hash, size).await.unwrap();
+        let mut writer = entry.batch_writer().await.unwrap();
+        content.write_all_batch(&mut writer).await.unwrap();
+        writer.sync().await.unwrap();
+        drop(writer);
+
+        // Resuming the download starts after the verified first half.
+        let mut events = pin!(blobs.download_resumable(hash, provider_addr).await);
+        assert!(matches!(
+            events.next().await,
+            Some(DownloadBlobEvent::Started { resumed_from }) if resumed_from == half
+        ));
+
+        // All transferred bytes are from the second half of the blob.
+        let mut progress_events = 0;
+        loop {
+            match events.next().await.unwrap() {
+                DownloadBlobEvent::Progress { offset, .. } => {
+                    assert!(offset >= half);
+                    progress_events += 1;
+                }
+                DownloadBlobEvent::Done => break,
+                DownloadBlobEvent::Abort(err) => panic!("download failed: {err}"),
+                DownloadBlobEvent::Started { .. } => panic!("download started twice"),
+            }
+        }
+        assert!(progress_events > 0);
+
+        let entry = blobs.get(hash).await.unwrap().expect("blob to be in store");
+        assert!(entry.is_complete());
+        let mut reader = MapEntry::data_reader(&entry).await.unwrap();
+        let bytes = reader.read_to_end().await.unwrap();
+        assert_eq!(bytes.to_vec(), data);
+
+        network.shutdown().await.unwrap();
+        provider_network.shutdown().await.unwrap();
+    }
+}
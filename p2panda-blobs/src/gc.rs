@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use iroh_blobs::store::{MapEntry, Store};
+use iroh_blobs::Hash as IrohHash;
+use p2panda_core::Hash;
+use tracing::trace;
+
+/// Result of a garbage collection run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Number of removed blobs.
+    pub deleted_blobs: usize,
+    /// Number of blobs which were kept.
+    pub kept_blobs: usize,
+    /// Total size of all removed blobs in bytes.
+    pub bytes_freed: u64,
+}
+
+/// Remove all complete blobs from the store which are not in the `keep` set.
+///
+/// Partial blobs and blobs with an import in progress are never removed.
+pub(crate) async fn gc_blobs<S: Store>(store: &S, keep: &HashSet<Hash>) -> Result<GcReport> {
+    let keep: HashSet<IrohHash> = keep
+        .iter()
+        .map(|hash| IrohHash::from_bytes(*hash.as_bytes()))
+        .collect();
+
+    let mut report = GcReport::default();
+    let mut candidates = Vec::new();
+    for hash in store.blobs().await? {
+        let hash = hash?;
+        if keep.contains(&hash) {
+            report.kept_blobs += 1;
+            continue;
+        }
+
+        let size = match store.get(&hash).await? {
+            Some(entry) => entry.size().value(),
+            None => 0,
+        };
+        candidates.push((hash, size));
+    }
+
+    // Protect blobs which are currently being downloaded or imported. This is checked right
+    // before deleting, an import which started while we were looking at the store would otherwise
+    // lose its blob.
+    let mut protected = HashSet::new();
+    for hash in store.partial_blobs().await? {
+        protected.insert(hash?);
+    }
+    for hash_and_format in store.temp_tags() {
+        protected.insert(hash_and_format.hash);
+    }
+
+    let mut deleted = HashSet::new();
+    for (hash, size) in candidates {
+        if protected.contains(&hash) {
+            report.kept_blobs += 1;
+            continue;
+        }
+
+        trace!("removing blob {}", hash);
+        report.bytes_freed += size;
+        deleted.insert(hash);
+    }
+
+    report.deleted_blobs = deleted.len();
+    store.delete(deleted.iter().copied().collect()).await?;
+
+    // Remove tags pointing at deleted blobs, otherwise they would be dangling.
+    let mut dangling_tags = Vec::new();
+    for tag in store.tags().await? {
+        let (tag, hash_and_format) = tag?;
+        if deleted.contains(&hash_and_format.hash) {
+            dangling_tags.push(tag);
+        }
+    }
+    for tag in dangling_tags {
+        store.set_tag(tag, None).await?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+    use iroh_blobs::store::{Map, MapEntry, Store};
+    use iroh_blobs::BlobFormat;
+    use iroh_io::AsyncSliceReaderExt;
+    use p2panda_core::Hash;
+
+    use crate::MemoryStore;
+
+    use super::gc_blobs;
+
+    #[tokio::test]
+    async fn remove_unused_blobs() {
+        let store = MemoryStore::new();
+
+        let mut hashes = Vec::new();
+        for data in [&b"keep me"[..], b"delete me", b"delete me too"] {
+            let temp_tag = store
+                .import_bytes(Bytes::copy_from_slice(data), BlobFormat::Raw)
+                .await
+                .unwrap();
+            hashes.push(*temp_tag.hash());
+        }
+
+        let keep = HashSet::from([Hash::from_bytes(*hashes[0].as_bytes())]);
+        let report = gc_blobs(&store, &keep).await.unwrap();
+        assert_eq!(report.kept_blobs, 1);
+        assert_eq!(report.deleted_blobs, 2);
+        assert_eq!(report.bytes_freed, 22);
+
+        assert!(store.get(&hashes[1]).await.unwrap().is_none());
+        assert!(store.get(&hashes[2]).await.unwrap().is_none());
+
+        let entry = store.get(&hashes[0]).await.unwrap().unwrap();
+        let mut reader = MapEntry::data_reader(&entry).await.unwrap();
+        let data = reader.read_to_end().await.unwrap();
+        assert_eq!(data.as_ref(), b"keep me");
+    }
+}
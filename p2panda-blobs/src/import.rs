@@ -21,6 +21,13 @@ use serde_error::Error as RpcError;
 /// Status of a blob import attempt.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ImportBlobEvent {
+    /// Number of bytes imported so far and the total size of the blob, if already known.
+    ///
+    /// The size is not known in advance when importing from a stream.
+    Progress {
+        offset: u64,
+        total: Option<u64>,
+    },
     Done(Hash),
     Abort(RpcError),
 }
@@ -42,19 +49,7 @@ pub(crate) async fn import_blob<S: Store>(
         }
     });
 
-    receiver.filter_map(|event| {
-        match event {
-            AddProgress::AllDone { hash, .. } => {
-                Some(ImportBlobEvent::Done(Hash::from_bytes(*hash.as_bytes())))
-            }
-            // @TODO: Use own error type here
-            AddProgress::Abort(err) => Some(ImportBlobEvent::Abort(err)),
-            _ => {
-                // @TODO: Add more event types
-                None
-            }
-        }
-    })
+    into_events(receiver)
 }
 
 pub(crate) async fn import_blob_from_stream<S, T>(
@@ -78,17 +73,36 @@ where
         }
     });
 
-    receiver.filter_map(|event| {
+    into_events(receiver)
+}
+
+fn into_events(
+    receiver: async_channel::Receiver<AddProgress>,
+) -> impl Stream<Item = ImportBlobEvent> {
+    let mut offset = 0;
+    let mut total = None;
+
+    receiver.filter_map(move |event| {
         match event {
+            AddProgress::Found { size, .. } => {
+                total = Some(size);
+                Some(ImportBlobEvent::Progress { offset, total })
+            }
+            AddProgress::Progress {
+                offset: progress, ..
+            } => {
+                offset = progress;
+                Some(ImportBlobEvent::Progress { offset, total })
+            }
+            AddProgress::Done { .. } => total.map(|total| ImportBlobEvent::Progress {
+                offset: total,
+                total: Some(total),
+            }),
             AddProgress::AllDone { hash, .. } => {
                 Some(ImportBlobEvent::Done(Hash::from_bytes(*hash.as_bytes())))
             }
             // @TODO: Use own error type here
             AddProgress::Abort(err) => Some(ImportBlobEvent::Abort(err)),
-            _ => {
-                // @TODO: Add more event types
-                None
-            }
         }
     })
 }
@@ -153,9 +167,9 @@ where
             let name = names.lock().unwrap().remove(&id)?;
             Some(AddProgress::Found { id, name, size })
         }
-        ImportProgress::OutboardProgress { id, offset } => {
-            Some(AddProgress::Progress { id, offset })
-        }
+        // Computing the outboard starts counting from zero again after the data was copied, we're
+        // only reporting the copy progress to keep offsets increasing.
+        ImportProgress::OutboardProgress { .. } => None,
         ImportProgress::OutboardDone { hash, id } => Some(AddProgress::Done { hash, id }),
         ImportProgress::CopyProgress { id, offset } => Some(AddProgress::Progress { id, offset }),
     });
@@ -173,3 +187,44 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use futures_lite::StreamExt;
+    use iroh_blobs::util::local_pool::LocalPool;
+
+    use crate::MemoryStore;
+
+    use super::{import_blob, ImportBlobEvent};
+
+    #[tokio::test]
+    async fn progress_offsets_increase() {
+        let size = 1024 * 1024;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blob.bin");
+        let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        tokio::fs::write(&path, &data).await.unwrap();
+
+        let pool = LocalPool::single();
+        let mut events = pin!(import_blob(MemoryStore::new(), pool.handle().clone(), path).await);
+
+        let mut last_offset = 0;
+        let mut last_total = None;
+        loop {
+            match events.next().await.unwrap() {
+                ImportBlobEvent::Progress { offset, total } => {
+                    assert!(offset >= last_offset);
+                    last_offset = offset;
+                    last_total = total;
+                }
+                ImportBlobEvent::Done(_) => break,
+                ImportBlobEvent::Abort(err) => panic!("import failed: {err}"),
+            }
+        }
+
+        assert_eq!(last_total, Some(size));
+        assert_eq!(last_offset, size);
+    }
+}
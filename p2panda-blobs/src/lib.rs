@@ -10,19 +10,24 @@
 //! The blobs service integrates with `p2panda-net` to provide a means of synchronising files
 //! between devices using BLAKE3 verified streaming. Memory usage is generally low, even when
 //! transferring very large files.
+mod announce;
 mod blobs;
 mod config;
 mod download;
 mod export;
+mod gc;
 mod import;
 mod protocol;
+mod verify;
 
 use iroh::{NodeAddr as IrohNodeAddr, NodeId};
 use iroh_blobs::store;
 
+pub use announce::BlobAnnouncement;
 pub use blobs::Blobs;
 pub use config::Config;
 pub use download::DownloadBlobEvent;
+pub use gc::GcReport;
 pub use import::ImportBlobEvent;
 use p2panda_net::NodeAddress;
 pub use protocol::{BlobsProtocol, BLOBS_ALPN};
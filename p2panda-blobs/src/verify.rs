@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use anyhow::{Context, Result};
+use futures_util::{stream, Stream};
+use iroh_blobs::store::{MapEntry, Store};
+use iroh_blobs::Hash as IrohHash;
+use iroh_io::AsyncSliceReader;
+use p2panda_core::Hash;
+use tracing::{trace, warn};
+
+/// Number of bytes read from the store at once while re-hashing a blob.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Re-read a blob from the store and check if its BLAKE3 hash still matches.
+///
+/// Blobs which are not complete can not be verified, `false` is returned for them.
+pub(crate) async fn verify_blob<S: Store>(store: &S, hash: Hash) -> Result<bool> {
+    let entry = store
+        .get(&IrohHash::from_bytes(*hash.as_bytes()))
+        .await?
+        .context("blob not found in store")?;
+    if !entry.is_complete() {
+        return Ok(false);
+    }
+
+    trace!("verifying blob {}", hash);
+    let size = entry.size().value();
+    let mut reader = entry.data_reader().await?;
+    let mut hasher = blake3::Hasher::new();
+    let mut offset = 0;
+    while offset < size {
+        let chunk = reader.read_at(offset, READ_CHUNK_SIZE).await?;
+        // The data on disk might be shorter than expected after a partial write.
+        if chunk.is_empty() {
+            return Ok(false);
+        }
+        hasher.update(&chunk);
+        offset += chunk.len() as u64;
+    }
+
+    Ok(hasher.finalize().as_bytes() == hash.as_bytes())
+}
+
+/// Verify all complete blobs in the store, one after another.
+///
+/// Blobs which could not be read are reported as corrupted.
+pub(crate) async fn verify_all_blobs<S: Store>(
+    store: S,
+) -> Result<impl Stream<Item = (Hash, bool)>> {
+    let mut hashes = Vec::new();
+    for hash in store.blobs().await? {
+        hashes.push(Hash::from_bytes(*hash?.as_bytes()));
+    }
+
+    Ok(stream::unfold(
+        (store, hashes.into_iter()),
+        |(store, mut hashes)| async move {
+            let hash = hashes.next()?;
+            let intact = verify_blob(&store, hash).await.unwrap_or_else(|err| {
+                warn!("failed verifying blob {}: {err}", hash);
+                false
+            });
+            Some(((hash, intact), (store, hashes)))
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, SeekFrom, Write};
+
+    use bytes::Bytes;
+    use futures_lite::StreamExt;
+    use iroh_blobs::store::Store;
+    use iroh_blobs::BlobFormat;
+    use p2panda_core::Hash;
+
+    use crate::{FilesystemStore, MemoryStore};
+
+    use super::{verify_all_blobs, verify_blob};
+
+    /// Blobs of this size are not inlined into the database of the filesystem store but are kept
+    /// in their own file.
+    const BLOB_SIZE: usize = 64 * 1024;
+
+    /// Returns the paths of all files in the given directory and its subdirectories.
+    fn files(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                paths.extend(files(&path));
+            } else {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    #[tokio::test]
+    async fn verify_blobs() {
+        let store = MemoryStore::new();
+        let temp_tag = store
+            .import_bytes(Bytes::from_static(b"Hello, Penguin!"), BlobFormat::Raw)
+            .await
+            .unwrap();
+        let hash = Hash::from_bytes(*temp_tag.hash().as_bytes());
+
+        assert!(verify_blob(&store, hash).await.unwrap());
+        assert!(verify_blob(&store, Hash::new(b"unknown")).await.is_err());
+
+        let report: Vec<(Hash, bool)> = verify_all_blobs(store).await.unwrap().collect().await;
+        assert_eq!(report, vec![(hash, true)]);
+    }
+
+    #[tokio::test]
+    async fn detect_corrupted_blob() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FilesystemStore::load(dir.path()).await.unwrap();
+
+        let data: Vec<u8> = (0..BLOB_SIZE).map(|i| (i % 251) as u8).collect();
+        let temp_tag = store
+            .import_bytes(Bytes::from(data), BlobFormat::Raw)
+            .await
+            .unwrap();
+        let hash = Hash::from_bytes(*temp_tag.hash().as_bytes());
+        assert!(verify_blob(&store, hash).await.unwrap());
+
+        // Flip a byte in the file holding the blob data.
+        let data_file = files(dir.path())
+            .into_iter()
+            .find(|path| path.extension().is_some_and(|ext| ext == "data"))
+            .expect("blob data file");
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(data_file)
+            .unwrap();
+        file.seek(SeekFrom::Start(1000)).unwrap();
+        file.write_all(&[(1000 % 251) as u8 ^ 0xff]).unwrap();
+        file.sync_all().unwrap();
+
+        assert!(!verify_blob(&store, hash).await.unwrap());
+
+        let report: Vec<(Hash, bool)> = verify_all_blobs(store).await.unwrap().collect().await;
+        assert_eq!(report, vec![(hash, false)]);
+    }
+}
@@ -104,6 +104,23 @@ pub trait Extension<T>: Extensions {
     fn extract(_header: &Header<Self>) -> Option<T> {
         None
     }
+
+    /// Borrow the extension value from a header.
+    ///
+    /// Only extension values which are stored as-is in the extensions field of a header can be
+    /// borrowed, values which are derived from other header material need to be extracted with
+    /// [`Extension::extract`] instead.
+    fn extract_ref(_header: &Header<Self>) -> Option<&T> {
+        None
+    }
+
+    /// Mutably borrow the extension value from a header.
+    ///
+    /// Note that changing extensions invalidates the signature of a header, it needs to be signed
+    /// again afterwards.
+    fn extract_mut(_header: &mut Header<Self>) -> Option<&mut T> {
+        None
+    }
 }
 
 /// Super-trait defining trait bounds required by custom extensions types.
@@ -16,15 +16,21 @@
 //! )
 //! ```
 use std::fmt;
+use std::io::Read;
 use std::str::FromStr;
 
 #[cfg(feature = "arbitrary")]
 use arbitrary::Arbitrary;
 use thiserror::Error;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// The length of a BLAKE3 hash in bytes.
 pub const HASH_LEN: usize = blake3::KEY_LEN;
 
+/// Size of the chunks read into the hasher when computing a hash from a reader.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
 /// 32-byte BLAKE3 hash.
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Hash(blake3::Hash);
@@ -35,6 +41,46 @@ impl Hash {
         Self(blake3::hash(buf.as_ref()))
     }
 
+    /// Calculate the hash of all bytes read from the provided reader.
+    ///
+    /// Bytes are streamed into the hasher in chunks, the whole input never needs to be held in
+    /// memory at once. The resulting hash is equal to calling `Hash::new` over the same bytes.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, HashError> {
+        let mut hasher = blake3::Hasher::new();
+        let mut buf = vec![0; READ_CHUNK_SIZE];
+
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            hasher.update(&buf[..n]);
+        }
+
+        Ok(Self(hasher.finalize()))
+    }
+
+    /// Calculate the hash of all bytes read from the provided async reader.
+    ///
+    /// This is the async counterpart of [`Hash::from_reader`].
+    #[cfg(feature = "tokio")]
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(mut reader: R) -> Result<Self, HashError> {
+        let mut hasher = blake3::Hasher::new();
+        let mut buf = vec![0; READ_CHUNK_SIZE];
+
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+
+        Ok(Self(hasher.finalize()))
+    }
+
     /// Create a `Hash` from its raw bytes representation.
     pub const fn from_bytes(bytes: [u8; HASH_LEN]) -> Self {
         Self(blake3::Hash::from_bytes(bytes))
@@ -157,6 +203,10 @@ pub enum HashError {
     /// Hash string contains invalid hexadecimal characters.
     #[error("invalid hex encoding in hash string")]
     InvalidHexEncoding(#[from] hex::FromHexError),
+
+    /// Reading bytes to be hashed failed.
+    #[error("an error occurred while reading bytes: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 #[cfg(test)]
@@ -176,6 +226,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn hashing_from_reader() {
+        let bytes: Vec<u8> = (0..10 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let hash = Hash::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(hash, Hash::new(&bytes));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn hashing_from_async_reader() {
+        let bytes: Vec<u8> = (0..10 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let hash = Hash::from_async_reader(bytes.as_slice()).await.unwrap();
+        assert_eq!(hash, Hash::new(&bytes));
+    }
+
     #[test]
     fn invalid_length() {
         let bytes = vec![254, 100, 4, 7];
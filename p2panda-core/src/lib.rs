@@ -88,8 +88,8 @@ pub use extensions::{Extension, Extensions};
 pub use hash::{Hash, HashError};
 pub use identity::{IdentityError, PrivateKey, PublicKey, Signature};
 pub use operation::{
-    validate_backlink, validate_header, validate_operation, Body, Header, Operation,
-    OperationError, RawOperation,
+    causal_order, is_ancestor, validate_backlink, validate_header, validate_header_structure,
+    validate_operation, validate_operations, Body, Header, Operation, OperationError, RawOperation,
 };
 #[cfg(feature = "prune")]
 pub use prune::PruneFlag;
@@ -97,6 +97,9 @@
 //! let prune_flag: PruneFlag = header.extension().unwrap();
 //! assert!(prune_flag.is_set())
 //! ```
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
 use thiserror::Error;
 
 use crate::cbor::{decode_cbor, encode_cbor, DecodeError};
@@ -115,6 +118,29 @@ pub struct Operation<E = ()> {
     pub body: Option<Body>,
 }
 
+impl<E> Operation<E>
+where
+    E: Extensions,
+{
+    /// Verify the integrity of this operation.
+    ///
+    /// Performs all checks of [`validate_operation`], that is signature verification, header
+    /// field and backlink structure checks and payload hash and size consistency with the body
+    /// (when given). Additionally the operation hash is checked to match the header.
+    ///
+    /// This does not validate the backlink against the previous operation in the log, use
+    /// [`validate_backlink`] for that.
+    pub fn verify(&self) -> Result<(), OperationError> {
+        validate_operation(self)?;
+
+        if self.hash != self.header.hash() {
+            return Err(OperationError::HashMismatch);
+        }
+
+        Ok(())
+    }
+}
+
 impl<E> PartialEq for Operation<E> {
     fn eq(&self, other: &Self) -> bool {
         self.hash.eq(&other.hash)
@@ -248,6 +274,22 @@ where
         self.signature = Some(private_key.sign(&bytes));
     }
 
+    /// Add signatures to many headers at once using the provided `PrivateKey`.
+    ///
+    /// The secret key material is only expanded once and re-used for every header, which is
+    /// considerably faster than calling [`Header::sign`] for each item when signing large batches.
+    /// The resulting signatures are identical to the ones created by `sign`.
+    pub fn sign_batch(headers: &mut [Header<E>], private_key: &PrivateKey) {
+        let expanded_key = private_key.expand();
+
+        for header in headers.iter_mut() {
+            header.signature = None;
+
+            let bytes = header.to_bytes();
+            header.signature = Some(expanded_key.sign(&bytes));
+        }
+    }
+
     /// Verify that the signature contained in this `Header` was generated by the claimed
     /// public key.
     pub fn verify(&self) -> bool {
@@ -262,6 +304,40 @@ where
         }
     }
 
+    /// Verify the signatures of many headers at once.
+    ///
+    /// Uses Ed25519 batch verification which is considerably faster than calling
+    /// [`Header::verify`] for each item when verifying large batches. Returns `true` only if all
+    /// headers are signed and every signature was generated by the claimed public key, it does not
+    /// tell which header failed, use `verify` for this.
+    ///
+    /// Headers signed by weak public keys are always rejected, similar to `verify`.
+    pub fn verify_batch(headers: &[Header<E>]) -> bool {
+        let mut unsigned_bytes = Vec::with_capacity(headers.len());
+        let mut signatures = Vec::with_capacity(headers.len());
+        let mut verifying_keys = Vec::with_capacity(headers.len());
+
+        for header in headers {
+            let Some(claimed_signature) = header.signature else {
+                return false;
+            };
+
+            let verifying_key = ed25519_dalek::VerifyingKey::from(header.public_key);
+            if verifying_key.is_weak() {
+                return false;
+            }
+
+            let mut unsigned_header = header.clone();
+            unsigned_header.signature = None;
+            unsigned_bytes.push(unsigned_header.to_bytes());
+            signatures.push(ed25519_dalek::Signature::from(claimed_signature));
+            verifying_keys.push(verifying_key);
+        }
+
+        let messages: Vec<&[u8]> = unsigned_bytes.iter().map(Vec::as_slice).collect();
+        ed25519_dalek::verify_batch(&messages, &signatures, &verifying_keys).is_ok()
+    }
+
     /// BLAKE3 hash of the header bytes.
     ///
     /// This hash is used as the unique identifier of an operation, aka the Operation Id.
@@ -276,6 +352,74 @@ where
     {
         E::extract(self)
     }
+
+    /// Borrow an extension value from the header.
+    ///
+    /// In contrast to [`Header::extension`] this does not clone the value, it is only available
+    /// for extensions which are stored as-is in the extensions field of the header.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use p2panda_core::{Extension, Hash, Header, PruneFlag};
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Clone, Debug, Serialize, Deserialize)]
+    /// struct LogId(Hash);
+    ///
+    /// // Composite of all extensions used by this application.
+    /// #[derive(Clone, Debug, Serialize, Deserialize)]
+    /// struct CustomExtensions(PruneFlag, LogId);
+    ///
+    /// impl Extension<PruneFlag> for CustomExtensions {
+    ///     fn extract_ref(header: &Header<Self>) -> Option<&PruneFlag> {
+    ///         header.extensions.as_ref().map(|extensions| &extensions.0)
+    ///     }
+    ///
+    ///     fn extract_mut(header: &mut Header<Self>) -> Option<&mut PruneFlag> {
+    ///         header.extensions.as_mut().map(|extensions| &mut extensions.0)
+    ///     }
+    /// }
+    ///
+    /// impl Extension<LogId> for CustomExtensions {
+    ///     fn extract_ref(header: &Header<Self>) -> Option<&LogId> {
+    ///         header.extensions.as_ref().map(|extensions| &extensions.1)
+    ///     }
+    /// }
+    ///
+    /// let mut header = Header {
+    ///     extensions: Some(CustomExtensions(
+    ///         PruneFlag::new(false),
+    ///         LogId(Hash::new(b"my log")),
+    ///     )),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Code reading the prune flag does not need to know about the concrete composition of
+    /// // the extensions.
+    /// let prune_flag = header.extension_ref::<PruneFlag>().unwrap();
+    /// assert!(prune_flag.is_not_set());
+    ///
+    /// *header.extension_mut::<PruneFlag>().unwrap() = PruneFlag::new(true);
+    /// assert!(header.extension_ref::<PruneFlag>().unwrap().is_set());
+    /// ```
+    pub fn extension_ref<T>(&self) -> Option<&T>
+    where
+        E: Extension<T>,
+    {
+        E::extract_ref(self)
+    }
+
+    /// Mutably borrow an extension value from the header.
+    ///
+    /// Changing an extension value invalidates the signature, the header needs to be signed again
+    /// afterwards.
+    pub fn extension_mut<T>(&mut self) -> Option<&mut T>
+    where
+        E: Extension<T>,
+    {
+        E::extract_mut(self)
+    }
 }
 
 impl<E> Header<E> {
@@ -386,6 +530,9 @@ pub enum OperationError {
 
     #[error("given backlink did not match previous operation")]
     BacklinkMismatch,
+
+    #[error("operation hash does not match header")]
+    HashMismatch,
 }
 
 /// Validate the header and body (when provided) of a single operation. All basic header
@@ -403,7 +550,41 @@ where
     E: Extensions,
 {
     validate_header(&operation.header)?;
+    validate_payload(operation)
+}
+
+/// Validate many operations at once.
+///
+/// All checks of [`validate_operation`] are performed, but the signatures of all headers are
+/// verified in one go with [`Header::verify_batch`], which considerably speeds up validating
+/// large batches, for example after syncing a long log. If the batch verification fails, every
+/// operation is verified individually again to find the offending ones.
+///
+/// Returns the validation result for each operation in the same order as they were given.
+pub fn validate_operations<E>(operations: &[Operation<E>]) -> Vec<Result<(), OperationError>>
+where
+    E: Extensions,
+{
+    let headers: Vec<Header<E>> = operations
+        .iter()
+        .map(|operation| operation.header.clone())
+        .collect();
+
+    if !Header::verify_batch(&headers) {
+        return operations.iter().map(validate_operation).collect();
+    }
+
+    operations
+        .iter()
+        .map(|operation| {
+            validate_header_fields(&operation.header)?;
+            validate_payload(operation)
+        })
+        .collect()
+}
 
+/// Validate that the body bytes hash and size match the ones claimed in the header.
+fn validate_payload<E>(operation: &Operation<E>) -> Result<(), OperationError> {
     let claimed_payload_size = operation.header.payload_size;
     let claimed_payload_hash: Option<Hash> = match claimed_payload_size {
         0 => None,
@@ -440,6 +621,30 @@ where
         return Err(OperationError::SignatureMismatch);
     }
 
+    validate_header_fields(header)
+}
+
+/// Validate the structure of an operation header without verifying its signature.
+///
+/// This is a cheap check which does not require a store or any cryptographic operations and can
+/// be used to reject obviously malformed headers early, for example before looking up the previous
+/// operation for [`validate_backlink`].
+///
+/// This method validates that the following conditions are true:
+/// * Header contains a signature
+/// * Header version is supported (currently only version 1 is supported)
+/// * If `payload_hash` is set the `payload_size` is > `0` otherwise it is zero
+/// * If `backlink` is set then `seq_num` is > `0` otherwise it is zero
+pub fn validate_header_structure<E>(header: &Header<E>) -> Result<(), OperationError> {
+    if header.signature.is_none() {
+        return Err(OperationError::MissingSignature);
+    }
+
+    validate_header_fields(header)
+}
+
+/// Validate all header fields except of the signature.
+fn validate_header_fields<E>(header: &Header<E>) -> Result<(), OperationError> {
     if header.version != 1 {
         return Err(OperationError::UnsupportedVersion(header.version, 1));
     }
@@ -500,6 +705,74 @@ where
     Ok(())
 }
 
+/// Returns `true` if the operation with the `ancestor` hash is reachable from the given header by
+/// following its `backlink` and `previous` links.
+///
+/// Only headers contained in `headers` are traversed, the result is `false` if the ancestry can't
+/// be determined because intermediate operations are missing.
+pub fn is_ancestor<E>(
+    ancestor: &Hash,
+    header: &Header<E>,
+    headers: &HashMap<Hash, Header<E>>,
+) -> bool {
+    let mut visited = HashSet::new();
+    let mut queue: Vec<Hash> = parents(header).collect();
+
+    while let Some(hash) = queue.pop() {
+        if &hash == ancestor {
+            return true;
+        }
+
+        if !visited.insert(hash) {
+            continue;
+        }
+
+        if let Some(parent) = headers.get(&hash) {
+            queue.extend(parents(parent));
+        }
+    }
+
+    false
+}
+
+/// Compares two headers by their causal relationship.
+///
+/// Returns `Ordering::Less` if `a` is an ancestor of `b`, `Ordering::Greater` if `b` is an
+/// ancestor of `a` and `Ordering::Equal` if both are the same operation. `None` is returned if the
+/// operations are concurrent, that is neither of them causally precedes the other.
+///
+/// See [`is_ancestor`] for how the given `headers` are used to traverse the graph.
+pub fn causal_order<E>(
+    a: &Header<E>,
+    b: &Header<E>,
+    headers: &HashMap<Hash, Header<E>>,
+) -> Option<Ordering>
+where
+    E: Extensions,
+{
+    let hash_a = a.hash();
+    let hash_b = b.hash();
+
+    if hash_a == hash_b {
+        Some(Ordering::Equal)
+    } else if is_ancestor(&hash_a, b, headers) {
+        Some(Ordering::Less)
+    } else if is_ancestor(&hash_b, a, headers) {
+        Some(Ordering::Greater)
+    } else {
+        None
+    }
+}
+
+/// Returns the hashes of all operations the given header directly depends on.
+fn parents<E>(header: &Header<E>) -> impl Iterator<Item = Hash> + '_ {
+    header
+        .backlink
+        .iter()
+        .chain(header.previous.iter())
+        .copied()
+}
+
 #[cfg(test)]
 mod tests {
     use serde::{Deserialize, Serialize};
@@ -559,6 +832,70 @@ mod tests {
         assert!(validate_operation(&operation).is_ok());
     }
 
+    #[test]
+    fn sign_batch() {
+        let private_key = PrivateKey::new();
+
+        let mut headers: Vec<Header<()>> = (0..1000)
+            .map(|_| {
+                let body = Body::new(&rand::random::<[u8; 16]>());
+                Header {
+                    version: 1,
+                    public_key: private_key.public_key(),
+                    signature: None,
+                    payload_size: body.size(),
+                    payload_hash: Some(body.hash()),
+                    timestamp: rand::random(),
+                    seq_num: rand::random(),
+                    backlink: Some(Hash::new(rand::random::<[u8; 32]>())),
+                    previous: vec![],
+                    extensions: None,
+                }
+            })
+            .collect();
+
+        let mut expected = headers.clone();
+        for header in expected.iter_mut() {
+            header.sign(&private_key);
+        }
+
+        Header::sign_batch(&mut headers, &private_key);
+
+        assert_eq!(headers, expected);
+        for header in headers {
+            assert!(header.verify());
+        }
+    }
+
+    #[test]
+    fn verify_batch() {
+        let private_key = PrivateKey::new();
+
+        let mut headers: Vec<Header<()>> = (0..100)
+            .map(|seq_num| Header {
+                version: 1,
+                public_key: private_key.public_key(),
+                signature: None,
+                payload_size: 0,
+                payload_hash: None,
+                timestamp: 0,
+                seq_num,
+                backlink: None,
+                previous: vec![],
+                extensions: None,
+            })
+            .collect();
+        assert!(!Header::verify_batch(&headers));
+
+        Header::sign_batch(&mut headers, &private_key);
+        assert!(Header::verify_batch(&headers));
+        assert!(Header::<()>::verify_batch(&[]));
+
+        // Tamper with one header after it was signed.
+        headers[42].timestamp = 12;
+        assert!(!Header::verify_batch(&headers));
+    }
+
     #[test]
     fn valid_backlink_header() {
         let private_key = PrivateKey::new();
@@ -596,6 +933,192 @@ mod tests {
         assert!(validate_backlink(&header_0, &header_1).is_ok());
     }
 
+    #[test]
+    fn verify_operation() {
+        let private_key = PrivateKey::new();
+        let body = Body::new("Hello, Sloth!".as_bytes());
+
+        let mut header = Header::<()> {
+            version: 1,
+            public_key: private_key.public_key(),
+            signature: None,
+            payload_size: body.size(),
+            payload_hash: Some(body.hash()),
+            timestamp: 0,
+            seq_num: 0,
+            backlink: None,
+            previous: vec![],
+            extensions: None,
+        };
+        header.sign(&private_key);
+
+        let operation = Operation {
+            hash: header.hash(),
+            header: header.clone(),
+            body: Some(body.clone()),
+        };
+        assert!(operation.verify().is_ok());
+
+        // Signature doesn't match public key
+        let mut invalid_header = header.clone();
+        invalid_header.public_key = PrivateKey::new().public_key();
+        let invalid_operation = Operation {
+            hash: invalid_header.hash(),
+            header: invalid_header,
+            body: Some(body.clone()),
+        };
+        assert!(matches!(
+            invalid_operation.verify(),
+            Err(OperationError::SignatureMismatch)
+        ));
+
+        // Payload hash doesn't match body
+        let invalid_operation = Operation {
+            body: Some(Body::new("Hello, Panda!".as_bytes())),
+            ..operation.clone()
+        };
+        assert!(matches!(
+            invalid_operation.verify(),
+            Err(OperationError::PayloadMismatch)
+        ));
+
+        // Operation hash doesn't match header
+        let invalid_operation = Operation {
+            hash: Hash::new(vec![1, 2, 3]),
+            ..operation
+        };
+        assert!(matches!(
+            invalid_operation.verify(),
+            Err(OperationError::HashMismatch)
+        ));
+    }
+
+    #[test]
+    fn causal_relations() {
+        let private_key = PrivateKey::new();
+
+        let header = |seq_num: u64, backlink: Option<Hash>, previous: Vec<Hash>| Header::<()> {
+            version: 1,
+            public_key: private_key.public_key(),
+            signature: None,
+            payload_size: 0,
+            payload_hash: None,
+            timestamp: seq_num,
+            seq_num,
+            backlink,
+            previous,
+            extensions: None,
+        };
+
+        //        /-- a1 ------- c0
+        // root --         \
+        //        \-- b0 --- a2
+        //
+        // Operations b0 and c0 link to their dependencies via `previous`.
+        let root = header(0, None, vec![]);
+        let a1 = header(1, Some(root.hash()), vec![]);
+        let b0 = header(0, None, vec![root.hash()]);
+        let a2 = header(2, Some(a1.hash()), vec![b0.hash()]);
+        let c0 = header(0, None, vec![a1.hash()]);
+
+        let headers: HashMap<Hash, Header> = [&root, &a1, &b0, &a2, &c0]
+            .into_iter()
+            .map(|header| (header.hash(), header.clone()))
+            .collect();
+
+        assert!(is_ancestor(&root.hash(), &a2, &headers));
+        assert!(is_ancestor(&b0.hash(), &a2, &headers));
+        assert!(!is_ancestor(&a2.hash(), &root, &headers));
+        assert!(!is_ancestor(&c0.hash(), &a2, &headers));
+
+        assert_eq!(causal_order(&a1, &a1, &headers), Some(Ordering::Equal));
+        assert_eq!(causal_order(&root, &a2, &headers), Some(Ordering::Less));
+        assert_eq!(causal_order(&c0, &root, &headers), Some(Ordering::Greater));
+        assert_eq!(causal_order(&a1, &c0, &headers), Some(Ordering::Less));
+        assert_eq!(causal_order(&a1, &b0, &headers), None);
+        assert_eq!(causal_order(&c0, &a2, &headers), None);
+        assert_eq!(causal_order(&c0, &b0, &headers), None);
+
+        // Ancestry can't be determined when intermediate operations are missing.
+        let mut incomplete = headers.clone();
+        incomplete.remove(&a1.hash());
+        assert!(!is_ancestor(&root.hash(), &c0, &incomplete));
+        assert_eq!(causal_order(&root, &c0, &incomplete), None);
+    }
+
+    #[test]
+    fn invalid_header_structure() {
+        let private_key = PrivateKey::new();
+
+        let mut header_base = Header::<()> {
+            version: 1,
+            public_key: private_key.public_key(),
+            signature: None,
+            payload_size: 0,
+            payload_hash: None,
+            timestamp: 0,
+            seq_num: 0,
+            backlink: None,
+            previous: vec![],
+            extensions: None,
+        };
+        header_base.sign(&private_key);
+        assert!(validate_header_structure(&header_base).is_ok());
+
+        // Signature missing
+        let mut header = header_base.clone();
+        header.signature = None;
+        assert!(matches!(
+            validate_header_structure(&header),
+            Err(OperationError::MissingSignature)
+        ));
+
+        // Signature is not verified
+        let mut header = header_base.clone();
+        header.timestamp = 1;
+        assert!(validate_header_structure(&header).is_ok());
+
+        // Incompatible operation format
+        let mut header = header_base.clone();
+        header.version = 2;
+        assert!(matches!(
+            validate_header_structure(&header),
+            Err(OperationError::UnsupportedVersion(2, 1))
+        ));
+
+        // Backlink missing
+        let mut header = header_base.clone();
+        header.seq_num = 1;
+        assert!(matches!(
+            validate_header_structure(&header),
+            Err(OperationError::BacklinkMissing)
+        ));
+
+        // Backlink given but sequence number indicates none
+        let mut header = header_base.clone();
+        header.backlink = Some(Hash::new(vec![1, 2, 3]));
+        assert!(matches!(
+            validate_header_structure(&header),
+            Err(OperationError::SeqNumMismatch)
+        ));
+
+        // Payload hash given without size
+        let mut header = header_base.clone();
+        header.payload_hash = Some(Hash::new(vec![1, 2, 3]));
+        assert!(matches!(
+            validate_header_structure(&header),
+            Err(OperationError::InconsistentPayloadInfo)
+        ));
+
+        // Payload size given without hash
+        let mut header = header_base.clone();
+        header.payload_size = 3;
+        assert!(matches!(
+            validate_header_structure(&header),
+            Err(OperationError::InconsistentPayloadInfo)
+        ));
+    }
+
     #[test]
     fn invalid_operations() {
         let private_key = PrivateKey::new();
@@ -745,4 +1268,57 @@ mod tests {
         assert_eq!(header.hash(), log_id.0);
         assert_eq!(extensions.expires.0, expiry.0);
     }
+
+    #[test]
+    fn borrowed_extensions() {
+        #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+        struct Expiry(u64);
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct CustomExtensions {
+            expires: Expiry,
+        }
+
+        impl Extension<Expiry> for CustomExtensions {
+            fn extract_ref(header: &Header<Self>) -> Option<&Expiry> {
+                header
+                    .extensions
+                    .as_ref()
+                    .map(|extensions| &extensions.expires)
+            }
+
+            fn extract_mut(header: &mut Header<Self>) -> Option<&mut Expiry> {
+                header
+                    .extensions
+                    .as_mut()
+                    .map(|extensions| &mut extensions.expires)
+            }
+        }
+
+        let private_key = PrivateKey::new();
+        let mut header = Header {
+            public_key: private_key.public_key(),
+            extensions: Some(CustomExtensions {
+                expires: Expiry(123456),
+            }),
+            ..Default::default()
+        };
+        header.sign(&private_key);
+
+        assert_eq!(header.extension_ref::<Expiry>(), Some(&Expiry(123456)));
+        // Not implemented, so nothing can be extracted
+        assert!(header.extension::<Expiry>().is_none());
+
+        header.extension_mut::<Expiry>().unwrap().0 = 789;
+        assert_eq!(header.extension_ref::<Expiry>(), Some(&Expiry(789)));
+
+        // Changed extensions invalidate the signature
+        assert!(!header.verify());
+        header.sign(&private_key);
+        assert!(header.verify());
+
+        // Headers without extensions do not return anything
+        let header = Header::<CustomExtensions>::default();
+        assert!(header.extension_ref::<Expiry>().is_none());
+    }
 }
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_lite::stream::Stream;
+use iroh::{NodeAddr, NodeId};
+use tokio_util::time::DelayQueue;
+
+use crate::{BoxedStream, DiscoveryEvent};
+
+/// Stream coalescing all discovery events for the same peer within a time window into a single
+/// event.
+///
+/// The first discovery of a peer opens a window, all further discoveries of that peer within that
+/// window are merged into the pending event which gets emitted as soon as the window closes.
+pub struct DedupStream {
+    inner: BoxedStream<Result<DiscoveryEvent>>,
+    inner_done: bool,
+    window: Duration,
+    pending: HashMap<NodeId, DiscoveryEvent>,
+    expirations: DelayQueue<NodeId>,
+}
+
+impl DedupStream {
+    pub fn new(inner: BoxedStream<Result<DiscoveryEvent>>, window: Duration) -> Self {
+        Self {
+            inner,
+            inner_done: false,
+            window,
+            pending: HashMap::new(),
+            expirations: DelayQueue::new(),
+        }
+    }
+
+    fn insert(&mut self, event: DiscoveryEvent) {
+        let node_id = event.node_addr.node_id;
+        match self.pending.get_mut(&node_id) {
+            Some(pending) => {
+                if merge_node_addr(&mut pending.node_addr, event.node_addr) {
+                    pending.provenance = event.provenance;
+                }
+            }
+            None => {
+                self.expirations.insert(node_id, self.window);
+                self.pending.insert(node_id, event);
+            }
+        }
+    }
+}
+
+/// Add all addressing information from `other` which is not yet known in `node_addr`.
+///
+/// Returns `true` if any new information was added.
+fn merge_node_addr(node_addr: &mut NodeAddr, other: NodeAddr) -> bool {
+    let len = node_addr.direct_addresses.len();
+    node_addr.direct_addresses.extend(other.direct_addresses);
+    let mut changed = node_addr.direct_addresses.len() != len;
+    if node_addr.relay_url.is_none() && other.relay_url.is_some() {
+        node_addr.relay_url = other.relay_url;
+        changed = true;
+    }
+    changed
+}
+
+impl Stream for DedupStream {
+    type Item = Result<DiscoveryEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        while !self.inner_done {
+            match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) => self.insert(event),
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => self.inner_done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        match self.expirations.poll_expired(cx) {
+            Poll::Ready(Some(expired)) => {
+                let event = self
+                    .pending
+                    .remove(expired.get_ref())
+                    .expect("pending event exists for every expiration");
+                Poll::Ready(Some(Ok(event)))
+            }
+            // No windows are open, we're done if the inner stream is exhausted as well.
+            Poll::Ready(None) if self.inner_done => Poll::Ready(None),
+            _ => Poll::Pending,
+        }
+    }
+}
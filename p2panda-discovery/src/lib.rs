@@ -2,21 +2,29 @@
 
 //! Peer discovery traits and services.
 //!
-//! This crate currently provides a single discovery service implementation: mDNS. It is disabled
-//! by default and can be selected by enabling the `mdns` feature flag.
+//! This crate currently provides three discovery service implementations: mDNS, rendezvous
+//! servers and a static list of bootstrap peers. mDNS and rendezvous are disabled by default and
+//! can be selected by enabling the `mdns` or `rendezvous` feature flags.
 //!
 //! Generic traits are provided to facitilate the creation of other peer discovery implementations.
+mod dedup;
 #[cfg(feature = "mdns")]
 pub mod mdns;
+#[cfg(feature = "rendezvous")]
+pub mod rendezvous;
+pub mod static_peers;
 
 use std::fmt::Debug;
 use std::pin::Pin;
+use std::time::Duration;
 
 use anyhow::Result;
 use futures_buffered::MergeBounded;
 use futures_lite::stream::Stream;
 use iroh::NodeAddr;
 
+use crate::dedup::DedupStream;
+
 pub type BoxedStream<T> = Pin<Box<dyn Stream<Item = T> + Send + 'static>>;
 
 /// A collection of discovery services.
@@ -25,15 +33,33 @@ pub type BoxedStream<T> = Pin<Box<dyn Stream<Item = T> + Send + 'static>>;
 /// a single stream comprising all events from multiple discovery strategies. This also allows updating the address
 /// information of the local node for all discovery services with a single call to
 /// `update_local_address`.
+///
+/// The same peer is often discovered by multiple services at around the same time. Optionally a
+/// deduplication window can be set with `with_dedup_window`, repeated discoveries of the same peer
+/// within that window are then suppressed.
 #[derive(Debug, Default)]
 pub struct DiscoveryMap {
     services: Vec<Box<dyn Discovery>>,
+    dedup_window: Option<Duration>,
 }
 
 impl DiscoveryMap {
     /// Instantiate a `DiscoveryMap` from a list of services.
     pub fn from_services(services: Vec<Box<dyn Discovery>>) -> Self {
-        Self { services }
+        Self {
+            services,
+            dedup_window: None,
+        }
+    }
+
+    /// Suppress repeated discoveries of the same peer within the given window.
+    ///
+    /// The first discovery of a peer opens the window, all discoveries of that peer within it are
+    /// merged and reported as a single event once the window closes. This delays reporting a peer
+    /// by the length of the window.
+    pub fn with_dedup_window(mut self, dedup_window: Duration) -> Self {
+        self.dedup_window = Some(dedup_window);
+        self
     }
 
     /// Add a single discovery service to the map.
@@ -48,8 +74,12 @@ impl Discovery for DiscoveryMap {
             .services
             .iter()
             .filter_map(|service| service.subscribe(network_id));
-        let streams = MergeBounded::from_iter(streams);
-        Some(Box::pin(streams))
+        let streams: BoxedStream<Result<DiscoveryEvent>> =
+            Box::pin(MergeBounded::from_iter(streams));
+        match self.dedup_window {
+            Some(window) => Some(Box::pin(DedupStream::new(streams, window))),
+            None => Some(streams),
+        }
     }
 
     fn update_local_address(&self, addr: &NodeAddr) -> Result<()> {
@@ -91,3 +121,80 @@ pub trait Discovery: Debug + Send + Sync {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use futures_lite::stream::{self, StreamExt};
+    use iroh::{NodeAddr, SecretKey};
+    use tokio::time::Instant;
+
+    use crate::{BoxedStream, Discovery, DiscoveryEvent, DiscoveryMap};
+
+    #[derive(Debug)]
+    struct MockDiscovery {
+        provenance: &'static str,
+        node_addr: NodeAddr,
+    }
+
+    impl Discovery for MockDiscovery {
+        fn update_local_address(&self, _node_addr: &NodeAddr) -> Result<()> {
+            Ok(())
+        }
+
+        fn subscribe(&self, _network_id: [u8; 32]) -> Option<BoxedStream<Result<DiscoveryEvent>>> {
+            let event = DiscoveryEvent {
+                provenance: self.provenance,
+                node_addr: self.node_addr.clone(),
+            };
+            Some(Box::pin(stream::once(Ok(event))))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn deduplicate_events() {
+        let node_id = SecretKey::from_bytes(&[1; 32]).public();
+        let addr_1 = SocketAddr::from(([192, 168, 0, 1], 2022));
+        let addr_2 = SocketAddr::from(([192, 168, 0, 2], 2022));
+
+        let services = || -> Vec<Box<dyn Discovery>> {
+            vec![
+                Box::new(MockDiscovery {
+                    provenance: "mdns",
+                    node_addr: NodeAddr::new(node_id).with_direct_addresses([addr_1]),
+                }),
+                Box::new(MockDiscovery {
+                    provenance: "static",
+                    node_addr: NodeAddr::new(node_id).with_direct_addresses([addr_1]),
+                }),
+                Box::new(MockDiscovery {
+                    provenance: "rendezvous",
+                    node_addr: NodeAddr::new(node_id).with_direct_addresses([addr_2]),
+                }),
+            ]
+        };
+
+        // Without a deduplication window every discovery is reported.
+        let discovery = DiscoveryMap::from_services(services());
+        let events: Vec<_> = discovery.subscribe([0; 32]).unwrap().collect().await;
+        assert_eq!(events.len(), 3);
+
+        // With a window all discoveries are merged into a single event reported when the window
+        // closes.
+        let discovery =
+            DiscoveryMap::from_services(services()).with_dedup_window(Duration::from_millis(100));
+        let mut stream = discovery.subscribe([0; 32]).unwrap();
+
+        let started_at = Instant::now();
+        let merged = stream.next().await.unwrap().unwrap();
+        assert_eq!(started_at.elapsed(), Duration::from_millis(100));
+        assert_eq!(merged.node_addr.node_id, node_id);
+        assert!(merged.node_addr.direct_addresses.contains(&addr_1));
+        assert!(merged.node_addr.direct_addresses.contains(&addr_2));
+
+        assert!(stream.next().await.is_none());
+    }
+}
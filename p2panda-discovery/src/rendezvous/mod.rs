@@ -0,0 +1,254 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Peer discovery via a rendezvous server.
+//!
+//! Nodes register their address with a rendezvous server under a network id and regularly ask
+//! the server for other nodes registered under the same network id. This allows discovering peers
+//! in wide area networks where mDNS is not available. Registrations are signed with the secret key
+//! of the node, the server only accepts registrations for addresses of the signing node.
+mod protocol;
+mod server;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use flume::Sender;
+use iroh::{NodeAddr, SecretKey};
+use tokio::net::TcpStream;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::warn;
+
+use crate::rendezvous::protocol::{read_frame, write_frame};
+pub use crate::rendezvous::protocol::{Registration, Request, Response};
+pub use crate::rendezvous::server::RendezvousServer;
+use crate::{BoxedStream, Discovery, DiscoveryEvent};
+
+const RENDEZVOUS_PROVENANCE: &str = "rendezvous";
+const RENDEZVOUS_INTERVAL: Duration = Duration::from_millis(5000);
+const RENDEZVOUS_TIMEOUT: Duration = Duration::from_millis(3000);
+
+type SubscribeSender = Sender<Result<DiscoveryEvent>>;
+
+enum Message {
+    Subscribe([u8; 32], SubscribeSender),
+    UpdateLocalAddress(NodeAddr),
+}
+
+/// Discovery service registering the local node with a rendezvous server and looking up other
+/// peers from it.
+#[derive(Debug)]
+pub struct RendezvousDiscovery {
+    #[allow(dead_code)]
+    handle: AbortOnDropHandle<()>,
+    tx: Sender<Message>,
+}
+
+impl RendezvousDiscovery {
+    /// Use the rendezvous server reachable at the given address.
+    ///
+    /// Registrations of the local node are signed with the given secret key, it needs to be the
+    /// secret key of the local node.
+    pub fn new(server_addr: SocketAddr, secret_key: SecretKey) -> Self {
+        let (tx, rx) = flume::bounded(64);
+
+        let mut subscribers: HashMap<[u8; 32], Vec<SubscribeSender>> = HashMap::new();
+        let mut my_node_addr: Option<NodeAddr> = None;
+
+        let handle = tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(RENDEZVOUS_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    biased;
+                    Ok(msg) = rx.recv_async() => {
+                        match msg {
+                            Message::Subscribe(network_id, subscribe_tx) => {
+                                subscribers.entry(network_id).or_default().push(subscribe_tx);
+                            }
+                            Message::UpdateLocalAddress(addr) => {
+                                my_node_addr = Some(addr);
+                            }
+                        }
+
+                        // Inform the server and look up peers right away.
+                        interval.reset_immediately();
+                    },
+                    _ = interval.tick() => {
+                        // Forget about subscribers who are not interested anymore.
+                        subscribers.retain(|_, senders| {
+                            senders.retain(|sender| !sender.is_disconnected());
+                            !senders.is_empty()
+                        });
+
+                        if subscribers.is_empty() {
+                            continue;
+                        }
+
+                        // Give up on unreachable or unresponsive servers, otherwise we would stop
+                        // handling new subscriptions and address updates.
+                        let result = tokio::time::timeout(
+                            RENDEZVOUS_TIMEOUT,
+                            rendezvous(
+                                server_addr,
+                                &secret_key,
+                                my_node_addr.as_ref(),
+                                &subscribers,
+                            ),
+                        ).await;
+                        match result {
+                            Ok(Ok(())) => (),
+                            Ok(Err(err)) => {
+                                warn!("failed to reach rendezvous server {server_addr}: {err}");
+                            }
+                            Err(_) => {
+                                warn!("rendezvous server {server_addr} timed out");
+                            }
+                        }
+                    },
+                    else => break,
+                }
+            }
+        });
+
+        Self {
+            handle: AbortOnDropHandle::new(handle),
+            tx,
+        }
+    }
+}
+
+/// Register our own address for all subscribed networks and forward all peers returned by the
+/// server to the subscribers.
+async fn rendezvous(
+    server_addr: SocketAddr,
+    secret_key: &SecretKey,
+    my_node_addr: Option<&NodeAddr>,
+    subscribers: &HashMap<[u8; 32], Vec<SubscribeSender>>,
+) -> Result<()> {
+    let mut stream = TcpStream::connect(server_addr).await?;
+
+    for (network_id, subscribers) in subscribers {
+        if let Some(my_node_addr) = my_node_addr {
+            let registration = Registration::new(*network_id, my_node_addr.clone(), secret_key);
+            let request = Request::Register(registration);
+            write_frame(&mut stream, &request.encode()).await?;
+            let Response::Registered = Response::decode(&read_frame(&mut stream).await?)? else {
+                bail!("unexpected response to register request");
+            };
+        }
+
+        write_frame(&mut stream, &Request::Lookup(*network_id).encode()).await?;
+        let Response::Peers(node_addrs) = Response::decode(&read_frame(&mut stream).await?)? else {
+            bail!("unexpected response to lookup request");
+        };
+
+        for node_addr in node_addrs {
+            if let Some(my_node_addr) = my_node_addr {
+                if node_addr.node_id == my_node_addr.node_id {
+                    continue;
+                }
+            }
+
+            // Do not wait for slow subscribers, the peer will be reported again on the next
+            // lookup.
+            for subscribe_tx in subscribers {
+                subscribe_tx
+                    .try_send(Ok(DiscoveryEvent {
+                        provenance: RENDEZVOUS_PROVENANCE,
+                        node_addr: node_addr.clone(),
+                    }))
+                    .ok();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Discovery for RendezvousDiscovery {
+    fn subscribe(&self, network_id: [u8; 32]) -> Option<BoxedStream<Result<DiscoveryEvent>>> {
+        let (subscribe_tx, subscribe_rx) = flume::bounded(16);
+        let service_tx = self.tx.clone();
+
+        tokio::spawn(async move {
+            service_tx
+                .send_async(Message::Subscribe(network_id, subscribe_tx))
+                .await
+                .ok();
+        });
+
+        Some(Box::pin(subscribe_rx.into_stream()))
+    }
+
+    fn update_local_address(&self, addr: &NodeAddr) -> Result<()> {
+        let tx = self.tx.clone();
+        let addr = addr.clone();
+        tokio::spawn(async move {
+            tx.send_async(Message::UpdateLocalAddress(addr)).await.ok();
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use futures_lite::StreamExt;
+    use iroh::{NodeAddr, SecretKey};
+
+    use crate::Discovery;
+
+    use super::{RendezvousDiscovery, RendezvousServer};
+
+    fn node_addr(secret_key: &SecretKey, seed: u8) -> NodeAddr {
+        NodeAddr::new(secret_key.public())
+            .with_direct_addresses([SocketAddr::from(([10, 0, 0, seed], 2022))])
+    }
+
+    #[tokio::test]
+    async fn discover_peers_via_server() {
+        let server = RendezvousServer::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let network_id = [1; 32];
+
+        let secret_key_a = SecretKey::from_bytes(&[1; 32]);
+        let secret_key_b = SecretKey::from_bytes(&[2; 32]);
+        let node_addr_a = node_addr(&secret_key_a, 1);
+        let node_addr_b = node_addr(&secret_key_b, 2);
+
+        let discovery_a = RendezvousDiscovery::new(server.local_addr(), secret_key_a);
+        let discovery_b = RendezvousDiscovery::new(server.local_addr(), secret_key_b);
+
+        discovery_a.update_local_address(&node_addr_a).unwrap();
+        let mut events_a = discovery_a.subscribe(network_id).unwrap();
+
+        // Wait until the first node registered itself before the second one looks up peers.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        discovery_b.update_local_address(&node_addr_b).unwrap();
+        let mut events_b = discovery_b.subscribe(network_id).unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(10), events_b.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.provenance, "rendezvous");
+        assert_eq!(event.node_addr, node_addr_a);
+
+        // Updating the address makes the first node look up peers again right away, it learns
+        // about the second node but never reports itself.
+        discovery_a.update_local_address(&node_addr_a).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(10), events_a.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.node_addr, node_addr_b);
+    }
+}
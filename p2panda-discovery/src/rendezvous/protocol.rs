@@ -0,0 +1,330 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Wire protocol between rendezvous clients and servers.
+//!
+//! Every message is framed by a big-endian `u32` length prefix followed by the encoded message.
+//! A client sends a request and waits for the response before sending the next request over the
+//! same connection.
+//!
+//! ```text
+//! Request:
+//!   0x00 Register  network_id[32] node_addr timestamp:u64 signature[64]
+//!   0x01 Lookup    network_id[32]
+//!
+//! Response:
+//!   0x00 Registered
+//!   0x01 Peers     count:u16 node_addr*
+//!
+//! node_addr:
+//!   node_id[32]
+//!   relay_url_len:u16 relay_url (UTF-8, length 0 if none)
+//!   direct_addresses_count:u16 (ip_version:u8 ip[4 or 16] port:u16)*
+//! ```
+//!
+//! Registrations are signed by the secret key of the registered node over the network id, the
+//! encoded node address and the timestamp (seconds since UNIX epoch) at which they were created.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use iroh::{NodeAddr, NodeId, RelayUrl, SecretKey};
+use iroh_base::Signature;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Maximum size of a single message in bytes.
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// Maximum number of peers returned in a single lookup response.
+pub const MAX_PEERS: usize = 128;
+
+/// Maximum number of direct addresses the server keeps per registered node.
+pub const MAX_DIRECT_ADDRESSES: usize = 16;
+
+/// Maximum length of a relay url the server keeps per registered node.
+///
+/// Together with `MAX_DIRECT_ADDRESSES` this bounds an encoded node address to 468 bytes, a
+/// response with `MAX_PEERS` nodes always fits into `MAX_FRAME_LEN`.
+pub const MAX_RELAY_URL_LEN: usize = 128;
+
+/// Address of a node announced for a network, signed by that node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registration {
+    pub network_id: [u8; 32],
+    pub node_addr: NodeAddr,
+    pub timestamp: u64,
+    pub signature: Signature,
+}
+
+impl Registration {
+    /// Sign the address of the local node for the given network.
+    ///
+    /// The secret key needs to belong to the node id of the given address, otherwise the server
+    /// will reject the registration.
+    pub fn new(network_id: [u8; 32], node_addr: NodeAddr, secret_key: &SecretKey) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is after UNIX epoch")
+            .as_secs();
+        let signature = secret_key.sign(&signed_bytes(&network_id, &node_addr, timestamp));
+        Self {
+            network_id,
+            node_addr,
+            timestamp,
+            signature,
+        }
+    }
+
+    /// Check that the registration was signed by the registered node and is not older or newer
+    /// than the given maximum age.
+    pub fn verify(&self, max_age: Duration) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is after UNIX epoch")
+            .as_secs();
+        if now.abs_diff(self.timestamp) > max_age.as_secs() {
+            bail!("registration timestamp is out of range");
+        }
+        self.node_addr
+            .node_id
+            .verify(
+                &signed_bytes(&self.network_id, &self.node_addr, self.timestamp),
+                &self.signature,
+            )
+            .context("invalid registration signature")
+    }
+}
+
+fn signed_bytes(network_id: &[u8; 32], node_addr: &NodeAddr, timestamp: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(network_id);
+    encode_node_addr(&mut buf, node_addr);
+    buf.extend_from_slice(&timestamp.to_be_bytes());
+    buf
+}
+
+/// Requests sent from a client to the rendezvous server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    /// Announce the address of the local node for a network.
+    Register(Registration),
+
+    /// Ask for peers which were announced for the given network.
+    Lookup([u8; 32]),
+}
+
+/// Responses sent from the rendezvous server to a client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    /// The node address was registered.
+    Registered,
+
+    /// Peers currently registered for the requested network.
+    Peers(Vec<NodeAddr>),
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Request::Register(registration) => {
+                buf.push(0);
+                buf.extend_from_slice(&registration.network_id);
+                encode_node_addr(&mut buf, &registration.node_addr);
+                buf.extend_from_slice(&registration.timestamp.to_be_bytes());
+                buf.extend_from_slice(&registration.signature.to_bytes());
+            }
+            Request::Lookup(network_id) => {
+                buf.push(1);
+                buf.extend_from_slice(network_id);
+            }
+        }
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let request = match reader.u8()? {
+            0 => {
+                let network_id = reader.array()?;
+                let node_addr = decode_node_addr(&mut reader)?;
+                let timestamp = u64::from_be_bytes(reader.array()?);
+                let signature = Signature::from_bytes(&reader.array()?);
+                Request::Register(Registration {
+                    network_id,
+                    node_addr,
+                    timestamp,
+                    signature,
+                })
+            }
+            1 => Request::Lookup(reader.array()?),
+            tag => bail!("unknown request type {tag}"),
+        };
+        reader.finish()?;
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Response::Registered => buf.push(0),
+            Response::Peers(node_addrs) => {
+                buf.push(1);
+                let node_addrs = &node_addrs[..node_addrs.len().min(MAX_PEERS)];
+                buf.extend_from_slice(&(node_addrs.len() as u16).to_be_bytes());
+                for node_addr in node_addrs {
+                    encode_node_addr(&mut buf, node_addr);
+                }
+            }
+        }
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let response = match reader.u8()? {
+            0 => Response::Registered,
+            1 => {
+                let count = reader.u16()? as usize;
+                if count > MAX_PEERS {
+                    bail!("too many peers in response");
+                }
+                let mut node_addrs = Vec::with_capacity(count);
+                for _ in 0..count {
+                    node_addrs.push(decode_node_addr(&mut reader)?);
+                }
+                Response::Peers(node_addrs)
+            }
+            tag => bail!("unknown response type {tag}"),
+        };
+        reader.finish()?;
+        Ok(response)
+    }
+}
+
+/// Write a length-prefixed frame.
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+    if bytes.len() > MAX_FRAME_LEN {
+        bail!("frame exceeds maximum length");
+    }
+    // Write the frame at once, sending the length prefix separately would stall every request
+    // on TCP sockets with Nagle's algorithm enabled.
+    let mut frame = Vec::with_capacity(4 + bytes.len());
+    frame.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    frame.extend_from_slice(bytes);
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read a length-prefixed frame.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = reader.read_u32().await? as usize;
+    if len > MAX_FRAME_LEN {
+        bail!("frame exceeds maximum length");
+    }
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+fn encode_node_addr(buf: &mut Vec<u8>, node_addr: &NodeAddr) {
+    buf.extend_from_slice(node_addr.node_id.as_bytes());
+
+    let relay_url = node_addr
+        .relay_url
+        .as_ref()
+        .map(|url| url.to_string())
+        .unwrap_or_default();
+    buf.extend_from_slice(&(relay_url.len() as u16).to_be_bytes());
+    buf.extend_from_slice(relay_url.as_bytes());
+
+    let direct_addresses: Vec<&SocketAddr> = node_addr.direct_addresses().collect();
+    buf.extend_from_slice(&(direct_addresses.len() as u16).to_be_bytes());
+    for addr in direct_addresses {
+        match addr.ip() {
+            IpAddr::V4(ip) => {
+                buf.push(4);
+                buf.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                buf.push(6);
+                buf.extend_from_slice(&ip.octets());
+            }
+        }
+        buf.extend_from_slice(&addr.port().to_be_bytes());
+    }
+}
+
+fn decode_node_addr(reader: &mut Reader) -> Result<NodeAddr> {
+    let node_id = NodeId::from_bytes(&reader.array()?).context("invalid node id")?;
+
+    let relay_url_len = reader.u16()? as usize;
+    let relay_url = match relay_url_len {
+        0 => None,
+        len => {
+            let url = std::str::from_utf8(reader.bytes(len)?).context("invalid relay url")?;
+            Some(RelayUrl::from_str(url).context("invalid relay url")?)
+        }
+    };
+
+    let count = reader.u16()? as usize;
+    let mut direct_addresses = Vec::with_capacity(count.min(MAX_PEERS));
+    for _ in 0..count {
+        let ip = match reader.u8()? {
+            4 => IpAddr::V4(Ipv4Addr::from(reader.array::<4>()?)),
+            6 => IpAddr::V6(Ipv6Addr::from(reader.array::<16>()?)),
+            version => bail!("unknown ip version {version}"),
+        };
+        let port = reader.u16()?;
+        direct_addresses.push(SocketAddr::new(ip, port));
+    }
+
+    let mut node_addr = NodeAddr::new(node_id).with_direct_addresses(direct_addresses);
+    if let Some(relay_url) = relay_url {
+        node_addr = node_addr.with_relay_url(relay_url);
+    }
+    Ok(node_addr)
+}
+
+/// Helper to read values from a byte slice, failing if not enough bytes are left.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!("unexpected end of message");
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.bytes(N)?;
+        Ok(bytes.try_into().expect("slice has correct length"))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn finish(self) -> Result<()> {
+        if !self.bytes.is_empty() {
+            bail!("unexpected trailing bytes in message");
+        }
+        Ok(())
+    }
+}
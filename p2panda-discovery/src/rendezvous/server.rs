@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use iroh::{NodeAddr, NodeId};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{debug, warn};
+
+use crate::rendezvous::protocol::{
+    read_frame, write_frame, Request, Response, MAX_DIRECT_ADDRESSES, MAX_PEERS, MAX_RELAY_URL_LEN,
+};
+
+/// Duration after which registrations expire if they were not renewed.
+const REGISTRATION_TTL: Duration = Duration::from_secs(60);
+
+/// Maximum number of nodes registered under the same network id.
+const MAX_REGISTRATIONS: usize = MAX_PEERS;
+
+type Registrations = Arc<Mutex<HashMap<[u8; 32], HashMap<NodeId, (NodeAddr, Instant)>>>>;
+
+/// Minimal rendezvous server keeping registrations of nodes in memory.
+///
+/// Nodes register their address under a network id and can look up all other nodes registered
+/// under the same network id. Registrations expire if they are not renewed regularly.
+///
+/// Registrations need to be signed by the registered node, so nodes can't register addresses
+/// on behalf of others. They are capped to `MAX_DIRECT_ADDRESSES` direct addresses and a relay url
+/// of at most `MAX_RELAY_URL_LEN` bytes, at most `MAX_PEERS` nodes are kept per network. Nodes
+/// registering with a full network replace the registration which was renewed the longest time
+/// ago.
+#[derive(Debug)]
+pub struct RendezvousServer {
+    local_addr: SocketAddr,
+    #[allow(dead_code)]
+    handle: AbortOnDropHandle<()>,
+}
+
+impl RendezvousServer {
+    /// Bind the server to the given address and start accepting connections.
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let registrations = Registrations::default();
+
+        let handle = tokio::task::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer_addr)) => {
+                        let registrations = registrations.clone();
+                        tokio::task::spawn(async move {
+                            if let Err(err) = handle_connection(stream, registrations).await {
+                                debug!("rendezvous connection with {peer_addr} closed: {err}");
+                            }
+                        });
+                    }
+                    Err(err) => warn!("failed to accept rendezvous connection: {err}"),
+                }
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            handle: AbortOnDropHandle::new(handle),
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, registrations: Registrations) -> Result<()> {
+    loop {
+        let request = Request::decode(&read_frame(&mut stream).await?)?;
+        let response = match request {
+            Request::Register(registration) => {
+                registration.verify(REGISTRATION_TTL)?;
+                let node_addr = limit_node_addr(registration.node_addr);
+                let mut registrations = registrations.lock().expect("lock is not poisoned");
+                let nodes = registrations.entry(registration.network_id).or_default();
+                remove_expired(nodes);
+                if nodes.len() >= MAX_REGISTRATIONS && !nodes.contains_key(&node_addr.node_id) {
+                    remove_oldest(nodes);
+                }
+                nodes.insert(node_addr.node_id, (node_addr, Instant::now()));
+                Response::Registered
+            }
+            Request::Lookup(network_id) => {
+                let mut registrations = registrations.lock().expect("lock is not poisoned");
+                let node_addrs = match registrations.get_mut(&network_id) {
+                    Some(nodes) => {
+                        remove_expired(nodes);
+                        nodes
+                            .values()
+                            .take(MAX_PEERS)
+                            .map(|(node_addr, _)| node_addr.clone())
+                            .collect()
+                    }
+                    None => Vec::new(),
+                };
+                Response::Peers(node_addrs)
+            }
+        };
+        write_frame(&mut stream, &response.encode()).await?;
+    }
+}
+
+fn remove_expired(nodes: &mut HashMap<NodeId, (NodeAddr, Instant)>) {
+    nodes.retain(|_, (_, registered_at)| registered_at.elapsed() < REGISTRATION_TTL);
+}
+
+fn remove_oldest(nodes: &mut HashMap<NodeId, (NodeAddr, Instant)>) {
+    let oldest = nodes
+        .iter()
+        .min_by_key(|(_, (_, registered_at))| *registered_at)
+        .map(|(node_id, _)| *node_id);
+    if let Some(node_id) = oldest {
+        debug!("too many registrations, evict {node_id}");
+        nodes.remove(&node_id);
+    }
+}
+
+/// Drop all information of a node address exceeding the limits, so lookup responses always fit
+/// into a single frame.
+fn limit_node_addr(node_addr: NodeAddr) -> NodeAddr {
+    let direct_addresses: Vec<SocketAddr> = node_addr
+        .direct_addresses()
+        .take(MAX_DIRECT_ADDRESSES)
+        .copied()
+        .collect();
+    let mut limited = NodeAddr::new(node_addr.node_id).with_direct_addresses(direct_addresses);
+    if let Some(relay_url) = node_addr.relay_url {
+        if relay_url.to_string().len() <= MAX_RELAY_URL_LEN {
+            limited = limited.with_relay_url(relay_url);
+        }
+    }
+    limited
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use iroh::{NodeAddr, SecretKey};
+    use tokio::net::TcpStream;
+
+    use crate::rendezvous::protocol::{
+        read_frame, write_frame, Registration, Request, Response, MAX_DIRECT_ADDRESSES, MAX_PEERS,
+    };
+
+    use super::RendezvousServer;
+
+    fn secret_key(i: u32) -> SecretKey {
+        let mut seed = [0; 32];
+        seed[..4].copy_from_slice(&i.to_be_bytes());
+        SecretKey::from_bytes(&seed)
+    }
+
+    async fn request(stream: &mut TcpStream, request: Request) -> anyhow::Result<Response> {
+        write_frame(stream, &request.encode()).await?;
+        Response::decode(&read_frame(stream).await?)
+    }
+
+    async fn lookup(stream: &mut TcpStream, network_id: [u8; 32]) -> Vec<NodeAddr> {
+        let Response::Peers(node_addrs) =
+            request(stream, Request::Lookup(network_id)).await.unwrap()
+        else {
+            panic!("expected peers response");
+        };
+        node_addrs
+    }
+
+    #[tokio::test]
+    async fn limit_registrations() {
+        let server = RendezvousServer::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        let network_id = [1; 32];
+
+        // Register more nodes with more addresses than a single response could hold.
+        for i in 0..(MAX_PEERS as u32 + 10) {
+            let secret_key = secret_key(i);
+            let direct_addresses = (0..100).map(|port| SocketAddr::from(([10, 0, 0, 1], port)));
+            let node_addr =
+                NodeAddr::new(secret_key.public()).with_direct_addresses(direct_addresses);
+
+            let registration = Registration::new(network_id, node_addr, &secret_key);
+            let response = request(&mut stream, Request::Register(registration))
+                .await
+                .unwrap();
+            assert_eq!(response, Response::Registered);
+        }
+
+        let node_addrs = lookup(&mut stream, network_id).await;
+        assert_eq!(node_addrs.len(), MAX_PEERS);
+        for node_addr in &node_addrs {
+            assert_eq!(node_addr.direct_addresses().count(), MAX_DIRECT_ADDRESSES);
+        }
+
+        // The oldest registrations were replaced by the newest ones.
+        let registered = |i| {
+            node_addrs
+                .iter()
+                .any(|node_addr| node_addr.node_id == secret_key(i).public())
+        };
+        assert!(!registered(0));
+        assert!(!registered(9));
+        assert!(registered(10));
+        assert!(registered(MAX_PEERS as u32 + 9));
+    }
+
+    #[tokio::test]
+    async fn reject_unsigned_registrations() {
+        let server = RendezvousServer::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let network_id = [1; 32];
+
+        // Register the address of another node.
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        let node_addr = NodeAddr::new(secret_key(1).public());
+        let registration = Registration::new(network_id, node_addr, &secret_key(2));
+        assert!(request(&mut stream, Request::Register(registration))
+            .await
+            .is_err());
+
+        // Tamper with a signed registration.
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        let node_addr = NodeAddr::new(secret_key(1).public());
+        let mut registration = Registration::new(network_id, node_addr, &secret_key(1));
+        registration.node_addr = registration
+            .node_addr
+            .with_direct_addresses([SocketAddr::from(([10, 0, 0, 1], 2022))]);
+        assert!(request(&mut stream, Request::Register(registration))
+            .await
+            .is_err());
+
+        // Nothing was registered.
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        assert!(lookup(&mut stream, network_id).await.is_empty());
+
+        // Correctly signed registrations are accepted.
+        let node_addr = NodeAddr::new(secret_key(1).public());
+        let registration = Registration::new(network_id, node_addr.clone(), &secret_key(1));
+        let response = request(&mut stream, Request::Register(registration))
+            .await
+            .unwrap();
+        assert_eq!(response, Response::Registered);
+        assert_eq!(lookup(&mut stream, network_id).await, vec![node_addr]);
+    }
+}
@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Discovery of a fixed list of peers, for example to bootstrap a node in a wide area network.
+use anyhow::Result;
+use futures_lite::stream;
+use iroh::NodeAddr;
+
+use crate::{BoxedStream, Discovery, DiscoveryEvent};
+
+const STATIC_PROVENANCE: &str = "static";
+
+/// Discovery service emitting a fixed list of known peers.
+///
+/// Every subscription immediately yields one event for each configured peer and ends afterwards.
+/// This can be used to seed a node with bootstrap peers when no local discovery mechanism like
+/// mDNS is available.
+#[derive(Clone, Debug, Default)]
+pub struct StaticDiscovery {
+    peers: Vec<NodeAddr>,
+}
+
+impl StaticDiscovery {
+    pub fn new(peers: Vec<NodeAddr>) -> Self {
+        Self { peers }
+    }
+}
+
+impl Discovery for StaticDiscovery {
+    fn update_local_address(&self, _node_addr: &NodeAddr) -> Result<()> {
+        Ok(())
+    }
+
+    fn subscribe(&self, _network_id: [u8; 32]) -> Option<BoxedStream<Result<DiscoveryEvent>>> {
+        let events: Vec<Result<DiscoveryEvent>> = self
+            .peers
+            .iter()
+            .map(|node_addr| {
+                Ok(DiscoveryEvent {
+                    provenance: STATIC_PROVENANCE,
+                    node_addr: node_addr.clone(),
+                })
+            })
+            .collect();
+        Some(Box::pin(stream::iter(events)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use futures_lite::StreamExt;
+    use iroh::{NodeAddr, SecretKey};
+
+    use crate::Discovery;
+
+    use super::{StaticDiscovery, STATIC_PROVENANCE};
+
+    #[tokio::test]
+    async fn yield_configured_peers() {
+        let peers: Vec<NodeAddr> = (1..=3)
+            .map(|i| {
+                let node_id = SecretKey::from_bytes(&[i; 32]).public();
+                let addr = SocketAddr::from(([192, 168, 0, i], 2022));
+                NodeAddr::new(node_id).with_direct_addresses([addr])
+            })
+            .collect();
+
+        let discovery = StaticDiscovery::new(peers.clone());
+        let events: Vec<_> = discovery.subscribe([0; 32]).unwrap().collect().await;
+        assert_eq!(events.len(), peers.len());
+
+        for (event, node_addr) in events.into_iter().zip(peers) {
+            let event = event.unwrap();
+            assert_eq!(event.provenance, STATIC_PROVENANCE);
+            assert_eq!(event.node_addr, node_addr);
+        }
+    }
+}
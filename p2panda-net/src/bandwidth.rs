@@ -0,0 +1,244 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Bandwidth limits for outbound and inbound traffic.
+//!
+//! Limits are enforced with a token bucket which is shared across all gossip overlays and sync
+//! sessions of a node. As soon as the bucket is empty, sending or receiving further bytes blocks
+//! until enough tokens have been refilled; no data is dropped.
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use futures_util::{AsyncRead, AsyncWrite};
+use tokio::time::{sleep, Duration, Instant, Sleep};
+
+/// Fraction of a second worth of tokens the bucket can hold, this limits the size of bursts.
+const BURST_SECS: f64 = 0.1;
+
+/// Upload and download limits of a node.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Bandwidth {
+    pub upload: RateLimiter,
+    pub download: RateLimiter,
+}
+
+impl Bandwidth {
+    /// Returns limits for the given rates in bytes per second, `None` means unlimited.
+    pub fn new(upload_rate_limit: Option<u64>, download_rate_limit: Option<u64>) -> Self {
+        Self {
+            upload: RateLimiter::new(upload_rate_limit),
+            download: RateLimiter::new(download_rate_limit),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            capacity: (rate * BURST_SECS).max(1.0),
+            // Start with an empty bucket, so we never exceed the rate, even right after startup.
+            tokens: 0.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes up to `max` tokens from the bucket.
+    ///
+    /// Returns the duration to wait for the next token if the bucket is empty.
+    fn take(&mut self, max: usize) -> Result<usize, Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+
+        let available = self.tokens.floor() as usize;
+        if available == 0 {
+            return Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate));
+        }
+
+        let taken = available.min(max);
+        self.tokens -= taken as f64;
+        Ok(taken)
+    }
+}
+
+/// Shared token bucket limiting the number of bytes per second, unlimited if no rate was given.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RateLimiter(Option<Arc<Mutex<TokenBucket>>>);
+
+impl RateLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        Self(rate.map(|rate| Arc::new(Mutex::new(TokenBucket::new(rate)))))
+    }
+
+    /// Takes up to `max` tokens without waiting.
+    ///
+    /// Returns the duration to wait for the next token if none are available.
+    pub fn take(&self, max: usize) -> Result<usize, Duration> {
+        match &self.0 {
+            Some(bucket) => bucket.lock().expect("lock is not poisoned").take(max),
+            None => Ok(max),
+        }
+    }
+
+    /// Waits until `len` bytes can be transmitted.
+    pub async fn acquire(&self, len: usize) {
+        let mut remaining = len;
+        while remaining > 0 {
+            match self.take(remaining) {
+                Ok(taken) => remaining -= taken,
+                Err(wait) => sleep(wait).await,
+            }
+        }
+    }
+}
+
+/// Wraps a stream and throttles reads and writes according to the given rate limiter.
+pub(crate) struct Throttled<S> {
+    inner: S,
+    limiter: RateLimiter,
+    reserved: usize,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Throttled<S> {
+    pub fn new(inner: S, limiter: RateLimiter) -> Self {
+        Self {
+            inner,
+            limiter,
+            reserved: 0,
+            delay: None,
+        }
+    }
+
+    /// Reserves tokens for transmitting up to `max` bytes, returns the number of reserved tokens.
+    ///
+    /// Reserved tokens are kept around until they were used by a read or write, even if the inner
+    /// stream was not ready yet.
+    fn poll_reserve(&mut self, cx: &mut Context<'_>, max: usize) -> Poll<usize> {
+        while self.reserved == 0 {
+            if let Some(delay) = &mut self.delay {
+                ready!(delay.as_mut().poll(cx));
+                self.delay = None;
+            }
+
+            match self.limiter.take(max) {
+                Ok(taken) => self.reserved = taken,
+                Err(wait) => self.delay = Some(Box::pin(sleep(wait))),
+            }
+        }
+
+        Poll::Ready(self.reserved.min(max))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Throttled<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        let len = ready!(this.poll_reserve(cx, buf.len()));
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..len]))?;
+        this.reserved -= written;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Throttled<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        let len = ready!(this.poll_reserve(cx, buf.len()));
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut buf[..len]))?;
+        this.reserved -= read;
+        Poll::Ready(Ok(read))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::io::Cursor;
+    use futures_util::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::{Duration, Instant};
+
+    use super::{RateLimiter, Throttled};
+
+    const PAYLOAD_SIZE: usize = 5_000;
+    const RATE_LIMIT: u64 = 10_000;
+
+    #[tokio::test]
+    async fn throttled_write() {
+        let payload = vec![7; PAYLOAD_SIZE];
+
+        let now = Instant::now();
+        let mut writer = Throttled::new(Vec::new(), RateLimiter::new(Some(RATE_LIMIT)));
+        writer.write_all(&payload).await.unwrap();
+        let elapsed = now.elapsed();
+
+        assert_eq!(writer.inner, payload);
+        assert!(elapsed >= Duration::from_secs_f64(PAYLOAD_SIZE as f64 / RATE_LIMIT as f64));
+    }
+
+    #[tokio::test]
+    async fn throttled_read() {
+        let payload = vec![7; PAYLOAD_SIZE];
+
+        let now = Instant::now();
+        let mut reader = Throttled::new(
+            Cursor::new(payload.clone()),
+            RateLimiter::new(Some(RATE_LIMIT)),
+        );
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).await.unwrap();
+        let elapsed = now.elapsed();
+
+        assert_eq!(received, payload);
+        assert!(elapsed >= Duration::from_secs_f64(PAYLOAD_SIZE as f64 / RATE_LIMIT as f64));
+    }
+
+    #[tokio::test]
+    async fn acquire() {
+        let now = Instant::now();
+        let limiter = RateLimiter::new(Some(RATE_LIMIT));
+        limiter.acquire(PAYLOAD_SIZE).await;
+        assert!(now.elapsed() >= Duration::from_secs_f64(PAYLOAD_SIZE as f64 / RATE_LIMIT as f64));
+
+        // Unlimited rate limiter never blocks.
+        let now = Instant::now();
+        RateLimiter::new(None).acquire(PAYLOAD_SIZE).await;
+        assert!(now.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -7,8 +7,13 @@
 //!
 //! `GossipConfig` allows configuration of swarm membership, gossip broadcast and maximum message
 //! size. It is passed into `Network::gossip`.
+//!
+//! `BackoffConfig` defines how frequently the node re-attempts connecting to the network after
+//! failing to do so. It is part of `Config` and can be passed into
+//! `NetworkBuilder::reconnect_backoff`.
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
@@ -17,6 +22,12 @@ use crate::{NetworkId, NodeAddress, RelayUrl};
 /// Default port of a node socket.
 pub const DEFAULT_BIND_PORT: u16 = 2022;
 
+/// Default number of peers we're maintaining active gossip connections with.
+pub const DEFAULT_GOSSIP_FANOUT: usize = 5;
+
+/// Default duration for which gossip messages are kept around to be forwarded to other peers.
+pub const DEFAULT_GOSSIP_MESSAGE_TTL: Duration = Duration::from_secs(30);
+
 /// Default network id.
 pub const DEFAULT_NETWORK_ID: NetworkId = [
     247, 69, 248, 242, 132, 120, 159, 230, 98, 100, 214, 200, 78, 40, 79, 94, 174, 8, 12, 27, 84,
@@ -52,6 +63,28 @@ pub struct Config {
     /// URL of a relay server to help in establishing a peer-to-peer connection if one or both peers
     /// are behind a NAT or firewall.
     pub relay: Option<RelayUrl>,
+
+    /// Backoff strategy for re-attempting to connect to the network after failure.
+    #[serde(default)]
+    pub reconnect_backoff: BackoffConfig,
+
+    /// Maximum number of bytes per second sent via gossip and sync. If not provided, uploads are
+    /// not limited.
+    #[serde(default)]
+    pub upload_rate_limit: Option<u64>,
+
+    /// Maximum number of bytes per second received via gossip and sync. If not provided,
+    /// downloads are not limited.
+    #[serde(default)]
+    pub download_rate_limit: Option<u64>,
+
+    /// Number of peers we're maintaining active gossip connections with.
+    #[serde(default = "default_gossip_fanout")]
+    pub gossip_fanout: usize,
+
+    /// Duration for which gossip messages are kept around to be forwarded to other peers.
+    #[serde(default = "default_gossip_message_ttl")]
+    pub gossip_message_ttl: Duration,
 }
 
 impl Default for Config {
@@ -65,21 +98,76 @@ impl Default for Config {
             network_id: DEFAULT_NETWORK_ID,
             private_key: None,
             relay: None,
+            reconnect_backoff: BackoffConfig::default(),
+            upload_rate_limit: None,
+            download_rate_limit: None,
+            gossip_fanout: DEFAULT_GOSSIP_FANOUT,
+            gossip_message_ttl: DEFAULT_GOSSIP_MESSAGE_TTL,
         }
     }
 }
 
+fn default_gossip_fanout() -> usize {
+    DEFAULT_GOSSIP_FANOUT
+}
+
+fn default_gossip_message_ttl() -> Duration {
+    DEFAULT_GOSSIP_MESSAGE_TTL
+}
+
 /// Configuration parameters for gossip overlays.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+///
+/// Dense local meshes can get away with a lower fan-out, while sparse topologies over the internet
+/// benefit from more active connections and messages being kept around for longer.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GossipConfig {
     /// Maximum gossip message size in bytes.
     pub max_message_size: usize,
+
+    /// Number of peers we're maintaining active connections with in every gossip overlay
+    /// (HyParView active view), messages are eagerly pushed to them.
+    pub fanout: usize,
+
+    /// Duration for which messages are kept in the cache to be forwarded to peers who missed
+    /// them (Plumtree message cache retention).
+    pub message_ttl: Duration,
 }
 
 impl Default for GossipConfig {
     fn default() -> Self {
         Self {
             max_message_size: 4096,
+            fanout: DEFAULT_GOSSIP_FANOUT,
+            message_ttl: DEFAULT_GOSSIP_MESSAGE_TTL,
+        }
+    }
+}
+
+/// Configuration parameters for re-attempting connections after failure.
+///
+/// The delay between two attempts starts at `initial_delay` and is multiplied by `multiplier`
+/// after every failed attempt, until `max_delay` is reached. The delay is reset as soon as a
+/// connection was established.
+///
+/// The default values retry with a fixed delay of 900 milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackoffConfig {
+    /// Delay before the first re-attempt.
+    pub initial_delay: Duration,
+
+    /// Upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+
+    /// Factor by which the delay grows after every failed attempt.
+    pub multiplier: f64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(900),
+            max_delay: Duration::from_millis(900),
+            multiplier: 1.0,
         }
     }
 }
@@ -3,9 +3,11 @@
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+use iroh::endpoint::Connection;
 use p2panda_core::PublicKey;
 use rand::seq::IteratorRandom;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
+use tracing::debug;
 
 use crate::{NetworkId, NodeAddress};
 
@@ -14,16 +16,21 @@ use crate::{NetworkId, NodeAddress};
 /// Manages a list of all peer addresses which are known to us (usually populated by a "peer
 /// discovery" process) and a list of all topic id's peers in this network are interested in
 /// (usually populated by a "topic discovery" process).
+///
+/// Peers can be denied by the application, these are excluded when choosing peers to connect to
+/// and any connections or messages from them are refused until they are allowed again.
 #[derive(Debug, Clone)]
 pub struct AddressBook {
     network_id: NetworkId,
     inner: Arc<RwLock<AddressBookInner>>,
+    denied_tx: Arc<watch::Sender<()>>,
 }
 
 #[derive(Debug)]
 struct AddressBookInner {
     known_peer_topic_ids: HashMap<PublicKey, HashSet<[u8; 32]>>,
     known_peer_addresses: HashMap<PublicKey, Vec<NodeAddress>>,
+    denied_peers: HashSet<PublicKey>,
 }
 
 impl AddressBook {
@@ -34,7 +41,9 @@ impl AddressBook {
             inner: Arc::new(RwLock::new(AddressBookInner {
                 known_peer_topic_ids: HashMap::new(),
                 known_peer_addresses: HashMap::new(),
+                denied_peers: HashSet::new(),
             })),
+            denied_tx: Arc::new(watch::Sender::new(())),
         }
     }
 
@@ -83,7 +92,75 @@ impl AddressBook {
             .collect()
     }
 
+    /// Return `true` if we know about an address of the given peer.
+    pub async fn is_known(&self, public_key: &PublicKey) -> bool {
+        let inner = self.inner.read().await;
+        inner.known_peer_addresses.contains_key(public_key)
+    }
+
+    /// Return all topic ids the given peer is known to be interested in.
+    pub async fn topic_ids(&self, public_key: &PublicKey) -> Vec<[u8; 32]> {
+        let inner = self.inner.read().await;
+        inner
+            .known_peer_topic_ids
+            .get(public_key)
+            .map(|topic_ids| topic_ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Deny all connections and messages from the given peer.
+    ///
+    /// Connections to the peer which were registered with `close_on_deny` are closed.
+    pub async fn deny_peer(&mut self, public_key: PublicKey) {
+        let mut inner = self.inner.write().await;
+        inner.denied_peers.insert(public_key);
+        self.denied_tx.send_replace(());
+    }
+
+    /// Allow a previously denied peer again.
+    pub async fn allow_peer(&mut self, public_key: PublicKey) {
+        let mut inner = self.inner.write().await;
+        inner.denied_peers.remove(&public_key);
+    }
+
+    /// Return `true` if the given peer is denied.
+    pub async fn is_denied(&self, public_key: &PublicKey) -> bool {
+        let inner = self.inner.read().await;
+        inner.denied_peers.contains(public_key)
+    }
+
+    /// Close the given connection to a peer as soon as they get denied.
+    ///
+    /// The connection is watched in a background task until it is closed.
+    pub fn close_on_deny(&self, peer: PublicKey, connection: Connection) {
+        let address_book = self.clone();
+        let mut denied_rx = self.denied_tx.subscribe();
+        tokio::spawn(async move {
+            loop {
+                // The peer might have been denied before we subscribed.
+                if address_book.is_denied(&peer).await {
+                    debug!("closing connection to denied peer {peer}");
+                    connection.close(0u32.into(), b"denied");
+                    break;
+                }
+
+                tokio::select! {
+                    _ = connection.closed() => break,
+                    Ok(()) = denied_rx.changed() => (),
+                }
+            }
+        });
+    }
+
+    /// Return list of all denied peers.
+    pub async fn denied_peers(&self) -> Vec<PublicKey> {
+        let inner = self.inner.read().await;
+        inner.denied_peers.iter().copied().collect()
+    }
+
     /// Return random set of known peers with an interest in the given topic.
+    ///
+    /// Denied peers are never included.
     pub async fn random_set(&self, topic_id: [u8; 32], sample_len: usize) -> Vec<PublicKey> {
         let inner = self.inner.read().await;
 
@@ -92,7 +169,7 @@ impl AddressBook {
                 .known_peer_topic_ids
                 .iter()
                 .fold(Vec::new(), |mut acc, (node_id, topics)| {
-                    if topics.contains(&topic_id) {
+                    if topics.contains(&topic_id) && !inner.denied_peers.contains(node_id) {
                         acc.push(*node_id);
                     }
                     acc
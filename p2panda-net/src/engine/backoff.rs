@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::time::Duration;
+
+use crate::config::BackoffConfig;
+
+/// Geometrically growing delays between re-attempts, bounded by a maximum delay.
+#[derive(Debug)]
+pub struct Backoff {
+    config: BackoffConfig,
+    next_delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(config: BackoffConfig) -> Self {
+        let next_delay = config.initial_delay.min(config.max_delay);
+        Self { config, next_delay }
+    }
+
+    /// Returns the delay to wait before the next attempt and increases it for the one after.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next_delay;
+        self.next_delay =
+            Duration::try_from_secs_f64(delay.as_secs_f64() * self.config.multiplier.max(1.0))
+                .unwrap_or(self.config.max_delay)
+                .min(self.config.max_delay);
+        delay
+    }
+
+    /// Starts again with the initial delay, for example after a successful attempt.
+    pub fn reset(&mut self) {
+        self.next_delay = self.config.initial_delay.min(self.config.max_delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::BackoffConfig;
+
+    use super::Backoff;
+
+    #[test]
+    fn geometric_progression() {
+        let mut backoff = Backoff::new(BackoffConfig {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+        });
+
+        // Simulate repeated failures.
+        let delays: Vec<Duration> = (0..6).map(|_| backoff.next_delay()).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(800),
+                Duration::from_secs(1),
+                Duration::from_secs(1),
+            ]
+        );
+
+        // Start from the beginning after a successful attempt.
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn default_fixed_delay() {
+        let mut backoff = Backoff::new(BackoffConfig::default());
+        for _ in 0..10 {
+            assert_eq!(backoff.next_delay(), Duration::from_millis(900));
+        }
+    }
+}
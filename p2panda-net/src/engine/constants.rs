@@ -8,11 +8,21 @@ use std::time::Duration;
 /// establish connections. As soon as we've joined the gossip we will learn about more peers.
 pub const JOIN_PEERS_SAMPLE_LEN: usize = 7;
 
-/// Frequency of attempts to join the gossip overlay which is used for "topic discovery".
-pub const JOIN_NETWORK_INTERVAL: Duration = Duration::from_millis(900);
-
 /// Frequency of topic id announcements (to network peers).
 pub const ANNOUNCE_TOPICS_INTERVAL: Duration = Duration::from_millis(2200);
 
 /// Frequency of attempts to join gossip overlays for application-defined topic ids.
 pub const JOIN_TOPICS_INTERVAL: Duration = Duration::from_millis(1200);
+
+/// Frequency of checks if the network paths to connected peers changed.
+pub const CONNECTION_PATHS_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Duration after which we consider a peer to be disconnected when nothing was received from them
+/// anymore.
+///
+/// Connections send keep-alive messages every second, this matches the idle timeout after which
+/// they are closed.
+pub const CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before joining a gossip overlay again after we've left it to drop a denied peer.
+pub const REJOIN_DELAY: Duration = Duration::from_millis(100);
@@ -1,26 +1,36 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::collections::HashSet;
+
 use anyhow::{Context, Result};
 use futures_lite::FutureExt;
+use iroh::endpoint::ConnectionType;
 use iroh::Endpoint;
 use netwatch::netmon::Monitor;
 use p2panda_core::{PrivateKey, PublicKey};
 use p2panda_sync::TopicQuery;
 use tokio::sync::{broadcast, mpsc, oneshot};
-use tokio::time::interval;
+use tokio::time::{interval, sleep, Duration, Instant};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, warn};
 
 use crate::addrs::{from_node_addr, to_relay_url};
+use crate::bandwidth::Bandwidth;
+use crate::config::BackoffConfig;
 use crate::engine::address_book::AddressBook;
+use crate::engine::backoff::Backoff;
 use crate::engine::constants::{
-    ANNOUNCE_TOPICS_INTERVAL, JOIN_NETWORK_INTERVAL, JOIN_TOPICS_INTERVAL,
+    ANNOUNCE_TOPICS_INTERVAL, CONNECTION_IDLE_TIMEOUT, CONNECTION_PATHS_INTERVAL,
+    JOIN_PEERS_SAMPLE_LEN, JOIN_TOPICS_INTERVAL,
 };
 use crate::engine::gossip::{GossipActor, ToGossipActor};
+use crate::engine::paths::{PathChange, PathTracker};
 use crate::engine::topic_discovery::TopicDiscovery;
 use crate::engine::topic_streams::TopicStreams;
-use crate::events::SystemEvent;
+use crate::engine::traffic::TrafficMeter;
+use crate::events::{DisconnectReason, SystemEvent};
 use crate::network::{FromNetwork, ToNetwork};
+use crate::stats::{ConnectionPath, ConnectionStats};
 use crate::sync::manager::{SyncActor, ToSyncActor};
 use crate::{from_public_key, to_public_key, NetworkId, NodeAddress, TopicId};
 
@@ -35,12 +45,28 @@ pub enum ToEngineActor<T> {
     KnownPeers {
         reply: oneshot::Sender<Vec<NodeAddress>>,
     },
+    DenyPeer {
+        public_key: PublicKey,
+    },
+    AllowPeer {
+        public_key: PublicKey,
+    },
+    DeniedPeers {
+        reply: oneshot::Sender<Vec<PublicKey>>,
+    },
+    ConnectionStats {
+        reply: oneshot::Sender<Vec<ConnectionStats>>,
+    },
     SubscribeTopic {
         topic: T,
         from_network_tx: mpsc::Sender<FromNetwork>,
         to_network_rx: mpsc::Receiver<ToNetwork>,
         gossip_ready_tx: oneshot::Sender<()>,
     },
+    UnsubscribeTopic {
+        topic: T,
+        reply: oneshot::Sender<()>,
+    },
     GossipJoined {
         topic_id: [u8; 32],
         peers: Vec<PublicKey>,
@@ -75,16 +101,26 @@ pub enum ToEngineActor<T> {
     SyncDone {
         topic: T,
         peer: PublicKey,
+        received: usize,
     },
     SyncFailed {
         topic: Option<T>,
         peer: PublicKey,
+        error: String,
     },
     Shutdown {
         reply: oneshot::Sender<()>,
     },
 }
 
+/// Shared state and settings handed to the engine actor.
+pub struct EngineContext {
+    pub address_book: AddressBook,
+    pub bandwidth: Bandwidth,
+    pub traffic: TrafficMeter,
+    pub reconnect_backoff: BackoffConfig,
+}
+
 /// The core event orchestrator of the networking layer.
 pub struct EngineActor<T> {
     private_key: PrivateKey,
@@ -92,11 +128,14 @@ pub struct EngineActor<T> {
     endpoint: Endpoint,
     gossip_actor_tx: mpsc::Sender<ToGossipActor>,
     inbox: mpsc::Receiver<ToEngineActor<T>>,
+    join_network_backoff: Backoff,
     network_id: NetworkId,
+    path_tracker: PathTracker,
     sync_actor_tx: Option<mpsc::Sender<ToSyncActor<T>>>,
     system_event_tx: Option<broadcast::Sender<SystemEvent<T>>>,
     topic_discovery: TopicDiscovery,
     topic_streams: TopicStreams<T>,
+    traffic: TrafficMeter,
 }
 
 impl<T> EngineActor<T>
@@ -106,18 +145,27 @@ where
     pub fn new(
         private_key: PrivateKey,
         endpoint: Endpoint,
-        address_book: AddressBook,
         inbox: mpsc::Receiver<ToEngineActor<T>>,
         gossip_actor_tx: mpsc::Sender<ToGossipActor>,
         sync_actor_tx: Option<mpsc::Sender<ToSyncActor<T>>>,
         network_id: NetworkId,
+        context: EngineContext,
     ) -> Self {
+        let EngineContext {
+            address_book,
+            bandwidth,
+            traffic,
+            reconnect_backoff,
+        } = context;
+
         let topic_discovery =
             TopicDiscovery::new(network_id, gossip_actor_tx.clone(), address_book.clone());
         let topic_streams = TopicStreams::new(
             gossip_actor_tx.clone(),
             address_book.clone(),
             sync_actor_tx.clone(),
+            bandwidth,
+            traffic.clone(),
         );
 
         Self {
@@ -126,11 +174,14 @@ where
             endpoint,
             gossip_actor_tx,
             inbox,
+            join_network_backoff: Backoff::new(reconnect_backoff),
             network_id,
+            path_tracker: PathTracker::new(),
             sync_actor_tx,
             system_event_tx: None,
             topic_discovery,
             topic_streams,
+            traffic,
         }
     }
 
@@ -140,10 +191,10 @@ where
         mut self,
         mut gossip_actor: GossipActor<T>,
         sync_actor: Option<SyncActor<T>>,
+        shutdown_token: CancellationToken,
     ) -> Result<()> {
-        // Used to shutdown the sync manager.
-        let shutdown_token = CancellationToken::new();
-
+        // The token is used to shutdown the sync manager, it might have been cancelled before
+        // already when running sync sessions were drained.
         if let Some(sync_actor) = sync_actor {
             let shutdown_token = shutdown_token.clone();
             tokio::task::spawn(async move {
@@ -181,12 +232,15 @@ where
 
     /// Runs the event loop of the engine actor.
     ///
-    /// Interval-based timers are used to trigger attempts to join the network-wide and
-    /// topic-specific gossip overlays, as well as to announce the locally-subscribed topics.
+    /// Interval-based timers are used to trigger attempts to join the topic-specific gossip
+    /// overlays, as well as to announce the locally-subscribed topics. Attempts to join the
+    /// network-wide gossip overlay are re-tried following the configured backoff strategy.
     async fn run_inner(&mut self) -> Result<oneshot::Sender<()>> {
-        let mut join_network_interval = interval(JOIN_NETWORK_INTERVAL);
+        let join_network_timer = sleep(Duration::ZERO);
+        tokio::pin!(join_network_timer);
         let mut join_topics_interval = interval(JOIN_TOPICS_INTERVAL);
         let mut announce_topics_interval = interval(ANNOUNCE_TOPICS_INTERVAL);
+        let mut connection_paths_interval = interval(CONNECTION_PATHS_INTERVAL);
 
         // Setup network monitoring. This allows us to detect major interface changes and reset
         // topic discovery and sync state.
@@ -229,14 +283,21 @@ where
                     // with our peers before entering "live mode" again.
                     debug!("detected major network interface change");
                     self.topic_discovery.reset_status().await;
+                    self.join_network_backoff.reset();
+                    join_network_timer.as_mut().reset(Instant::now());
                     self.topic_streams.move_joined_to_pending().await;
                     if let Some(sync_actor_tx) = &self.sync_actor_tx {
                         sync_actor_tx.send(ToSyncActor::Reset).await?;
                     }
                 }
                 // Attempt to start topic discovery if it didn't happen yet.
-                _ = join_network_interval.tick() => {
+                _ = &mut join_network_timer => {
                     self.topic_discovery.start().await?;
+                    if self.topic_discovery.is_active() {
+                        self.join_network_backoff.reset();
+                    }
+                    let delay = self.join_network_backoff.next_delay();
+                    join_network_timer.as_mut().reset(Instant::now() + delay);
                 },
                 // Attempt announcing our currently subscribed topics to other peers.
                 _ = announce_topics_interval.tick() => {
@@ -247,6 +308,10 @@ where
                 _ = join_topics_interval.tick() => {
                     self.topic_streams.try_join_pending_gossips().await?;
                 },
+                // Detect if connections to peers switched between direct and relayed paths.
+                _ = connection_paths_interval.tick() => {
+                    self.on_connection_paths_tick()?;
+                },
             }
         }
     }
@@ -265,6 +330,20 @@ where
                 let list = self.address_book.known_peers().await;
                 reply.send(list).ok();
             }
+            ToEngineActor::DenyPeer { public_key } => {
+                self.on_deny_peer(public_key).await?;
+            }
+            ToEngineActor::AllowPeer { public_key } => {
+                self.address_book.allow_peer(public_key).await;
+            }
+            ToEngineActor::DeniedPeers { reply } => {
+                let list = self.address_book.denied_peers().await;
+                reply.send(list).ok();
+            }
+            ToEngineActor::ConnectionStats { reply } => {
+                let stats = self.connection_stats().await;
+                reply.send(stats).ok();
+            }
             ToEngineActor::SubscribeTopic {
                 topic,
                 from_network_tx,
@@ -274,6 +353,9 @@ where
                 self.on_subscribe(topic, from_network_tx, to_network_rx, gossip_ready_tx)
                     .await?;
             }
+            ToEngineActor::UnsubscribeTopic { topic, reply } => {
+                self.on_unsubscribe(topic, reply).await?;
+            }
             ToEngineActor::GossipJoined { topic_id, peers } => {
                 self.on_gossip_joined(topic_id, peers).await?;
             }
@@ -307,11 +389,15 @@ where
                     .on_sync_message(topic, header, payload, delivered_from)
                     .await?;
             }
-            ToEngineActor::SyncDone { topic, peer } => {
-                self.on_sync_done(topic, peer).await?;
+            ToEngineActor::SyncDone {
+                topic,
+                peer,
+                received,
+            } => {
+                self.on_sync_done(topic, peer, received).await?;
             }
-            ToEngineActor::SyncFailed { topic, peer } => {
-                self.on_sync_failed(topic, peer).await?;
+            ToEngineActor::SyncFailed { topic, peer, error } => {
+                self.on_sync_failed(topic, peer, error).await?;
             }
             ToEngineActor::Shutdown { .. } => {
                 unreachable!("handled in run_inner");
@@ -342,10 +428,23 @@ where
 
         self.address_book.add_peer(node_addr).await;
 
+        // Never actively connect to denied peers.
+        if self.address_book.is_denied(&public_key).await {
+            return Ok(());
+        }
+
         // Hot path: Attempt starting topic discovery as soon as we've learned about at least one
         // peer. If this fails we'll try again soon in our internal loop.
         self.topic_discovery.start().await?;
 
+        // Hot path: Connect to the peer directly if we're already part of the network-wide gossip
+        // overlay and over all topics we know we have in common with them.
+        self.topic_discovery.join_peer(public_key).await?;
+        let their_topic_ids = self.address_book.topic_ids(&public_key).await;
+        self.topic_streams
+            .on_peer_added(their_topic_ids, public_key)
+            .await?;
+
         Ok(())
     }
 
@@ -360,6 +459,127 @@ where
         }
     }
 
+    /// Collect statistics for all peers the endpoint currently has a connection path to.
+    ///
+    /// Path and latency information is taken from the endpoint, while byte counters and active
+    /// topics are tracked by the gossip actor and sync sessions. The network-wide gossip overlay used
+    /// for topic discovery is not listed as an active topic.
+    async fn connection_stats(&self) -> Vec<ConnectionStats> {
+        let mut stats = Vec::new();
+        for info in self.endpoint.remote_info_iter() {
+            if matches!(info.conn_type, ConnectionType::None) {
+                continue;
+            }
+
+            let public_key = to_public_key(info.node_id);
+            let traffic = self.traffic.get(&public_key);
+            let topic_ids = traffic
+                .topic_ids
+                .into_iter()
+                .filter(|topic_id| topic_id != &self.network_id)
+                .collect();
+
+            stats.push(ConnectionStats {
+                node_addr: NodeAddress {
+                    public_key,
+                    direct_addresses: info.addrs.iter().map(|addr| addr.addr).collect(),
+                    relay_url: info.relay_url.map(|info| to_relay_url(info.relay_url)),
+                },
+                path: info.conn_type.into(),
+                rtt: info.latency,
+                bytes_sent: traffic.bytes_sent,
+                bytes_received: traffic.bytes_received,
+                topic_ids,
+            });
+        }
+        stats
+    }
+
+    /// Check the network paths the endpoint currently has to remote peers.
+    fn on_connection_paths_tick(&mut self) -> Result<()> {
+        let paths = self
+            .endpoint
+            .remote_info_iter()
+            .map(|info| {
+                let last_received = info.last_received();
+                (
+                    to_public_key(info.node_id),
+                    info.conn_type.into(),
+                    last_received,
+                )
+            })
+            .collect();
+        self.on_connection_paths(paths)
+    }
+
+    /// Inform the application when a connection to a peer was established or lost, or when the
+    /// network path to a peer fell back to a relay or was upgraded to a direct connection.
+    ///
+    /// Expects the current path to every peer known to the endpoint, together with the duration
+    /// since we've last received something from them. Paths of peers the endpoint doesn't know
+    /// about anymore and traffic counters of peers we're not connected to anymore are removed as
+    /// well.
+    fn on_connection_paths(
+        &mut self,
+        paths: Vec<(PublicKey, ConnectionPath, Option<Duration>)>,
+    ) -> Result<()> {
+        let mut known = HashSet::new();
+        let mut connected = HashSet::new();
+
+        for (peer, path, last_received) in paths {
+            known.insert(peer);
+            if !matches!(path, ConnectionPath::None) {
+                connected.insert(peer);
+            }
+
+            // The endpoint keeps paths to peers around for a while after the connection was
+            // lost, we treat them as gone as soon as the peer did not respond for too long.
+            let idle =
+                last_received.is_some_and(|last_received| last_received > CONNECTION_IDLE_TIMEOUT);
+            let path = if idle { ConnectionPath::None } else { path };
+
+            let Some(change) = self.path_tracker.update(peer, path) else {
+                continue;
+            };
+
+            let Some(event_tx) = &self.system_event_tx else {
+                continue;
+            };
+
+            match change {
+                PathChange::Connected { direct } => {
+                    debug!("connected to {peer} (direct: {direct})");
+                    event_tx.send(SystemEvent::PeerConnected { peer, direct })?;
+                }
+                PathChange::Disconnected(reason) => {
+                    debug!("disconnected from {peer}: {reason:?}");
+                    event_tx.send(SystemEvent::PeerDisconnected { peer, reason })?;
+                }
+                PathChange::RelayFallback(relay) => {
+                    debug!("connection to {peer} fell back to relay {relay}");
+                    event_tx.send(SystemEvent::RelayFallback { peer, relay })?;
+                }
+                PathChange::DirectUpgraded(addr) => {
+                    debug!("connection to {peer} upgraded to direct path {addr}");
+                    event_tx.send(SystemEvent::DirectConnectionUpgraded { peer, addr })?;
+                }
+            }
+        }
+
+        for peer in self.path_tracker.retain(&known) {
+            debug!("disconnected from {peer}: endpoint forgot about peer");
+            if let Some(event_tx) = &self.system_event_tx {
+                event_tx.send(SystemEvent::PeerDisconnected {
+                    peer,
+                    reason: DisconnectReason::Forgotten,
+                })?;
+            }
+        }
+        self.traffic.retain_connected(&connected);
+
+        Ok(())
+    }
+
     /// Update the join status for the given gossip overlay.
     async fn on_gossip_joined(&mut self, topic_id: [u8; 32], peers: Vec<PublicKey>) -> Result<()> {
         if topic_id == self.network_id {
@@ -375,11 +595,45 @@ where
         Ok(())
     }
 
+    /// Deny the given peer and drop all connections to them.
+    ///
+    /// Connections the peer established with us and our sync sessions with them are closed by
+    /// the address book. Gossip connections we've dialed ourselves can't be closed directly, we
+    /// leave and rejoin all overlays in which the peer is our direct neighbor instead.
+    async fn on_deny_peer(&mut self, peer: PublicKey) -> Result<()> {
+        self.address_book.deny_peer(peer).await;
+
+        for topic_id in self.traffic.get(&peer).topic_ids {
+            self.rejoin_without(topic_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Leave the given gossip overlay and join it again with peers which are not denied.
+    async fn rejoin_without(&mut self, topic_id: [u8; 32]) -> Result<()> {
+        let peers = self
+            .address_book
+            .random_set(topic_id, JOIN_PEERS_SAMPLE_LEN)
+            .await;
+        self.gossip_actor_tx
+            .send(ToGossipActor::Rejoin { topic_id, peers })
+            .await?;
+        Ok(())
+    }
+
     /// Register the topic and public key of a peer who just became our direct neighbor in the
     /// gossip overlay.
     ///
     /// Through this we can use gossip algorithms also as an additional "peer discovery" mechanism.
     async fn on_peer_connected(&mut self, topic_id: [u8; 32], peer: PublicKey) -> Result<()> {
+        // We might have dialed a denied peer ourselves after they've been forwarded to us by
+        // another peer in the overlay.
+        if self.address_book.is_denied(&peer).await {
+            self.rejoin_without(topic_id).await?;
+            return Ok(());
+        }
+
         self.address_book.add_topic_id(peer, topic_id).await;
 
         // At this point we only have the public key of the peer, which is not enough to establish
@@ -453,6 +707,23 @@ where
         Ok(())
     }
 
+    /// Handle a topic unsubscription.
+    ///
+    /// Outbound messages are flushed into the gossip overlay before the streams are removed. The
+    /// reply channel is informed when this has completed, which happens asynchronously so we're
+    /// not blocking the engine in the meantime.
+    async fn on_unsubscribe(&mut self, topic: T, reply: oneshot::Sender<()>) -> Result<()> {
+        self.topic_streams.unsubscribe(&topic, reply).await?;
+
+        // Hot path: Let other peers know that our "topics of interest" changed.
+        let my_topic_ids = self.topic_streams.topic_ids();
+        self.topic_discovery
+            .announce(my_topic_ids, &self.private_key)
+            .await?;
+
+        Ok(())
+    }
+
     /// Process sync session starting.
     pub async fn on_sync_start(&mut self, topic: Option<T>, peer: PublicKey) -> Result<()> {
         self.topic_streams.on_sync_start(topic.clone(), peer);
@@ -465,25 +736,34 @@ where
     }
 
     /// Process sync session finishing.
-    pub async fn on_sync_done(&mut self, topic: T, peer: PublicKey) -> Result<()> {
+    pub async fn on_sync_done(&mut self, topic: T, peer: PublicKey, received: usize) -> Result<()> {
         self.topic_streams.on_sync_done(topic.clone(), peer).await?;
 
         // Notify any system event subscribers.
         if let Some(event_tx) = &self.system_event_tx {
-            event_tx.send(SystemEvent::SyncDone { topic, peer })?;
+            event_tx.send(SystemEvent::SyncDone {
+                topic,
+                peer,
+                received,
+            })?;
         }
 
         Ok(())
     }
 
     /// Process sync session failure.
-    pub async fn on_sync_failed(&mut self, topic: Option<T>, peer: PublicKey) -> Result<()> {
+    pub async fn on_sync_failed(
+        &mut self,
+        topic: Option<T>,
+        peer: PublicKey,
+        error: String,
+    ) -> Result<()> {
         self.topic_streams
             .on_sync_failed(topic.clone(), peer)
             .await?;
 
         if let Some(event_tx) = &self.system_event_tx {
-            event_tx.send(SystemEvent::SyncFailed { topic, peer })?;
+            event_tx.send(SystemEvent::SyncFailed { topic, peer, error })?;
         }
 
         Ok(())
@@ -500,6 +780,13 @@ where
         delivered_from: PublicKey,
         topic_id: [u8; 32],
     ) -> Result<()> {
+        // Messages might still arrive from denied peers which were connected before, we ignore
+        // them.
+        if self.address_book.is_denied(&delivered_from).await {
+            debug!("ignoring gossip message from denied peer {delivered_from}");
+            return Ok(());
+        }
+
         if topic_id == self.network_id {
             match self.topic_discovery.on_gossip_message(&bytes).await {
                 Ok((topic_ids, peer)) => {
@@ -537,3 +824,199 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use iroh::{Endpoint, RelayMode};
+    use p2panda_core::PrivateKey;
+    use p2panda_sync::TopicQuery;
+    use serde::{Deserialize, Serialize};
+    use tokio::sync::mpsc;
+    use tokio::time::{sleep, Duration, Instant};
+
+    use crate::bandwidth::Bandwidth;
+    use crate::config::BackoffConfig;
+    use crate::engine::address_book::AddressBook;
+    use crate::engine::gossip::ToGossipActor;
+    use crate::engine::traffic::TrafficMeter;
+    use crate::events::{DisconnectReason, SystemEvent};
+    use crate::stats::ConnectionPath;
+    use crate::{from_private_key, NodeAddress, RelayUrl, TopicId};
+
+    use super::{EngineActor, EngineContext};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct TestTopic;
+
+    impl TopicQuery for TestTopic {}
+
+    impl TopicId for TestTopic {
+        fn id(&self) -> [u8; 32] {
+            [0; 32]
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn join_network_with_backoff() {
+        let network_id = [1; 32];
+        let private_key = PrivateKey::new();
+        let endpoint = Endpoint::builder()
+            .secret_key(from_private_key(private_key.clone()))
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await
+            .unwrap();
+
+        let (_engine_actor_tx, engine_actor_rx) = mpsc::channel(64);
+        let (gossip_actor_tx, mut gossip_actor_rx) = mpsc::channel(64);
+        let mut address_book = AddressBook::new(network_id);
+
+        let mut engine_actor = EngineActor::<TestTopic>::new(
+            private_key,
+            endpoint,
+            engine_actor_rx,
+            gossip_actor_tx,
+            None,
+            network_id,
+            EngineContext {
+                address_book: address_book.clone(),
+                bandwidth: Bandwidth::default(),
+                traffic: TrafficMeter::new(),
+                reconnect_backoff: BackoffConfig {
+                    initial_delay: Duration::from_millis(100),
+                    max_delay: Duration::from_secs(1),
+                    multiplier: 2.0,
+                },
+            },
+        );
+
+        let start = Instant::now();
+        tokio::spawn(async move { engine_actor.run_inner().await });
+
+        // Without any known peers, joining the network is attempted after 0ms, 100ms, 300ms,
+        // 700ms and so on.
+        sleep(Duration::from_millis(350)).await;
+
+        // Learn about a peer without informing the engine, it will only be picked up with the
+        // next attempt.
+        let peer = PrivateKey::new().public_key();
+        address_book
+            .add_peer(NodeAddress::from_public_key(peer))
+            .await;
+
+        let Some(ToGossipActor::Join { topic_id, peers }) = gossip_actor_rx.recv().await else {
+            panic!("expected join message");
+        };
+        assert_eq!(topic_id, network_id);
+        assert_eq!(peers, vec![peer]);
+
+        // With a fixed delay of 100ms we would have attempted joining after 400ms already.
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(700));
+        assert!(elapsed < Duration::from_millis(800));
+    }
+
+    #[tokio::test]
+    async fn connection_path_events() {
+        let network_id = [1; 32];
+        let private_key = PrivateKey::new();
+        let endpoint = Endpoint::builder()
+            .secret_key(from_private_key(private_key.clone()))
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await
+            .unwrap();
+
+        let (_engine_actor_tx, engine_actor_rx) = mpsc::channel(64);
+        let (gossip_actor_tx, _gossip_actor_rx) = mpsc::channel(64);
+
+        let mut engine_actor = EngineActor::<TestTopic>::new(
+            private_key,
+            endpoint,
+            engine_actor_rx,
+            gossip_actor_tx,
+            None,
+            network_id,
+            EngineContext {
+                address_book: AddressBook::new(network_id),
+                bandwidth: Bandwidth::default(),
+                traffic: TrafficMeter::new(),
+                reconnect_backoff: BackoffConfig::default(),
+            },
+        );
+        let mut event_rx = engine_actor.events();
+
+        let peer = PrivateKey::new().public_key();
+        let relay: RelayUrl = "https://relay.example.net".parse().unwrap();
+        let addr = "192.168.1.2:2022".parse().unwrap();
+
+        // Connections start via the relay while hole-punching is attempted.
+        engine_actor
+            .on_connection_paths(vec![(peer, ConnectionPath::Relay(relay.clone()), None)])
+            .unwrap();
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            SystemEvent::PeerConnected {
+                peer,
+                direct: false
+            }
+        );
+
+        // Hole-punching succeeded.
+        engine_actor
+            .on_connection_paths(vec![(peer, ConnectionPath::Direct(addr), None)])
+            .unwrap();
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            SystemEvent::DirectConnectionUpgraded { peer, addr }
+        );
+
+        // The direct path broke and traffic is routed through the relay again.
+        engine_actor
+            .on_connection_paths(vec![(peer, ConnectionPath::Relay(relay.clone()), None)])
+            .unwrap();
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            SystemEvent::RelayFallback {
+                peer,
+                relay: relay.clone()
+            }
+        );
+
+        // Nothing changed, no events are emitted.
+        engine_actor
+            .on_connection_paths(vec![(peer, ConnectionPath::Relay(relay.clone()), None)])
+            .unwrap();
+        assert!(event_rx.try_recv().is_err());
+
+        // The peer did not respond for too long.
+        engine_actor
+            .on_connection_paths(vec![(
+                peer,
+                ConnectionPath::Relay(relay),
+                Some(Duration::from_secs(60)),
+            )])
+            .unwrap();
+        assert!(matches!(
+            event_rx.try_recv().unwrap(),
+            SystemEvent::PeerDisconnected { peer: disconnected, .. } if disconnected == peer
+        ));
+
+        // A peer the endpoint forgot about is disconnected as well.
+        engine_actor
+            .on_connection_paths(vec![(peer, ConnectionPath::Direct(addr), None)])
+            .unwrap();
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            SystemEvent::PeerConnected { peer, direct: true }
+        );
+        engine_actor.on_connection_paths(vec![]).unwrap();
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            SystemEvent::PeerDisconnected {
+                peer,
+                reason: DisconnectReason::Forgotten
+            }
+        );
+    }
+}
@@ -1,19 +1,31 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self, ready, Poll};
 
 use anyhow::{Context, Result};
-use futures_lite::StreamExt;
+use futures_lite::future::Boxed as BoxedFuture;
+use futures_lite::{Stream, StreamExt};
+use iroh::endpoint::{self, Connecting, Connection};
 use iroh_gossip::net::{Event, Gossip, GossipEvent, GossipReceiver, GossipSender, GossipTopic};
 use p2panda_core::PublicKey;
 use p2panda_sync::TopicQuery;
 use tokio::sync::mpsc;
 use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Sleep};
 use tokio_stream::StreamMap;
-use tracing::{error, warn};
+use tracing::{debug, error, warn};
 
+use crate::bandwidth::{Bandwidth, RateLimiter};
+use crate::engine::address_book::AddressBook;
+use crate::engine::constants::REJOIN_DELAY;
+use crate::engine::traffic::TrafficMeter;
 use crate::engine::ToEngineActor;
-use crate::{from_public_key, to_public_key};
+use crate::protocols::ProtocolHandler;
+use crate::{from_public_key, to_public_key, NodeAddress};
 
 #[derive(Debug)]
 pub enum ToGossipActor {
@@ -25,23 +37,34 @@ pub enum ToGossipActor {
         topic_id: [u8; 32],
         peers: Vec<PublicKey>,
     },
-    #[allow(dead_code)]
+    JoinPeers {
+        topic_id: [u8; 32],
+        peers: Vec<PublicKey>,
+    },
     Leave {
         topic_id: [u8; 32],
     },
+    /// Leave and join a gossip overlay again, for example to drop a denied peer we've connected
+    /// to from our neighbors.
+    Rejoin {
+        topic_id: [u8; 32],
+        peers: Vec<PublicKey>,
+    },
     Shutdown,
 }
 
 /// The `GossipActor` manages gossip topic membership (joining and leaving of topics) and
 /// facilitates flows of messages into and out of individual gossip overlays.
 pub struct GossipActor<T> {
+    bandwidth: Bandwidth,
     engine_actor_tx: mpsc::Sender<ToEngineActor<T>>,
     gossip: Gossip,
-    gossip_events: StreamMap<[u8; 32], GossipReceiver>,
+    gossip_events: StreamMap<[u8; 32], ThrottledReceiver>,
     gossip_senders: HashMap<[u8; 32], GossipSender>,
     inbox: mpsc::Receiver<ToGossipActor>,
     joined: HashSet<[u8; 32]>,
     pending_joins: JoinSet<([u8; 32], Result<GossipTopic>)>,
+    traffic: TrafficMeter,
     want_join: HashSet<[u8; 32]>,
 }
 
@@ -53,8 +76,11 @@ where
         inbox: mpsc::Receiver<ToGossipActor>,
         gossip: Gossip,
         engine_actor_tx: mpsc::Sender<ToEngineActor<T>>,
+        traffic: TrafficMeter,
+        bandwidth: Bandwidth,
     ) -> Self {
         Self {
+            bandwidth,
             engine_actor_tx,
             gossip,
             gossip_events: Default::default(),
@@ -62,6 +88,7 @@ where
             inbox,
             joined: Default::default(),
             pending_joins: Default::default(),
+            traffic,
             want_join: Default::default(),
         }
     }
@@ -103,33 +130,52 @@ where
     async fn on_actor_message(&mut self, msg: ToGossipActor) -> Result<bool> {
         match msg {
             ToGossipActor::Broadcast { topic_id, bytes } => {
+                // Outbound messages are throttled before they reach the actor, see `TopicStreams`.
                 if let Some(gossip_tx) = self.gossip_senders.get(&topic_id) {
-                    if let Err(err) = gossip_tx.broadcast(bytes.into()).await {
-                        error!(
+                    let len = bytes.len();
+                    match gossip_tx.broadcast(bytes.into()).await {
+                        Ok(()) => self.traffic.record_broadcast(topic_id, len),
+                        Err(err) => error!(
                             topic_id = "{topic_id:?}",
                             "failed to broadcast gossip msg: {}", err
+                        ),
+                    }
+                }
+            }
+            ToGossipActor::JoinPeers { topic_id, peers } => {
+                if let Some(gossip_tx) = self.gossip_senders.get(&topic_id) {
+                    let peers = peers.into_iter().map(from_public_key).collect();
+                    if let Err(err) = gossip_tx.join_peers(peers).await {
+                        error!(
+                            topic_id = "{topic_id:?}",
+                            "failed to join gossip peers: {}", err
                         )
                     }
                 }
             }
             ToGossipActor::Join { topic_id, peers } => {
-                let gossip = self.gossip.clone();
-                let peers = peers
-                    .iter()
-                    .map(|key: &p2panda_core::PublicKey| from_public_key(*key))
-                    .collect();
-                let fut = async move {
-                    let stream = gossip.subscribe_and_join(topic_id.into(), peers).await;
-                    (topic_id, stream)
-                };
-                self.want_join.insert(topic_id);
-                self.pending_joins.spawn(fut);
+                self.join(topic_id, peers, Duration::ZERO);
             }
             ToGossipActor::Leave { topic_id } => {
                 // Quit the topic by dropping all handles to `GossipTopic` for the given topic id.
                 let _handle = self.gossip_events.remove(&topic_id);
+                self.gossip_senders.remove(&topic_id);
                 self.joined.remove(&topic_id);
                 self.want_join.remove(&topic_id);
+                self.traffic.on_topic_left(topic_id);
+            }
+            ToGossipActor::Rejoin { topic_id, peers } => {
+                if !self.joined.remove(&topic_id) {
+                    return Ok(true);
+                }
+
+                // Dropping all handles quits the overlay, which disconnects all our neighbors in
+                // it, including the ones we've dialed ourselves. We give gossip a moment to
+                // process this before we subscribe again.
+                let _handle = self.gossip_events.remove(&topic_id);
+                self.gossip_senders.remove(&topic_id);
+                self.traffic.on_topic_left(topic_id);
+                self.join(topic_id, peers, REJOIN_DELAY);
             }
             ToGossipActor::Shutdown => {
                 for topic_id in self.joined.iter() {
@@ -142,6 +188,20 @@ where
         Ok(true)
     }
 
+    fn join(&mut self, topic_id: [u8; 32], peers: Vec<PublicKey>, delay: Duration) {
+        let gossip = self.gossip.clone();
+        let peers = peers.into_iter().map(from_public_key).collect();
+        let fut = async move {
+            if !delay.is_zero() {
+                sleep(delay).await;
+            }
+            let stream = gossip.subscribe_and_join(topic_id.into(), peers).await;
+            (topic_id, stream)
+        };
+        self.want_join.insert(topic_id);
+        self.pending_joins.spawn(fut);
+    }
+
     async fn on_gossip_event(&mut self, event: Option<([u8; 32], Result<Event>)>) -> Result<()> {
         let (topic_id, event) = event.context("gossip event channel closed")?;
         let event = match event {
@@ -182,31 +242,32 @@ where
     ) -> Result<()> {
         match event {
             GossipEvent::Received(msg) => {
+                let delivered_from = to_public_key(msg.delivered_from);
+                self.traffic
+                    .record_received(delivered_from, msg.content.len());
                 self.engine_actor_tx
                     .send(ToEngineActor::GossipMessage {
                         bytes: msg.content.into(),
-                        delivered_from: to_public_key(msg.delivered_from),
+                        delivered_from,
                         topic_id,
                     })
                     .await?;
             }
             GossipEvent::NeighborUp(peer) => {
+                let peer = to_public_key(peer);
+                self.traffic.on_neighbor_up(peer, topic_id);
                 self.engine_actor_tx
-                    .send(ToEngineActor::GossipNeighborUp {
-                        topic_id,
-                        peer: to_public_key(peer),
-                    })
+                    .send(ToEngineActor::GossipNeighborUp { topic_id, peer })
                     .await?;
             }
             GossipEvent::Joined(_peers) => {
                 // We send this event to the engine actor in `on_joined()`.
             }
             GossipEvent::NeighborDown(peer) => {
+                let peer = to_public_key(peer);
+                self.traffic.on_neighbor_down(peer, topic_id);
                 self.engine_actor_tx
-                    .send(ToEngineActor::GossipNeighborDown {
-                        topic_id,
-                        peer: to_public_key(peer),
-                    })
+                    .send(ToEngineActor::GossipNeighborDown { topic_id, peer })
                     .await?;
             }
         }
@@ -221,8 +282,14 @@ where
 
         // Collect all our current direct neighbors for this gossip topic.
         let peers: Vec<PublicKey> = stream_rx.neighbors().map(to_public_key).collect();
+        for peer in &peers {
+            self.traffic.on_neighbor_up(*peer, topic_id);
+        }
 
-        self.gossip_events.insert(topic_id, stream_rx);
+        self.gossip_events.insert(
+            topic_id,
+            ThrottledReceiver::new(stream_rx, self.bandwidth.download.clone()),
+        );
         self.gossip_senders.insert(topic_id, stream_tx);
 
         self.engine_actor_tx
@@ -232,3 +299,107 @@ where
         Ok(())
     }
 }
+
+/// Gossip receiver which holds back received messages until the download limit allows them.
+///
+/// Waiting for the limit only holds back events of this gossip overlay, all other overlays and
+/// the gossip actor itself continue processing.
+struct ThrottledReceiver {
+    inner: GossipReceiver,
+    limiter: RateLimiter,
+    pending: Option<(Event, usize)>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl ThrottledReceiver {
+    fn new(inner: GossipReceiver, limiter: RateLimiter) -> Self {
+        Self {
+            inner,
+            limiter,
+            pending: None,
+            delay: None,
+        }
+    }
+}
+
+impl Stream for ThrottledReceiver {
+    type Item = Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            // Take tokens for the held back message until we have enough to pass it on.
+            if let Some((_, remaining)) = &mut this.pending {
+                if *remaining == 0 {
+                    let (event, _) = this.pending.take().expect("pending event");
+                    return Poll::Ready(Some(Ok(event)));
+                }
+
+                if let Some(delay) = &mut this.delay {
+                    ready!(delay.as_mut().poll(cx));
+                    this.delay = None;
+                }
+
+                match this.limiter.take(*remaining) {
+                    Ok(taken) => *remaining -= taken,
+                    Err(wait) => this.delay = Some(Box::pin(sleep(wait))),
+                }
+                continue;
+            }
+
+            match ready!(this.inner.poll_next(cx)) {
+                Some(Ok(Event::Gossip(GossipEvent::Received(msg)))) => {
+                    let len = msg.content.len();
+                    this.pending = Some((Event::Gossip(GossipEvent::Received(msg)), len));
+                }
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+/// Protocol handler for inbound gossip connections which refuses connections from denied peers.
+#[derive(Debug)]
+pub struct GossipConnection {
+    gossip: Gossip,
+    address_book: AddressBook,
+}
+
+impl GossipConnection {
+    pub fn new(gossip: Gossip, address_book: AddressBook) -> Self {
+        Self {
+            gossip,
+            address_book,
+        }
+    }
+
+    async fn handle_connection(&self, connection: Connection) -> Result<()> {
+        let peer = to_public_key(endpoint::get_remote_node_id(&connection)?);
+        if self.address_book.is_denied(&peer).await {
+            debug!("refusing gossip connection from denied peer {peer}");
+            connection.close(0u32.into(), b"denied");
+            return Ok(());
+        }
+
+        // Peers which dialed us might not be known to us yet, we learn about them here so we can
+        // join the network-wide gossip overlay with them.
+        let mut address_book = self.address_book.clone();
+        if !address_book.is_known(&peer).await {
+            address_book
+                .add_peer(NodeAddress::from_public_key(peer))
+                .await;
+        }
+
+        // Connections which dialed us are closed as soon as the peer gets denied, which makes
+        // them leave our gossip overlays.
+        self.address_book.close_on_deny(peer, connection.clone());
+
+        self.gossip.handle_connection(connection).await
+    }
+}
+
+impl ProtocolHandler for GossipConnection {
+    fn accept(self: Arc<Self>, connecting: Connecting) -> BoxedFuture<Result<()>> {
+        Box::pin(async move { self.handle_connection(connecting.await?).await })
+    }
+}
@@ -1,13 +1,16 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 mod address_book;
+mod backoff;
 mod constants;
 #[allow(clippy::module_inception)]
 mod engine;
 mod gossip;
 mod gossip_buffer;
+mod paths;
 mod topic_discovery;
 mod topic_streams;
+mod traffic;
 
 use std::fmt::Debug;
 
@@ -16,18 +19,23 @@ use futures_util::future::{MapErr, Shared};
 use futures_util::{FutureExt, TryFutureExt};
 use iroh::Endpoint;
 use iroh_gossip::net::Gossip;
-use p2panda_core::PrivateKey;
+use p2panda_core::{PrivateKey, PublicKey};
 use p2panda_sync::TopicQuery;
 use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::task::JoinError;
-use tokio_util::task::AbortOnDropHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::{AbortOnDropHandle, TaskTracker};
 use tracing::{debug, error};
 
+use crate::bandwidth::Bandwidth;
+use crate::config::BackoffConfig;
 pub use crate::engine::address_book::AddressBook;
-use crate::engine::engine::EngineActor;
-use crate::engine::gossip::GossipActor;
+use crate::engine::engine::{EngineActor, EngineContext};
+use crate::engine::gossip::{GossipActor, GossipConnection};
+pub(crate) use crate::engine::traffic::{Metered, TrafficMeter};
 use crate::events::SystemEvent;
 use crate::network::{FromNetwork, JoinErrToStr, ToNetwork};
+use crate::stats::ConnectionStats;
 use crate::sync::manager::SyncActor;
 use crate::sync::{SyncConfiguration, SyncConnection};
 use crate::{NetworkId, NodeAddress, TopicId};
@@ -37,8 +45,13 @@ pub use engine::ToEngineActor;
 /// and sync connection actors) and exposes an API for interacting with the engine actor.
 #[derive(Debug)]
 pub struct Engine<T> {
+    address_book: AddressBook,
+    bandwidth: Bandwidth,
     engine_actor_tx: mpsc::Sender<ToEngineActor<T>>,
+    traffic: TrafficMeter,
     sync_config: Option<SyncConfiguration<T>>,
+    sync_sessions: TaskTracker,
+    sync_shutdown_token: CancellationToken,
     #[allow(dead_code)]
     actor_handle: Shared<MapErr<AbortOnDropHandle<()>, JoinErrToStr>>,
 }
@@ -53,17 +66,28 @@ where
         endpoint: Endpoint,
         gossip: Gossip,
         sync_config: Option<SyncConfiguration<T>>,
+        reconnect_backoff: BackoffConfig,
+        bandwidth: Bandwidth,
     ) -> Self {
         let address_book = AddressBook::new(network_id);
+        let traffic = TrafficMeter::new();
 
         let (engine_actor_tx, engine_actor_rx) = mpsc::channel(64);
         let (gossip_actor_tx, gossip_actor_rx) = mpsc::channel(256);
 
+        // Keeps track of all running sync sessions, inbound and outbound.
+        let sync_sessions = TaskTracker::new();
+        let sync_shutdown_token = CancellationToken::new();
+
         let (sync_actor, sync_actor_tx) = if let Some(ref sync_config) = sync_config {
             let (sync_actor, sync_actor_tx) = SyncActor::new(
                 sync_config.clone(),
                 endpoint.clone(),
+                address_book.clone(),
+                bandwidth.clone(),
+                traffic.clone(),
                 engine_actor_tx.clone(),
+                sync_sessions.clone(),
             );
             (Some(sync_actor), Some(sync_actor_tx))
         } else {
@@ -73,17 +97,34 @@ where
         let engine_actor = EngineActor::new(
             private_key,
             endpoint,
-            address_book,
             engine_actor_rx,
             gossip_actor_tx,
             sync_actor_tx,
             network_id,
+            EngineContext {
+                address_book: address_book.clone(),
+                bandwidth: bandwidth.clone(),
+                traffic: traffic.clone(),
+                reconnect_backoff,
+            },
+        );
+        let gossip_actor = GossipActor::new(
+            gossip_actor_rx,
+            gossip,
+            engine_actor_tx.clone(),
+            traffic.clone(),
+            bandwidth.clone(),
         );
-        let gossip_actor = GossipActor::new(gossip_actor_rx, gossip, engine_actor_tx.clone());
 
-        let actor_handle = tokio::task::spawn(async move {
-            if let Err(err) = engine_actor.run(gossip_actor, sync_actor).await {
-                error!("engine actor failed: {err:?}");
+        let actor_handle = tokio::task::spawn({
+            let sync_shutdown_token = sync_shutdown_token.clone();
+            async move {
+                if let Err(err) = engine_actor
+                    .run(gossip_actor, sync_actor, sync_shutdown_token)
+                    .await
+                {
+                    error!("engine actor failed: {err:?}");
+                }
             }
         });
 
@@ -92,9 +133,14 @@ where
             .shared();
 
         Self {
+            address_book,
+            bandwidth,
             engine_actor_tx,
+            traffic,
             actor_handle: actor_drop_handle,
             sync_config,
+            sync_sessions,
+            sync_shutdown_token,
         }
     }
 
@@ -130,6 +176,40 @@ where
         Ok(reply_rx.await?)
     }
 
+    /// Returns statistics for all peers we currently hold a connection path to.
+    pub async fn connection_stats(&self) -> Result<Vec<ConnectionStats>> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.engine_actor_tx
+            .send(ToEngineActor::ConnectionStats { reply })
+            .await?;
+        Ok(reply_rx.await?)
+    }
+
+    /// Denies all connections and messages from the given peer until it is allowed again.
+    pub async fn deny_peer(&self, public_key: PublicKey) -> Result<()> {
+        self.engine_actor_tx
+            .send(ToEngineActor::DenyPeer { public_key })
+            .await?;
+        Ok(())
+    }
+
+    /// Allows a previously denied peer again.
+    pub async fn allow_peer(&self, public_key: PublicKey) -> Result<()> {
+        self.engine_actor_tx
+            .send(ToEngineActor::AllowPeer { public_key })
+            .await?;
+        Ok(())
+    }
+
+    /// Retrieves the public keys of all denied peers.
+    pub async fn denied_peers(&self) -> Result<Vec<PublicKey>> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.engine_actor_tx
+            .send(ToEngineActor::DeniedPeers { reply })
+            .await?;
+        Ok(reply_rx.await?)
+    }
+
     /// Subscribes to the given topic and provides a channel for network message passing.
     pub async fn subscribe(
         &self,
@@ -149,6 +229,27 @@ where
         Ok(())
     }
 
+    /// Unsubscribes from the given topic and waits until all queued outbound messages have been
+    /// flushed.
+    pub async fn unsubscribe(&self, topic: T) -> Result<()> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.engine_actor_tx
+            .send(ToEngineActor::UnsubscribeTopic { topic, reply })
+            .await?;
+        reply_rx.await?;
+        Ok(())
+    }
+
+    /// Stops initiating new sync sessions and waits until all running ones completed.
+    ///
+    /// The engine actor keeps running meanwhile, so everything received during these sessions is
+    /// still forwarded to the subscribed topic streams.
+    pub async fn drain_sync_sessions(&self) {
+        self.sync_shutdown_token.cancel();
+        self.sync_sessions.close();
+        self.sync_sessions.wait().await;
+    }
+
     /// Sends a shutdown signal to the engine actor and waits for a confirmation reply.
     pub async fn shutdown(&self) -> Result<()> {
         let (reply, reply_rx) = oneshot::channel();
@@ -160,12 +261,35 @@ where
         Ok(())
     }
 
-    /// Returns a sync connection protocol handler for inbound connections.
+    /// Returns sync connection protocol handlers for inbound connections, along with the ALPN
+    /// of the sync protocol each of them handles.
     // @TODO: This method feels like the odd-one-out in this module. Could we move it somewhere
     // else?
-    pub(super) fn sync_handler(&self) -> Option<SyncConnection<T>> {
-        self.sync_config.as_ref().map(|sync_config| {
-            SyncConnection::new(sync_config.protocol(), self.engine_actor_tx.clone())
-        })
+    pub(super) fn sync_handlers(&self) -> Vec<(Vec<u8>, SyncConnection<T>)> {
+        let Some(sync_config) = self.sync_config.as_ref() else {
+            return Vec::new();
+        };
+
+        sync_config
+            .alpn_protocols()
+            .into_iter()
+            .map(|(alpn, protocol)| {
+                let handler = SyncConnection::new(
+                    protocol,
+                    sync_config.handshake_timeout,
+                    self.bandwidth.clone(),
+                    self.traffic.clone(),
+                    self.address_book.clone(),
+                    self.engine_actor_tx.clone(),
+                    self.sync_sessions.clone(),
+                );
+                (alpn, handler)
+            })
+            .collect()
+    }
+
+    /// Returns a gossip protocol handler for inbound connections.
+    pub(super) fn gossip_handler(&self, gossip: Gossip) -> GossipConnection {
+        GossipConnection::new(gossip, self.address_book.clone())
     }
 }
@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+
+use p2panda_core::PublicKey;
+
+use crate::events::DisconnectReason;
+use crate::stats::ConnectionPath;
+use crate::RelayUrl;
+
+/// Kind of the last observed path to a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PathKind {
+    Direct,
+    Mixed,
+    Relay,
+}
+
+/// Change of the network path to a peer which is relevant to the application.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathChange {
+    /// A path to a peer we were not connected to before was established.
+    Connected { direct: bool },
+
+    /// The endpoint does not have a path to the peer anymore.
+    Disconnected(DisconnectReason),
+
+    /// Hole-punching did not succeed and traffic is routed through the given relay.
+    RelayFallback(RelayUrl),
+
+    /// A direct path was established after traffic was routed through a relay.
+    DirectUpgraded(SocketAddr),
+}
+
+/// Observes the network paths to remote peers and detects when connections open and close or
+/// switch between direct and relayed paths.
+///
+/// Connections usually start via a relay while hole-punching is attempted in the background
+/// ("mixed" path), this is why only switching from a direct or mixed path to a relay is considered
+/// to be a fallback. Switching between paths never counts as a disconnect, only losing all paths
+/// to a peer does.
+#[derive(Debug, Default)]
+pub struct PathTracker {
+    paths: HashMap<PublicKey, PathKind>,
+}
+
+impl PathTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the current path to a peer, returns a change if one occurred since the last call.
+    pub fn update(&mut self, peer: PublicKey, path: ConnectionPath) -> Option<PathChange> {
+        let direct = path.is_direct();
+        let (kind, change) = match path {
+            ConnectionPath::Direct(addr) => {
+                let change = match self.paths.get(&peer) {
+                    Some(PathKind::Relay) | Some(PathKind::Mixed) => {
+                        Some(PathChange::DirectUpgraded(addr))
+                    }
+                    _ => None,
+                };
+                (PathKind::Direct, change)
+            }
+            ConnectionPath::Relay(relay_url) => {
+                let change = match self.paths.get(&peer) {
+                    Some(PathKind::Direct) | Some(PathKind::Mixed) => {
+                        Some(PathChange::RelayFallback(relay_url))
+                    }
+                    _ => None,
+                };
+                (PathKind::Relay, change)
+            }
+            ConnectionPath::Mixed(_, _) => (PathKind::Mixed, None),
+            ConnectionPath::None => {
+                // Forget about peers we're not connected to anymore, new connections will start
+                // over again.
+                return self
+                    .paths
+                    .remove(&peer)
+                    .map(|_| PathChange::Disconnected(DisconnectReason::ConnectionLost));
+            }
+        };
+
+        match self.paths.insert(peer, kind) {
+            Some(_) => change,
+            None => Some(PathChange::Connected { direct }),
+        }
+    }
+
+    /// Forget about all peers which are not in the given set of peers known to the endpoint.
+    ///
+    /// Returns the peers we were connected to before.
+    pub fn retain(&mut self, peers: &HashSet<PublicKey>) -> Vec<PublicKey> {
+        let mut forgotten = Vec::new();
+        self.paths.retain(|peer, _| {
+            let known = peers.contains(peer);
+            if !known {
+                forgotten.push(*peer);
+            }
+            known
+        });
+        forgotten
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+
+    use p2panda_core::PrivateKey;
+
+    use crate::events::DisconnectReason;
+    use crate::stats::ConnectionPath;
+    use crate::RelayUrl;
+
+    use super::{PathChange, PathTracker};
+
+    #[test]
+    fn detect_path_changes() {
+        let peer = PrivateKey::new().public_key();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 2022));
+        let relay_url = RelayUrl::from_str("https://relay.example.org").unwrap();
+
+        let mut tracker = PathTracker::new();
+
+        // Connection starts via relay, no fallback occurred yet.
+        assert_eq!(
+            tracker.update(peer, ConnectionPath::Relay(relay_url.clone())),
+            Some(PathChange::Connected { direct: false })
+        );
+
+        // Hole-punching is attempted and succeeds.
+        assert_eq!(
+            tracker.update(peer, ConnectionPath::Mixed(addr, relay_url.clone())),
+            None
+        );
+        assert_eq!(
+            tracker.update(peer, ConnectionPath::Direct(addr)),
+            Some(PathChange::DirectUpgraded(addr))
+        );
+        assert_eq!(tracker.update(peer, ConnectionPath::Direct(addr)), None);
+
+        // Direct path breaks and we fall back to the relay.
+        assert_eq!(
+            tracker.update(peer, ConnectionPath::Relay(relay_url.clone())),
+            Some(PathChange::RelayFallback(relay_url.clone()))
+        );
+        assert_eq!(
+            tracker.update(peer, ConnectionPath::Relay(relay_url.clone())),
+            None
+        );
+
+        // Connection was closed, a new one starting via relay is not a fallback.
+        assert_eq!(
+            tracker.update(peer, ConnectionPath::None),
+            Some(PathChange::Disconnected(DisconnectReason::ConnectionLost))
+        );
+        assert_eq!(tracker.update(peer, ConnectionPath::None), None);
+        assert_eq!(
+            tracker.update(peer, ConnectionPath::Relay(relay_url)),
+            Some(PathChange::Connected { direct: false })
+        );
+    }
+
+    #[test]
+    fn no_disconnect_on_upgrade() {
+        let peer = PrivateKey::new().public_key();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 2022));
+        let relay_url = RelayUrl::from_str("https://relay.example.org").unwrap();
+
+        let mut tracker = PathTracker::new();
+        let changes: Vec<PathChange> = [
+            ConnectionPath::Relay(relay_url.clone()),
+            ConnectionPath::Mixed(addr, relay_url),
+            ConnectionPath::Direct(addr),
+        ]
+        .into_iter()
+        .filter_map(|path| tracker.update(peer, path))
+        .collect();
+
+        assert_eq!(
+            changes,
+            vec![
+                PathChange::Connected { direct: false },
+                PathChange::DirectUpgraded(addr)
+            ]
+        );
+    }
+
+    #[test]
+    fn forget_unknown_peers() {
+        let peer_1 = PrivateKey::new().public_key();
+        let peer_2 = PrivateKey::new().public_key();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 2022));
+        let relay_url = RelayUrl::from_str("https://relay.example.org").unwrap();
+
+        let mut tracker = PathTracker::new();
+        assert_eq!(
+            tracker.update(peer_1, ConnectionPath::Direct(addr)),
+            Some(PathChange::Connected { direct: true })
+        );
+        tracker.update(peer_2, ConnectionPath::Direct(addr));
+
+        // The endpoint doesn't know about the second peer anymore.
+        assert_eq!(tracker.retain(&HashSet::from([peer_1])), vec![peer_2]);
+
+        assert_eq!(
+            tracker.update(peer_1, ConnectionPath::Relay(relay_url.clone())),
+            Some(PathChange::RelayFallback(relay_url.clone()))
+        );
+        assert_eq!(
+            tracker.update(peer_2, ConnectionPath::Relay(relay_url)),
+            Some(PathChange::Connected { direct: false })
+        );
+    }
+}
@@ -77,6 +77,25 @@ impl TopicDiscovery {
         Ok(())
     }
 
+    /// Attempts connecting to the given peer within the network-wide gossip overlay.
+    ///
+    /// This is only required if we're already part of the overlay, otherwise the peer will be
+    /// considered when calling `start`.
+    pub async fn join_peer(&self, peer: PublicKey) -> Result<()> {
+        if self.status != Status::Active {
+            return Ok(());
+        }
+
+        self.gossip_actor_tx
+            .send(ToGossipActor::JoinPeers {
+                topic_id: self.network_id,
+                peers: vec![peer],
+            })
+            .await?;
+
+        Ok(())
+    }
+
     /// Reset the topic discovery status to idle.
     ///
     /// Resetting the status allows the network-wide gossip overlay to be rejoined after a loss of
@@ -85,6 +104,11 @@ impl TopicDiscovery {
         self.status = Status::Idle;
     }
 
+    /// Returns `true` if we've joined the network-wide gossip overlay.
+    pub fn is_active(&self) -> bool {
+        self.status == Status::Active
+    }
+
     pub fn on_gossip_joined(&mut self) {
         if self.status == Status::Active {
             return;
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 use p2panda_core::PublicKey;
@@ -9,10 +9,12 @@ use p2panda_sync::TopicQuery;
 use tokio::sync::{mpsc, oneshot, RwLock};
 use tracing::{debug, error, warn};
 
+use crate::bandwidth::Bandwidth;
 use crate::engine::address_book::AddressBook;
 use crate::engine::constants::JOIN_PEERS_SAMPLE_LEN;
 use crate::engine::gossip::ToGossipActor;
 use crate::engine::gossip_buffer::GossipBuffer;
+use crate::engine::traffic::TrafficMeter;
 use crate::network::{FromNetwork, ToNetwork};
 use crate::sync::manager::ToSyncActor;
 use crate::TopicId;
@@ -23,6 +25,9 @@ type TopicStream<T> = (T, mpsc::Sender<FromNetwork>);
 /// Every stream has a unique identifier.
 type TopicStreamId = usize;
 
+/// Handle to close the outbound channel of a stream and await all queued messages being flushed.
+type FlushHandle = (oneshot::Sender<()>, oneshot::Receiver<()>);
+
 /// Manages subscriptions to topics in form of data streams.
 ///
 /// A stream has quite a bit of state to deal with, this includes:
@@ -38,15 +43,18 @@ type TopicStreamId = usize;
 #[derive(Debug)]
 pub struct TopicStreams<T> {
     address_book: AddressBook,
+    bandwidth: Bandwidth,
     gossip_actor_tx: mpsc::Sender<ToGossipActor>,
     gossip_buffer: GossipBuffer,
     gossip_joined: Arc<RwLock<HashSet<[u8; 32]>>>,
-    gossip_pending: HashMap<[u8; 32], oneshot::Sender<()>>,
+    gossip_pending: HashMap<[u8; 32], Vec<oneshot::Sender<()>>>,
     next_stream_id: usize,
     subscribed: HashMap<TopicStreamId, TopicStream<T>>,
+    flush_handles: Arc<Mutex<HashMap<TopicStreamId, FlushHandle>>>,
     topic_id_to_stream: HashMap<[u8; 32], Vec<TopicStreamId>>,
     topic_to_stream: HashMap<T, Vec<TopicStreamId>>,
     sync_actor_tx: Option<mpsc::Sender<ToSyncActor<T>>>,
+    traffic: TrafficMeter,
 }
 
 impl<T> TopicStreams<T>
@@ -57,18 +65,23 @@ where
         gossip_actor_tx: mpsc::Sender<ToGossipActor>,
         address_book: AddressBook,
         sync_actor_tx: Option<mpsc::Sender<ToSyncActor<T>>>,
+        bandwidth: Bandwidth,
+        traffic: TrafficMeter,
     ) -> Self {
         Self {
             address_book,
+            bandwidth,
             gossip_actor_tx,
             gossip_buffer: Default::default(),
             gossip_joined: Arc::new(RwLock::new(HashSet::new())),
             gossip_pending: HashMap::new(),
             next_stream_id: 1,
             subscribed: HashMap::new(),
+            flush_handles: Arc::new(Mutex::new(HashMap::new())),
             topic_id_to_stream: HashMap::new(),
             topic_to_stream: HashMap::new(),
             sync_actor_tx,
+            traffic,
         }
     }
 
@@ -97,7 +110,6 @@ where
         // gossip, buffering or sync.
         self.subscribed
             .insert(stream_id, (topic.clone(), from_network_tx));
-        self.gossip_pending.insert(topic.id(), gossip_ready_tx);
         self.topic_to_stream
             .entry(topic.clone())
             .and_modify(|stream_ids| stream_ids.push(stream_id))
@@ -107,19 +119,60 @@ where
             .and_modify(|stream_ids| stream_ids.push(stream_id))
             .or_insert(vec![stream_id]);
 
+        // Another subscription with the same topic id might have joined the gossip overlay
+        // already, in this case the stream is ready right away.
+        if self.has_joined_gossip(topic.id()).await {
+            gossip_ready_tx.send(()).ok();
+        } else {
+            self.gossip_pending
+                .entry(topic.id())
+                .or_default()
+                .push(gossip_ready_tx);
+        }
+
         // Hot path: If we haven't joined a gossip overlay for this topic yet, optimistically try
         // to do it now. If this fails we should re-try sometime later using the
         // "try_join_pending_gossips" method.
         self.join_gossip(topic.id()).await?;
 
         // Spawn task to establish a channel for sending messages into gossip overlay.
+        //
+        // The task keeps running until the sending half of the channel was dropped or the stream
+        // was closed by unsubscribing. In the latter case all already queued messages are still
+        // forwarded to the gossip overlay before the task signals that it has been flushed.
         {
             let gossip_actor_tx = self.gossip_actor_tx.clone();
             let gossip_joined = self.gossip_joined.clone();
+            let flush_handles = self.flush_handles.clone();
+            let upload = self.bandwidth.upload.clone();
+            let traffic = self.traffic.clone();
+            let (close_tx, mut close_rx) = oneshot::channel();
+            let (flushed_tx, flushed_rx) = oneshot::channel();
+            self.flush_handles
+                .lock()
+                .expect("lock is not poisoned")
+                .insert(stream_id, (close_tx, flushed_rx));
+
             tokio::task::spawn(async move {
-                while let Some(event) = to_network_rx.recv().await {
-                    let gossip_joined = gossip_joined.read().await;
-                    if !gossip_joined.contains(&topic.id()) {
+                let mut closed = false;
+                loop {
+                    let event = tokio::select! {
+                        biased;
+                        event = to_network_rx.recv() => event,
+                        _ = &mut close_rx, if !closed => {
+                            // Do not accept any new messages but drain the ones which are already
+                            // queued.
+                            to_network_rx.close();
+                            closed = true;
+                            continue;
+                        }
+                    };
+
+                    let Some(event) = event else {
+                        break;
+                    };
+
+                    if !gossip_joined.read().await.contains(&topic.id()) {
                         // If we haven't joined the gossip yet messages will be silently dropped
                         // here.
                         //
@@ -136,6 +189,13 @@ where
 
                     let result = match event {
                         ToNetwork::Message { bytes } => {
+                            // Gossip messages are eagerly pushed to all direct neighbors, wait
+                            // until the upload limit allows sending them to every one of them.
+                            // Throttling happens here and not in the gossip actor to only hold
+                            // back this stream and not all others.
+                            upload
+                                .acquire(bytes.len() * traffic.neighbor_count(topic.id()))
+                                .await;
                             gossip_actor_tx
                                 .send(ToGossipActor::Broadcast {
                                     topic_id: topic.id(),
@@ -152,12 +212,77 @@ where
                         break;
                     }
                 }
+
+                // Nothing needs to be flushed anymore when unsubscribing after the stream ended.
+                flush_handles
+                    .lock()
+                    .expect("lock is not poisoned")
+                    .remove(&stream_id);
+                flushed_tx.send(()).ok();
             });
         }
 
         Ok(())
     }
 
+    /// Removes all streams subscribed to the given topic.
+    ///
+    /// Messages which were already queued by the application are flushed into the gossip overlay
+    /// before the stream is closed. If no other stream is interested in the same topic id, the
+    /// related gossip overlay is left afterwards.
+    ///
+    /// The given reply channel is informed as soon as all streams have been flushed.
+    pub async fn unsubscribe(&mut self, topic: &T, reply: oneshot::Sender<()>) -> Result<()> {
+        let topic_id = topic.id();
+        let stream_ids = self.topic_to_stream.remove(topic).unwrap_or_default();
+
+        let mut flushed = Vec::with_capacity(stream_ids.len());
+        {
+            let mut flush_handles = self.flush_handles.lock().expect("lock is not poisoned");
+            for stream_id in &stream_ids {
+                self.subscribed.remove(stream_id);
+                if let Some((close_tx, flushed_rx)) = flush_handles.remove(stream_id) {
+                    close_tx.send(()).ok();
+                    flushed.push(flushed_rx);
+                }
+            }
+        }
+
+        // Other topics with the same topic id might still be subscribed, in this case we stay in
+        // the gossip overlay.
+        let mut leave_gossip = false;
+        if let Some(topic_id_streams) = self.topic_id_to_stream.get_mut(&topic_id) {
+            topic_id_streams.retain(|stream_id| !stream_ids.contains(stream_id));
+            if topic_id_streams.is_empty() {
+                self.topic_id_to_stream.remove(&topic_id);
+                self.gossip_pending.remove(&topic_id);
+                leave_gossip = true;
+            }
+        }
+
+        let gossip_actor_tx = self.gossip_actor_tx.clone();
+        let gossip_joined = self.gossip_joined.clone();
+        tokio::task::spawn(async move {
+            for flushed_rx in flushed {
+                flushed_rx.await.ok();
+            }
+
+            if leave_gossip {
+                gossip_joined.write().await.remove(&topic_id);
+                if let Err(err) = gossip_actor_tx
+                    .send(ToGossipActor::Leave { topic_id })
+                    .await
+                {
+                    error!("failed leaving gossip for topic id {topic_id:?}: {err}");
+                }
+            }
+
+            reply.send(()).ok();
+        });
+
+        Ok(())
+    }
+
     /// Returns a list of all gossip topic ids we're interested in.
     pub fn topic_ids(&self) -> Vec<[u8; 32]> {
         self.subscribed
@@ -169,14 +294,13 @@ where
     /// Moves all gossip topics which were previously joined into the set of pending joins.
     ///
     /// This is useful for rejoining gossip topic overlays after an extended loss of network
-    /// connectivity. One important consideration is that the ready receiver is immediately
-    /// dropped, meaning that the application layer is never made aware when the topic has been
+    /// connectivity. One important consideration is that no ready signal is sent for these
+    /// topics, meaning that the application layer is never made aware when the topic has been
     /// rejoined.
     pub async fn move_joined_to_pending(&mut self) {
         let mut gossip_joined = self.gossip_joined.write().await;
         for topic in gossip_joined.drain() {
-            let (ready_tx, _ready_rx) = oneshot::channel();
-            self.gossip_pending.insert(topic, ready_tx);
+            self.gossip_pending.entry(topic).or_default();
         }
     }
 
@@ -195,14 +319,16 @@ where
 
     /// Mark that we've successfully joined a gossip overlay for this topic.
     pub async fn on_gossip_joined(&mut self, topic_id: [u8; 32]) {
-        if let Some(ready_tx) = self.gossip_pending.remove(&topic_id) {
+        if let Some(ready_txs) = self.gossip_pending.remove(&topic_id) {
             let mut gossip_joined = self.gossip_joined.write().await;
             gossip_joined.insert(topic_id);
 
             // Inform local topic subscribers that the gossip overlay has been joined and is ready
             // for messages.
-            if ready_tx.send(()).is_err() {
-                warn!("gossip topic oneshot ready receiver dropped")
+            for ready_tx in ready_txs {
+                if ready_tx.send(()).is_err() {
+                    warn!("gossip topic oneshot ready receiver dropped")
+                }
             }
         }
     }
@@ -259,10 +385,12 @@ where
 
         // Different topics can be subscribed to the same gossip overlay, this is why we need to
         // multiplex the gossip message to potentially multiple streams.
-        let stream_ids = self
-            .topic_id_to_stream
-            .get(&topic_id)
-            .expect("consistent topic id to stream id mapping");
+        // The stream might have been removed in the meantime.
+        let Some(stream_ids) = self.topic_id_to_stream.get(&topic_id) else {
+            debug!("received gossip message for unsubscribed topic {topic_id:?}");
+            return Ok(());
+        };
+
         for stream_id in stream_ids {
             let (_, from_network_tx) = self.subscribed.get(stream_id).expect("stream should exist");
             from_network_tx
@@ -285,6 +413,10 @@ where
     ) -> Result<()> {
         debug!("learned about topic ids of {}: {:?}", peer, their_topic_ids);
 
+        if self.address_book.is_denied(&peer).await {
+            return Ok(());
+        }
+
         // Inform the sync manager about any peer-topic combinations which are of interest to us.
         //
         // This queues up a sync session which will eventually request the data we are interested
@@ -308,6 +440,29 @@ where
         Ok(())
     }
 
+    /// Process a peer which was manually added or found by peer discovery.
+    ///
+    /// If we already know about topic ids of this peer we directly attempt connecting to them over
+    /// the gossip overlays we have in common and schedule sync sessions.
+    pub async fn on_peer_added(
+        &mut self,
+        their_topic_ids: Vec<[u8; 32]>,
+        peer: PublicKey,
+    ) -> Result<()> {
+        for topic_id in self.topic_ids() {
+            if their_topic_ids.contains(&topic_id) && self.has_joined_gossip(topic_id).await {
+                self.gossip_actor_tx
+                    .send(ToGossipActor::JoinPeers {
+                        topic_id,
+                        peers: vec![peer],
+                    })
+                    .await?;
+            }
+        }
+
+        self.on_discovered_topic_ids(their_topic_ids, peer).await
+    }
+
     /// Process new sync session starting with a peer.
     ///
     /// If a topic is known we've initiated the sync session. If it is `None` we accepted a sync
@@ -333,10 +488,11 @@ where
         payload: Option<Vec<u8>>,
         delivered_from: PublicKey,
     ) -> Result<()> {
-        let stream_ids = self
-            .topic_to_stream
-            .get(&topic)
-            .expect("consistent topic to stream id mapping");
+        // The stream might have been removed in the meantime.
+        let Some(stream_ids) = self.topic_to_stream.get(&topic) else {
+            debug!("received sync message for unsubscribed topic {topic:?}");
+            return Ok(());
+        };
 
         for stream_id in stream_ids {
             let (_, from_network_tx) = self.subscribed.get(stream_id).expect("stream should exist");
@@ -402,10 +558,13 @@ mod tests {
     use p2panda_sync::TopicQuery;
     use serde::{Deserialize, Serialize};
     use tokio::sync::{mpsc, oneshot};
+    use tokio::time::{Duration, Instant};
     use tokio_stream::wrappers::ReceiverStream;
 
-    use crate::engine::AddressBook;
-    use crate::network::FromNetwork;
+    use crate::bandwidth::Bandwidth;
+    use crate::engine::gossip::ToGossipActor;
+    use crate::engine::{AddressBook, TrafficMeter};
+    use crate::network::{FromNetwork, ToNetwork};
     use crate::{NodeAddress, TopicId};
 
     use super::TopicStreams;
@@ -449,8 +608,13 @@ mod tests {
             .add_topic_id(peer_1.public_key, topic.id())
             .await;
 
-        let mut topic_streams =
-            TopicStreams::<TestTopic>::new(gossip_actor_tx, address_book, Some(sync_actor_tx));
+        let mut topic_streams = TopicStreams::<TestTopic>::new(
+            gossip_actor_tx,
+            address_book,
+            Some(sync_actor_tx),
+            Bandwidth::default(),
+            TrafficMeter::new(),
+        );
 
         topic_streams
             .subscribe(
@@ -501,4 +665,141 @@ mod tests {
             }
         );
     }
+
+    #[tokio::test]
+    async fn remove_flush_handle_when_stream_ends() {
+        let (gossip_actor_tx, _gossip_actor_rx) = mpsc::channel(128);
+        let (from_network_tx, _from_network_rx) = mpsc::channel(128);
+        let (to_network_tx, to_network_rx) = mpsc::channel(128);
+        let (gossip_ready_tx, _) = oneshot::channel();
+
+        let address_book = AddressBook::new([1; 32]);
+        let mut topic_streams = TopicStreams::<TestTopic>::new(
+            gossip_actor_tx,
+            address_book,
+            None,
+            Bandwidth::default(),
+            TrafficMeter::new(),
+        );
+
+        topic_streams
+            .subscribe(
+                TestTopic::Primary,
+                from_network_tx,
+                to_network_rx,
+                gossip_ready_tx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(topic_streams.flush_handles.lock().unwrap().len(), 1);
+
+        // The application drops the sending half of the stream without unsubscribing.
+        drop(to_network_tx);
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !topic_streams.flush_handles.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("flush handle was removed");
+    }
+
+    #[tokio::test]
+    async fn gossip_ready_for_every_subscription() {
+        let (gossip_actor_tx, _gossip_actor_rx) = mpsc::channel(128);
+        let mut topic_streams = TopicStreams::<TestTopic>::new(
+            gossip_actor_tx,
+            AddressBook::new([1; 32]),
+            None,
+            Bandwidth::default(),
+            TrafficMeter::new(),
+        );
+
+        // Two subscriptions with the same topic id wait for the gossip overlay to be joined.
+        let mut ready_rxs = Vec::new();
+        for topic in [TestTopic::Primary, TestTopic::Secondary] {
+            let (from_network_tx, _from_network_rx) = mpsc::channel(128);
+            let (_to_network_tx, to_network_rx) = mpsc::channel(128);
+            let (gossip_ready_tx, gossip_ready_rx) = oneshot::channel();
+            topic_streams
+                .subscribe(topic, from_network_tx, to_network_rx, gossip_ready_tx)
+                .await
+                .unwrap();
+            ready_rxs.push(gossip_ready_rx);
+        }
+
+        topic_streams
+            .on_gossip_joined(TestTopic::Primary.id())
+            .await;
+
+        for ready_rx in ready_rxs {
+            assert_eq!(ready_rx.await, Ok(()));
+        }
+
+        // Subscribing after the overlay was joined signals readiness right away.
+        let (from_network_tx, _from_network_rx) = mpsc::channel(128);
+        let (_to_network_tx, to_network_rx) = mpsc::channel(128);
+        let (gossip_ready_tx, gossip_ready_rx) = oneshot::channel();
+        topic_streams
+            .subscribe(
+                TestTopic::Primary,
+                from_network_tx,
+                to_network_rx,
+                gossip_ready_tx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(gossip_ready_rx.await, Ok(()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throttle_upload_per_neighbor() {
+        let (gossip_actor_tx, mut gossip_actor_rx) = mpsc::channel(128);
+        let (from_network_tx, _from_network_rx) = mpsc::channel(128);
+        let (to_network_tx, to_network_rx) = mpsc::channel(128);
+        let (gossip_ready_tx, _) = oneshot::channel();
+
+        let topic = TestTopic::Primary;
+
+        // We're connected to two direct neighbors in the gossip overlay of this topic.
+        let traffic = TrafficMeter::new();
+        traffic.on_neighbor_up(PrivateKey::new().public_key(), topic.id());
+        traffic.on_neighbor_up(PrivateKey::new().public_key(), topic.id());
+
+        let mut topic_streams = TopicStreams::<TestTopic>::new(
+            gossip_actor_tx,
+            AddressBook::new([1; 32]),
+            None,
+            Bandwidth::new(Some(10_000), None),
+            traffic,
+        );
+
+        topic_streams
+            .subscribe(
+                topic.clone(),
+                from_network_tx,
+                to_network_rx,
+                gossip_ready_tx,
+            )
+            .await
+            .unwrap();
+        topic_streams.on_gossip_joined(topic.id()).await;
+
+        let start = Instant::now();
+        to_network_tx
+            .send(ToNetwork::Message {
+                bytes: vec![0; 1_000],
+            })
+            .await
+            .unwrap();
+
+        let Some(ToGossipActor::Broadcast { bytes, .. }) = gossip_actor_rx.recv().await else {
+            panic!("expected broadcast message");
+        };
+        assert_eq!(bytes.len(), 1_000);
+
+        // The message is pushed to both neighbors, so sending it takes 2_000 bytes of upload.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
 }
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{ready, Context, Poll};
+
+use futures_util::{AsyncRead, AsyncWrite};
+use p2panda_core::PublicKey;
+
+/// Traffic counters and active gossip topics for a single peer.
+#[derive(Clone, Debug, Default)]
+pub struct PeerTraffic {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub topic_ids: HashSet<[u8; 32]>,
+}
+
+/// Keeps track of the amount of application data exchanged with each peer and the gossip
+/// overlays in which they are our direct neighbors.
+///
+/// The meter is shared between the gossip actor, the engine actor and all sync sessions, which
+/// record traffic as it passes through them. Counters of peers we're not connected to anymore are
+/// removed with `retain_connected`.
+#[derive(Clone, Debug, Default)]
+pub struct TrafficMeter {
+    inner: Arc<RwLock<HashMap<PublicKey, PeerTraffic>>>,
+}
+
+impl TrafficMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a peer as our direct neighbor in the given gossip overlay.
+    pub fn on_neighbor_up(&self, peer: PublicKey, topic_id: [u8; 32]) {
+        let mut inner = self.inner.write().expect("lock is not poisoned");
+        inner.entry(peer).or_default().topic_ids.insert(topic_id);
+    }
+
+    /// Remove a peer from the neighbors of the given gossip overlay.
+    pub fn on_neighbor_down(&self, peer: PublicKey, topic_id: [u8; 32]) {
+        let mut inner = self.inner.write().expect("lock is not poisoned");
+        if let Some(traffic) = inner.get_mut(&peer) {
+            traffic.topic_ids.remove(&topic_id);
+        }
+    }
+
+    /// Remove the given gossip overlay from all peers, for example after leaving it.
+    pub fn on_topic_left(&self, topic_id: [u8; 32]) {
+        let mut inner = self.inner.write().expect("lock is not poisoned");
+        for traffic in inner.values_mut() {
+            traffic.topic_ids.remove(&topic_id);
+        }
+    }
+
+    /// Account bytes received from a peer.
+    pub fn record_received(&self, peer: PublicKey, len: usize) {
+        let mut inner = self.inner.write().expect("lock is not poisoned");
+        inner.entry(peer).or_default().bytes_received += len as u64;
+    }
+
+    /// Account bytes sent to a peer.
+    pub fn record_sent(&self, peer: PublicKey, len: usize) {
+        let mut inner = self.inner.write().expect("lock is not poisoned");
+        inner.entry(peer).or_default().bytes_sent += len as u64;
+    }
+
+    /// Account bytes broadcast into a gossip overlay.
+    ///
+    /// Gossip messages are eagerly pushed to all direct neighbors of the overlay, which is why
+    /// every one of them is accounted for.
+    pub fn record_broadcast(&self, topic_id: [u8; 32], len: usize) {
+        let mut inner = self.inner.write().expect("lock is not poisoned");
+        for traffic in inner.values_mut() {
+            if traffic.topic_ids.contains(&topic_id) {
+                traffic.bytes_sent += len as u64;
+            }
+        }
+    }
+
+    /// Return the number of our direct neighbors in the given gossip overlay.
+    pub fn neighbor_count(&self, topic_id: [u8; 32]) -> usize {
+        let inner = self.inner.read().expect("lock is not poisoned");
+        inner
+            .values()
+            .filter(|traffic| traffic.topic_ids.contains(&topic_id))
+            .count()
+    }
+
+    /// Return the current traffic counters for the given peer.
+    pub fn get(&self, peer: &PublicKey) -> PeerTraffic {
+        let inner = self.inner.read().expect("lock is not poisoned");
+        inner.get(peer).cloned().unwrap_or_default()
+    }
+
+    /// Forget about all peers which are not in the given set of connected peers.
+    pub fn retain_connected(&self, connected: &HashSet<PublicKey>) {
+        let mut inner = self.inner.write().expect("lock is not poisoned");
+        inner.retain(|peer, _| connected.contains(peer));
+    }
+}
+
+/// Wraps a stream to a peer and accounts all bytes read from and written to it.
+pub(crate) struct Metered<S> {
+    inner: S,
+    peer: PublicKey,
+    traffic: TrafficMeter,
+}
+
+impl<S> Metered<S> {
+    pub fn new(inner: S, peer: PublicKey, traffic: TrafficMeter) -> Self {
+        Self {
+            inner,
+            peer,
+            traffic,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Metered<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.traffic.record_sent(this.peer, written);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Metered<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.traffic.record_received(this.peer, read);
+        Poll::Ready(Ok(read))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use futures_util::io::Cursor;
+    use futures_util::{AsyncReadExt, AsyncWriteExt};
+    use p2panda_core::PrivateKey;
+
+    use super::{Metered, TrafficMeter};
+
+    #[tokio::test]
+    async fn count_both_directions() {
+        let peer = PrivateKey::new().public_key();
+        let traffic = TrafficMeter::new();
+
+        let mut writer = Metered::new(Vec::new(), peer, traffic.clone());
+        writer.write_all(&[1; 100]).await.unwrap();
+
+        let mut reader = Metered::new(Cursor::new(vec![2; 40]), peer, traffic.clone());
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+
+        let counters = traffic.get(&peer);
+        assert_eq!(counters.bytes_sent, 100);
+        assert_eq!(counters.bytes_received, 40);
+
+        // Counters of disconnected peers are removed.
+        traffic.retain_connected(&HashSet::new());
+        assert_eq!(traffic.get(&peer).bytes_sent, 0);
+    }
+}
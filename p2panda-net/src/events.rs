@@ -1,8 +1,12 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 //! System events API.
+use std::net::SocketAddr;
+
 use p2panda_core::PublicKey;
 
+use crate::RelayUrl;
+
 /// Network system events.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum SystemEvent<T> {
@@ -28,12 +32,55 @@ pub enum SystemEvent<T> {
     /// Discovered a new peer in the network.
     PeerDiscovered { peer: PublicKey },
 
+    /// Established a connection to a peer.
+    ///
+    /// The connection is direct when it does not need to be routed through a relay. Later
+    /// upgrades from a relayed to a direct connection are reported with `DirectConnectionUpgraded`.
+    PeerConnected { peer: PublicKey, direct: bool },
+
+    /// Lost the connection to a peer.
+    ///
+    /// This event will be emitted approximately 10 seconds after the connection is lost.
+    PeerDisconnected {
+        peer: PublicKey,
+        reason: DisconnectReason,
+    },
+
     /// Started a sync session.
     SyncStarted { topic: Option<T>, peer: PublicKey },
 
-    /// Completed a sync session.
-    SyncDone { topic: T, peer: PublicKey },
+    /// Completed a sync session, receiving the given number of operations from the peer.
+    SyncDone {
+        topic: T,
+        peer: PublicKey,
+        received: usize,
+    },
 
     /// Failed to complete a sync session.
-    SyncFailed { topic: Option<T>, peer: PublicKey },
+    ///
+    /// The topic is not known if the session failed before the handshake phase completed.
+    SyncFailed {
+        topic: Option<T>,
+        peer: PublicKey,
+        error: String,
+    },
+
+    /// Failed to hole-punch a direct connection to a peer, traffic is routed through a relay.
+    ///
+    /// Relayed connections usually have a higher latency.
+    RelayFallback { peer: PublicKey, relay: RelayUrl },
+
+    /// Established a direct connection to a peer which was previously only reachable via a relay.
+    DirectConnectionUpgraded { peer: PublicKey, addr: SocketAddr },
+}
+
+/// Reason why the connection to a peer was lost.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DisconnectReason {
+    /// No network path to the peer is left or nothing was received from them for a while, for
+    /// example because the connection was closed or the peer went offline.
+    ConnectionLost,
+
+    /// The endpoint forgot about the peer.
+    Forgotten,
 }
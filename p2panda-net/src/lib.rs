@@ -139,20 +139,23 @@
 //! # }
 //! ```
 mod addrs;
+mod bandwidth;
 mod bytes;
 pub mod config;
 mod engine;
 mod events;
 pub mod network;
 mod protocols;
+mod stats;
 mod sync;
 
 pub use addrs::{NodeAddress, RelayUrl};
 pub use config::Config;
-pub use events::SystemEvent;
+pub use events::{DisconnectReason, SystemEvent};
 pub use network::{FromNetwork, Network, NetworkBuilder, RelayMode, ToNetwork};
-pub use protocols::ProtocolHandler;
-pub use sync::{ResyncConfiguration, SyncConfiguration};
+pub use protocols::{ProtocolError, ProtocolHandler};
+pub use stats::{ConnectionPath, ConnectionStats};
+pub use sync::{ResyncConfiguration, ResyncSchedule, SyncConfiguration};
 
 #[cfg(feature = "log-sync")]
 pub use p2panda_sync::log_sync::LogSyncProtocol;
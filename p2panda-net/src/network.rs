@@ -90,8 +90,8 @@
 //! Sync is disabled by default and can be enabled by adding a `SyncProtocol` implementation to the
 //! node.
 //!
-//! Sync sessions are only running once per peer per topic but can optionally be re-attempted after
-//! a certain duration if a `ResyncConfiguration` was given.
+//! Sync sessions are only running once per peer per topic but can optionally be re-attempted if a
+//! `ResyncConfiguration` was given, following a fixed, exponential or jittered `ResyncSchedule`.
 //!
 //! ## Gossip Buffer
 //!
@@ -122,26 +122,30 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
-use futures_lite::StreamExt;
+use futures_lite::{Stream, StreamExt};
 use futures_util::future::{MapErr, Shared};
 use futures_util::{FutureExt, TryFutureExt};
 use iroh::{Endpoint, RelayMap, RelayNode};
 use iroh_gossip::net::{Gossip, GOSSIP_ALPN};
+use iroh_gossip::proto::{HyparviewConfig, PlumtreeConfig};
 use iroh_quinn::TransportConfig;
 use p2panda_core::{PrivateKey, PublicKey};
 use p2panda_discovery::{Discovery, DiscoveryMap};
 use p2panda_sync::TopicQuery;
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use tokio::task::{JoinError, JoinSet};
+use tokio_stream::wrappers::WatchStream;
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::AbortOnDropHandle;
 use tracing::{debug, error, error_span, warn, Instrument};
 
 use crate::addrs::{to_node_addr, to_relay_url, DEFAULT_STUN_PORT};
-use crate::config::{Config, GossipConfig, DEFAULT_BIND_PORT};
+use crate::bandwidth::Bandwidth;
+use crate::config::{BackoffConfig, Config, GossipConfig, DEFAULT_BIND_PORT};
 use crate::engine::Engine;
 use crate::events::SystemEvent;
-use crate::protocols::{ProtocolHandler, ProtocolMap};
+use crate::protocols::{ProtocolError, ProtocolHandler, ProtocolMap};
+use crate::stats::ConnectionStats;
 use crate::sync::{SyncConfiguration, SYNC_CONNECTION_ALPN};
 use crate::{from_private_key, NetworkId, NodeAddress, RelayUrl, TopicId};
 
@@ -151,6 +155,9 @@ const MAX_STREAMS: u32 = 1024;
 /// Timeout duration for receiving of at least one peer's direct address.
 const DIRECT_ADDRESSES_WAIT: Duration = Duration::from_secs(5);
 
+/// Maximum duration to wait for running sync sessions to complete during shutdown.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Relay server configuration mode.
 #[derive(Debug, PartialEq)]
 pub enum RelayMode {
@@ -183,12 +190,16 @@ pub struct NetworkBuilder<T> {
     bind_port_v6: Option<u16>,
     direct_node_addresses: Vec<NodeAddress>,
     discovery: DiscoveryMap,
+    download_rate_limit: Option<u64>,
+    duplicate_alpns: Vec<&'static [u8]>,
     gossip_config: Option<GossipConfig>,
     network_id: NetworkId,
     protocols: ProtocolMap,
     relay_mode: RelayMode,
     private_key: Option<PrivateKey>,
+    reconnect_backoff: BackoffConfig,
     sync_config: Option<SyncConfiguration<T>>,
+    upload_rate_limit: Option<u64>,
 }
 
 impl<T> NetworkBuilder<T>
@@ -207,12 +218,16 @@ where
             bind_port_v6: None,
             direct_node_addresses: Vec::new(),
             discovery: DiscoveryMap::default(),
+            download_rate_limit: None,
+            duplicate_alpns: Vec::new(),
             gossip_config: None,
             network_id,
             protocols: Default::default(),
             relay_mode: RelayMode::Disabled,
             private_key: None,
+            reconnect_backoff: BackoffConfig::default(),
             sync_config: None,
+            upload_rate_limit: None,
         }
     }
 
@@ -222,7 +237,8 @@ where
             .bind_ip_v4(config.bind_ip_v4)
             .bind_port_v4(config.bind_port_v4)
             .bind_ip_v6(config.bind_ip_v6)
-            .bind_port_v6(config.bind_port_v6);
+            .bind_port_v6(config.bind_port_v6)
+            .reconnect_backoff(config.reconnect_backoff);
 
         for addr in config.direct_node_addresses {
             network_builder = network_builder.direct_address(
@@ -237,7 +253,19 @@ where
             network_builder = network_builder.relay(url, false, port)
         }
 
-        network_builder
+        if let Some(bytes_per_second) = config.upload_rate_limit {
+            network_builder = network_builder.upload_rate_limit(bytes_per_second)
+        }
+
+        if let Some(bytes_per_second) = config.download_rate_limit {
+            network_builder = network_builder.download_rate_limit(bytes_per_second)
+        }
+
+        network_builder.gossip(GossipConfig {
+            fanout: config.gossip_fanout,
+            message_ttl: config.gossip_message_ttl,
+            ..GossipConfig::default()
+        })
     }
 
     /// Sets or overwrites the local IP for IPv4 sockets.
@@ -346,16 +374,78 @@ where
         self
     }
 
+    /// Sets the backoff strategy for re-attempting to connect to the network after failure.
+    ///
+    /// Default is a fixed delay of 900 milliseconds between attempts.
+    pub fn reconnect_backoff(mut self, config: BackoffConfig) -> Self {
+        self.reconnect_backoff = config;
+        self
+    }
+
+    /// Limits the number of bytes per second sent via gossip and sync.
+    ///
+    /// When the limit is reached, sending blocks until bandwidth is available again. No messages
+    /// are dropped. Gossip messages count once for every direct neighbor they are pushed to.
+    /// Uploads are not limited by default.
+    pub fn upload_rate_limit(mut self, bytes_per_second: u64) -> Self {
+        self.upload_rate_limit = Some(bytes_per_second);
+        self
+    }
+
+    /// Limits the number of bytes per second received via gossip and sync.
+    ///
+    /// When the limit is reached, processing of inbound data blocks until bandwidth is available
+    /// again. Downloads are not limited by default.
+    pub fn download_rate_limit(mut self, bytes_per_second: u64) -> Self {
+        self.download_rate_limit = Some(bytes_per_second);
+        self
+    }
+
     /// Adds additional, custom protocols for communication between two peers.
+    ///
+    /// Every protocol needs to be registered under a unique ALPN which is not used by any of the
+    /// core protocols (gossip and sync). Conflicting registrations cause `build` to fail with a
+    /// [`ProtocolError`].
     pub fn protocol(
         mut self,
         protocol_name: &'static [u8],
         handler: impl ProtocolHandler + 'static,
     ) -> Self {
-        self.protocols.insert(protocol_name, Arc::new(handler));
+        if self.protocols.insert(protocol_name, Arc::new(handler)) {
+            self.duplicate_alpns.push(protocol_name);
+        }
         self
     }
 
+    /// Checks that custom protocols neither use a reserved ALPN nor share an ALPN with each other.
+    fn validate_protocols(&self) -> Result<(), ProtocolError> {
+        if self.protocols.contains(GOSSIP_ALPN) {
+            return Err(ProtocolError::ReservedAlpn(
+                String::from_utf8_lossy(GOSSIP_ALPN).to_string(),
+            ));
+        }
+
+        // All sync protocols are accepted under ALPNs starting with the sync ALPN.
+        if let Some(alpn) = self
+            .protocols
+            .alpns()
+            .into_iter()
+            .find(|alpn| alpn.starts_with(SYNC_CONNECTION_ALPN))
+        {
+            return Err(ProtocolError::ReservedAlpn(
+                String::from_utf8_lossy(&alpn).to_string(),
+            ));
+        }
+
+        if let Some(alpn) = self.duplicate_alpns.first() {
+            return Err(ProtocolError::DuplicateAlpn(
+                String::from_utf8_lossy(alpn).to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
     /// Returns a handle to a newly-spawned instance of `Network`.
     ///
     /// A peer-to-peer endpoint is created and bound to a QUIC socket, after which the gossip,
@@ -371,6 +461,8 @@ where
     where
         T: TopicQuery + TopicId + 'static,
     {
+        self.validate_protocols()?;
+
         let private_key = self.private_key.unwrap_or_default();
 
         let relay: Option<RelayNode> = match self.relay_mode {
@@ -412,8 +504,25 @@ where
 
         let node_addr = endpoint.node_addr().await?;
 
+        let gossip_config = self.gossip_config.unwrap_or_default();
+        let membership_config = HyparviewConfig {
+            active_view_capacity: gossip_config.fanout,
+            ..HyparviewConfig::default()
+        };
+        let broadcast_config = {
+            let default = PlumtreeConfig::default();
+            PlumtreeConfig {
+                message_cache_retention: gossip_config.message_ttl,
+                // Message ids need to be remembered at least as long as the messages themselves,
+                // otherwise we would accept them again.
+                message_id_retention: default.message_id_retention.max(gossip_config.message_ttl),
+                ..default
+            }
+        };
         let gossip = Gossip::builder()
-            .max_message_size(self.gossip_config.unwrap_or_default().max_message_size)
+            .max_message_size(gossip_config.max_message_size)
+            .membership_config(membership_config)
+            .broadcast_config(broadcast_config)
             .spawn(endpoint.clone())
             .await?;
 
@@ -423,9 +532,19 @@ where
             endpoint.clone(),
             gossip.clone(),
             self.sync_config,
+            self.reconnect_backoff,
+            Bandwidth::new(self.upload_rate_limit, self.download_rate_limit),
         );
 
-        let sync_handler = engine.sync_handler();
+        let sync_handlers = engine.sync_handlers();
+
+        let (node_address_tx, _) = watch::channel(NodeAddress {
+            public_key: private_key.public_key(),
+            direct_addresses: Vec::new(),
+            relay_url: relay
+                .as_ref()
+                .map(|relay_node| to_relay_url(relay_node.url.clone())),
+        });
 
         let inner = Arc::new(NetworkInner {
             cancel_token: CancellationToken::new(),
@@ -434,15 +553,19 @@ where
             endpoint: endpoint.clone(),
             engine,
             gossip: gossip.clone(),
+            gossip_config,
             network_id: self.network_id,
+            node_address: node_address_tx,
             private_key,
         });
 
-        self.protocols.insert(GOSSIP_ALPN, Arc::new(gossip.clone()));
-        if let Some(sync_handler) = sync_handler {
-            self.protocols
-                .insert(SYNC_CONNECTION_ALPN, Arc::new(sync_handler));
-        };
+        self.protocols.insert(
+            GOSSIP_ALPN,
+            Arc::new(inner.engine.gossip_handler(gossip.clone())),
+        );
+        for (alpn, sync_handler) in sync_handlers {
+            self.protocols.insert(&alpn, Arc::new(sync_handler));
+        }
         let protocols = Arc::new(self.protocols.clone());
         let alpns = self.protocols.alpns();
         if let Err(err) = inner.endpoint.set_alpns(alpns) {
@@ -468,14 +591,18 @@ where
         // Wait for a single direct address update, to make sure we found at least one direct
         // address.
         let wait_for_endpoints = {
+            let inner = network.inner.clone();
             async move {
-                tokio::time::timeout(
+                let endpoints = tokio::time::timeout(
                     DIRECT_ADDRESSES_WAIT,
                     endpoint.direct_addresses().initialized(),
                 )
                 .await
                 .context("waiting for endpoint")?
                 .context("no endpoints given to establish at least one connection")?;
+                inner.update_direct_addresses(
+                    endpoints.iter().map(|endpoint| endpoint.addr).collect(),
+                );
                 Ok(())
             }
         };
@@ -511,7 +638,9 @@ struct NetworkInner<T> {
     engine: Engine<T>,
     #[allow(dead_code)]
     gossip: Gossip,
+    gossip_config: GossipConfig,
     network_id: NetworkId,
+    node_address: watch::Sender<NodeAddress>,
     #[allow(dead_code)]
     private_key: PrivateKey,
 }
@@ -554,6 +683,7 @@ where
                     let direct_addresses: Option<Vec<SocketAddr>> = endpoints
                         .map(|endpoints| endpoints.iter().map(|endpoint| endpoint.addr).collect());
                     if let Some(addresses) = direct_addresses {
+                        inner.update_direct_addresses(addresses.clone());
                         my_node_addr = my_node_addr.with_direct_addresses(addresses);
                         if let Err(err) = inner.discovery.update_local_address(&my_node_addr) {
                             warn!("failed to update direct addresses for discovery: {err:?}");
@@ -636,12 +766,35 @@ where
             }
         }
 
+        // We don't accept any new connections at this point. Give sync sessions which are still
+        // underway a chance to complete, so that everything we've received so far reaches the
+        // application layer.
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.engine.drain_sync_sessions())
+            .await
+            .is_err()
+        {
+            warn!("sync sessions did not complete before shutdown timeout");
+        }
+
         self.shutdown(protocols).await;
 
         // Abort remaining tasks.
         join_set.shutdown().await;
     }
 
+    /// Updates the direct addresses of our own node address, subscribers are only informed if
+    /// they actually changed.
+    fn update_direct_addresses(&self, mut addresses: Vec<SocketAddr>) {
+        addresses.sort();
+        self.node_address.send_if_modified(|node_address| {
+            if node_address.direct_addresses == addresses {
+                return false;
+            }
+            node_address.direct_addresses = addresses;
+            true
+        });
+    }
+
     /// Closes all connections and shuts down the network engine.
     async fn shutdown(&self, protocols: Arc<ProtocolMap>) {
         // We ignore all errors during shutdown.
@@ -685,7 +838,14 @@ impl<T> Network<T>
 where
     T: TopicQuery + TopicId + 'static,
 {
-    /// Adds a peer to the address book.
+    /// Adds a peer to the address book and immediately attempts to connect to it.
+    ///
+    /// This allows manually dialing peers whose addresses are already known, for example a
+    /// dedicated server in a controlled deployment, next to any ambient discovery services.
+    ///
+    /// The peer is joined into the network-wide gossip overlay right away. As soon as we know
+    /// about topics of interest we have in common with the peer (learned through "topic
+    /// discovery") the related gossip overlays are joined and sync sessions are scheduled.
     pub async fn add_peer(&self, node_addr: NodeAddress) -> Result<()> {
         self.inner.engine.add_peer(node_addr).await
     }
@@ -703,6 +863,41 @@ where
         self.inner.engine.known_peers().await
     }
 
+    /// Denies all connections and messages from the given peer.
+    ///
+    /// Inbound connections of the peer are refused and we don't attempt connecting to them
+    /// anymore, neither for gossip nor for sync. Gossip messages which still arrive from them are
+    /// ignored. This stays in place until the peer is explicitly allowed again.
+    ///
+    /// This can be used by applications to implement moderation, for example after repeated sync
+    /// failures with a misbehaving peer.
+    pub async fn deny_peer(&self, public_key: PublicKey) -> Result<()> {
+        self.inner.engine.deny_peer(public_key).await
+    }
+
+    /// Allows a previously denied peer again.
+    pub async fn allow_peer(&self, public_key: PublicKey) -> Result<()> {
+        self.inner.engine.allow_peer(public_key).await
+    }
+
+    /// Returns the public keys of all denied peers.
+    pub async fn denied_peers(&self) -> Result<Vec<PublicKey>> {
+        self.inner.engine.denied_peers().await
+    }
+
+    /// Returns runtime statistics for all peers we're currently connected to.
+    ///
+    /// Each entry holds the address of the remote peer, the network path (direct or relayed), a
+    /// round-trip time estimate, the number of bytes exchanged with them and the gossip topics in
+    /// which they are our direct neighbor.
+    pub async fn connection_stats(&self) -> Vec<ConnectionStats> {
+        self.inner
+            .engine
+            .connection_stats()
+            .await
+            .unwrap_or_default()
+    }
+
     /// Returns the direct addresses of this node.
     pub async fn direct_addresses(&self) -> Option<Vec<SocketAddr>> {
         match self
@@ -729,13 +924,39 @@ where
         &self.inner.endpoint
     }
 
+    /// Returns the configuration of the gossip overlays.
+    pub fn gossip_config(&self) -> &GossipConfig {
+        &self.inner.gossip_config
+    }
+
     /// Returns the public key of the node.
     pub fn node_id(&self) -> PublicKey {
         PublicKey::from_bytes(self.inner.endpoint.node_id().as_bytes())
             .expect("public key already checked")
     }
 
+    /// Returns the address of this node, including its direct addresses and relay URL.
+    ///
+    /// This is the address other peers can use to connect to us, for example when sharing it
+    /// out-of-band as part of an invite.
+    pub fn node_address(&self) -> NodeAddress {
+        self.inner.node_address.borrow().clone()
+    }
+
+    /// Returns a stream of node address updates.
+    ///
+    /// A new address is yielded whenever the set of direct addresses of this node changes, for
+    /// example after a STUN request revealed a new public address.
+    pub fn node_address_updates(&self) -> impl Stream<Item = NodeAddress> + Send + Unpin {
+        WatchStream::from_changes(self.inner.node_address.subscribe())
+    }
+
     /// Terminates all internal tasks and shuts down the node.
+    ///
+    /// The node stops accepting new connections and initiating new sync sessions. Running sync
+    /// sessions are given some time to complete, all data received through them is delivered to
+    /// the topic streams before the returned future resolves. Afterwards all connections are
+    /// closed.
     pub async fn shutdown(self) -> Result<()> {
         // Trigger shutdown of the main run task by activating the cancel token.
         self.inner.cancel_token.cancel();
@@ -767,6 +988,16 @@ where
 
         Ok((to_network_tx, from_network_rx, gossip_ready_rx))
     }
+
+    /// Unsubscribes from a topic.
+    ///
+    /// All messages which were already sent into the stream are flushed into the gossip overlay
+    /// before the stream gets closed, the returned future resolves only after this has completed.
+    /// Afterwards no more messages will be received for this topic and the gossip overlay is left
+    /// if no other subscription shares the same topic id.
+    pub async fn unsubscribe(&self, topic: T) -> Result<()> {
+        self.inner.engine.unsubscribe(topic).await
+    }
 }
 
 /// An event to be broadcast to the network.
@@ -828,6 +1059,7 @@ pub(crate) mod sync_protocols {
     use p2panda_sync::cbor::{into_cbor_sink, into_cbor_stream};
     use p2panda_sync::{FromSync, SyncError, SyncProtocol};
     use serde::{Deserialize, Serialize};
+    use tokio::time::{sleep, Duration};
     use tracing::debug;
 
     use super::tests::TestTopic;
@@ -919,6 +1151,147 @@ pub(crate) mod sync_protocols {
         }
     }
 
+    /// A sync implementation which fails right after the handshake phase.
+    #[derive(Debug)]
+    pub struct FaultyProtocol {}
+
+    #[async_trait]
+    impl<'a> SyncProtocol<'a, TestTopic> for FaultyProtocol {
+        fn name(&self) -> &'static str {
+            static FAULTY_PROTOCOL_NAME: &str = "faulty_protocol";
+            FAULTY_PROTOCOL_NAME
+        }
+
+        async fn initiate(
+            self: Arc<Self>,
+            topic_query: TestTopic,
+            tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+            _rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+            mut app_tx: Box<
+                &'a mut (dyn Sink<FromSync<TestTopic>, Error = SyncError> + Send + Unpin),
+            >,
+        ) -> Result<(), SyncError> {
+            let mut sink = into_cbor_sink(tx);
+            sink.send(DummyProtocolMessage::TopicQuery(topic_query.clone()))
+                .await?;
+            app_tx.send(FromSync::HandshakeSuccess(topic_query)).await?;
+
+            Err(SyncError::UnexpectedBehaviour(
+                "faulty protocol failed as initiator".into(),
+            ))
+        }
+
+        async fn accept(
+            self: Arc<Self>,
+            _tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+            rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+            mut app_tx: Box<
+                &'a mut (dyn Sink<FromSync<TestTopic>, Error = SyncError> + Send + Unpin),
+            >,
+        ) -> Result<(), SyncError> {
+            let mut stream = into_cbor_stream(rx);
+            if let Some(result) = stream.next().await {
+                if let DummyProtocolMessage::TopicQuery(topic_query) = result? {
+                    app_tx.send(FromSync::HandshakeSuccess(topic_query)).await?;
+                }
+            }
+
+            Err(SyncError::UnexpectedBehaviour(
+                "faulty protocol failed as acceptor".into(),
+            ))
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    enum SlowProtocolMessage {
+        TopicQuery(TestTopic),
+        Data(u8),
+        Done,
+    }
+
+    /// A sync implementation where the acceptor sends a number of messages with a delay between
+    /// each of them, the initiator forwards them to the application layer.
+    #[derive(Debug)]
+    pub struct SlowProtocol {
+        pub messages: u8,
+        pub delay: Duration,
+    }
+
+    #[async_trait]
+    impl<'a> SyncProtocol<'a, TestTopic> for SlowProtocol {
+        fn name(&self) -> &'static str {
+            static SLOW_PROTOCOL_NAME: &str = "slow_protocol";
+            SLOW_PROTOCOL_NAME
+        }
+
+        async fn initiate(
+            self: Arc<Self>,
+            topic_query: TestTopic,
+            tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+            rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+            mut app_tx: Box<
+                &'a mut (dyn Sink<FromSync<TestTopic>, Error = SyncError> + Send + Unpin),
+            >,
+        ) -> Result<(), SyncError> {
+            let mut sink = into_cbor_sink(tx);
+            let mut stream = into_cbor_stream(rx);
+
+            sink.send(SlowProtocolMessage::TopicQuery(topic_query.clone()))
+                .await?;
+            app_tx.send(FromSync::HandshakeSuccess(topic_query)).await?;
+
+            while let Some(result) = stream.next().await {
+                let message: SlowProtocolMessage = result?;
+                match message {
+                    SlowProtocolMessage::TopicQuery(_) => panic!(),
+                    SlowProtocolMessage::Data(index) => {
+                        app_tx
+                            .send(FromSync::Data {
+                                header: vec![index],
+                                payload: None,
+                            })
+                            .await?;
+                    }
+                    SlowProtocolMessage::Done => break,
+                }
+            }
+
+            sink.flush().await?;
+            app_tx.flush().await?;
+
+            Ok(())
+        }
+
+        async fn accept(
+            self: Arc<Self>,
+            tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+            rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+            mut app_tx: Box<
+                &'a mut (dyn Sink<FromSync<TestTopic>, Error = SyncError> + Send + Unpin),
+            >,
+        ) -> Result<(), SyncError> {
+            let mut sink = into_cbor_sink(tx);
+            let mut stream = into_cbor_stream(rx);
+
+            if let Some(result) = stream.next().await {
+                if let SlowProtocolMessage::TopicQuery(topic_query) = result? {
+                    app_tx.send(FromSync::HandshakeSuccess(topic_query)).await?;
+                }
+            }
+
+            for index in 0..self.messages {
+                sleep(self.delay).await;
+                sink.send(SlowProtocolMessage::Data(index)).await?;
+            }
+            sink.send(SlowProtocolMessage::Done).await?;
+
+            sink.flush().await?;
+            app_tx.flush().await?;
+
+            Ok(())
+        }
+    }
+
     // The protocol message types.
     #[derive(Serialize, Deserialize)]
     enum Message {
@@ -1042,10 +1415,15 @@ pub(crate) mod tests {
     use std::collections::HashMap;
     use std::net::{Ipv4Addr, Ipv6Addr};
     use std::path::PathBuf;
+    use std::sync::Arc;
     use std::time::Duration;
 
+    use anyhow::Result;
     use async_trait::async_trait;
+    use futures_lite::future::Boxed as BoxedFuture;
+    use iroh::endpoint::Connecting;
     use iroh::{RelayNode, RelayUrl as IrohRelayUrl};
+    use iroh_gossip::net::GOSSIP_ALPN;
     use p2panda_core::{Body, Extensions, Hash, Header, PrivateKey, PublicKey};
     use p2panda_store::{MemoryStore, OperationStore};
     use p2panda_sync::log_sync::{LogSyncProtocol, TopicLogMap};
@@ -1058,11 +1436,14 @@ pub(crate) mod tests {
 
     use crate::addrs::{to_node_addr, DEFAULT_STUN_PORT};
     use crate::bytes::ToBytes;
-    use crate::config::Config;
+    use crate::config::{BackoffConfig, Config, GossipConfig};
     use crate::events::SystemEvent;
-    use crate::network::sync_protocols::PingPongProtocol;
-    use crate::sync::SyncConfiguration;
-    use crate::{to_public_key, NetworkBuilder, NodeAddress, RelayMode, RelayUrl, TopicId};
+    use crate::network::sync_protocols::{FaultyProtocol, PingPongProtocol, SlowProtocol};
+    use crate::protocols::{ProtocolError, ProtocolHandler};
+    use crate::sync::{SyncConfiguration, SYNC_CONNECTION_ALPN};
+    use crate::{
+        to_public_key, ConnectionPath, NetworkBuilder, NodeAddress, RelayMode, RelayUrl, TopicId,
+    };
 
     use super::{FromNetwork, Network, ToNetwork};
 
@@ -1134,6 +1515,15 @@ pub(crate) mod tests {
                 relay_url: None,
             }],
             relay: Some(relay_address.clone()),
+            reconnect_backoff: BackoffConfig {
+                initial_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(30),
+                multiplier: 1.5,
+            },
+            upload_rate_limit: Some(1024),
+            download_rate_limit: None,
+            gossip_fanout: 8,
+            gossip_message_ttl: Duration::from_secs(60),
         };
 
         let builder = NetworkBuilder::<TestTopic>::from_config(config);
@@ -1155,6 +1545,94 @@ pub(crate) mod tests {
             quic: None,
         };
         assert_eq!(builder.relay_mode, RelayMode::Custom(relay_node));
+        assert_eq!(builder.reconnect_backoff.max_delay, Duration::from_secs(30));
+        assert_eq!(builder.upload_rate_limit, Some(1024));
+        assert!(builder.download_rate_limit.is_none());
+        let gossip_config = builder.gossip_config.unwrap();
+        assert_eq!(gossip_config.fanout, 8);
+        assert_eq!(gossip_config.message_ttl, Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn gossip_config() {
+        let node = NetworkBuilder::<TestTopic>::new([1; 32])
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(node.gossip_config(), &GossipConfig::default());
+        node.shutdown().await.unwrap();
+
+        let node = NetworkBuilder::<TestTopic>::new([1; 32])
+            .gossip(GossipConfig {
+                max_message_size: 1024,
+                fanout: 2,
+                message_ttl: Duration::from_secs(5),
+            })
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(node.gossip_config().fanout, 2);
+        assert_eq!(node.gossip_config().message_ttl, Duration::from_secs(5));
+        assert_eq!(node.inner.gossip.max_message_size(), 1024);
+        node.shutdown().await.unwrap();
+    }
+
+    #[derive(Debug)]
+    struct DummyHandler;
+
+    impl ProtocolHandler for DummyHandler {
+        fn accept(self: Arc<Self>, _conn: Connecting) -> BoxedFuture<Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn reserved_alpns() {
+        let result = NetworkBuilder::<TestTopic>::new([1; 32])
+            .protocol(GOSSIP_ALPN, DummyHandler)
+            .build()
+            .await;
+        let err = result.expect_err("gossip ALPN is reserved");
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::ReservedAlpn(
+                String::from_utf8_lossy(GOSSIP_ALPN).to_string()
+            ))
+        );
+
+        let result = NetworkBuilder::<TestTopic>::new([1; 32])
+            .protocol(SYNC_CONNECTION_ALPN, DummyHandler)
+            .build()
+            .await;
+        let err = result.expect_err("sync ALPN is reserved");
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::ReservedAlpn(
+                String::from_utf8_lossy(SYNC_CONNECTION_ALPN).to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn node_address() {
+        let private_key = PrivateKey::new();
+        let node = NetworkBuilder::<TestTopic>::new([1; 32])
+            .private_key(private_key.clone())
+            .bind_ip_v4(Ipv4Addr::LOCALHOST)
+            .bind_port_v4(2222)
+            .build()
+            .await
+            .unwrap();
+
+        let node_address = node.node_address();
+        assert_eq!(node_address.public_key, private_key.public_key());
+        assert!(node_address.relay_url.is_none());
+
+        let (bound_v4, _) = node.endpoint().bound_sockets();
+        assert_eq!(bound_v4.port(), 2222);
+        assert!(node_address.direct_addresses.contains(&bound_v4));
+
+        node.shutdown().await.unwrap();
     }
 
     #[tokio::test]
@@ -1202,53 +1680,401 @@ pub(crate) mod tests {
     }
 
     #[tokio::test]
-    async fn ping_pong() {
+    async fn connection_stats() {
         setup_logging();
 
         let network_id = [1; 32];
-        let topic = TestTopic::new("ping_pong");
-        let ping_pong = PingPongProtocol {};
-        let sync_config = SyncConfiguration::new(ping_pong);
+        let topic = TestTopic::new("stats");
 
-        let node_1 = NetworkBuilder::new(network_id)
-            .sync(sync_config.clone())
-            .build()
-            .await
-            .unwrap();
-        let node_2 = NetworkBuilder::new(network_id)
-            .sync(sync_config)
-            .build()
-            .await
-            .unwrap();
+        let node_1 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_2 = NetworkBuilder::new(network_id).build().await.unwrap();
 
         let node_1_addr = node_1.endpoint().node_addr().await.unwrap();
         let node_2_addr = node_2.endpoint().node_addr().await.unwrap();
 
-        node_1.add_peer(to_node_addr(node_1_addr)).await.unwrap();
-        node_2.add_peer(to_node_addr(node_2_addr)).await.unwrap();
-
-        // Subscribe to the same topic from both nodes which should kick off sync
-        let topic_clone = topic.clone();
-        let handle1 = tokio::spawn(async move {
-            let (_tx, _rx, _ready) = node_1.subscribe(topic_clone).await.unwrap();
-            tokio::time::sleep(Duration::from_secs(2)).await;
-            node_1.shutdown().await.unwrap();
-        });
-        let handle2 = tokio::spawn(async move {
-            let (_tx, _rx, _ready) = node_2.subscribe(topic).await.unwrap();
-            tokio::time::sleep(Duration::from_secs(2)).await;
-            node_2.shutdown().await.unwrap();
-        });
+        node_1.add_peer(to_node_addr(node_2_addr)).await.unwrap();
+        node_2.add_peer(to_node_addr(node_1_addr)).await.unwrap();
 
-        let (result1, result2) = tokio::join!(handle1, handle2);
-        assert!(result1.is_ok());
-        assert!(result2.is_ok());
-    }
+        let (tx_1, _rx_1, ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (_tx_2, mut rx_2, ready_2) = node_2.subscribe(topic.clone()).await.unwrap();
 
-    type Logs<T> = HashMap<PublicKey, Vec<T>>;
+        assert!(ready_2.await.is_ok());
+        assert!(ready_1.await.is_ok());
 
-    #[derive(Clone, Debug)]
-    struct LogIdTopicMap<T>(HashMap<T, Logs<u64>>);
+        // Exchange a gossip message between both nodes
+        tx_1.send(ToNetwork::Message {
+            bytes: "Hello, Node".to_bytes(),
+        })
+        .await
+        .unwrap();
+        assert!(rx_2.recv().await.is_some());
+
+        // Node 1 accounted the broadcast message as sent to node 2
+        let stats_1 = node_1.connection_stats().await;
+        let peer_2 = stats_1
+            .iter()
+            .find(|stats| stats.node_addr.public_key == node_2.node_id())
+            .expect("node 2 is connected");
+        assert!(peer_2.bytes_sent > 0);
+        assert!(peer_2.topic_ids.contains(&topic.id()));
+        assert_ne!(peer_2.path, ConnectionPath::None);
+
+        // Node 2 accounted the message as received from node 1
+        let stats_2 = node_2.connection_stats().await;
+        let peer_1 = stats_2
+            .iter()
+            .find(|stats| stats.node_addr.public_key == node_1.node_id())
+            .expect("node 1 is connected");
+        assert!(peer_1.bytes_received > 0);
+        assert!(peer_1.topic_ids.contains(&topic.id()));
+
+        node_1.shutdown().await.unwrap();
+        node_2.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn manual_dialing() {
+        setup_logging();
+
+        let network_id = [1; 32];
+        let topic = TestTopic::new("dial");
+
+        // No discovery services (like mDNS) are registered, so both nodes can only learn about
+        // each other through manually adding the address.
+        let node_1 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_2 = NetworkBuilder::new(network_id).build().await.unwrap();
+
+        let (tx_1, _rx_1, ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (_tx_2, mut rx_2, ready_2) = node_2.subscribe(topic).await.unwrap();
+
+        // Only node 1 dials node 2, node 2 learns about node 1 through the inbound connection.
+        let node_2_addr = node_2.endpoint().node_addr().await.unwrap();
+        node_1.add_peer(to_node_addr(node_2_addr)).await.unwrap();
+
+        assert!(ready_1.await.is_ok());
+        assert!(ready_2.await.is_ok());
+
+        tx_1.send(ToNetwork::Message {
+            bytes: "Hello, Node".to_bytes(),
+        })
+        .await
+        .unwrap();
+
+        let rx_2_msg = rx_2.recv().await.unwrap();
+        assert_eq!(
+            rx_2_msg,
+            FromNetwork::GossipMessage {
+                bytes: "Hello, Node".to_bytes(),
+                delivered_from: node_1.node_id(),
+            }
+        );
+
+        let known_peers = node_1.known_peers().await.unwrap();
+        assert!(known_peers
+            .iter()
+            .any(|addr| addr.public_key == node_2.node_id()));
+
+        node_1.shutdown().await.unwrap();
+        node_2.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_flushes_messages() {
+        setup_logging();
+
+        const NUM_MESSAGES: usize = 50;
+
+        let network_id = [1; 32];
+        let topic = TestTopic::new("flush");
+
+        let node_1 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_2 = NetworkBuilder::new(network_id).build().await.unwrap();
+
+        let node_1_addr = node_1.endpoint().node_addr().await.unwrap();
+        let node_2_addr = node_2.endpoint().node_addr().await.unwrap();
+
+        node_1.add_peer(to_node_addr(node_2_addr)).await.unwrap();
+        node_2.add_peer(to_node_addr(node_1_addr)).await.unwrap();
+
+        let (tx_1, _rx_1, ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (_tx_2, mut rx_2, ready_2) = node_2.subscribe(topic.clone()).await.unwrap();
+
+        assert!(ready_2.await.is_ok());
+        assert!(ready_1.await.is_ok());
+
+        // Publish messages and immediately unsubscribe afterwards
+        for i in 0..NUM_MESSAGES {
+            tx_1.send(ToNetwork::Message {
+                bytes: format!("message {i}").to_bytes(),
+            })
+            .await
+            .unwrap();
+        }
+        node_1.unsubscribe(topic).await.unwrap();
+
+        // The stream is closed for new messages
+        assert!(tx_1
+            .send(ToNetwork::Message {
+                bytes: "too late".to_bytes(),
+            })
+            .await
+            .is_err());
+
+        // All messages arrived at the other node
+        let mut received = Vec::new();
+        while received.len() < NUM_MESSAGES {
+            match rx_2.recv().await.unwrap() {
+                FromNetwork::GossipMessage { bytes, .. } => received.push(bytes),
+                FromNetwork::SyncMessage { .. } => panic!("unexpected sync message"),
+            }
+        }
+        for i in 0..NUM_MESSAGES {
+            assert!(received.contains(&format!("message {i}").to_bytes()));
+        }
+
+        node_1.shutdown().await.unwrap();
+        node_2.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deny_peer() {
+        setup_logging();
+
+        let network_id = [1; 32];
+        let topic = TestTopic::new("moderation");
+
+        let node_1 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_2 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_3 = NetworkBuilder::new(network_id).build().await.unwrap();
+
+        // Node 1 denies node 2 before any connection was established.
+        node_1.deny_peer(node_2.node_id()).await.unwrap();
+        assert_eq!(node_1.denied_peers().await.unwrap(), vec![node_2.node_id()]);
+
+        let (_tx_1, mut rx_1, ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (_tx_2, _rx_2, ready_2) = node_2.subscribe(topic.clone()).await.unwrap();
+        let (tx_3, _rx_3, ready_3) = node_3.subscribe(topic).await.unwrap();
+
+        // Node 2 and node 3 both only know about node 1.
+        let node_1_addr = node_1.endpoint().node_addr().await.unwrap();
+        node_2
+            .add_peer(to_node_addr(node_1_addr.clone()))
+            .await
+            .unwrap();
+        node_3.add_peer(to_node_addr(node_1_addr)).await.unwrap();
+
+        // The allowed peer successfully connects and exchanges messages.
+        assert!(ready_3.await.is_ok());
+        assert!(ready_1.await.is_ok());
+
+        tx_3.send(ToNetwork::Message {
+            bytes: "Hello, Node".to_bytes(),
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            rx_1.recv().await.unwrap(),
+            FromNetwork::GossipMessage {
+                bytes: "Hello, Node".to_bytes(),
+                delivered_from: node_3.node_id(),
+            }
+        );
+
+        // Connection attempts of the denied peer are rejected, so it never manages to join.
+        assert!(tokio::time::timeout(Duration::from_secs(3), ready_2)
+            .await
+            .is_err());
+        assert!(!node_1
+            .known_peers()
+            .await
+            .unwrap()
+            .iter()
+            .any(|addr| addr.public_key == node_2.node_id()));
+
+        // Allowing the peer again removes it from the deny list.
+        node_1.allow_peer(node_2.node_id()).await.unwrap();
+        assert!(node_1.denied_peers().await.unwrap().is_empty());
+
+        node_1.shutdown().await.unwrap();
+        node_2.shutdown().await.unwrap();
+        node_3.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn denial_survives_reconnection() {
+        setup_logging();
+
+        let network_id = [1; 32];
+        let topic = TestTopic::new("moderation");
+
+        let node_1 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_2 = NetworkBuilder::new(network_id).build().await.unwrap();
+
+        let (tx_1, mut rx_1, ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (tx_2, mut rx_2, ready_2) = node_2.subscribe(topic).await.unwrap();
+
+        let node_1_addr = to_node_addr(node_1.endpoint().node_addr().await.unwrap());
+        node_2.add_peer(node_1_addr.clone()).await.unwrap();
+
+        assert!(ready_1.await.is_ok());
+        assert!(ready_2.await.is_ok());
+
+        tx_1.send(ToNetwork::Message {
+            bytes: "Hello, Node".to_bytes(),
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            rx_2.recv().await.unwrap(),
+            FromNetwork::GossipMessage {
+                bytes: "Hello, Node".to_bytes(),
+                delivered_from: node_1.node_id(),
+            }
+        );
+
+        // Denying the connected peer drops our connection to it.
+        node_1.deny_peer(node_2.node_id()).await.unwrap();
+
+        // The denied peer learns about us again and tries to reconnect.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        node_2.add_peer(node_1_addr).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        assert!(!node_1
+            .connection_stats()
+            .await
+            .iter()
+            .any(|stats| stats.node_addr.public_key == node_2.node_id()
+                && !stats.topic_ids.is_empty()));
+
+        // Messages are neither delivered to nor accepted from the denied peer.
+        tx_1.send(ToNetwork::Message {
+            bytes: "Goodbye, Node".to_bytes(),
+        })
+        .await
+        .unwrap();
+        tx_2.send(ToNetwork::Message {
+            bytes: "Let me in".to_bytes(),
+        })
+        .await
+        .unwrap();
+
+        assert!(tokio::time::timeout(Duration::from_secs(2), rx_2.recv())
+            .await
+            .is_err());
+        assert!(tokio::time::timeout(Duration::from_secs(2), rx_1.recv())
+            .await
+            .is_err());
+
+        node_1.shutdown().await.unwrap();
+        node_2.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn ping_pong() {
+        setup_logging();
+
+        let network_id = [1; 32];
+        let topic = TestTopic::new("ping_pong");
+        let ping_pong = PingPongProtocol {};
+        let sync_config = SyncConfiguration::new(ping_pong);
+
+        let node_1 = NetworkBuilder::new(network_id)
+            .sync(sync_config.clone())
+            .build()
+            .await
+            .unwrap();
+        let node_2 = NetworkBuilder::new(network_id)
+            .sync(sync_config)
+            .build()
+            .await
+            .unwrap();
+
+        let node_1_addr = node_1.endpoint().node_addr().await.unwrap();
+        let node_2_addr = node_2.endpoint().node_addr().await.unwrap();
+
+        node_1.add_peer(to_node_addr(node_1_addr)).await.unwrap();
+        node_2.add_peer(to_node_addr(node_2_addr)).await.unwrap();
+
+        // Subscribe to the same topic from both nodes which should kick off sync
+        let topic_clone = topic.clone();
+        let handle1 = tokio::spawn(async move {
+            let (_tx, _rx, _ready) = node_1.subscribe(topic_clone).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            node_1.shutdown().await.unwrap();
+        });
+        let handle2 = tokio::spawn(async move {
+            let (_tx, _rx, _ready) = node_2.subscribe(topic).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            node_2.shutdown().await.unwrap();
+        });
+
+        let (result1, result2) = tokio::join!(handle1, handle2);
+        assert!(result1.is_ok());
+        assert!(result2.is_ok());
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_sync_sessions() {
+        setup_logging();
+
+        let network_id = [1; 32];
+        let topic = TestTopic::new("shutdown");
+        let sync_config = SyncConfiguration::new(SlowProtocol {
+            messages: 10,
+            delay: Duration::from_millis(200),
+        });
+
+        let node_1 = NetworkBuilder::new(network_id)
+            .sync(sync_config.clone())
+            .build()
+            .await
+            .unwrap();
+        let node_2 = NetworkBuilder::new(network_id)
+            .sync(sync_config)
+            .build()
+            .await
+            .unwrap();
+
+        let node_1_addr = node_1.endpoint().node_addr().await.unwrap();
+        let node_2_addr = node_2.endpoint().node_addr().await.unwrap();
+        node_1.add_peer(to_node_addr(node_2_addr)).await.unwrap();
+        node_2.add_peer(to_node_addr(node_1_addr)).await.unwrap();
+
+        let (_tx_1, mut rx_1, _ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (_tx_2, _rx_2, _ready_2) = node_2.subscribe(topic).await.unwrap();
+
+        // Wait until the sync session is underway.
+        let first_message = tokio::time::timeout(Duration::from_secs(30), rx_1.recv())
+            .await
+            .expect("sync message within timeout")
+            .unwrap();
+
+        node_1.shutdown().await.unwrap();
+
+        // All messages of the running session were delivered before the shutdown completed.
+        let mut received = vec![first_message];
+        while let Ok(message) = rx_1.try_recv() {
+            received.push(message);
+        }
+        let indices: Vec<u8> = received
+            .into_iter()
+            .map(|message| match message {
+                FromNetwork::SyncMessage { header, .. } => header[0],
+                message => panic!("unexpected message {message:?}"),
+            })
+            .collect();
+        assert_eq!(indices, (0..10).collect::<Vec<u8>>());
+
+        node_2.shutdown().await.unwrap();
+    }
+
+    type Logs<T> = HashMap<PublicKey, Vec<T>>;
+
+    #[derive(Clone, Debug)]
+    struct LogIdTopicMap<T>(HashMap<T, Logs<u64>>);
 
     impl<T> LogIdTopicMap<T>
     where
@@ -1628,6 +2454,7 @@ pub(crate) mod tests {
             SystemEvent::SyncDone {
                 topic: chat_topic.clone(),
                 peer: to_public_key(node_2_id),
+                received: 1,
             },
             // Start sync (part two) with node 2.
             SystemEvent::SyncStarted {
@@ -1643,6 +2470,7 @@ pub(crate) mod tests {
             SystemEvent::SyncDone {
                 topic: chat_topic.clone(),
                 peer: to_public_key(node_2_id),
+                received: 1,
             },
             // Gain a direct neighbor in the network-wide gossip overlay by connecting to node 3.
             SystemEvent::GossipNeighborUp {
@@ -1664,19 +2492,185 @@ pub(crate) mod tests {
             },
         ];
 
-        // Receive events on the node one receiver.
-        let mut received_events = Vec::new();
-        while let Ok(event) = event_rx_1.recv().await {
-            received_events.push(event);
-            if received_events.len() == 11 {
-                break;
+        // Receive events on the node one receiver until all expected events arrived. Sync
+        // sessions, gossip overlays and the periodic connection path checks run concurrently, so
+        // events can arrive in any order and are interleaved with other events.
+        let mut missing_events = expected_events;
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while !missing_events.is_empty() {
+                let event = event_rx_1.recv().await.unwrap();
+                if let Some(index) = missing_events
+                    .iter()
+                    .position(|expected| expected == &event)
+                {
+                    missing_events.remove(index);
+                }
             }
-        }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("missing events: {missing_events:?}"));
+
+        node_1.shutdown().await.unwrap();
+        node_2.shutdown().await.unwrap();
+    }
+
+    /// Run a sync session between two nodes and return the first sync outcome event of the first
+    /// node.
+    async fn first_sync_outcome(
+        sync_config: SyncConfiguration<TestTopic>,
+        topic: TestTopic,
+    ) -> SystemEvent<TestTopic> {
+        let network_id = [1; 32];
+
+        let node_1 = NetworkBuilder::new(network_id)
+            .sync(sync_config.clone())
+            .build()
+            .await
+            .unwrap();
+        let node_2 = NetworkBuilder::new(network_id)
+            .sync(sync_config)
+            .build()
+            .await
+            .unwrap();
+
+        let node_1_addr = node_1.endpoint().node_addr().await.unwrap();
+        let node_2_addr = node_2.endpoint().node_addr().await.unwrap();
+        node_1.add_peer(to_node_addr(node_2_addr)).await.unwrap();
+        node_2.add_peer(to_node_addr(node_1_addr)).await.unwrap();
 
-        // Ensure the correct events are received.
-        assert_eq!(received_events, expected_events);
+        let mut event_rx = node_1.events().await.unwrap();
+        let (_tx_1, _rx_1, _ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (_tx_2, _rx_2, _ready_2) = node_2.subscribe(topic).await.unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                match event_rx.recv().await.unwrap() {
+                    event @ (SystemEvent::SyncDone { .. } | SystemEvent::SyncFailed { .. }) => {
+                        break event
+                    }
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .expect("sync outcome within timeout");
 
         node_1.shutdown().await.unwrap();
         node_2.shutdown().await.unwrap();
+
+        event
+    }
+
+    #[tokio::test]
+    async fn sync_outcome_events() {
+        setup_logging();
+
+        let node_event = first_sync_outcome(
+            SyncConfiguration::new(PingPongProtocol {}),
+            TestTopic::new("chat"),
+        )
+        .await;
+        let SystemEvent::SyncDone {
+            topic, received, ..
+        } = node_event
+        else {
+            panic!("expected sync done event, got {node_event:?}");
+        };
+        assert_eq!(topic, TestTopic::new("chat"));
+        assert_eq!(received, 1);
+
+        let node_event = first_sync_outcome(
+            SyncConfiguration::new(FaultyProtocol {}),
+            TestTopic::new("chat"),
+        )
+        .await;
+        let SystemEvent::SyncFailed { topic, error, .. } = node_event else {
+            panic!("expected sync failed event, got {node_event:?}");
+        };
+        // The accepting side might fail before it learned the topic from the handshake.
+        if let Some(topic) = topic {
+            assert_eq!(topic, TestTopic::new("chat"));
+        }
+        assert!(error.contains("faulty protocol failed"));
+    }
+
+    #[tokio::test]
+    async fn sync_protocol_per_topic_class() {
+        setup_logging();
+
+        let sync_config = SyncConfiguration::new(PingPongProtocol {})
+            .class_protocol("faulty", FaultyProtocol {})
+            .classifier(|topic: &TestTopic| (topic.0 == "faulty").then_some("faulty"));
+
+        // Topics without a class are synced with the default protocol.
+        let node_event = first_sync_outcome(sync_config.clone(), TestTopic::new("chat")).await;
+        let SystemEvent::SyncDone { received, .. } = node_event else {
+            panic!("expected sync done event, got {node_event:?}");
+        };
+        assert_eq!(received, 1);
+
+        // Topics of the "faulty" class are synced with the protocol registered for it.
+        let node_event = first_sync_outcome(sync_config, TestTopic::new("faulty")).await;
+        let SystemEvent::SyncFailed { error, .. } = node_event else {
+            panic!("expected sync failed event, got {node_event:?}");
+        };
+        assert!(error.contains("faulty protocol failed"));
+    }
+
+    #[tokio::test]
+    async fn peer_connection_events() {
+        setup_logging();
+
+        let network_id = [1; 32];
+        let topic = TestTopic::new("chat");
+
+        let node_1 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_2 = NetworkBuilder::new(network_id).build().await.unwrap();
+        let node_2_id = to_public_key(node_2.endpoint().node_id());
+
+        let node_2_addr = node_2.endpoint().node_addr().await.unwrap();
+        node_1.add_peer(to_node_addr(node_2_addr)).await.unwrap();
+
+        let mut event_rx = node_1.events().await.unwrap();
+        let (_tx_1, _rx_1, ready_1) = node_1.subscribe(topic.clone()).await.unwrap();
+        let (_tx_2, _rx_2, _ready_2) = node_2.subscribe(topic).await.unwrap();
+        assert!(ready_1.await.is_ok());
+
+        let event = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let event @ SystemEvent::PeerConnected { .. } = event_rx.recv().await.unwrap() {
+                    break event;
+                }
+            }
+        })
+        .await
+        .expect("connect event within timeout");
+        assert_eq!(
+            event,
+            SystemEvent::PeerConnected {
+                peer: node_2_id,
+                direct: true,
+            }
+        );
+
+        // Node 2 goes away.
+        node_2.shutdown().await.unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                if let event @ SystemEvent::PeerDisconnected { .. } = event_rx.recv().await.unwrap()
+                {
+                    break event;
+                }
+            }
+        })
+        .await
+        .expect("disconnect event within timeout");
+        let SystemEvent::PeerDisconnected { peer, .. } = event else {
+            unreachable!();
+        };
+        assert_eq!(peer, node_2_id);
+
+        node_1.shutdown().await.unwrap();
     }
 }
@@ -9,6 +9,7 @@ use anyhow::Result;
 use futures_lite::future::Boxed as BoxedFuture;
 use futures_util::future::join_all;
 use iroh::endpoint::Connecting;
+use thiserror::Error;
 use tracing::debug;
 
 /// Interface to accept incoming connections for custom protocol implementations.
@@ -40,8 +41,20 @@ impl<T: Send + Sync + 'static> IntoArcAny for T {
     }
 }
 
+/// Errors which can occur when registering protocol handlers on the node.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The ALPN is already used by one of the core protocols of the node (gossip or sync).
+    #[error("ALPN \"{0}\" is reserved for a core protocol of the node")]
+    ReservedAlpn(String),
+
+    /// More than one protocol handler was registered for the same ALPN.
+    #[error("ALPN \"{0}\" is registered by more than one protocol handler")]
+    DuplicateAlpn(String),
+}
+
 #[derive(Debug, Clone, Default)]
-pub(super) struct ProtocolMap(BTreeMap<&'static [u8], Arc<dyn ProtocolHandler>>);
+pub(super) struct ProtocolMap(BTreeMap<Vec<u8>, Arc<dyn ProtocolHandler>>);
 
 impl ProtocolMap {
     /// Returns the registered protocol handler for an ALPN as a [`Arc<dyn ProtocolHandler>`].
@@ -50,13 +63,21 @@ impl ProtocolMap {
     }
 
     /// Inserts a protocol handler.
-    pub(super) fn insert(&mut self, alpn: &'static [u8], handler: Arc<dyn ProtocolHandler>) {
-        self.0.insert(alpn, handler);
+    ///
+    /// Returns `true` if a handler was already registered for this ALPN, in this case it gets
+    /// replaced.
+    pub(super) fn insert(&mut self, alpn: &[u8], handler: Arc<dyn ProtocolHandler>) -> bool {
+        self.0.insert(alpn.to_vec(), handler).is_some()
+    }
+
+    /// Returns `true` if a protocol handler is registered for this ALPN.
+    pub(super) fn contains(&self, alpn: &[u8]) -> bool {
+        self.0.contains_key(alpn)
     }
 
     /// Returns an iterator of all registered ALPN protocol identifiers.
     pub(super) fn alpns(&self) -> Vec<Vec<u8>> {
-        self.0.keys().map(|alpn| alpn.to_vec()).collect::<Vec<_>>()
+        self.0.keys().cloned().collect::<Vec<_>>()
     }
 
     /// Shuts down all protocol handlers.
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Connection statistics API.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use iroh::endpoint::ConnectionType;
+
+use crate::addrs::to_relay_url;
+use crate::{NodeAddress, RelayUrl};
+
+/// Network path currently used to reach a remote peer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionPath {
+    /// Direct UDP connection to the given socket address.
+    Direct(SocketAddr),
+
+    /// Connection via the given relay server.
+    Relay(RelayUrl),
+
+    /// Both a direct path and a relay are in use, usually while hole-punching is in progress.
+    Mixed(SocketAddr, RelayUrl),
+
+    /// No path is currently known.
+    None,
+}
+
+impl ConnectionPath {
+    /// Returns `true` if the peer can be reached without a relay.
+    pub fn is_direct(&self) -> bool {
+        matches!(
+            self,
+            ConnectionPath::Direct(_) | ConnectionPath::Mixed(_, _)
+        )
+    }
+
+    /// Returns `true` if traffic to the peer is routed through a relay server.
+    pub fn is_relayed(&self) -> bool {
+        matches!(self, ConnectionPath::Relay(_) | ConnectionPath::Mixed(_, _))
+    }
+}
+
+impl From<ConnectionType> for ConnectionPath {
+    fn from(value: ConnectionType) -> Self {
+        match value {
+            ConnectionType::Direct(addr) => ConnectionPath::Direct(addr),
+            ConnectionType::Relay(url) => ConnectionPath::Relay(to_relay_url(url)),
+            ConnectionType::Mixed(addr, url) => ConnectionPath::Mixed(addr, to_relay_url(url)),
+            ConnectionType::None => ConnectionPath::None,
+        }
+    }
+}
+
+/// Runtime statistics of the connection to a remote peer.
+///
+/// Byte counters reflect the data exchanged with this peer since we're connected to them: gossip
+/// messages and sync session streams in both directions. Transport overhead is not included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Address of the remote peer.
+    pub node_addr: NodeAddress,
+
+    /// Path used to reach the remote peer.
+    pub path: ConnectionPath,
+
+    /// Latest round-trip time estimate.
+    pub rtt: Option<Duration>,
+
+    /// Number of bytes sent to this peer.
+    pub bytes_sent: u64,
+
+    /// Number of bytes received from this peer.
+    pub bytes_received: u64,
+
+    /// Topic ids of gossip overlays in which this peer is currently our direct neighbor.
+    pub topic_ids: Vec<[u8; 32]>,
+}
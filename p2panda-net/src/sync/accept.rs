@@ -8,10 +8,12 @@ use p2panda_core::PublicKey;
 use p2panda_sync::{FromSync, SyncError, SyncProtocol, TopicQuery};
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinHandle;
+use tokio::time::Duration;
 use tokio_util::sync::PollSender;
 use tracing::{debug, error};
 
 use crate::engine::ToEngineActor;
+use crate::sync::handshake_deadline;
 
 /// Accept a sync protocol session over the provided bi-directional stream for the given peer and
 /// topic.
@@ -31,6 +33,9 @@ use crate::engine::ToEngineActor;
 /// behaviour from the remote peer), the acceptor will send an `SyncFailed` message instead of the
 /// `SyncDone`.
 ///
+/// If a `handshake_timeout` is given and the sync session did not complete the handshake phase
+/// within that time, the session is dropped with an "unexpected behaviour" error.
+///
 /// Errors can be roughly categorized by:
 ///
 /// 1. Critical system failures (bug in p2panda code or sync implementation, sync implementation
@@ -42,6 +47,7 @@ pub async fn accept_sync<T, S, R>(
     mut recv: &mut R,
     peer: PublicKey,
     sync_protocol: Arc<dyn for<'a> SyncProtocol<'a, T> + 'static>,
+    handshake_timeout: Option<Duration>,
     engine_actor_tx: mpsc::Sender<ToEngineActor<T>>,
 ) -> Result<(), SyncError>
 where
@@ -65,6 +71,9 @@ where
     // Set up a channel for sending over errors to the "glue" task which occurred during sync.
     let (sync_error_tx, mut sync_error_rx) = oneshot::channel::<SyncError>();
 
+    // Set up a channel for signalling that the handshake phase completed.
+    let (handshake_tx, handshake_rx) = oneshot::channel::<()>();
+
     // Spawn a "glue" task which represents the layer between the sync session and the engine.
     //
     // It picks up any messages from the sync session, making sure that the "Two-Phase Sync Flow"
@@ -78,6 +87,8 @@ where
     // Additionally, the task forwards any synced application data straight to the engine.
     let glue_task_handle: JoinHandle<Result<(), SyncError>> = tokio::spawn(async move {
         let mut topic = None;
+        let mut handshake_tx = Some(handshake_tx);
+        let mut received = 0;
 
         loop {
             tokio::select! {
@@ -88,6 +99,7 @@ where
                         .send(ToEngineActor::SyncFailed {
                             peer,
                             topic: topic.clone(),
+                            error: err.to_string(),
                         })
                         .await
                         .map_err(|err| {
@@ -124,6 +136,11 @@ where
 
                         topic = Some(handshake_topic.clone());
 
+                        // Stop the handshake timeout, if any.
+                        if let Some(handshake_tx) = handshake_tx.take() {
+                            let _ = handshake_tx.send(());
+                        }
+
                         // Inform the engine that we are expecting sync messages from the peer on
                         // this topic.
                         engine_actor_tx
@@ -187,6 +204,7 @@ where
                                 format!("engine_actor_tx failed sending sync message: {err}")
                             )
                         })?;
+                    received += 1;
                 },
             }
         }
@@ -198,7 +216,11 @@ where
         };
 
         engine_actor_tx
-            .send(ToEngineActor::SyncDone { peer, topic })
+            .send(ToEngineActor::SyncDone {
+                peer,
+                topic,
+                received,
+            })
             .await
             .map_err(|err| {
                 SyncError::Critical(format!("engine_actor_tx failed sending sync done: {err}"))
@@ -207,14 +229,18 @@ where
         Ok(())
     });
 
-    // Run the "accepting peer" side of the sync protocol.
-    let result = sync_protocol
-        .accept(
+    // Run the "accepting peer" side of the sync protocol, dropping the session if the handshake
+    // phase didn't complete in time.
+    let result = tokio::select! {
+        result = sync_protocol.accept(
             Box::new(&mut send),
             Box::new(&mut recv),
             Box::new(&mut sink),
-        )
-        .await;
+        ) => result,
+        _ = handshake_deadline(handshake_timeout, handshake_rx) => {
+            Err(SyncError::UnexpectedBehaviour("handshake timeout".into()))
+        }
+    };
 
     // Drop the tx, so the rx in the glue task receives the closing event.
     drop(sink);
@@ -1,24 +1,80 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::collections::BTreeMap;
+use std::fmt;
 use std::sync::Arc;
 
-use tokio::time::Duration;
+use rand::Rng;
+use tokio::time::{Duration, Instant};
 
 use p2panda_sync::{SyncProtocol, TopicQuery};
 
+use crate::sync::handler::sync_connection_alpn;
+use crate::sync::SYNC_CONNECTION_ALPN;
+
 const MAX_CONCURRENT_SYNC_SESSIONS: usize = 128;
 const MAX_RETRY_ATTEMPTS: u8 = 5;
 const RESYNC_INTERVAL: Duration = Duration::from_secs(60);
 const RESYNC_POLL_INTERVAL: Duration = Duration::from_secs(1);
 const SYNC_QUEUE_SEND_TIMEOUT: Duration = Duration::from_millis(100);
 
+/// Schedule of resync attempts for a single peer-topic combination.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResyncSchedule {
+    /// Resync after a fixed interval.
+    Fixed(Duration),
+
+    /// Resync after geometrically growing intervals, starting with `initial` and multiplied by
+    /// `multiplier` after every resync, bounded by `max`.
+    Exponential {
+        initial: Duration,
+        max: Duration,
+        multiplier: f64,
+    },
+
+    /// Resync after the given interval, randomly shortened or extended by up to `jitter`.
+    ///
+    /// This spreads out the resync attempts of many peers which all synced at the same time, for
+    /// example after a network partition healed.
+    Jittered {
+        interval: Duration,
+        jitter: Duration,
+    },
+}
+
+impl ResyncSchedule {
+    /// Returns the duration to wait after a completed sync session before resyncing, given the
+    /// number of resyncs which already happened for that peer-topic combination.
+    pub(crate) fn delay(&self, resyncs: u32) -> Duration {
+        match self {
+            ResyncSchedule::Fixed(interval) => *interval,
+            ResyncSchedule::Exponential {
+                initial,
+                max,
+                multiplier,
+            } => {
+                let factor = multiplier
+                    .max(1.0)
+                    .powi(resyncs.min(i32::MAX as u32) as i32);
+                Duration::try_from_secs_f64(initial.as_secs_f64() * factor)
+                    .unwrap_or(*max)
+                    .min(*max)
+            }
+            ResyncSchedule::Jittered { interval, jitter } => {
+                let offset = jitter.mul_f64(rand::thread_rng().gen_range(0.0..=2.0));
+                interval.saturating_sub(*jitter) + offset
+            }
+        }
+    }
+}
+
 /// Configuration parameters for resync behaviour.
 #[derive(Clone, Debug)]
 pub struct ResyncConfiguration {
-    /// Minimum interval between resync attempts for a single peer-topic combination.
+    /// Schedule of resync attempts for a single peer-topic combination.
     ///
-    /// Default: 60 seconds.
-    pub(crate) interval: Duration,
+    /// Default: Fixed interval of 60 seconds.
+    pub(crate) schedule: ResyncSchedule,
 
     /// Minimum interval between each poll of the resync queue.
     ///
@@ -34,8 +90,16 @@ impl ResyncConfiguration {
 
     /// Define the minimum number of seconds between resync attempts for a single peer-topic
     /// combination.
+    ///
+    /// This is a shorthand for a fixed resync schedule.
     pub fn interval(mut self, seconds: u64) -> Self {
-        self.interval = Duration::from_secs(seconds);
+        self.schedule = ResyncSchedule::Fixed(Duration::from_secs(seconds));
+        self
+    }
+
+    /// Define the schedule of resync attempts for a single peer-topic combination.
+    pub fn schedule(mut self, schedule: ResyncSchedule) -> Self {
+        self.schedule = schedule;
         self
     }
 
@@ -44,21 +108,40 @@ impl ResyncConfiguration {
         self.poll_interval = Duration::from_secs(seconds);
         self
     }
+
+    /// Returns the instant at which a peer-topic combination should be resynced after a sync
+    /// session completed, given the number of resyncs which already happened.
+    pub(crate) fn next_resync(&self, completed: Instant, resyncs: u32) -> Instant {
+        completed + self.schedule.delay(resyncs)
+    }
 }
 
 impl Default for ResyncConfiguration {
     fn default() -> Self {
         ResyncConfiguration {
-            interval: RESYNC_INTERVAL,
+            schedule: ResyncSchedule::Fixed(RESYNC_INTERVAL),
             poll_interval: RESYNC_POLL_INTERVAL,
         }
     }
 }
 
+/// Sync protocol shared between sync sessions.
+type SharedSyncProtocol<T> = Arc<dyn for<'a> SyncProtocol<'a, T> + 'static>;
+
+/// Function assigning topics to classes of sync protocols.
+type TopicClassifier<T> = Arc<dyn Fn(&T) -> Option<&'static str> + Send + Sync>;
+
 /// Configuration parameters for data synchronisation between peers.
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct SyncConfiguration<T> {
-    protocol: Arc<dyn for<'a> SyncProtocol<'a, T> + 'static>,
+    protocol: SharedSyncProtocol<T>,
+
+    /// Additional sync protocols for topics of a specific class.
+    class_protocols: BTreeMap<&'static str, SharedSyncProtocol<T>>,
+
+    /// Assigns topics to the classes of `class_protocols` (`None` represents using the default
+    /// protocol for all topics).
+    classifier: Option<TopicClassifier<T>>,
 
     /// Resync configuration (`None` represents no resync).
     pub(crate) resync: Option<ResyncConfiguration>,
@@ -77,6 +160,12 @@ pub struct SyncConfiguration<T> {
     ///
     /// Default: 100 milliseconds.
     pub(crate) sync_queue_send_timeout: Duration,
+
+    /// Maximum time to wait for a sync session to complete the handshake phase before dropping it
+    /// (`None` represents no timeout).
+    ///
+    /// Default: `None`.
+    pub(crate) handshake_timeout: Option<Duration>,
 }
 
 impl<T> SyncConfiguration<T>
@@ -87,10 +176,13 @@ where
     pub fn new(protocol: impl for<'a> SyncProtocol<'a, T> + 'static) -> Self {
         Self {
             protocol: Arc::new(protocol),
+            class_protocols: BTreeMap::new(),
+            classifier: None,
             max_concurrent_sync_sessions: MAX_CONCURRENT_SYNC_SESSIONS,
             max_retry_attempts: MAX_RETRY_ATTEMPTS,
             resync: None,
             sync_queue_send_timeout: SYNC_QUEUE_SEND_TIMEOUT,
+            handshake_timeout: None,
         }
     }
 
@@ -112,6 +204,53 @@ where
         self.protocol.clone()
     }
 
+    /// Register an additional sync protocol for topics of the given class.
+    ///
+    /// Topics are assigned to a class by the `classifier`, topics without a class or of a class
+    /// without a registered protocol are synced with the default protocol. Peers select the
+    /// protocol during connection establishment, so all of them need to register the same
+    /// protocols under the same classes.
+    pub fn class_protocol(
+        mut self,
+        class: &'static str,
+        protocol: impl for<'a> SyncProtocol<'a, T> + 'static,
+    ) -> Self {
+        self.class_protocols.insert(class, Arc::new(protocol));
+        self
+    }
+
+    /// Define the function assigning topics to the classes of sync protocols.
+    pub fn classifier(
+        mut self,
+        classifier: impl Fn(&T) -> Option<&'static str> + Send + Sync + 'static,
+    ) -> Self {
+        self.classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    /// Returns the sync protocol for the given topic, along with the ALPN it is accepted under.
+    pub(crate) fn protocol_for_topic(&self, topic: &T) -> (Vec<u8>, SharedSyncProtocol<T>) {
+        let class = self
+            .classifier
+            .as_ref()
+            .and_then(|classifier| classifier(topic));
+        match class.and_then(|class| Some((class, self.class_protocols.get(class)?))) {
+            Some((class, protocol)) => (sync_connection_alpn(class), protocol.clone()),
+            None => (SYNC_CONNECTION_ALPN.to_vec(), self.protocol.clone()),
+        }
+    }
+
+    /// Returns all sync protocols, along with the ALPN they are accepted under.
+    pub(crate) fn alpn_protocols(&self) -> Vec<(Vec<u8>, SharedSyncProtocol<T>)> {
+        let mut protocols = vec![(SYNC_CONNECTION_ALPN.to_vec(), self.protocol.clone())];
+        protocols.extend(
+            self.class_protocols
+                .iter()
+                .map(|(class, protocol)| (sync_connection_alpn(class), protocol.clone())),
+        );
+        protocols
+    }
+
     /// Provide the resync configuration for the sync scheduler.
     pub fn resync(mut self, config: ResyncConfiguration) -> Self {
         self.resync = Some(config);
@@ -129,4 +268,92 @@ where
         self.sync_queue_send_timeout = Duration::from_secs(seconds);
         self
     }
+
+    /// Define the maximum number of seconds to wait for a sync session to complete the handshake
+    /// phase.
+    ///
+    /// Sessions where the remote peer didn't send the topic query within this window are dropped,
+    /// protecting against slow or malicious peers holding connections open.
+    pub fn handshake_timeout(mut self, seconds: u64) -> Self {
+        self.handshake_timeout = Some(Duration::from_secs(seconds));
+        self
+    }
+}
+
+impl<T> fmt::Debug for SyncConfiguration<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncConfiguration")
+            .field("protocol", &self.protocol)
+            .field("class_protocols", &self.class_protocols)
+            .field("classifier", &self.classifier.is_some())
+            .field("resync", &self.resync)
+            .field(
+                "max_concurrent_sync_sessions",
+                &self.max_concurrent_sync_sessions,
+            )
+            .field("max_retry_attempts", &self.max_retry_attempts)
+            .field("sync_queue_send_timeout", &self.sync_queue_send_timeout)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use tokio::time::{Duration, Instant};
+
+    use super::{ResyncConfiguration, ResyncSchedule};
+
+    #[test]
+    fn jittered_schedule() {
+        let config = ResyncConfiguration::new().schedule(ResyncSchedule::Jittered {
+            interval: Duration::from_secs(60),
+            jitter: Duration::from_secs(10),
+        });
+
+        let completed = Instant::now();
+        let mut next_resyncs = HashSet::new();
+        for resyncs in 0..100 {
+            let next_resync = config.next_resync(completed, resyncs);
+            assert!(next_resync >= completed + Duration::from_secs(50));
+            assert!(next_resync <= completed + Duration::from_secs(70));
+            next_resyncs.insert(next_resync);
+        }
+
+        // Resyncs are spread out instead of all happening at the same time.
+        assert!(next_resyncs.len() > 1);
+    }
+
+    #[test]
+    fn exponential_schedule() {
+        let config = ResyncConfiguration::new().schedule(ResyncSchedule::Exponential {
+            initial: Duration::from_secs(10),
+            max: Duration::from_secs(60),
+            multiplier: 2.0,
+        });
+
+        let completed = Instant::now();
+        let delays: Vec<Duration> = (0..5)
+            .map(|resyncs| config.next_resync(completed, resyncs) - completed)
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_secs(10),
+                Duration::from_secs(20),
+                Duration::from_secs(40),
+                Duration::from_secs(60),
+                Duration::from_secs(60),
+            ]
+        );
+
+        // A fixed interval never changes.
+        let config = ResyncConfiguration::new().interval(30);
+        assert_eq!(
+            config.next_resync(completed, 7),
+            completed + Duration::from_secs(30)
+        );
+    }
 }
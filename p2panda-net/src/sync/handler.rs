@@ -7,18 +7,31 @@ use futures_lite::future::Boxed as BoxedFuture;
 use iroh::endpoint::{self, Connecting, Connection};
 use p2panda_sync::{SyncProtocol, TopicQuery};
 use tokio::sync::mpsc;
+use tokio::time::Duration;
+use tokio_util::task::TaskTracker;
 use tracing::{debug, debug_span};
 
-use crate::engine::ToEngineActor;
+use crate::bandwidth::{Bandwidth, Throttled};
+use crate::engine::{AddressBook, Metered, ToEngineActor, TrafficMeter};
 use crate::protocols::ProtocolHandler;
 use crate::{sync, to_public_key};
 
 pub const SYNC_CONNECTION_ALPN: &[u8] = b"/p2panda-net-sync/0";
 
+/// Returns the ALPN of the sync protocol registered for the given class of topics.
+pub(crate) fn sync_connection_alpn(class: &str) -> Vec<u8> {
+    [SYNC_CONNECTION_ALPN, b"/", class.as_bytes()].concat()
+}
+
 #[derive(Debug)]
 pub struct SyncConnection<T> {
     sync_protocol: Arc<dyn for<'a> SyncProtocol<'a, T> + 'static>,
+    handshake_timeout: Option<Duration>,
+    bandwidth: Bandwidth,
+    traffic: TrafficMeter,
+    address_book: AddressBook,
     engine_actor_tx: mpsc::Sender<ToEngineActor<T>>,
+    sync_sessions: TaskTracker,
 }
 
 impl<T> SyncConnection<T>
@@ -27,11 +40,21 @@ where
 {
     pub fn new(
         sync_protocol: Arc<dyn for<'a> SyncProtocol<'a, T> + 'static>,
+        handshake_timeout: Option<Duration>,
+        bandwidth: Bandwidth,
+        traffic: TrafficMeter,
+        address_book: AddressBook,
         engine_actor_tx: mpsc::Sender<ToEngineActor<T>>,
+        sync_sessions: TaskTracker,
     ) -> Self {
         Self {
             sync_protocol,
+            handshake_timeout,
+            bandwidth,
+            traffic,
+            address_book,
             engine_actor_tx,
+            sync_sessions,
         }
     }
 
@@ -44,6 +67,13 @@ where
         let _span = debug_span!("connection", connection_id, %remote_addr);
         debug!(parent: &_span, "handling inbound sync connection...");
 
+        if self.address_book.is_denied(&peer).await {
+            debug!(parent: &_span, "refusing sync connection from denied peer");
+            connection.close(0u32.into(), b"denied");
+            return Ok(());
+        }
+        self.address_book.close_on_deny(peer, connection.clone());
+
         let (mut send, mut recv) = connection.accept_bi().await?;
 
         let sync_protocol = self.sync_protocol.clone();
@@ -53,8 +83,23 @@ where
         //
         // Sync failure or successful completion is reported to the engine actor internally, so
         // there's no need for us to do that in the context of handling the connection.
-        let result =
-            sync::accept_sync(&mut send, &mut recv, peer, sync_protocol, engine_actor_tx).await;
+        let result = sync::accept_sync(
+            &mut Metered::new(
+                Throttled::new(&mut send, self.bandwidth.upload.clone()),
+                peer,
+                self.traffic.clone(),
+            ),
+            &mut Metered::new(
+                Throttled::new(&mut recv, self.bandwidth.download.clone()),
+                peer,
+                self.traffic.clone(),
+            ),
+            peer,
+            sync_protocol,
+            self.handshake_timeout,
+            engine_actor_tx,
+        )
+        .await;
 
         send.finish()?;
         send.stopped().await?;
@@ -76,6 +121,11 @@ where
     T: TopicQuery + 'static,
 {
     fn accept(self: Arc<Self>, connecting: Connecting) -> BoxedFuture<Result<()>> {
-        Box::pin(async move { self.handle_connection(connecting.await?).await })
+        // Sessions are tracked so that a shutdown can wait for them to complete.
+        let sync_sessions = self.sync_sessions.clone();
+        Box::pin(
+            sync_sessions
+                .track_future(async move { self.handle_connection(connecting.await?).await }),
+        )
     }
 }
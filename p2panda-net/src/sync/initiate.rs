@@ -6,12 +6,14 @@ use anyhow::Result;
 use futures_util::{AsyncRead, AsyncWrite, SinkExt};
 use p2panda_core::PublicKey;
 use p2panda_sync::{FromSync, SyncError, SyncProtocol, TopicQuery};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinHandle;
+use tokio::time::Duration;
 use tokio_util::sync::PollSender;
 use tracing::{debug, error, warn};
 
 use crate::engine::ToEngineActor;
+use crate::sync::handshake_deadline;
 
 /// Initiate a sync protocol session over the provided bi-directional stream for the given peer and
 /// topic.
@@ -32,6 +34,9 @@ use crate::engine::ToEngineActor;
 /// behaviour from the remote peer), the initiator is _not_ sending a `SyncDone` message. A
 /// `SyncFailed` message will be sent instead. This is handled in the sync actor.
 ///
+/// If a `handshake_timeout` is given and the sync session did not complete the handshake phase
+/// within that time, the session is dropped with an "unexpected behaviour" error.
+///
 /// Errors can be roughly categorized by:
 ///
 /// 1. Critical system failures (bug in p2panda code or sync implementation, sync implementation
@@ -44,6 +49,7 @@ pub async fn initiate_sync<T, S, R>(
     peer: PublicKey,
     topic: T,
     sync_protocol: Arc<dyn for<'a> SyncProtocol<'a, T> + 'static>,
+    handshake_timeout: Option<Duration>,
     engine_actor_tx: mpsc::Sender<ToEngineActor<T>>,
 ) -> Result<(), SyncError>
 where
@@ -70,6 +76,9 @@ where
     let (tx, mut rx) = mpsc::channel::<FromSync<T>>(128);
     let mut sink = PollSender::new(tx).sink_map_err(|e| SyncError::Critical(e.to_string()));
 
+    // Set up a channel for signalling that the handshake phase completed.
+    let (handshake_tx, handshake_rx) = oneshot::channel::<()>();
+
     // Spawn a "glue" task which represents the layer between the sync session and the engine.
     //
     // It picks up any messages from the sync session, making sure that the "Two-Phase Sync Flow"
@@ -81,12 +90,14 @@ where
     // the engine.
     //
     // Additionally, the task forwards any synced application data straight to the engine.
-    let glue_task_handle: JoinHandle<Result<(), SyncError>> = {
+    let glue_task_handle: JoinHandle<Result<usize, SyncError>> = {
         let engine_actor_tx = engine_actor_tx.clone();
         let mut sync_handshake_success = false;
+        let mut handshake_tx = Some(handshake_tx);
         let topic = topic.clone();
 
         tokio::spawn(async move {
+            let mut received = 0;
             while let Some(message) = rx.recv().await {
                 // I. Handshake Phase.
                 //
@@ -103,6 +114,11 @@ where
                     }
                     sync_handshake_success = true;
 
+                    // Stop the handshake timeout, if any.
+                    if let Some(handshake_tx) = handshake_tx.take() {
+                        let _ = handshake_tx.send(());
+                    }
+
                     // Inform the engine that we are expecting sync messages from the peer on this
                     // topic.
                     engine_actor_tx
@@ -139,21 +155,26 @@ where
                             "engine_actor_tx failed sending sync message: {err}"
                         ))
                     })?;
+                received += 1;
             }
 
-            Ok(())
+            Ok(received)
         })
     };
 
-    // Run the "initiating peer" side of the sync protocol.
-    let result = sync_protocol
-        .initiate(
+    // Run the "initiating peer" side of the sync protocol, dropping the session if the handshake
+    // phase didn't complete in time.
+    let result = tokio::select! {
+        result = sync_protocol.initiate(
             topic.clone(),
             Box::new(&mut send),
             Box::new(&mut recv),
             Box::new(&mut sink),
-        )
-        .await;
+        ) => result,
+        _ = handshake_deadline(handshake_timeout, handshake_rx) => {
+            Err(SyncError::UnexpectedBehaviour("handshake timeout".into()))
+        }
+    };
 
     // Drop the tx, so the rx in the glue task receives the closing event.
     drop(sink);
@@ -169,7 +190,7 @@ where
     }
 
     // .. and forward it further.
-    glue_task_result?;
+    let received = glue_task_result?;
 
     // We also return any error originating from the sync protocol implementation itself.
     if let Err(err) = result {
@@ -188,7 +209,11 @@ where
     // sync manager which drives this "initiator" session with additional re-attempt logic.
 
     engine_actor_tx
-        .send(ToEngineActor::SyncDone { peer, topic })
+        .send(ToEngineActor::SyncDone {
+            peer,
+            topic,
+            received,
+        })
         .await
         .map_err(|err| {
             SyncError::Critical(format!("engine_actor_tx failed sending sync done: {err}"))
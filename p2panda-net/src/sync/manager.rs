@@ -10,11 +10,14 @@ use thiserror::Error;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tokio::time::{interval, Duration, Instant};
 use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 use tracing::{debug, error, trace, warn};
 
-use crate::engine::ToEngineActor;
+use crate::bandwidth::{Bandwidth, Throttled};
+use crate::engine::{AddressBook, ToEngineActor};
+use crate::engine::{Metered, TrafficMeter};
 use crate::from_public_key;
-use crate::sync::{self, SYNC_CONNECTION_ALPN};
+use crate::sync;
 
 use super::SyncConfiguration;
 
@@ -40,7 +43,8 @@ struct SyncAttempt<T> {
     peer: PublicKey,
     topic: T,
     attempts: u8,
-    completed: Option<Instant>,
+    resyncs: u32,
+    resync_at: Option<Instant>,
 }
 
 impl<T> SyncAttempt<T> {
@@ -49,7 +53,8 @@ impl<T> SyncAttempt<T> {
             peer,
             topic,
             attempts: 0,
-            completed: None,
+            resyncs: 0,
+            resync_at: None,
         }
     }
 }
@@ -61,6 +66,10 @@ enum SyncAttemptError {
     #[error("sync attempt failed due to connection or stream error")]
     Connection,
 
+    /// Sync attempt was aborted because the peer is denied.
+    #[error("sync attempt aborted as peer is denied")]
+    Denied,
+
     /// Error occurred while initiating or accepting a sync session.
     #[error(transparent)]
     Sync(#[from] SyncError),
@@ -69,6 +78,8 @@ enum SyncAttemptError {
 /// An API for scheduling outbound connections and sync attempts.
 #[derive(Debug)]
 pub(crate) struct SyncActor<T> {
+    address_book: AddressBook,
+    bandwidth: Bandwidth,
     config: SyncConfiguration<T>,
     pending_sync_sessions: HashMap<T, HashSet<PublicKey>>,
     active_sync_sessions: HashMap<T, HashSet<PublicKey>>,
@@ -79,6 +90,8 @@ pub(crate) struct SyncActor<T> {
     resync_queue: VecDeque<SyncAttempt<T>>,
     sync_queue_tx: Sender<SyncAttempt<T>>,
     sync_queue_rx: Receiver<SyncAttempt<T>>,
+    sync_sessions: TaskTracker,
+    traffic: TrafficMeter,
 }
 
 impl<T> SyncActor<T>
@@ -89,12 +102,18 @@ where
     pub(crate) fn new(
         config: SyncConfiguration<T>,
         endpoint: Endpoint,
+        address_book: AddressBook,
+        bandwidth: Bandwidth,
+        traffic: TrafficMeter,
         engine_actor_tx: Sender<ToEngineActor<T>>,
+        sync_sessions: TaskTracker,
     ) -> (Self, Sender<ToSyncActor<T>>) {
         let (sync_queue_tx, sync_queue_rx) = mpsc::channel(config.max_concurrent_sync_sessions);
         let (sync_manager_tx, sync_manager_rx) = mpsc::channel(256);
 
         let sync_manager = Self {
+            address_book,
+            bandwidth,
             config,
             pending_sync_sessions: HashMap::new(),
             active_sync_sessions: HashMap::new(),
@@ -105,6 +124,8 @@ where
             resync_queue: VecDeque::new(),
             sync_queue_tx,
             sync_queue_rx,
+            sync_sessions,
+            traffic,
         };
 
         (sync_manager, sync_manager_tx)
@@ -178,16 +199,13 @@ where
     /// - A new peer and topic combination received from the engine
     /// - A tick of the resync poll interval, resulting in a resync attempt if one is in the queue
     pub async fn run(mut self, token: CancellationToken) -> Result<()> {
-        // Define the resync intervals based on supplied configuration parameters if resync has
-        // been enabled. Otherwise create long-duration fallback values; this is mostly just
-        // necessary for the resync poll interval tick.
-        let (mut resync_poll_interval, resync_interval) =
-            if let Some(ref resync) = self.config.resync {
-                (interval(resync.poll_interval), resync.interval)
-            } else {
-                let one_hour = Duration::from_secs(FALLBACK_RESYNC_INTERVAL_SEC);
-                (interval(one_hour), one_hour)
-            };
+        // Define the resync poll interval based on supplied configuration parameters if resync has
+        // been enabled. Otherwise create a long-duration fallback value.
+        let mut resync_poll_interval = if let Some(ref resync) = self.config.resync {
+            interval(resync.poll_interval)
+        } else {
+            interval(Duration::from_secs(FALLBACK_RESYNC_INTERVAL_SEC))
+        };
 
         loop {
             tokio::select! {
@@ -198,8 +216,12 @@ where
                     break;
                 }
                 Some(sync_attempt) = self.sync_queue_rx.recv() => {
-                    match self
-                       .connect_and_sync(sync_attempt.peer, sync_attempt.topic.clone())
+                    // Sessions are tracked so that a shutdown can wait for them to complete.
+                    let sync_sessions = self.sync_sessions.clone();
+                    match sync_sessions
+                       .track_future(
+                           self.connect_and_sync(sync_attempt.peer, sync_attempt.topic.clone()),
+                       )
                        .await
                    {
                        Ok(()) => self.complete_successful_sync(sync_attempt).await?,
@@ -231,8 +253,8 @@ where
                 }
                 _ = resync_poll_interval.tick() => {
                     if let Some(attempt) = self.resync_queue.pop_front() {
-                        if let Some(completion) = attempt.completed {
-                            if completion.elapsed() >= resync_interval {
+                        if let Some(resync_at) = attempt.resync_at {
+                            if Instant::now() >= resync_at {
                                 trace!("schedule resync attempt {attempt:?}");
                                 if let Err(err) = self.schedule_resync_attempt(attempt).await {
                                     error!("failed to schedule resync attempt: {}", err)
@@ -289,27 +311,44 @@ where
             session.remove(&peer);
         }
 
+        // The peer might have been denied after the attempt was scheduled.
+        if self.address_book.is_denied(&peer).await {
+            return Err(SyncAttemptError::Denied.into());
+        }
+
+        // Select the sync protocol for this topic, peers accept it under a dedicated ALPN.
+        let (alpn, sync_protocol) = self.config.protocol_for_topic(&topic);
+
         let connection = self
             .endpoint
-            .connect(from_public_key(peer), SYNC_CONNECTION_ALPN)
+            .connect(from_public_key(peer), &alpn)
             .await
             .map_err(|_| SyncAttemptError::Connection)?;
+        self.address_book.close_on_deny(peer, connection.clone());
 
         let (mut send, mut recv) = connection
             .open_bi()
             .await
             .map_err(|_| SyncAttemptError::Connection)?;
 
-        let sync_protocol = self.config.protocol();
         let engine_actor_tx = self.engine_actor_tx.clone();
 
         // Run a sync session as the initiator.
         sync::initiate_sync(
-            &mut send,
-            &mut recv,
+            &mut Metered::new(
+                Throttled::new(&mut send, self.bandwidth.upload.clone()),
+                peer,
+                self.traffic.clone(),
+            ),
+            &mut Metered::new(
+                Throttled::new(&mut recv, self.bandwidth.download.clone()),
+                peer,
+                self.traffic.clone(),
+            ),
             peer,
             topic.clone(),
             sync_protocol,
+            self.config.handshake_timeout,
             engine_actor_tx,
         )
         .await?;
@@ -344,11 +383,16 @@ where
                         return Ok(());
                     }
                 }
-                SyncAttemptError::Sync(_) => {
+                // Denied peers are not retried, the engine never knew about this attempt.
+                SyncAttemptError::Denied => {
+                    debug!("sync attempt aborted as peer is denied");
+                }
+                SyncAttemptError::Sync(err) => {
                     self.engine_actor_tx
                         .send(ToEngineActor::SyncFailed {
                             topic: Some(sync_attempt.topic),
                             peer: sync_attempt.peer,
+                            error: err.to_string(),
                         })
                         .await?;
                 }
@@ -366,8 +410,8 @@ where
     /// Remove the given topic from the set of active sync sessions for the given peer and add them
     /// to the set of completed sync sessions.
     ///
-    /// If resync is active, a timestamp is created to mark the time of sync completion and the
-    /// attempt is then pushed to the back of the resync queue.
+    /// If resync is active, the time of the next resync is calculated from the configured schedule
+    /// and the attempt is then pushed to the back of the resync queue.
     async fn complete_successful_sync(&mut self, mut sync_attempt: SyncAttempt<T>) -> Result<()> {
         trace!("complete_successful_sync");
         self.completed_sync_sessions
@@ -379,9 +423,10 @@ where
             session.remove(&sync_attempt.peer);
         }
 
-        if self.config.is_resync() {
+        if let Some(ref resync) = self.config.resync {
             trace!("schedule re-sync attempt");
-            sync_attempt.completed = Some(Instant::now());
+            sync_attempt.resync_at = Some(resync.next_resync(Instant::now(), sync_attempt.resyncs));
+            sync_attempt.resyncs += 1;
             self.resync_queue.push_back(sync_attempt);
         }
 
@@ -401,9 +446,11 @@ mod tests {
     use tokio::sync::mpsc;
     use tokio::time::{sleep, Duration};
     use tokio_util::sync::CancellationToken;
+    use tokio_util::task::TaskTracker;
     use tracing::warn;
 
-    use crate::engine::ToEngineActor;
+    use crate::bandwidth::Bandwidth;
+    use crate::engine::{AddressBook, ToEngineActor, TrafficMeter};
     use crate::network::sync_protocols::PingPongProtocol;
     use crate::network::tests::TestTopic;
     use crate::protocols::ProtocolMap;
@@ -468,14 +515,29 @@ mod tests {
         let endpoint_b = build_endpoint(2024).await;
 
         let mut protocols_a = ProtocolMap::default();
-        let sync_handler_a =
-            SyncConnection::new(Arc::new(ping_pong.clone()), engine_actor_tx_a.clone());
+        let sync_handler_a = SyncConnection::new(
+            Arc::new(ping_pong.clone()),
+            None,
+            Bandwidth::default(),
+            TrafficMeter::new(),
+            AddressBook::new([1; 32]),
+            engine_actor_tx_a.clone(),
+            TaskTracker::new(),
+        );
         protocols_a.insert(SYNC_CONNECTION_ALPN, Arc::new(sync_handler_a));
         let alpns_a = protocols_a.alpns();
         endpoint_a.set_alpns(alpns_a).unwrap();
 
         let mut protocols_b = ProtocolMap::default();
-        let sync_handler_b = SyncConnection::new(Arc::new(ping_pong), engine_actor_tx_b.clone());
+        let sync_handler_b = SyncConnection::new(
+            Arc::new(ping_pong),
+            None,
+            Bandwidth::default(),
+            TrafficMeter::new(),
+            AddressBook::new([1; 32]),
+            engine_actor_tx_b.clone(),
+            TaskTracker::new(),
+        );
         protocols_b.insert(SYNC_CONNECTION_ALPN, Arc::new(sync_handler_b));
         let alpns_b = protocols_b.alpns();
         endpoint_b.set_alpns(alpns_b).unwrap();
@@ -489,10 +551,24 @@ mod tests {
         endpoint_a.add_node_addr(peer_addr_b).unwrap();
         endpoint_b.add_node_addr(peer_addr_a).unwrap();
 
-        let (sync_actor_a, sync_actor_tx_a) =
-            SyncActor::new(config_a, endpoint_a.clone(), engine_actor_tx_a);
-        let (sync_actor_b, _sync_actor_tx_b) =
-            SyncActor::new(config_b, endpoint_b.clone(), engine_actor_tx_b);
+        let (sync_actor_a, sync_actor_tx_a) = SyncActor::new(
+            config_a,
+            endpoint_a.clone(),
+            AddressBook::new([1; 32]),
+            Bandwidth::default(),
+            TrafficMeter::new(),
+            engine_actor_tx_a,
+            TaskTracker::new(),
+        );
+        let (sync_actor_b, _sync_actor_tx_b) = SyncActor::new(
+            config_b,
+            endpoint_b.clone(),
+            AddressBook::new([1; 32]),
+            Bandwidth::default(),
+            TrafficMeter::new(),
+            engine_actor_tx_b,
+            TaskTracker::new(),
+        );
 
         let shutdown_token_a = CancellationToken::new();
         let shutdown_token_b = CancellationToken::new();
@@ -617,8 +693,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_a.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_a.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer a")
         };
 
@@ -651,8 +726,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_b.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_b.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer b")
         };
     }
@@ -742,8 +816,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_a.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_a.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer a")
         };
 
@@ -776,8 +849,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_b.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_b.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer b")
         };
 
@@ -893,8 +965,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_a.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_a.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer a")
         };
 
@@ -943,8 +1014,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_a.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_a.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer a")
         };
     }
@@ -1041,8 +1111,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_a.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_a.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer a")
         };
 
@@ -1080,8 +1149,7 @@ mod tests {
         };
 
         // Receive `SyncDone`.
-        let Some(ToEngineActor::SyncDone { topic: _, peer: _ }) = engine_actor_rx_a.recv().await
-        else {
+        let Some(ToEngineActor::SyncDone { .. }) = engine_actor_rx_a.recv().await else {
             panic!("expected to receive SyncDone on engine actor receiver for peer a")
         };
     }
@@ -7,8 +7,10 @@ mod initiate;
 pub(crate) mod manager;
 #[cfg(test)]
 mod tests;
+mod timeout;
 
 pub use accept::accept_sync;
-pub use config::{ResyncConfiguration, SyncConfiguration};
+pub use config::{ResyncConfiguration, ResyncSchedule, SyncConfiguration};
 pub use handler::{SyncConnection, SYNC_CONNECTION_ALPN};
 pub use initiate::initiate_sync;
+pub(crate) use timeout::handshake_deadline;
@@ -36,6 +36,9 @@ mod sync_protocols {
         /// `accept()`.
         AcceptorSendsTopic,
 
+        /// Neither `initiate()` nor `accept()` ever complete the handshake.
+        StallsHandshake,
+
         /// No errors are explicitly triggered; used for "happy path" test.
         NoError,
     }
@@ -55,6 +58,11 @@ mod sync_protocols {
                 &'a mut (dyn Sink<FromSync<TestTopic>, Error = SyncError> + Send + Unpin),
             >,
         ) -> Result<(), SyncError> {
+            // Simulate a slow or malicious peer which never sends the topic.
+            if let FailingProtocol::StallsHandshake = *self {
+                std::future::pending::<()>().await;
+            }
+
             let mut sink = into_cbor_sink(tx);
             let mut stream = into_cbor_stream(rx);
 
@@ -108,6 +116,11 @@ mod sync_protocols {
                 ));
             }
 
+            // Simulate a slow or malicious peer which never receives the topic.
+            if let FailingProtocol::StallsHandshake = *self {
+                std::future::pending::<()>().await;
+            }
+
             let mut sink = into_cbor_sink(tx);
             let mut stream = into_cbor_stream(rx);
 
@@ -154,6 +167,7 @@ use p2panda_sync::{SyncError, TopicQuery};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio::time::Duration;
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
 use crate::engine::ToEngineActor;
@@ -175,6 +189,7 @@ impl TopicQuery for TestTopic {}
 /// Helper method to establish a sync session between the initiator and acceptor.
 async fn run_sync_impl(
     protocol: FailingProtocol,
+    handshake_timeout: Option<Duration>,
 ) -> (
     mpsc::Receiver<ToEngineActor<TestTopic>>,
     mpsc::Receiver<ToEngineActor<TestTopic>>,
@@ -209,6 +224,7 @@ async fn run_sync_impl(
                 acceptor_node_id,
                 topic.clone(),
                 sync_protocol,
+                handshake_timeout,
                 initiator_tx,
             )
             .await
@@ -222,6 +238,7 @@ async fn run_sync_impl(
                 &mut acceptor_read.compat(),
                 initiator_node_id,
                 sync_protocol_clone,
+                handshake_timeout,
                 acceptor_tx,
             )
             .await
@@ -234,7 +251,7 @@ async fn run_sync_impl(
 #[tokio::test]
 async fn initiator_fails_critical() {
     let (mut rx_initiator, mut rx_acceptor, initiator_handle, acceptor_handle) =
-        run_sync_impl(FailingProtocol::InitiatorFailsCritical).await;
+        run_sync_impl(FailingProtocol::InitiatorFailsCritical, None).await;
 
     // Expected initiator messages.
     assert!(matches!(
@@ -279,7 +296,7 @@ async fn initiator_fails_critical() {
 #[tokio::test]
 async fn initiator_sends_topic_twice() {
     let (mut rx_initiator, mut rx_acceptor, initiator_handle, acceptor_handle) =
-        run_sync_impl(FailingProtocol::InitiatorSendsTopicTwice).await;
+        run_sync_impl(FailingProtocol::InitiatorSendsTopicTwice, None).await;
 
     // Expected initiator messages.
     assert!(matches!(
@@ -322,7 +339,7 @@ async fn initiator_sends_topic_twice() {
 #[tokio::test]
 async fn acceptor_fails_critical() {
     let (mut rx_initiator, mut rx_acceptor, initiator_handle, acceptor_handle) =
-        run_sync_impl(FailingProtocol::AcceptorFailsCritical).await;
+        run_sync_impl(FailingProtocol::AcceptorFailsCritical, None).await;
 
     // Expected initiator messages.
     assert!(matches!(
@@ -366,7 +383,7 @@ async fn acceptor_fails_critical() {
 #[tokio::test]
 async fn acceptor_sends_topic() {
     let (mut rx_initiator, mut rx_acceptor, initiator_handle, acceptor_handle) =
-        run_sync_impl(FailingProtocol::AcceptorSendsTopic).await;
+        run_sync_impl(FailingProtocol::AcceptorSendsTopic, None).await;
 
     // Expected initiator messages.
     assert!(matches!(
@@ -411,7 +428,7 @@ async fn acceptor_sends_topic() {
 #[tokio::test]
 async fn run_sync_without_error() {
     let (mut rx_initiator, mut rx_acceptor, initiator_handle, acceptor_handle) =
-        run_sync_impl(FailingProtocol::NoError).await;
+        run_sync_impl(FailingProtocol::NoError, None).await;
 
     // Expected initiator messages.
     assert!(matches!(
@@ -449,3 +466,42 @@ async fn run_sync_without_error() {
     assert_eq!(initiator_handle.await.unwrap(), Ok(()));
     assert_eq!(acceptor_handle.await.unwrap(), Ok(()));
 }
+
+#[tokio::test]
+async fn handshake_timeout() {
+    let (mut rx_initiator, mut rx_acceptor, initiator_handle, acceptor_handle) = run_sync_impl(
+        FailingProtocol::StallsHandshake,
+        Some(Duration::from_millis(100)),
+    )
+    .await;
+
+    // Expected initiator messages.
+    assert!(matches!(
+        rx_initiator.recv().await,
+        Some(ToEngineActor::SyncStart { .. })
+    ));
+
+    // Expected acceptor messages.
+    assert!(matches!(
+        rx_acceptor.recv().await,
+        Some(ToEngineActor::SyncStart { .. })
+    ));
+
+    assert!(matches!(
+        rx_acceptor.recv().await,
+        Some(ToEngineActor::SyncFailed { topic: None, .. })
+    ));
+
+    // Expected handler results.
+    assert_eq!(
+        initiator_handle.await.unwrap(),
+        Err(SyncError::UnexpectedBehaviour("handshake timeout".into()))
+    );
+    assert_eq!(
+        acceptor_handle.await.unwrap(),
+        Err(SyncError::UnexpectedBehaviour("handshake timeout".into()))
+    );
+
+    // Note: "SyncFailed" message is handled by manager for initiators.
+    assert!(rx_initiator.recv().await.is_none());
+}
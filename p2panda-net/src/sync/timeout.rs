@@ -0,0 +1,22 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use tokio::sync::oneshot;
+use tokio::time::Duration;
+
+/// Resolves when the given handshake timeout has been reached before the handshake phase of a
+/// sync session completed.
+///
+/// The future never resolves when no timeout was given or when the handshake phase completed in
+/// time (signalled via `handshake_rx`).
+pub(crate) async fn handshake_deadline(
+    timeout: Option<Duration>,
+    handshake_rx: oneshot::Receiver<()>,
+) {
+    if let Some(timeout) = timeout {
+        if tokio::time::timeout(timeout, handshake_rx).await.is_err() {
+            return;
+        }
+    }
+
+    std::future::pending::<()>().await
+}
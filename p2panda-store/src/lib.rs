@@ -24,11 +24,13 @@
 pub mod memory_store;
 
 use std::fmt::{Debug, Display};
+use std::future::Future;
 
 #[cfg(feature = "memory")]
-pub use memory_store::MemoryStore;
+pub use memory_store::{MemoryStore, MemoryStoreSnapshot};
 
-use p2panda_core::{Body, Hash, Header, PublicKey, RawOperation};
+use futures_util::stream::{self, Stream, StreamExt};
+use p2panda_core::{Body, Hash, Header, Operation, PublicKey, RawOperation};
 
 /// Uniquely identify a single-author log.
 ///
@@ -67,6 +69,9 @@ impl<T> LogId for T where T: Clone + Debug + Eq + std::hash::Hash {}
 pub trait LocalOperationStore<LogId, Extensions>: Clone {
     type Error: Display + Debug;
 
+    /// Point-in-time view of the store, see `snapshot`.
+    type Snapshot: LogStore<LogId, Extensions>;
+
     /// Insert an operation.
     ///
     /// Returns `true` when the insert occurred, or `false` when the operation already existed and
@@ -80,6 +85,48 @@ pub trait LocalOperationStore<LogId, Extensions>: Clone {
         log_id: &LogId,
     ) -> Result<bool, Self::Error>;
 
+    /// Insert many operations into the same log at once.
+    ///
+    /// This is more efficient than calling `insert_operation` in a loop when ingesting large
+    /// amounts of operations. All operations are inserted atomically, either all or none of them
+    /// are written to the store.
+    ///
+    /// Returns the number of inserted operations. Operations which already existed, or occur more
+    /// than once in the batch, are matched by their hash and skipped without an error, this makes
+    /// re-ingesting the same operations idempotent.
+    ///
+    /// The default implementation calls `insert_operation` for each operation, it is neither
+    /// faster nor atomic. Stores should override it with a single transaction.
+    fn insert_operations_batch(
+        &mut self,
+        operations: &[Operation<Extensions>],
+        log_id: &LogId,
+    ) -> impl Future<Output = Result<usize, Self::Error>>
+    where
+        LogId: Sync,
+        Extensions: p2panda_core::Extensions + Sync,
+    {
+        async move {
+            let mut inserted = 0;
+            for operation in operations {
+                let header_bytes = operation.header.to_bytes();
+                if self
+                    .insert_operation(
+                        operation.hash,
+                        &operation.header,
+                        operation.body.as_ref(),
+                        &header_bytes,
+                        log_id,
+                    )
+                    .await?
+                {
+                    inserted += 1;
+                }
+            }
+            Ok(inserted)
+        }
+    }
+
     /// Get an operation.
     async fn get_operation(
         &self,
@@ -100,11 +147,65 @@ pub trait LocalOperationStore<LogId, Extensions>: Clone {
     /// the store.
     async fn delete_operation(&mut self, hash: Hash) -> Result<bool, Self::Error>;
 
+    /// Delete all operations in a log whose timestamp is before the given cutoff.
+    ///
+    /// Operations of all authors stored under the passed log id are considered. This is useful
+    /// for garbage-collecting old data without walking the whole store in application code.
+    ///
+    /// Timestamps are not guaranteed to increase along a log, which is why operations can be
+    /// deleted from anywhere in it and not only from its beginning. This leaves gaps in the
+    /// sequence numbers of the log and backlinks pointing at operations which are gone, use
+    /// `LogStore::delete_operations` instead if the remaining log needs to stay intact.
+    ///
+    /// Returns the number of deleted operations.
+    ///
+    /// The default implementation looks up all operations of the log with `LogStore` methods and
+    /// deletes them one by one, stores should override it to delete them in one transaction.
+    fn delete_operations_before(
+        &mut self,
+        log_id: &LogId,
+        before_timestamp: u64,
+    ) -> impl Future<Output = Result<usize, <Self as LocalOperationStore<LogId, Extensions>>::Error>>
+    where
+        Self: LogStore<
+                LogId,
+                Extensions,
+                Error = <Self as LocalOperationStore<LogId, Extensions>>::Error,
+            > + Send
+            + Sync,
+        LogId: Sync,
+        Extensions: p2panda_core::Extensions + Send + Sync,
+    {
+        async move {
+            let mut deleted = 0;
+            let log_heights = LogStore::get_log_heights(self, log_id).await?;
+            for (public_key, _) in log_heights {
+                let log = LogStore::get_log(self, &public_key, log_id, None).await?;
+                for (header, _) in log.unwrap_or_default() {
+                    if header.timestamp < before_timestamp
+                        && self.delete_operation(header.hash()).await?
+                    {
+                        deleted += 1;
+                    }
+                }
+            }
+            Ok(deleted)
+        }
+    }
+
     /// Delete the payload of an operation.
     ///
     /// Returns `true` when the removal occurred and `false` when the operation was not found in
     /// the store or the payload was already deleted.
     async fn delete_payload(&mut self, hash: Hash) -> Result<bool, Self::Error>;
+
+    /// Take a snapshot of the current state of the store.
+    ///
+    /// The snapshot reflects the contents of the store at the time it was taken, operations
+    /// inserted into or deleted from the store afterwards are not visible in it. This gives
+    /// readers a consistent view for the duration of longer processes, for example a sync session
+    /// where the store might be written to concurrently.
+    async fn snapshot(&self) -> Result<Self::Snapshot, Self::Error>;
 }
 
 /// Interface for storing, deleting and querying logs.
@@ -128,6 +229,35 @@ pub trait LocalLogStore<LogId, Extensions> {
         from: Option<u64>,
     ) -> Result<Option<Vec<(Header<Extensions>, Option<Body>)>>, Self::Error>;
 
+    /// Stream operations from an authors' log ordered by sequence number.
+    ///
+    /// In contrast to `get_log` operations are yielded one by one, without loading the whole log
+    /// into memory first. The `from` value will be used as the starting index for log retrieval,
+    /// if supplied, otherwise all operations will be returned.
+    ///
+    /// The stream is empty when either the author or a log with the requested id was not found.
+    ///
+    /// The default implementation is based on `get_log` and still loads the whole log at once,
+    /// stores should override it to fetch operations lazily.
+    fn get_log_stream(
+        &self,
+        public_key: &PublicKey,
+        log_id: &LogId,
+        from: Option<u64>,
+    ) -> impl Stream<Item = Result<(Header<Extensions>, Option<Body>), Self::Error>>
+    where
+        Extensions: Send,
+        Self::Error: Send,
+    {
+        stream::once(self.get_log(public_key, log_id, from)).flat_map(|result| {
+            let operations: Vec<_> = match result {
+                Ok(log) => log.unwrap_or_default().into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
+            };
+            stream::iter(operations)
+        })
+    }
+
     /// Get "raw" header and body bytes from an authors' log ordered by sequence number.
     ///
     /// The `from` value will be used as the starting index for log retrieval, if supplied,
@@ -141,9 +271,54 @@ pub trait LocalLogStore<LogId, Extensions> {
         from: Option<u64>,
     ) -> Result<Option<Vec<RawOperation>>, Self::Error>;
 
+    /// Get only the "raw" header bytes from an authors' log ordered by sequence number.
+    ///
+    /// This allows sending operations without their payloads, for example during "header-only"
+    /// sync sessions. The `from` value will be used as the starting index for log retrieval, if
+    /// supplied, otherwise all headers will be returned.
+    ///
+    /// Returns `None` when either the author or a log with the requested id was not found.
+    ///
+    /// The default implementation is based on `get_raw_log` and still loads all payloads, stores
+    /// should override it to only read the headers.
+    fn get_raw_log_headers(
+        &self,
+        public_key: &PublicKey,
+        log_id: &LogId,
+        from: Option<u64>,
+    ) -> impl Future<Output = Result<Option<Vec<Vec<u8>>>, Self::Error>>
+    where
+        Self: Sync,
+        LogId: Sync,
+    {
+        async move {
+            let log = self.get_raw_log(public_key, log_id, from).await?;
+            Ok(log.map(|log| log.into_iter().map(|(header, _)| header).collect()))
+        }
+    }
+
     /// Get the log heights of all logs, by any author, which are stored under the passed log id.
+    ///
+    /// Log heights are ordered by the public key of the author.
     async fn get_log_heights(&self, log_id: &LogId) -> Result<Vec<(PublicKey, u64)>, Self::Error>;
 
+    /// Get a page of the ids of all logs of an author.
+    ///
+    /// Log ids are returned in ascending order, skipping the first `offset` ones and returning at
+    /// most `limit` of them. This allows walking through all logs of an author in a large store
+    /// without loading all ids at once.
+    ///
+    /// There is no default implementation as none of the other methods allow listing the logs of
+    /// an author, every store needs to implement this method itself.
+    async fn get_log_ids_page(
+        &self,
+        public_key: &PublicKey,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<LogId>, Self::Error>
+    where
+        LogId: Ord;
+
     /// Get only the latest operation from an authors' log.
     ///
     /// Returns None when the author or a log with the requested id was not found.
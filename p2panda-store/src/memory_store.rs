@@ -1,12 +1,13 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 //! In-memory persistence for p2panda operations and logs.
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::Infallible;
-use std::fmt::Debug;
-use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::fmt::{Debug, Display};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use p2panda_core::{Body, Extensions, Hash, Header, PublicKey, RawOperation};
+use futures_util::stream::{self, Stream};
+use p2panda_core::{Body, Extensions, Hash, Header, Operation, PublicKey, RawOperation};
 
 use crate::{LogId, LogStore, OperationStore};
 
@@ -18,10 +19,181 @@ type LogMeta = (SeqNum, Timestamp, Hash);
 type StoredOperation<L, E> = (L, Header<E>, Option<Body>, RawHeader);
 
 /// An in-memory store for core p2panda data types: `Operation` and `Log`.
-#[derive(Clone, Debug)]
+///
+/// Both maps are reference-counted, cloning the store only clones the pointers. Writes copy a map
+/// only when it is still shared with a snapshot.
+#[derive(Debug)]
 pub struct InnerMemoryStore<L, E> {
-    operations: HashMap<Hash, StoredOperation<L, E>>,
-    logs: HashMap<(PublicKey, L), BTreeSet<LogMeta>>,
+    operations: Arc<HashMap<Hash, StoredOperation<L, E>>>,
+    logs: Arc<HashMap<(PublicKey, L), BTreeSet<LogMeta>>>,
+
+    /// Log ids of an author in ascending order, built on the first page request and dropped
+    /// again as soon as logs of that author get added or removed.
+    sorted_log_ids: Mutex<HashMap<PublicKey, Arc<Vec<L>>>>,
+}
+
+impl<L, E> Clone for InnerMemoryStore<L, E> {
+    fn clone(&self) -> Self {
+        Self {
+            operations: self.operations.clone(),
+            logs: self.logs.clone(),
+            sorted_log_ids: Mutex::new(
+                self.sorted_log_ids
+                    .lock()
+                    .expect("lock is not poisoned")
+                    .clone(),
+            ),
+        }
+    }
+}
+
+impl<L, E> InnerMemoryStore<L, E> {
+    fn invalidate_log_ids(&mut self, public_key: &PublicKey) {
+        self.sorted_log_ids
+            .get_mut()
+            .expect("lock is not poisoned")
+            .remove(public_key);
+    }
+}
+
+impl<L, E> InnerMemoryStore<L, E>
+where
+    L: LogId,
+    E: Extensions,
+{
+    fn log_hashes(&self, public_key: &PublicKey, log_id: &L, from: Option<u64>) -> Vec<Hash> {
+        self.logs
+            .get(&(*public_key, log_id.to_owned()))
+            .map(|log| {
+                log.iter()
+                    .filter(|(seq_num, _, _)| *seq_num >= from.unwrap_or(0))
+                    .map(|(_, _, hash)| *hash)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn get_log(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> Option<Vec<(Header<E>, Option<Body>)>> {
+        let log = self.logs.get(&(*public_key, log_id.to_owned()))?;
+        let result = log
+            .iter()
+            .filter(|(seq_num, _, _)| *seq_num >= from.unwrap_or(0))
+            .map(|(_, _, hash)| {
+                let (_, header, body, _) = self.operations.get(hash).expect("exists in hash map");
+                (header.to_owned(), body.to_owned())
+            })
+            .collect();
+        Some(result)
+    }
+
+    fn get_raw_log(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> Option<Vec<RawOperation>> {
+        let log = self.logs.get(&(*public_key, log_id.to_owned()))?;
+        let result = log
+            .iter()
+            .filter(|(seq_num, _, _)| *seq_num >= from.unwrap_or(0))
+            .map(|(_, _, hash)| {
+                let (_, _, body, header_bytes) =
+                    self.operations.get(hash).expect("exists in hash map");
+                (
+                    header_bytes.clone(),
+                    body.as_ref().map(|body| body.to_bytes()),
+                )
+            })
+            .collect();
+        Some(result)
+    }
+
+    fn get_raw_log_headers(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> Option<Vec<RawHeader>> {
+        let log = self.logs.get(&(*public_key, log_id.to_owned()))?;
+        let result = log
+            .iter()
+            .filter(|(seq_num, _, _)| *seq_num >= from.unwrap_or(0))
+            .map(|(_, _, hash)| {
+                let (_, _, _, header_bytes) =
+                    self.operations.get(hash).expect("exists in hash map");
+                header_bytes.clone()
+            })
+            .collect();
+        Some(result)
+    }
+
+    fn latest_operation(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+    ) -> Option<(Header<E>, Option<Body>)> {
+        let log = self.logs.get(&(*public_key, log_id.to_owned()))?;
+        let (_, _, hash) = log.last()?;
+        let (_, header, body, _) = self.operations.get(hash)?;
+        Some((header.to_owned(), body.to_owned()))
+    }
+
+    fn get_log_heights(&self, log_id: &L) -> Vec<(PublicKey, SeqNum)> {
+        let mut log_heights: Vec<(PublicKey, SeqNum)> = self
+            .logs
+            .iter()
+            .filter_map(|((public_key, inner_log_id), log)| {
+                if inner_log_id == log_id {
+                    let log_height = log
+                        .last()
+                        .expect("all logs contain at least one operation")
+                        .0;
+                    Some((*public_key, log_height))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        // Logs are kept in a hash map, sort them to not leak its random iteration order.
+        log_heights.sort_unstable_by_key(|(public_key, _)| *public_key);
+
+        log_heights
+    }
+
+    fn get_log_ids_page(&self, public_key: &PublicKey, offset: usize, limit: usize) -> Vec<L>
+    where
+        L: Ord,
+    {
+        let log_ids = self
+            .sorted_log_ids
+            .lock()
+            .expect("lock is not poisoned")
+            .entry(*public_key)
+            .or_insert_with(|| {
+                let mut log_ids: Vec<L> = self
+                    .logs
+                    .keys()
+                    .filter(|(author, _)| author == public_key)
+                    .map(|(_, log_id)| log_id.to_owned())
+                    .collect();
+                log_ids.sort_unstable();
+                Arc::new(log_ids)
+            })
+            .clone();
+        log_ids
+            .get(offset..)
+            .unwrap_or_default()
+            .iter()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
 }
 
 /// An in-memory store for core p2panda data types: `Operation` and log.
@@ -38,8 +210,9 @@ impl<L, E> MemoryStore<L, E> {
     /// Create a new in-memory store.
     pub fn new() -> Self {
         let inner = InnerMemoryStore {
-            operations: HashMap::new(),
-            logs: HashMap::new(),
+            operations: Arc::new(HashMap::new()),
+            logs: Arc::new(HashMap::new()),
+            sorted_log_ids: Mutex::new(HashMap::new()),
         };
 
         Self {
@@ -77,6 +250,8 @@ where
 {
     type Error = Infallible;
 
+    type Snapshot = MemoryStoreSnapshot<L, E>;
+
     async fn insert_operation(
         &mut self,
         hash: Hash,
@@ -88,9 +263,12 @@ where
         let mut store = self.write_store();
 
         let log_meta = (header.seq_num, header.timestamp, hash);
-        let insertion_occured = store
-            .logs
-            .entry((header.public_key, log_id.to_owned()))
+        let log_key = (header.public_key, log_id.to_owned());
+        if !store.logs.contains_key(&log_key) {
+            store.invalidate_log_ids(&header.public_key);
+        }
+        let insertion_occured = Arc::make_mut(&mut store.logs)
+            .entry(log_key)
             .or_default()
             .insert(log_meta);
 
@@ -101,12 +279,58 @@ where
                 body.cloned(),
                 header_bytes.to_vec(),
             );
-            store.operations.insert(hash, entry);
+            Arc::make_mut(&mut store.operations).insert(hash, entry);
         }
 
         Ok(insertion_occured)
     }
 
+    async fn insert_operations_batch(
+        &mut self,
+        operations: &[Operation<E>],
+        log_id: &L,
+    ) -> Result<usize, Self::Error> {
+        // Hold the write-lock for the whole batch, this way readers never observe a partially
+        // inserted batch.
+        let mut store = self.write_store();
+        let InnerMemoryStore {
+            operations: stored_operations,
+            logs,
+            sorted_log_ids,
+        } = &mut *store;
+        let stored_operations = Arc::make_mut(stored_operations);
+        let logs = Arc::make_mut(logs);
+        let sorted_log_ids = sorted_log_ids.get_mut().expect("lock is not poisoned");
+        stored_operations.reserve(operations.len());
+
+        let mut inserted = 0;
+        for operation in operations {
+            let log_meta = (
+                operation.header.seq_num,
+                operation.header.timestamp,
+                operation.hash,
+            );
+            let log_key = (operation.header.public_key, log_id.to_owned());
+            if !logs.contains_key(&log_key) {
+                sorted_log_ids.remove(&operation.header.public_key);
+            }
+            let insertion_occured = logs.entry(log_key).or_default().insert(log_meta);
+
+            if insertion_occured {
+                let entry = (
+                    log_id.to_owned(),
+                    operation.header.to_owned(),
+                    operation.body.to_owned(),
+                    operation.header.to_bytes(),
+                );
+                stored_operations.insert(operation.hash, entry);
+                inserted += 1;
+            }
+        }
+
+        Ok(inserted)
+    }
+
     async fn get_operation(
         &self,
         hash: Hash,
@@ -133,34 +357,78 @@ where
 
     async fn delete_operation(&mut self, hash: Hash) -> Result<bool, Self::Error> {
         let mut store = self.write_store();
-        let Some((_, header, _, _)) = store.operations.remove(&hash) else {
+        let Some((_, header, _, _)) = Arc::make_mut(&mut store.operations).remove(&hash) else {
             return Ok(false);
         };
-        store.logs = store
-            .logs
-            .clone()
-            .into_iter()
-            .filter_map(|(key, mut log)| {
-                log.remove(&(header.seq_num, header.timestamp, hash));
-                if log.is_empty() {
-                    None
-                } else {
-                    Some((key, log))
-                }
-            })
-            .collect();
+        store.logs = Arc::new(
+            store
+                .logs
+                .as_ref()
+                .clone()
+                .into_iter()
+                .filter_map(|(key, mut log)| {
+                    log.remove(&(header.seq_num, header.timestamp, hash));
+                    if log.is_empty() {
+                        None
+                    } else {
+                        Some((key, log))
+                    }
+                })
+                .collect(),
+        );
+        store.invalidate_log_ids(&header.public_key);
 
         Ok(true)
     }
 
+    async fn delete_operations_before(
+        &mut self,
+        log_id: &L,
+        before_timestamp: u64,
+    ) -> Result<usize, Self::Error> {
+        let mut deleted = HashSet::new();
+        let mut removed_logs = Vec::new();
+        let mut store = self.write_store();
+        Arc::make_mut(&mut store.logs).retain(|(public_key, stored_log_id), log| {
+            if stored_log_id != log_id {
+                return true;
+            }
+            log.retain(|(_, timestamp, hash)| {
+                let remove = *timestamp < before_timestamp;
+                if remove {
+                    deleted.insert(*hash);
+                }
+                !remove
+            });
+            if log.is_empty() {
+                removed_logs.push(*public_key);
+            }
+            !log.is_empty()
+        });
+        Arc::make_mut(&mut store.operations).retain(|hash, _| !deleted.contains(hash));
+        for public_key in removed_logs {
+            store.invalidate_log_ids(&public_key);
+        }
+        Ok(deleted.len())
+    }
+
     async fn delete_payload(&mut self, hash: Hash) -> Result<bool, Self::Error> {
-        if let Some(operation) = self.write_store().operations.get_mut(&hash) {
+        let mut store = self.write_store();
+        if let Some(operation) = Arc::make_mut(&mut store.operations).get_mut(&hash) {
             operation.2 = None;
             Ok(true)
         } else {
             Ok(false)
         }
     }
+
+    async fn snapshot(&self) -> Result<Self::Snapshot, Self::Error> {
+        // Only the reference-counted maps are cloned here, the store copies them on the next
+        // write which keeps the snapshot unaffected.
+        Ok(MemoryStoreSnapshot {
+            inner: self.read_store().clone(),
+        })
+    }
 }
 
 impl<L, E> LogStore<L, E> for MemoryStore<L, E>
@@ -176,29 +444,27 @@ where
         log_id: &L,
         from: Option<u64>,
     ) -> Result<Option<Vec<(Header<E>, Option<Body>)>>, Self::Error> {
-        let store = self.read_store();
-        match store.logs.get(&(*public_key, log_id.to_owned())) {
-            Some(log) => {
-                let mut result = Vec::new();
-                if let Some(from) = from {
-                    log.iter().for_each(|(seq_num, _, hash)| {
-                        if *seq_num >= from {
-                            let (_, header, body, _) =
-                                store.operations.get(hash).expect("exists in hash map");
-                            result.push((header.to_owned(), body.to_owned()));
-                        }
-                    });
-                } else {
-                    log.iter().for_each(|(_, _, hash)| {
-                        let (_, header, body, _) =
-                            store.operations.get(hash).expect("exists in hash map");
-                        result.push((header.to_owned(), body.to_owned()));
-                    });
-                }
-                Ok(Some(result))
-            }
-            None => Ok(None),
-        }
+        Ok(self.read_store().get_log(public_key, log_id, from))
+    }
+
+    fn get_log_stream(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> impl Stream<Item = Result<(Header<E>, Option<Body>), Self::Error>> {
+        // Only collect the hashes up-front, operations are looked up lazily when the stream gets
+        // polled. Operations which were deleted in the meantime are skipped.
+        let hashes = self.read_store().log_hashes(public_key, log_id, from);
+
+        let store = self.clone();
+        stream::iter(hashes.into_iter().filter_map(move |hash| {
+            let store = store.read_store();
+            store
+                .operations
+                .get(&hash)
+                .map(|(_, header, body, _)| Ok((header.to_owned(), body.to_owned())))
+        }))
     }
 
     async fn get_raw_log(
@@ -207,35 +473,18 @@ where
         log_id: &L,
         from: Option<u64>,
     ) -> Result<Option<Vec<RawOperation>>, Self::Error> {
-        let store = self.read_store();
-        match store.logs.get(&(*public_key, log_id.to_owned())) {
-            Some(log) => {
-                let mut result = Vec::new();
-                if let Some(from) = from {
-                    log.iter().for_each(|(seq_num, _, hash)| {
-                        if *seq_num >= from {
-                            let (_, _, body, header_bytes) =
-                                store.operations.get(hash).expect("exists in hash map");
-                            result.push((
-                                header_bytes.clone(),
-                                body.as_ref().map(|body| body.to_bytes()),
-                            ));
-                        }
-                    });
-                } else {
-                    log.iter().for_each(|(_, _, hash)| {
-                        let (_, _, body, header_bytes) =
-                            store.operations.get(hash).expect("exists in hash map");
-                        result.push((
-                            header_bytes.clone(),
-                            body.as_ref().map(|body| body.to_bytes()),
-                        ));
-                    });
-                }
-                Ok(Some(result))
-            }
-            None => Ok(None),
-        }
+        Ok(self.read_store().get_raw_log(public_key, log_id, from))
+    }
+
+    async fn get_raw_log_headers(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> Result<Option<Vec<RawHeader>>, Self::Error> {
+        Ok(self
+            .read_store()
+            .get_raw_log_headers(public_key, log_id, from))
     }
 
     async fn latest_operation(
@@ -243,21 +492,7 @@ where
         public_key: &PublicKey,
         log_id: &L,
     ) -> Result<Option<(Header<E>, Option<Body>)>, Self::Error> {
-        let store = self.read_store();
-
-        let Some(log) = store.logs.get(&(*public_key, log_id.to_owned())) else {
-            return Ok(None);
-        };
-
-        let Some((_, _, hash)) = log.last() else {
-            return Ok(None);
-        };
-
-        let Some((_, header, body, _)) = store.operations.get(hash) else {
-            return Ok(None);
-        };
-
-        Ok(Some((header.to_owned(), body.to_owned())))
+        Ok(self.read_store().latest_operation(public_key, log_id))
     }
 
     async fn delete_operations(
@@ -268,7 +503,8 @@ where
     ) -> Result<bool, Self::Error> {
         let mut deleted = vec![];
         let mut store = self.write_store();
-        if let Some(log) = store.logs.get_mut(&(*public_key, log_id.to_owned())) {
+        if let Some(log) = Arc::make_mut(&mut store.logs).get_mut(&(*public_key, log_id.to_owned()))
+        {
             log.retain(|(seq_num, _, hash)| {
                 let remove = *seq_num < before;
                 if remove {
@@ -277,7 +513,7 @@ where
                 !remove
             });
         };
-        store.operations.retain(|hash, _| !deleted.contains(hash));
+        Arc::make_mut(&mut store.operations).retain(|hash, _| !deleted.contains(hash));
         Ok(!deleted.is_empty())
     }
 
@@ -300,40 +536,151 @@ where
             };
         }
         let mut store = self.write_store();
+        let operations = Arc::make_mut(&mut store.operations);
         for hash in &deleted {
-            let operation = store
-                .operations
-                .get_mut(hash)
-                .expect("operation exists in store");
+            let operation = operations.get_mut(hash).expect("operation exists in store");
             operation.2 = None;
         }
         Ok(!deleted.is_empty())
     }
 
     async fn get_log_heights(&self, log_id: &L) -> Result<Vec<(PublicKey, SeqNum)>, Self::Error> {
-        let log_heights = self
+        Ok(self.read_store().get_log_heights(log_id))
+    }
+
+    async fn get_log_ids_page(
+        &self,
+        public_key: &PublicKey,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<L>, Self::Error>
+    where
+        L: Ord,
+    {
+        Ok(self
             .read_store()
-            .logs
-            .iter()
-            .filter_map(|((public_key, inner_log_id), log)| {
-                if inner_log_id == log_id {
-                    let log_height = log
-                        .last()
-                        .expect("all logs contain at least one operation")
-                        .0;
-                    Some((*public_key, log_height))
-                } else {
-                    None
-                }
-            })
-            .collect();
-        Ok(log_heights)
+            .get_log_ids_page(public_key, offset, limit))
+    }
+}
+
+/// Read-only, point-in-time view of a `MemoryStore`.
+///
+/// Returned by `OperationStore::snapshot`. It shares the underlying maps with the store it was
+/// taken from, operations written to the store afterwards are not visible here.
+#[derive(Clone, Debug)]
+pub struct MemoryStoreSnapshot<L, E = ()> {
+    inner: InnerMemoryStore<L, E>,
+}
+
+/// Error returned when trying to delete from a `MemoryStoreSnapshot`.
+#[derive(Debug)]
+pub struct ReadOnlySnapshotError;
+
+impl Display for ReadOnlySnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "store snapshot is read-only")
+    }
+}
+
+impl std::error::Error for ReadOnlySnapshotError {}
+
+impl<L, E> LogStore<L, E> for MemoryStoreSnapshot<L, E>
+where
+    L: LogId + Send + Sync,
+    E: Extensions + Send + Sync,
+{
+    type Error = ReadOnlySnapshotError;
+
+    async fn get_log(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> Result<Option<Vec<(Header<E>, Option<Body>)>>, Self::Error> {
+        Ok(self.inner.get_log(public_key, log_id, from))
+    }
+
+    fn get_log_stream(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> impl Stream<Item = Result<(Header<E>, Option<Body>), Self::Error>> {
+        let hashes = self.inner.log_hashes(public_key, log_id, from);
+
+        let operations = self.inner.operations.clone();
+        stream::iter(hashes.into_iter().map(move |hash| {
+            let (_, header, body, _) = operations.get(&hash).expect("exists in hash map");
+            Ok((header.to_owned(), body.to_owned()))
+        }))
+    }
+
+    async fn get_raw_log(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> Result<Option<Vec<RawOperation>>, Self::Error> {
+        Ok(self.inner.get_raw_log(public_key, log_id, from))
+    }
+
+    async fn get_raw_log_headers(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+        from: Option<u64>,
+    ) -> Result<Option<Vec<RawHeader>>, Self::Error> {
+        Ok(self.inner.get_raw_log_headers(public_key, log_id, from))
+    }
+
+    async fn latest_operation(
+        &self,
+        public_key: &PublicKey,
+        log_id: &L,
+    ) -> Result<Option<(Header<E>, Option<Body>)>, Self::Error> {
+        Ok(self.inner.latest_operation(public_key, log_id))
+    }
+
+    async fn delete_operations(
+        &mut self,
+        _public_key: &PublicKey,
+        _log_id: &L,
+        _before: u64,
+    ) -> Result<bool, Self::Error> {
+        Err(ReadOnlySnapshotError)
+    }
+
+    async fn delete_payloads(
+        &mut self,
+        _public_key: &PublicKey,
+        _log_id: &L,
+        _from: u64,
+        _to: u64,
+    ) -> Result<bool, Self::Error> {
+        Err(ReadOnlySnapshotError)
+    }
+
+    async fn get_log_heights(&self, log_id: &L) -> Result<Vec<(PublicKey, SeqNum)>, Self::Error> {
+        Ok(self.inner.get_log_heights(log_id))
+    }
+
+    async fn get_log_ids_page(
+        &self,
+        public_key: &PublicKey,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<L>, Self::Error>
+    where
+        L: Ord,
+    {
+        Ok(self.inner.get_log_ids_page(public_key, offset, limit))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use p2panda_core::{Body, Hash, Header, PrivateKey};
+    use futures_util::StreamExt;
+    use p2panda_core::{Body, Hash, Header, Operation, PrivateKey};
     use serde::{Deserialize, Serialize};
 
     use crate::{LogStore, OperationStore};
@@ -412,6 +759,57 @@ mod tests {
         assert!(inserted);
     }
 
+    #[tokio::test]
+    async fn snapshot() {
+        let mut store = MemoryStore::default();
+        let private_key = PrivateKey::new();
+        let body = Body::new("hello!".as_bytes());
+
+        let (hash_0, header_0, header_bytes_0) = create_operation(&private_key, &body, 0, 0, None);
+        let (hash_1, header_1, header_bytes_1) =
+            create_operation(&private_key, &body, 1, 1, Some(hash_0));
+
+        store
+            .insert_operation(hash_0, &header_0, Some(&body), &header_bytes_0, &0)
+            .await
+            .unwrap();
+
+        let snapshot = store.snapshot().await.unwrap();
+
+        // Mutate the store after the snapshot was taken.
+        store
+            .insert_operation(hash_1, &header_1, Some(&body), &header_bytes_1, &0)
+            .await
+            .unwrap();
+        store.delete_payload(hash_0).await.unwrap();
+
+        // The snapshot still reflects the original contents.
+        let log = snapshot
+            .get_log(&private_key.public_key(), &0, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(log, vec![(header_0.clone(), Some(body.clone()))]);
+        assert_eq!(
+            snapshot.get_log_heights(&0).await.unwrap(),
+            vec![(private_key.public_key(), 0)]
+        );
+
+        // Snapshots can't be written to.
+        let mut snapshot = snapshot;
+        assert!(snapshot
+            .delete_operations(&private_key.public_key(), &0, 1)
+            .await
+            .is_err());
+
+        // While the store itself moved on.
+        assert!(store.has_operation(hash_1).await.unwrap());
+        assert_eq!(
+            store.get_log_heights(&0).await.unwrap(),
+            vec![(private_key.public_key(), 1)]
+        );
+    }
+
     #[tokio::test]
     async fn insert_get_operation() {
         let mut store = MemoryStore::default();
@@ -597,6 +995,214 @@ mod tests {
         assert_eq!(log[1].0, header_bytes_2);
         assert_eq!(log[0].1, Some(body_1.to_bytes()));
         assert_eq!(log[1].1, Some(body_2.to_bytes()));
+
+        // Get only the raw headers starting from sequence number 1.
+        let headers = store
+            .get_raw_log_headers(&private_key.public_key(), &log_id, Some(1))
+            .await
+            .expect("no errors")
+            .expect("log should exist");
+
+        assert_eq!(headers, vec![header_bytes_1, header_bytes_2]);
+
+        // Unknown logs return nothing.
+        let headers = store
+            .get_raw_log_headers(&private_key.public_key(), &1, None)
+            .await
+            .expect("no errors");
+
+        assert!(headers.is_none());
+    }
+
+    #[tokio::test]
+    async fn insert_operations_batch() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+
+        let mut operations = Vec::new();
+        let mut backlink = None;
+        for seq_num in 0..1000 {
+            let body = Body::new(format!("hello #{seq_num}").as_bytes());
+            let (hash, header, _) = create_operation(&private_key, &body, seq_num, 0, backlink);
+            backlink = Some(hash);
+            operations.push(Operation {
+                hash,
+                header,
+                body: Some(body),
+            });
+        }
+
+        // Insert operations one-by-one.
+        let mut store_loop = MemoryStore::default();
+        for operation in &operations {
+            store_loop
+                .insert_operation(
+                    operation.hash,
+                    &operation.header,
+                    operation.body.as_ref(),
+                    &operation.header.to_bytes(),
+                    &log_id,
+                )
+                .await
+                .expect("no errors");
+        }
+
+        // Insert all operations in one batch.
+        let mut store_batch = MemoryStore::default();
+        let inserted = store_batch
+            .insert_operations_batch(&operations, &log_id)
+            .await
+            .expect("no errors");
+        assert_eq!(inserted, 1000);
+
+        // Both stores contain the same data.
+        let log_loop = store_loop
+            .get_raw_log(&private_key.public_key(), &log_id, None)
+            .await
+            .expect("no errors");
+        let log_batch = store_batch
+            .get_raw_log(&private_key.public_key(), &log_id, None)
+            .await
+            .expect("no errors");
+        assert_eq!(log_loop, log_batch);
+
+        // Already existing operations are skipped.
+        let inserted = store_batch
+            .insert_operations_batch(&operations[..10], &log_id)
+            .await
+            .expect("no errors");
+        assert_eq!(inserted, 0);
+
+        // Duplicates within the same batch are skipped as well.
+        let mut store_duplicates = MemoryStore::default();
+        let inserted = store_duplicates
+            .insert_operations_batch(&[operations[0].clone(), operations[0].clone()], &log_id)
+            .await
+            .expect("no errors");
+        assert_eq!(inserted, 1);
+        let log = store_duplicates
+            .get_log(&private_key.public_key(), &log_id, None)
+            .await
+            .expect("no errors")
+            .expect("log should exist");
+        assert_eq!(log.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn delete_operations_before() {
+        let mut store = MemoryStore::default();
+        let private_key_a = PrivateKey::new();
+        let private_key_b = PrivateKey::new();
+        let log_id = 0;
+        let other_log_id = 1;
+
+        let body = Body::new("hello!".as_bytes());
+
+        // Two authors write into the same log with timestamps out of order.
+        let (hash_a0, header_a0, header_bytes_a0) =
+            create_operation(&private_key_a, &body, 0, 300, None);
+        let (hash_a1, header_a1, header_bytes_a1) =
+            create_operation(&private_key_a, &body, 1, 100, Some(hash_a0));
+        let (hash_a2, header_a2, header_bytes_a2) =
+            create_operation(&private_key_a, &body, 2, 400, Some(hash_a1));
+        let (hash_b0, header_b0, header_bytes_b0) =
+            create_operation(&private_key_b, &body, 0, 50, None);
+
+        // The operation in the other log is older than the cutoff but should not be touched.
+        let (hash_c0, header_c0, header_bytes_c0) =
+            create_operation(&private_key_a, &body, 0, 10, None);
+
+        for (hash, header, header_bytes, log_id) in [
+            (hash_a0, &header_a0, &header_bytes_a0, &log_id),
+            (hash_a1, &header_a1, &header_bytes_a1, &log_id),
+            (hash_a2, &header_a2, &header_bytes_a2, &log_id),
+            (hash_b0, &header_b0, &header_bytes_b0, &log_id),
+            (hash_c0, &header_c0, &header_bytes_c0, &other_log_id),
+        ] {
+            store
+                .insert_operation(hash, header, Some(&body), header_bytes, log_id)
+                .await
+                .expect("no errors");
+        }
+
+        // Delete all operations older than timestamp 300.
+        let deleted = store
+            .delete_operations_before(&log_id, 300)
+            .await
+            .expect("no errors");
+        assert_eq!(deleted, 2);
+
+        // Only operations with a timestamp of 300 and later remain, which leaves a gap in the log
+        // of the first author.
+        let log = store
+            .get_log(&private_key_a.public_key(), &log_id, None)
+            .await
+            .expect("no errors")
+            .expect("log should exist");
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].0.hash(), hash_a0);
+        assert_eq!(log[1].0.hash(), hash_a2);
+        assert!(!store.has_operation(hash_a1).await.expect("no errors"));
+
+        // The log of the second author is empty now and was removed.
+        let log = store
+            .get_log(&private_key_b.public_key(), &log_id, None)
+            .await
+            .expect("no errors");
+        assert!(log.is_none());
+        assert!(!store.has_operation(hash_b0).await.expect("no errors"));
+
+        // Operations in other logs are kept.
+        assert!(store.has_operation(hash_c0).await.expect("no errors"));
+    }
+
+    #[tokio::test]
+    async fn get_log_stream() {
+        let mut store = MemoryStore::default();
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+
+        // Insert operations in reverse order to make sure the stream yields them sorted by
+        // sequence number.
+        let mut hashes = Vec::new();
+        let mut operations = Vec::new();
+        let mut backlink = None;
+        for seq_num in 0..10 {
+            let body = Body::new(format!("hello #{seq_num}").as_bytes());
+            let (hash, header, header_bytes) =
+                create_operation(&private_key, &body, seq_num, 0, backlink);
+            backlink = Some(hash);
+            hashes.push(hash);
+            operations.push((hash, header, body, header_bytes));
+        }
+        for (hash, header, body, header_bytes) in operations.iter().rev() {
+            store
+                .insert_operation(*hash, header, Some(body), header_bytes, &log_id)
+                .await
+                .expect("no errors");
+        }
+
+        let log: Vec<_> = store
+            .get_log_stream(&private_key.public_key(), &log_id, None)
+            .map(|result| result.expect("no errors").0.hash())
+            .collect()
+            .await;
+        assert_eq!(log, hashes);
+
+        // Stream all log operations starting from sequence number 5.
+        let log: Vec<_> = store
+            .get_log_stream(&private_key.public_key(), &log_id, Some(5))
+            .map(|result| result.expect("no errors").0.hash())
+            .collect()
+            .await;
+        assert_eq!(log, hashes[5..]);
+
+        // Streams of unknown logs are empty.
+        let log: Vec<_> = store
+            .get_log_stream(&PrivateKey::new().public_key(), &log_id, None)
+            .collect()
+            .await;
+        assert!(log.is_empty());
     }
 
     #[tokio::test]
@@ -730,6 +1336,44 @@ mod tests {
         assert_eq!(author_b_log[0].0.hash(), header_b.hash());
     }
 
+    #[tokio::test]
+    async fn deterministic_log_heights() {
+        let private_keys: Vec<PrivateKey> = (0..10).map(|_| PrivateKey::new()).collect();
+        let log_id = 0;
+        let body = Body::new("hello!".as_bytes());
+
+        // Insert the same operations into two stores, in reverse order for the second one.
+        let mut store_a = MemoryStore::default();
+        let mut store_b = MemoryStore::default();
+        for private_key in &private_keys {
+            let (hash, header, header_bytes) = create_operation(private_key, &body, 0, 0, None);
+            store_a
+                .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                .await
+                .expect("no errors");
+        }
+        for private_key in private_keys.iter().rev() {
+            let (hash, header, header_bytes) = create_operation(private_key, &body, 0, 0, None);
+            store_b
+                .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                .await
+                .expect("no errors");
+        }
+
+        let log_heights_a = store_a.get_log_heights(&log_id).await.expect("no errors");
+        let log_heights_b = store_b.get_log_heights(&log_id).await.expect("no errors");
+        assert_eq!(log_heights_a.len(), 10);
+        assert_eq!(log_heights_a, log_heights_b);
+
+        // Log heights are ordered by public key.
+        let mut expected: Vec<_> = private_keys
+            .iter()
+            .map(|private_key| (private_key.public_key(), 0))
+            .collect();
+        expected.sort();
+        assert_eq!(log_heights_a, expected);
+    }
+
     #[tokio::test]
     async fn get_latest_operation() {
         let mut store = MemoryStore::default();
@@ -875,4 +1519,57 @@ mod tests {
         assert_eq!(log[1].1, None);
         assert_eq!(log[2].1, Some(body_2));
     }
+
+    #[tokio::test]
+    async fn get_log_ids_page() {
+        let mut store = MemoryStore::default();
+        let private_key_a = PrivateKey::new();
+        let private_key_b = PrivateKey::new();
+        let body = Body::new("hello!".as_bytes());
+
+        for log_id in (0..100).rev() {
+            let (hash, header, header_bytes) =
+                create_operation(&private_key_a, &body, 0, log_id, None);
+            store
+                .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                .await
+                .expect("no errors");
+        }
+
+        // Logs of other authors are not included.
+        let (hash, header, header_bytes) = create_operation(&private_key_b, &body, 0, 0, None);
+        store
+            .insert_operation(hash, &header, Some(&body), &header_bytes, &1000)
+            .await
+            .expect("no errors");
+
+        let mut log_ids = Vec::new();
+        let mut offset = 0;
+        loop {
+            let page = store
+                .get_log_ids_page(&private_key_a.public_key(), offset, 10)
+                .await
+                .expect("no errors");
+            if page.is_empty() {
+                break;
+            }
+            assert_eq!(page.len(), 10);
+            offset += page.len();
+            log_ids.extend(page);
+        }
+
+        assert_eq!(log_ids, (0..100).collect::<Vec<_>>());
+
+        // Logs added after walking through them show up in the next pages.
+        let (hash, header, header_bytes) = create_operation(&private_key_a, &body, 0, 100, None);
+        store
+            .insert_operation(hash, &header, Some(&body), &header_bytes, &100)
+            .await
+            .expect("no errors");
+        let page = store
+            .get_log_ids_page(&private_key_a.public_key(), 95, 10)
+            .await
+            .expect("no errors");
+        assert_eq!(page, vec![95, 96, 97, 98, 99, 100]);
+    }
 }
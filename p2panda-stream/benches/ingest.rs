@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Compares replaying operations through `ingest` with and without signature verification.
+//!
+//! Run with `cargo bench -p p2panda-stream`.
+use std::time::{Duration, Instant};
+
+use futures_util::stream::iter;
+use futures_util::{StreamExt, TryStreamExt};
+use p2panda_core::prune::PruneFlag;
+use p2panda_core::{Body, Extension, Header, Operation, PrivateKey, RawOperation};
+use p2panda_store::MemoryStore;
+use p2panda_stream::{DecodeExt, IngestExt};
+use serde::{Deserialize, Serialize};
+
+const OPERATIONS_NUM: usize = 1000;
+
+const ITERATIONS: u32 = 10;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Extensions {
+    log_id: u64,
+    prune_flag: PruneFlag,
+}
+
+impl Extension<u64> for Extensions {
+    fn extract(header: &Header<Self>) -> Option<u64> {
+        header
+            .extensions
+            .as_ref()
+            .map(|extensions| extensions.log_id)
+    }
+}
+
+impl Extension<PruneFlag> for Extensions {
+    fn extract(header: &Header<Self>) -> Option<PruneFlag> {
+        header
+            .extensions
+            .as_ref()
+            .map(|extensions| extensions.prune_flag.clone())
+    }
+}
+
+fn operations(num: usize) -> Vec<RawOperation> {
+    let private_key = PrivateKey::new();
+    let body = Body::new(b"Hello, Penguin!");
+
+    let mut backlink = None;
+    (0..num as u64)
+        .map(|seq_num| {
+            let mut header = Header::<Extensions> {
+                public_key: private_key.public_key(),
+                version: 1,
+                signature: None,
+                payload_size: body.size(),
+                payload_hash: Some(body.hash()),
+                timestamp: 0,
+                seq_num,
+                backlink,
+                previous: vec![],
+                extensions: Some(Extensions::default()),
+            };
+            header.sign(&private_key);
+            backlink = Some(header.hash());
+            (header.to_bytes(), Some(body.to_bytes()))
+        })
+        .collect()
+}
+
+async fn replay(operations: Vec<RawOperation>, trust_local: bool) -> Duration {
+    let store = MemoryStore::<u64, Extensions>::new();
+    let stream = iter(operations)
+        .decode()
+        .filter_map(|item| async { item.ok() })
+        .ingest(store, 16);
+    let stream = if trust_local {
+        stream.trust_local()
+    } else {
+        stream
+    };
+
+    let now = Instant::now();
+    let res: Vec<Operation<Extensions>> = stream.try_collect().await.expect("valid operations");
+    let elapsed = now.elapsed();
+    assert_eq!(res.len(), OPERATIONS_NUM);
+    elapsed
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("tokio runtime");
+    let operations = operations(OPERATIONS_NUM);
+
+    for trust_local in [false, true] {
+        let total: Duration = (0..ITERATIONS)
+            .map(|_| runtime.block_on(replay(operations.clone(), trust_local)))
+            .sum();
+        println!(
+            "ingest {OPERATIONS_NUM} operations (trust_local: {trust_local}): {:?} per iteration",
+            total / ITERATIONS
+        );
+    }
+}
@@ -2,7 +2,8 @@
 
 //! Methods to handle p2panda operations.
 use p2panda_core::{
-    validate_backlink, validate_operation, Body, Extensions, Header, Operation, OperationError,
+    validate_backlink, validate_header_structure, validate_operation, validate_operations, Body,
+    Extensions, Hash, Header, Operation, OperationError,
 };
 use p2panda_store::{LogStore, OperationStore};
 use thiserror::Error;
@@ -35,6 +36,107 @@ where
         return Err(IngestError::InvalidOperation(err));
     }
 
+    ingest_validated_operation(store, operation, header_bytes, log_id, prune_flag).await
+}
+
+/// Checks an operation coming from a trusted source for log integrity and persists it into the
+/// store.
+///
+/// This behaves like [`ingest_operation`] but skips all cryptographic checks: Neither the
+/// signature nor the payload hash and size are verified. The structure of the header and the
+/// integrity of the log are still checked.
+///
+/// This is useful when replaying operations which were already validated before, for example
+/// when re-building an index from our own local store.
+///
+/// **Warning:** Never use this method with operations coming from untrusted sources (for example
+/// other peers), as forged or tampered operations would be persisted without notice.
+pub async fn ingest_trusted_operation<S, L, E>(
+    store: &mut S,
+    header: Header<E>,
+    body: Option<Body>,
+    header_bytes: Vec<u8>,
+    log_id: &L,
+    prune_flag: bool,
+) -> Result<IngestResult<E>, IngestError>
+where
+    S: OperationStore<L, E> + LogStore<L, E>,
+    E: Extensions,
+{
+    if let Err(err) = validate_header_structure(&header) {
+        return Err(IngestError::InvalidOperation(err));
+    }
+
+    let operation = Operation {
+        hash: header.hash(),
+        header,
+        body,
+    };
+
+    ingest_validated_operation(store, operation, header_bytes, log_id, prune_flag).await
+}
+
+/// Checks many incoming operations of the same log and persists them into the store when valid.
+///
+/// This behaves like calling [`ingest_operation`] for each operation in the given order, but
+/// verifies the signatures of all operations at once, which considerably speeds up ingesting
+/// large batches, for example after syncing a long log. Invalid operations are still detected
+/// individually and only they will be rejected.
+///
+/// Returns the ingest result for each operation in the same order as they were given.
+pub async fn ingest_operations<S, L, E>(
+    store: &mut S,
+    operations: Vec<(Header<E>, Option<Body>, Vec<u8>)>,
+    log_id: &L,
+    prune_flag: bool,
+) -> Vec<Result<IngestResult<E>, IngestError>>
+where
+    S: OperationStore<L, E> + LogStore<L, E>,
+    E: Extensions,
+{
+    let (operations, header_bytes): (Vec<Operation<E>>, Vec<Vec<u8>>) = operations
+        .into_iter()
+        .map(|(header, body, header_bytes)| {
+            let operation = Operation {
+                hash: header.hash(),
+                header,
+                body,
+            };
+            (operation, header_bytes)
+        })
+        .unzip();
+
+    let validation_results = validate_operations(&operations);
+
+    let mut results = Vec::with_capacity(operations.len());
+    for ((operation, header_bytes), validation_result) in operations
+        .into_iter()
+        .zip(header_bytes)
+        .zip(validation_results)
+    {
+        let result = match validation_result {
+            Ok(()) => {
+                ingest_validated_operation(store, operation, header_bytes, log_id, prune_flag).await
+            }
+            Err(err) => Err(IngestError::InvalidOperation(err)),
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// Checks the log integrity of an already validated operation and persists it.
+async fn ingest_validated_operation<S, L, E>(
+    store: &mut S,
+    operation: Operation<E>,
+    header_bytes: Vec<u8>,
+    log_id: &L,
+    prune_flag: bool,
+) -> Result<IngestResult<E>, IngestError>
+where
+    S: OperationStore<L, E> + LogStore<L, E>,
+    E: Extensions,
+{
     let already_exists = store
         .has_operation(operation.hash)
         .await
@@ -141,14 +243,29 @@ pub enum IngestError {
     /// out-of-order. This error comes up when all given attempts have been exhausted.
     #[error("too many attempts to ingest out-of-order operation ({0} behind in log)")]
     MaxAttemptsReached(u64),
+
+    /// Operation payload exceeds the configured maximum size.
+    #[error("payload size of {0} bytes exceeds maximum of {1} bytes")]
+    PayloadTooLarge(u64, u64),
+
+    /// A single operation was rejected while the ingest stream keeps on processing the following
+    /// ones.
+    ///
+    /// This error contains the hash of the offending operation and the reason why it could not be
+    /// ingested.
+    #[error("rejected operation {hash}: {reason}")]
+    Rejected {
+        hash: Hash,
+        reason: Box<IngestError>,
+    },
 }
 
 #[cfg(test)]
 mod tests {
-    use p2panda_core::{Hash, Header, PrivateKey};
-    use p2panda_store::MemoryStore;
+    use p2panda_core::{Hash, Header, OperationError, PrivateKey};
+    use p2panda_store::{MemoryStore, OperationStore};
 
-    use crate::operation::{ingest_operation, IngestResult};
+    use crate::operation::{ingest_operation, ingest_operations, IngestError, IngestResult};
     use crate::test_utils::Extensions;
 
     #[tokio::test]
@@ -196,4 +313,57 @@ mod tests {
         let result = ingest_operation(&mut store, header, None, header_bytes, &log_id, false).await;
         assert!(matches!(result, Ok(IngestResult::Retry(_, None, _, 11))));
     }
+
+    #[tokio::test]
+    async fn ingest_batch_with_tampered_operation() {
+        let mut store = MemoryStore::<usize, Extensions>::new();
+        let private_key = PrivateKey::new();
+        let log_id = 1;
+
+        let mut operations = Vec::new();
+        let mut backlink = None;
+        for seq_num in 0..10 {
+            let mut header = Header {
+                public_key: private_key.public_key(),
+                version: 1,
+                signature: None,
+                payload_size: 0,
+                payload_hash: None,
+                timestamp: seq_num,
+                seq_num,
+                backlink,
+                previous: vec![],
+                extensions: None,
+            };
+            header.sign(&private_key);
+            backlink = Some(header.hash());
+            operations.push(header);
+        }
+
+        // Tamper with the last operation after it was signed.
+        operations[9].timestamp = 12;
+        let tampered_hash = operations[9].hash();
+
+        let operations = operations
+            .into_iter()
+            .map(|header| {
+                let header_bytes = header.to_bytes();
+                (header, None, header_bytes)
+            })
+            .collect();
+        let results = ingest_operations(&mut store, operations, &log_id, false).await;
+        assert_eq!(results.len(), 10);
+
+        // All valid operations got ingested, only the tampered one was rejected.
+        for result in &results[..9] {
+            assert!(matches!(result, Ok(IngestResult::Complete(_))));
+        }
+        assert!(matches!(
+            results[9],
+            Err(IngestError::InvalidOperation(
+                OperationError::SignatureMismatch
+            ))
+        ));
+        assert!(!store.has_operation(tampered_hash).await.unwrap());
+    }
 }
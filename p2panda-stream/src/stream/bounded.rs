@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+use futures_util::stream::{Fuse, FusedStream};
+use futures_util::task::{Context, Poll};
+use futures_util::{Sink, Stream, StreamExt};
+use pin_project::pin_project;
+
+use crate::macros::{delegate_access_inner, delegate_sink};
+
+/// An extension trait for `Stream`s that provides a convenient [`bounded`](BoundedExt::bounded)
+/// method.
+pub trait BoundedExt: Stream {
+    /// Pulls items from the upstream ahead of time and holds them in a buffer of the given
+    /// capacity.
+    ///
+    /// This decouples the stacked stages before and after this method: upstream items (for
+    /// example incoming operations waiting to be decoded) are taken in as soon as they're
+    /// available, while the downstream (for example a slow store writer) consumes them at its own
+    /// pace. As soon as the buffer is full the upstream is not polled anymore until the downstream
+    /// took items out, no item is ever dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is zero.
+    fn bounded(self, capacity: usize) -> Bounded<Self>
+    where
+        Self: Sized,
+    {
+        Bounded::new(self, capacity)
+    }
+}
+
+impl<T: ?Sized> BoundedExt for T where T: Stream {}
+
+/// Stream for the [`bounded`](BoundedExt::bounded) method.
+#[derive(Debug)]
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+pub struct Bounded<St>
+where
+    St: Stream,
+{
+    #[pin]
+    stream: Fuse<St>,
+    buffer: VecDeque<St::Item>,
+    capacity: usize,
+}
+
+impl<St> Bounded<St>
+where
+    St: Stream,
+{
+    pub(super) fn new(stream: St, capacity: usize) -> Bounded<St> {
+        assert!(
+            capacity > 0,
+            "capacity of bounded stream must be at least 1"
+        );
+
+        Bounded {
+            stream: stream.fuse(),
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    delegate_access_inner!(stream, St, (.));
+}
+
+impl<St> Stream for Bounded<St>
+where
+    St: Stream,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        // Take in as many items as are available from the upstream, as long as there's space left
+        // in the buffer.
+        while this.buffer.len() < *this.capacity {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => this.buffer.push_back(item),
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
+        match this.buffer.pop_front() {
+            Some(item) => Poll::Ready(Some(item)),
+            None if this.stream.is_done() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.stream.size_hint();
+        let buffered = self.buffer.len();
+        (
+            lower.saturating_add(buffered),
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+}
+
+impl<St> FusedStream for Bounded<St>
+where
+    St: Stream,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated() && self.buffer.is_empty()
+    }
+}
+
+impl<St, Item> Sink<Item> for Bounded<St>
+where
+    St: Stream + Sink<Item>,
+{
+    type Error = St::Error;
+
+    delegate_sink!(stream, Item);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+    use p2panda_store::MemoryStore;
+
+    use crate::stream::decode::DecodeExt;
+    use crate::stream::ingest::IngestExt;
+    use crate::test_utils::{mock_stream, Extensions, StreamName};
+
+    use super::BoundedExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_consumer() {
+        let items_num = 50;
+        let capacity = 4;
+        let store = MemoryStore::<StreamName, Extensions>::new();
+        let produced = Arc::new(AtomicUsize::new(0));
+
+        let stream = mock_stream()
+            .take(items_num)
+            .inspect({
+                let produced = produced.clone();
+                move |_| {
+                    produced.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .decode()
+            .bounded(capacity)
+            .filter_map(|item| async { item.ok() })
+            .ingest(store, 16);
+        tokio::pin!(stream);
+
+        let mut consumed = 0;
+        while let Some(result) = stream.next().await {
+            assert!(result.is_ok());
+            consumed += 1;
+
+            // The upstream never runs further ahead than the capacity of the buffer.
+            assert!(produced.load(Ordering::SeqCst) <= consumed + capacity);
+
+            // Deliberately slow consumer.
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // No operation got dropped.
+        assert_eq!(consumed, items_num);
+        assert_eq!(produced.load(Ordering::SeqCst), items_num);
+    }
+}
@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use p2panda_core::cbor::{decode_cbor, encode_cbor, DecodeError, EncodeError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Snapshot of all operations waiting in the out-of-order buffer of an
+/// [`ingest`](crate::IngestExt::ingest) stream.
+///
+/// Operations which were already ingested are persisted in the store and are not part of the
+/// checkpoint, it only captures the ones which arrived before their dependencies. Persisting the
+/// checkpoint and restoring it after a restart allows resuming ingest exactly where it was left
+/// off, without requiring the remote peers to send these operations again.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub(crate) operations: Vec<BufferedOperation>,
+}
+
+impl Checkpoint {
+    /// Encodes the checkpoint into bytes which can be persisted.
+    pub fn save(&self) -> Result<Vec<u8>, CheckpointError> {
+        Ok(encode_cbor(self)?)
+    }
+
+    /// Decodes a checkpoint from bytes previously returned by [`save`](Checkpoint::save).
+    pub fn restore(bytes: &[u8]) -> Result<Self, CheckpointError> {
+        Ok(decode_cbor(bytes)?)
+    }
+
+    /// Returns the number of operations in the checkpoint.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Returns `true` if no operations were waiting in the buffer.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+/// Operation waiting in the out-of-order buffer together with the number of ingest attempts so
+/// far.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BufferedOperation {
+    pub header_bytes: Vec<u8>,
+    pub body_bytes: Option<Vec<u8>>,
+    pub attempts: usize,
+}
+
+/// Errors which can occur when saving or restoring a checkpoint.
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error(transparent)]
+    Encode(#[from] EncodeError),
+
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    /// The checkpoint holds more operations than fit into the out-of-order buffer of the stream
+    /// it was restored into.
+    #[error("checkpoint with {0} operations exceeds out-of-order buffer size")]
+    BufferTooSmall(usize),
+}
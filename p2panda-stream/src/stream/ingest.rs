@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::collections::HashSet;
 use std::future::Future;
 use std::marker::PhantomData;
 use std::pin::Pin;
@@ -8,14 +9,16 @@ use futures_channel::mpsc::{self};
 use futures_util::stream::{Fuse, FusedStream};
 use futures_util::task::{Context, Poll};
 use futures_util::{ready, Sink, Stream, StreamExt};
+use p2panda_core::cbor::decode_cbor;
 use p2panda_core::prune::PruneFlag;
-use p2panda_core::{Body, Extension, Extensions, Header, Operation};
+use p2panda_core::{Body, Extension, Extensions, Hash, Header, Operation};
 use p2panda_store::{LogStore, OperationStore};
 use pin_project::pin_project;
 use pin_utils::pin_mut;
 
 use crate::macros::{delegate_access_inner, delegate_sink};
-use crate::operation::{ingest_operation, IngestError, IngestResult};
+use crate::operation::{ingest_operation, ingest_trusted_operation, IngestError, IngestResult};
+use crate::stream::checkpoint::{BufferedOperation, Checkpoint, CheckpointError};
 
 /// An extension trait for `Stream`s that provides a convenient [`ingest`](IngestExt::ingest)
 /// method.
@@ -63,6 +66,9 @@ where
     ooo_buffer_tx: mpsc::Sender<IngestAttempt<E>>,
     #[pin]
     ooo_buffer_rx: mpsc::Receiver<IngestAttempt<E>>,
+    report_rejected: bool,
+    trust_local: bool,
+    max_payload_size: Option<u64>,
     _marker: PhantomData<L>,
 }
 
@@ -88,10 +94,141 @@ where
             ooo_buffer_size,
             ooo_buffer_tx,
             ooo_buffer_rx,
+            report_rejected: false,
+            trust_local: false,
+            max_payload_size: None,
             _marker: PhantomData,
         }
     }
 
+    /// Restores operations from a checkpoint into the out-of-order buffer.
+    ///
+    /// The restored operations are re-attempted like any other buffered operation, this includes
+    /// operations whose dependencies were already persisted in the store before the checkpoint
+    /// was taken.
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Result<Self, CheckpointError> {
+        if checkpoint.len() > self.ooo_buffer_size {
+            return Err(CheckpointError::BufferTooSmall(checkpoint.len()));
+        }
+
+        for operation in checkpoint.operations {
+            let header: Header<E> = decode_cbor(&operation.header_bytes[..])?;
+            self.ooo_buffer_tx
+                .try_send(IngestAttempt(
+                    header,
+                    operation.body_bytes.map(Body::from),
+                    operation.header_bytes,
+                    operation.attempts,
+                ))
+                .expect("buffer has enough capacity");
+        }
+
+        Ok(self)
+    }
+
+    /// Reports invalid operations together with their hash.
+    ///
+    /// A single invalid operation never terminates the stream, following operations are still
+    /// ingested. With this option enabled, every operation which failed validation, is missing
+    /// required header extensions, exceeds the maximum payload size or exhausted its re-attempts
+    /// is emitted as an
+    /// [`IngestError::Rejected`] item, carrying the hash of the offending operation next to the
+    /// reason. This allows consumers to skip or report single bad operations (for example coming
+    /// from a misbehaving peer) instead of aborting sync altogether.
+    ///
+    /// Critical storage failures are not related to a particular operation and are still emitted
+    /// as [`IngestError::StoreError`].
+    ///
+    /// Consumers should handle each item individually in this mode, as combinators like
+    /// `try_collect` stop at the first error.
+    pub fn report_rejected(mut self) -> Self {
+        self.report_rejected = true;
+        self
+    }
+
+    /// Skips cryptographic verification of all ingested operations.
+    ///
+    /// Signatures and payload hashes are not verified anymore, while the header structure, log
+    /// integrity and ordering are still checked as usual. See [`ingest_trusted_operation`] for
+    /// details.
+    ///
+    /// This speeds up replaying operations which were already validated before, for example when
+    /// re-building an index from our own local store.
+    ///
+    /// **Warning:** This is unsafe for untrusted input. Never enable this option for streams
+    /// carrying operations from other peers, as forged or tampered operations would be persisted
+    /// without notice.
+    pub fn trust_local(mut self) -> Self {
+        self.trust_local = true;
+        self
+    }
+
+    /// Rejects operations with a payload larger than the given number of bytes.
+    ///
+    /// Both the payload size claimed in the header and the size of the given body are checked,
+    /// oversized operations are rejected with [`IngestError::PayloadTooLarge`] before they get
+    /// validated or persisted.
+    ///
+    /// Note that the body was already read into memory by the time it reaches this stream, this
+    /// option does not protect against large allocations. Limit the size of incoming messages on
+    /// the transport layer for this.
+    pub fn max_payload_size(mut self, max_payload_size: u64) -> Self {
+        self.max_payload_size = Some(max_payload_size);
+        self
+    }
+
+    /// Takes a snapshot of all operations currently waiting in the out-of-order buffer.
+    ///
+    /// The stream keeps on working as usual after taking the checkpoint.
+    pub fn checkpoint(self: Pin<&mut Self>) -> Checkpoint {
+        let mut operations = Vec::new();
+        self.inspect_buffer(|IngestAttempt(_, body, header_bytes, counter)| {
+            operations.push(BufferedOperation {
+                header_bytes: header_bytes.clone(),
+                body_bytes: body.as_ref().map(|body| body.to_bytes()),
+                attempts: *counter,
+            });
+        });
+        Checkpoint { operations }
+    }
+
+    /// Returns the number of operations waiting in the out-of-order buffer and the hashes of the
+    /// operations they are waiting for.
+    ///
+    /// This is purely observational and does not change the order in which buffered operations
+    /// are re-attempted.
+    pub fn pending_operations(self: Pin<&mut Self>) -> PendingOperations {
+        let mut pending = PendingOperations::default();
+        self.inspect_buffer(|IngestAttempt(header, _, _, _)| {
+            pending.count += 1;
+            if let Some(backlink) = header.backlink {
+                pending.missing.insert(backlink);
+            }
+        });
+        pending
+    }
+
+    /// Calls the given function for every operation in the out-of-order buffer, in the order in
+    /// which they will be re-attempted.
+    fn inspect_buffer(self: Pin<&mut Self>, mut f: impl FnMut(&IngestAttempt<E>)) {
+        let this = self.project();
+        let ooo_buffer_rx = this.ooo_buffer_rx.get_mut();
+
+        let mut attempts = Vec::new();
+        while let Ok(attempt) = ooo_buffer_rx.try_recv() {
+            attempts.push(attempt);
+        }
+
+        for attempt in attempts {
+            f(&attempt);
+
+            // Put the operation back, we've just made space for it.
+            this.ooo_buffer_tx
+                .try_send(attempt)
+                .expect("buffer has enough capacity");
+        }
+    }
+
     delegate_access_inner!(stream, St, (.));
 }
 
@@ -150,24 +287,54 @@ where
                 return Poll::Ready(None);
             };
 
+            // Remember the hash of the operation in case we need to report it as rejected.
+            let hash = if *this.report_rejected {
+                Some(header.hash())
+            } else {
+                None
+            };
+
             // 2. Validate and check the log-integrity of the incoming operation. If it is valid it
             //    get's persisted and the log optionally pruned.
+            let trust_local = *this.trust_local;
+            let max_payload_size = *this.max_payload_size;
             let ingest_fut = async {
+                if let Some(max_payload_size) = max_payload_size {
+                    // Check against the actual body size as well, in case the header lied about it.
+                    let body_size = body.as_ref().map_or(0, |body| body.size());
+                    let payload_size = header.payload_size.max(body_size);
+                    if payload_size > max_payload_size {
+                        return Err(IngestError::PayloadTooLarge(payload_size, max_payload_size));
+                    }
+                }
+
                 let log_id = header
                     .extension()
                     .ok_or(IngestError::MissingHeaderExtension("log_id".into()))?;
                 let prune_flag: PruneFlag = header
                     .extension()
                     .ok_or(IngestError::MissingHeaderExtension("prune_flag".into()))?;
-                ingest_operation::<S, L, E>(
-                    &mut store,
-                    header,
-                    body,
-                    header_bytes,
-                    &log_id,
-                    prune_flag.is_set(),
-                )
-                .await
+                if trust_local {
+                    ingest_trusted_operation::<S, L, E>(
+                        &mut store,
+                        header,
+                        body,
+                        header_bytes,
+                        &log_id,
+                        prune_flag.is_set(),
+                    )
+                    .await
+                } else {
+                    ingest_operation::<S, L, E>(
+                        &mut store,
+                        header,
+                        body,
+                        header_bytes,
+                        &log_id,
+                        prune_flag.is_set(),
+                    )
+                    .await
+                }
             };
             pin_mut!(ingest_fut);
             let ingest_res = ready!(ingest_fut.poll(cx));
@@ -182,8 +349,9 @@ where
                     // different parameters as in a worst-case distribution of items (exact
                     // reverse) this will be the max. and min. required bound.
                     if counter > *this.ooo_buffer_size {
-                        return Poll::Ready(Some(Err(IngestError::MaxAttemptsReached(
-                            num_missing,
+                        return Poll::Ready(Some(Err(rejected(
+                            hash,
+                            IngestError::MaxAttemptsReached(num_missing),
                         ))));
                     }
 
@@ -212,7 +380,7 @@ where
                 }
                 Err(err) => {
                     // Ingest failed and we want the stream consumers to be aware of that.
-                    return Poll::Ready(Some(Err(err)));
+                    return Poll::Ready(Some(Err(rejected(hash, err))));
                 }
             }
         }
@@ -242,22 +410,49 @@ where
     delegate_sink!(stream, (Header<E>, Option<Body>, Vec<u8>));
 }
 
+/// Operations waiting in the out-of-order buffer of the [`ingest`](IngestExt::ingest) method.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PendingOperations {
+    /// Number of buffered operations.
+    pub count: usize,
+
+    /// Hashes of the backlinks the buffered operations are waiting for.
+    pub missing: HashSet<Hash>,
+}
+
 #[derive(Debug)]
 struct IngestAttempt<E>(Header<E>, Option<Body>, Vec<u8>, usize);
 
+/// Wraps an ingest error into [`IngestError::Rejected`] when the hash of the offending operation
+/// should be reported.
+///
+/// Critical storage failures are not caused by the operation itself and are never wrapped.
+fn rejected(hash: Option<Hash>, err: IngestError) -> IngestError {
+    match (hash, err) {
+        (Some(hash), err) if !matches!(err, IngestError::StoreError(_)) => IngestError::Rejected {
+            hash,
+            reason: Box::new(err),
+        },
+        (_, err) => err,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
     use std::time::Duration;
 
-    use futures_util::stream::iter;
-    use futures_util::{StreamExt, TryStreamExt};
-    use p2panda_core::{Operation, RawOperation};
+    use futures_util::stream::{iter, pending};
+    use futures_util::{FutureExt, StreamExt, TryStreamExt};
+    use p2panda_core::cbor::decode_cbor;
+    use p2panda_core::{Body, Hash, Header, Operation, OperationError, PrivateKey, RawOperation};
     use p2panda_store::MemoryStore;
     use tokio::sync::mpsc;
     use tokio::time;
     use tokio_stream::wrappers::ReceiverStream;
 
     use crate::operation::IngestError;
+    use crate::stream::checkpoint::Checkpoint;
     use crate::stream::decode::DecodeExt;
     use crate::test_utils::{mock_stream, Extensions, StreamName};
 
@@ -344,4 +539,295 @@ mod tests {
         let res: Vec<Operation<Extensions>> = stream.try_collect().await.expect("not fail");
         assert_eq!(res.len(), 10);
     }
+
+    #[tokio::test]
+    async fn resume_from_checkpoint() {
+        let store = MemoryStore::<StreamName, Extensions>::new();
+        let operations: Vec<RawOperation> = mock_stream().take(10).collect().await;
+
+        // Operation 3 is missing, 4 and 5 end up in the out-of-order buffer.
+        let first_half: Vec<RawOperation> = [0, 1, 2, 4, 5]
+            .iter()
+            .map(|index| operations[*index].clone())
+            .collect();
+        let mut stream = Box::pin(
+            iter(first_half)
+                .chain(pending())
+                .decode()
+                .filter_map(|item| async { item.ok() })
+                .ingest(store.clone(), 16),
+        );
+
+        for seq_num in 0..3 {
+            let operation = stream.next().await.unwrap().unwrap();
+            assert_eq!(operation.header.seq_num, seq_num);
+        }
+        assert!(stream.next().now_or_never().is_none());
+
+        let bytes = stream.as_mut().checkpoint().save().unwrap();
+        drop(stream);
+
+        // Restore after the "restart" and feed the rest of the log.
+        let checkpoint = Checkpoint::restore(&bytes).unwrap();
+        assert_eq!(checkpoint.len(), 2);
+
+        let second_half: Vec<RawOperation> = [3, 6, 7, 8, 9]
+            .iter()
+            .map(|index| operations[*index].clone())
+            .collect();
+        let stream = iter(second_half)
+            .decode()
+            .filter_map(|item| async { item.ok() })
+            .ingest(store, 16)
+            .with_checkpoint(checkpoint)
+            .unwrap();
+
+        let res: Vec<Operation<Extensions>> = stream.try_collect().await.expect("not fail");
+        let seq_nums: Vec<u64> = res
+            .iter()
+            .map(|operation| operation.header.seq_num)
+            .collect();
+        assert_eq!(seq_nums, vec![3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[tokio::test]
+    async fn restore_with_dependencies_in_store() {
+        let store = MemoryStore::<StreamName, Extensions>::new();
+        let operations: Vec<RawOperation> = mock_stream().take(4).collect().await;
+
+        // Operation 2 waits for operation 1 in the buffer when we take the checkpoint.
+        let mut stream = Box::pin(
+            iter([operations[0].clone(), operations[2].clone()])
+                .chain(pending())
+                .decode()
+                .filter_map(|item| async { item.ok() })
+                .ingest(store.clone(), 16),
+        );
+        assert_eq!(stream.next().await.unwrap().unwrap().header.seq_num, 0);
+        assert!(stream.next().now_or_never().is_none());
+        let checkpoint = stream.as_mut().checkpoint();
+        drop(stream);
+
+        // Operation 1 arrives and gets persisted before the "crash".
+        let stream = iter([operations[1].clone()])
+            .decode()
+            .filter_map(|item| async { item.ok() })
+            .ingest(store.clone(), 16);
+        let res: Vec<Operation<Extensions>> = stream.try_collect().await.expect("not fail");
+        assert_eq!(res.len(), 1);
+
+        // The restored operation gets released even though its dependency is not part of the
+        // stream anymore.
+        let stream = iter([operations[3].clone()])
+            .decode()
+            .filter_map(|item| async { item.ok() })
+            .ingest(store, 16)
+            .with_checkpoint(checkpoint)
+            .unwrap();
+        let res: Vec<Operation<Extensions>> = stream.try_collect().await.expect("not fail");
+        let seq_nums: Vec<u64> = res
+            .iter()
+            .map(|operation| operation.header.seq_num)
+            .collect();
+        assert_eq!(seq_nums, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn pending_operations() {
+        let store = MemoryStore::<StreamName, Extensions>::new();
+        let operations: Vec<RawOperation> = mock_stream().take(3).collect().await;
+        let (tx, rx) = mpsc::channel::<RawOperation>(10);
+
+        let mut stream = Box::pin(
+            ReceiverStream::new(rx)
+                .decode()
+                .filter_map(|item| async { item.ok() })
+                .ingest(store, 16),
+        );
+
+        // Operation 2 arrives before its backlink.
+        tx.send(operations[0].clone()).await.unwrap();
+        tx.send(operations[2].clone()).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().header.seq_num, 0);
+        assert!(stream.next().now_or_never().is_none());
+
+        let operation_1 = decode_cbor::<Header<Extensions>, _>(&operations[1].0[..]).unwrap();
+        let pending = stream.as_mut().pending_operations();
+        assert_eq!(pending.count, 1);
+        assert_eq!(pending.missing, HashSet::from([operation_1.hash()]));
+
+        // Looking at the buffer does not change it.
+        assert_eq!(stream.as_mut().pending_operations(), pending);
+
+        // The backlink arrives and the buffered operation gets released.
+        tx.send(operations[1].clone()).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().header.seq_num, 1);
+        assert_eq!(stream.next().await.unwrap().unwrap().header.seq_num, 2);
+
+        let pending = stream.as_mut().pending_operations();
+        assert_eq!(pending.count, 0);
+        assert!(pending.missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn report_rejected_operation() {
+        let store = MemoryStore::<StreamName, Extensions>::new();
+        let mut operations: Vec<RawOperation> = mock_stream().take(5).collect().await;
+
+        // Tamper with the last operation after it was signed.
+        let mut header = decode_cbor::<Header<Extensions>, _>(&operations[4].0[..]).unwrap();
+        header.timestamp = 12;
+        let tampered_hash = header.hash();
+        operations[4].0 = header.to_bytes();
+
+        let stream = iter(operations)
+            .decode()
+            .filter_map(|item| async { item.ok() })
+            .ingest(store, 16)
+            .report_rejected();
+
+        let res: Vec<Result<Operation<Extensions>, IngestError>> = stream.collect().await;
+        assert_eq!(res.len(), 5);
+
+        // All valid operations are still ingested.
+        let seq_nums: Vec<u64> = res
+            .iter()
+            .filter_map(|item| item.as_ref().ok())
+            .map(|operation| operation.header.seq_num)
+            .collect();
+        assert_eq!(seq_nums, vec![0, 1, 2, 3]);
+
+        // Only the tampered operation gets reported, together with its hash.
+        let errors: Vec<&IngestError> = res.iter().filter_map(|item| item.as_ref().err()).collect();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            IngestError::Rejected { hash, reason }
+                if *hash == tampered_hash
+                    && matches!(
+                        **reason,
+                        IngestError::InvalidOperation(OperationError::SignatureMismatch)
+                    )
+        ));
+    }
+
+    #[tokio::test]
+    async fn trust_local() {
+        let mut operations: Vec<RawOperation> = mock_stream().take(5).collect().await;
+
+        // Replace the signature of the last operation with the one of another operation, the
+        // header is still well-formed but its signature is not valid anymore.
+        let mut header = decode_cbor::<Header<Extensions>, _>(&operations[4].0[..]).unwrap();
+        let other_header = decode_cbor::<Header<Extensions>, _>(&operations[3].0[..]).unwrap();
+        header.signature = other_header.signature;
+        operations[4].0 = header.to_bytes();
+
+        async fn replay(
+            operations: Vec<RawOperation>,
+            trust_local: bool,
+        ) -> Vec<Result<Operation<Extensions>, IngestError>> {
+            let store = MemoryStore::<StreamName, Extensions>::new();
+            let stream = iter(operations)
+                .decode()
+                .filter_map(|item| async { item.ok() })
+                .ingest(store, 16);
+            let stream = if trust_local {
+                stream.trust_local()
+            } else {
+                stream
+            };
+            stream.collect().await
+        }
+
+        // The invalid signature is detected when verifying operations.
+        let verified = replay(operations.clone(), false).await;
+        assert_eq!(verified.len(), 5);
+        assert!(verified[..4].iter().all(|item| item.is_ok()));
+        assert!(matches!(
+            verified[4],
+            Err(IngestError::InvalidOperation(
+                OperationError::SignatureMismatch
+            ))
+        ));
+
+        // Verification is skipped for trusted operations, all of them are accepted.
+        let trusted = replay(operations, true).await;
+        assert_eq!(trusted.len(), 5);
+        let seq_nums: Vec<u64> = trusted
+            .into_iter()
+            .map(|item| item.expect("not fail").header.seq_num)
+            .collect();
+        assert_eq!(seq_nums, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn trust_local_checks_log_integrity() {
+        let store = MemoryStore::<StreamName, Extensions>::new();
+        let operations: Vec<RawOperation> = mock_stream().take(3).collect().await;
+
+        // Operation with a broken backlink is still rejected, even though its signature is not
+        // verified.
+        let mut header = decode_cbor::<Header<Extensions>, _>(&operations[2].0[..]).unwrap();
+        header.backlink = Some(Hash::new(b"unknown operation"));
+        let tampered = (header.to_bytes(), operations[2].1.clone());
+
+        let stream = iter([operations[0].clone(), operations[1].clone(), tampered])
+            .decode()
+            .filter_map(|item| async { item.ok() })
+            .ingest(store, 16)
+            .trust_local();
+
+        let res: Vec<Result<Operation<Extensions>, IngestError>> = stream.collect().await;
+        assert_eq!(res.len(), 3);
+        assert!(res[0].is_ok());
+        assert!(res[1].is_ok());
+        assert!(matches!(
+            res[2],
+            Err(IngestError::InvalidOperation(
+                OperationError::BacklinkMismatch
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn max_payload_size() {
+        let store = MemoryStore::<StreamName, Extensions>::new();
+        let mut operations: Vec<RawOperation> = mock_stream().take(3).collect().await;
+
+        // Header claims an absurdly large payload which was never sent.
+        let private_key = PrivateKey::new();
+        let mut header = Header::<Extensions> {
+            public_key: private_key.public_key(),
+            version: 1,
+            payload_size: u64::MAX,
+            payload_hash: Some(Body::new(b"Hello, Penguin!").hash()),
+            extensions: Some(Extensions::default()),
+            ..Default::default()
+        };
+        header.sign(&private_key);
+        operations.push((header.to_bytes(), None));
+
+        // Body is larger than claimed in the header.
+        header.payload_size = 15;
+        header.sign(&private_key);
+        operations.push((header.to_bytes(), Some(vec![0; 2048])));
+
+        let stream = iter(operations)
+            .decode()
+            .filter_map(|item| async { item.ok() })
+            .ingest(store, 16)
+            .max_payload_size(1024);
+
+        let res: Vec<Result<Operation<Extensions>, IngestError>> = stream.collect().await;
+        assert_eq!(res.len(), 5);
+        assert!(res[..3].iter().all(|item| item.is_ok()));
+        assert!(matches!(
+            res[3],
+            Err(IngestError::PayloadTooLarge(u64::MAX, 1024))
+        ));
+        assert!(matches!(
+            res[4],
+            Err(IngestError::PayloadTooLarge(2048, 1024))
+        ));
+    }
 }
@@ -1,7 +1,11 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+mod bounded;
+mod checkpoint;
 mod decode;
 mod ingest;
 
+pub use bounded::{Bounded, BoundedExt};
+pub use checkpoint::{Checkpoint, CheckpointError};
 pub use decode::{Decode, DecodeExt};
-pub use ingest::{Ingest, IngestExt};
+pub use ingest::{Ingest, IngestExt, PendingOperations};
@@ -2,8 +2,18 @@
 
 //! Utility methods to encode or decode wire protocol messages in [CBOR] format.
 //!
+//! With the `compression` feature enabled, messages can optionally be compressed with [zstd].
+//!
+//! For debugging purposes a [`MessageObserver`] can be attached to the codec to inspect the CBOR
+//! bytes of every message sent or received.
+//!
 //! [CBOR]: https://cbor.io/
+//! [zstd]: https://facebook.github.io/zstd/
+use std::fmt;
+#[cfg(feature = "compression")]
+use std::io::Write;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use futures::{AsyncRead, AsyncWrite, Sink, Stream};
 use p2panda_core::cbor::{decode_cbor, encode_cbor, DecodeError};
@@ -16,6 +26,13 @@ use tokio_util::compat::{FuturesAsyncReadCompatExt, FuturesAsyncWriteCompatExt};
 
 use crate::SyncError;
 
+/// Maximum size of a single decompressed message.
+///
+/// Compressed chunks are rejected as soon as they decompress to more than this, so a small chunk
+/// can't make us allocate arbitrary amounts of memory.
+#[cfg(feature = "compression")]
+const MAX_DECOMPRESSED_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
 /// Implementation of the tokio codec traits to encode- and decode CBOR data as a stream.
 ///
 /// CBOR allows message framing based on initial "headers" for each "data item", which indicate the
@@ -24,17 +41,79 @@ use crate::SyncError;
 ///
 /// Read more on CBOR in streaming applications here:
 /// <https://www.rfc-editor.org/rfc/rfc8949.html#section-5.1>
+///
+/// With the `compression` feature enabled the codec can be switched to compress all further
+/// messages with zstd. One compression context is kept for the whole stream, so repetitions across
+/// messages are compressed as well, and flushed after every message. Each flushed chunk is framed
+/// as a CBOR byte string. Both peers need to agree on using compression before switching the
+/// codec, for example during a handshake.
 #[derive(Clone, Debug)]
 pub struct CborCodec<T> {
+    #[cfg(feature = "compression")]
+    compression: Option<ZstdContext>,
+    observer: Option<MessageObserver>,
     _phantom: PhantomData<T>,
 }
 
 impl<M> CborCodec<M> {
     pub fn new() -> Self {
         CborCodec {
+            #[cfg(feature = "compression")]
+            compression: None,
+            observer: None,
             _phantom: PhantomData {},
         }
     }
+
+    /// Passes the CBOR bytes of every encoded and decoded message to the given observer.
+    ///
+    /// Observers see the messages before compression was applied.
+    pub fn with_observer(mut self, observer: MessageObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Compress all further encoded messages and expect all further decoded messages to be
+    /// compressed.
+    #[cfg(feature = "compression")]
+    pub fn enable_compression(&mut self) {
+        self.compression.get_or_insert_with(ZstdContext::default);
+    }
+}
+
+/// Direction of a wire message passed to a [`MessageObserver`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Message received from the remote peer.
+    Inbound,
+
+    /// Message sent to the remote peer.
+    Outbound,
+}
+
+/// Callback receiving the CBOR bytes of wire messages, for example to log them when diagnosing
+/// protocol mismatches.
+///
+/// Observers can only inspect messages, they can't modify them.
+#[derive(Clone)]
+pub struct MessageObserver(Arc<ObserverFn>);
+
+type ObserverFn = dyn Fn(Direction, &[u8]) + Send + Sync;
+
+impl MessageObserver {
+    pub fn new(observer: impl Fn(Direction, &[u8]) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(observer))
+    }
+
+    fn observe(&self, direction: Direction, bytes: &[u8]) {
+        (self.0)(direction, bytes)
+    }
+}
+
+impl fmt::Debug for MessageObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageObserver").finish_non_exhaustive()
+    }
 }
 
 impl<M> Default for CborCodec<M> {
@@ -55,6 +134,14 @@ where
             // When we've failed encoding our _own_ messages something seriously went wrong.
             SyncError::Critical(format!("CBOR codec failed encoding message, {err}"))
         })?;
+        if let Some(observer) = &self.observer {
+            observer.observe(Direction::Outbound, &bytes);
+        }
+        #[cfg(feature = "compression")]
+        let bytes = match &mut self.compression {
+            Some(context) => context.compress(&bytes)?,
+            None => bytes,
+        };
         // Append the encoded CBOR bytes to the buffer instead of replacing it, we might already
         // have previously encoded items in it.
         dst.extend_from_slice(&bytes);
@@ -76,45 +163,183 @@ where
     /// 2. The buffer contains exactly a full frame.
     /// 3. The buffer contains more than a full frame.
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        // Keep a reference of the buffer to not advance the main buffer itself (yet).
-        let mut bytes: &[u8] = src.as_ref();
-        let starting = bytes.len();
-
-        // Attempt decoding the buffer and remember how many bytes we've advanced it doing that.
-        //
-        // This will succeed in case 2. and 3.
-        let result: Result<Self::Item, _> = decode_cbor(&mut bytes);
-        let ending = bytes.len();
-
-        match result {
-            Ok(item) => {
-                // We've successfully read one full frame from the buffer. We're finally
-                // advancing it for the next decode iteration and yield the resulting data item to
-                // the stream.
-                src.advance(starting - ending);
-                Ok(Some(item))
+        #[cfg(feature = "compression")]
+        if let Some(context) = &mut self.compression {
+            // Decompressed bytes can hold more or less than one frame, we're first attempting to
+            // decode an item from what we have and only decompress the next chunk if necessary.
+            loop {
+                if let Some(item) = decode_frame(&mut context.decompressed, self.observer.as_ref())?
+                {
+                    return Ok(Some(item));
+                }
+
+                match decode_frame::<serde_bytes::ByteBuf>(src, None)? {
+                    Some(chunk) => context.decompress(&chunk)?,
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        decode_frame(src, self.observer.as_ref())
+    }
+}
+
+/// Attempts decoding one CBOR data item from the buffer, advancing it only if a full frame was
+/// read.
+///
+/// The bytes of the decoded frame are passed to the observer, if given.
+fn decode_frame<T>(
+    src: &mut BytesMut,
+    observer: Option<&MessageObserver>,
+) -> Result<Option<T>, SyncError>
+where
+    T: DeserializeOwned,
+{
+    // Keep a reference of the buffer to not advance the main buffer itself (yet).
+    let mut bytes: &[u8] = src.as_ref();
+    let starting = bytes.len();
+
+    // Attempt decoding the buffer and remember how many bytes we've advanced it doing that.
+    //
+    // This will succeed in case 2. and 3.
+    let result: Result<T, _> = decode_cbor(&mut bytes);
+    let ending = bytes.len();
+
+    match result {
+        Ok(item) => {
+            // We've successfully read one full frame from the buffer. We're finally
+            // advancing it for the next decode iteration and yield the resulting data item to
+            // the stream.
+            if let Some(observer) = observer {
+                observer.observe(Direction::Inbound, &src[..starting - ending]);
             }
-            // Note that the buffer is not further advanced in case of an error.
-            Err(ref error) => match error {
-                DecodeError::Io(err) => {
-                    if err.kind() == std::io::ErrorKind::UnexpectedEof {
-                        // EOF errors indicate that our buffer doesn't contain enough data to
-                        // decode a whole CBOR frame. We're yielding no data item and re-try
-                        // decoding in the next iteration.
-                        //
-                        // This is handling case 1.
-                        Ok(None)
-                    } else {
-                        // An I/O error during decoding usually indicates something wrong with our
-                        // system (lack of system memory etc.).
-                        Err(SyncError::Critical(format!(
-                            "CBOR codec failed decoding message due to i/o error, {err}"
-                        )))
-                    }
+            src.advance(starting - ending);
+            Ok(Some(item))
+        }
+        // Note that the buffer is not further advanced in case of an error.
+        Err(ref error) => match error {
+            DecodeError::Io(err) => {
+                if err.kind() == std::io::ErrorKind::UnexpectedEof {
+                    // EOF errors indicate that our buffer doesn't contain enough data to
+                    // decode a whole CBOR frame. We're yielding no data item and re-try
+                    // decoding in the next iteration.
+                    //
+                    // This is handling case 1.
+                    Ok(None)
+                } else {
+                    // An I/O error during decoding usually indicates something wrong with our
+                    // system (lack of system memory etc.).
+                    Err(SyncError::Critical(format!(
+                        "CBOR codec failed decoding message due to i/o error, {err}"
+                    )))
                 }
-                err => Err(SyncError::InvalidEncoding(err.to_string())),
-            },
+            }
+            err => Err(SyncError::InvalidEncoding(err.to_string())),
+        },
+    }
+}
+
+/// zstd compression and decompression state of a stream.
+///
+/// The zstd encoder and decoder are initialised when they're used for the first time.
+#[cfg(feature = "compression")]
+#[derive(Default)]
+struct ZstdContext {
+    encoder: Option<zstd::stream::write::Encoder<'static, Vec<u8>>>,
+    decoder: Option<zstd::stream::write::Decoder<'static, LimitedBuffer>>,
+    decompressed: BytesMut,
+}
+
+#[cfg(feature = "compression")]
+impl ZstdContext {
+    /// Compresses the given bytes and returns the flushed chunk framed as a CBOR byte string.
+    fn compress(&mut self, bytes: &[u8]) -> Result<Vec<u8>, SyncError> {
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => self.encoder.insert(
+                zstd::stream::write::Encoder::new(Vec::new(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .map_err(|err| {
+                        SyncError::Critical(format!("zstd failed initialising, {err}"))
+                    })?,
+            ),
+        };
+        encoder
+            .write_all(bytes)
+            .and_then(|_| encoder.flush())
+            .map_err(|err| {
+                SyncError::Critical(format!("zstd failed compressing message, {err}"))
+            })?;
+        let chunk = std::mem::take(encoder.get_mut());
+        encode_cbor(&serde_bytes::Bytes::new(&chunk)).map_err(|err| {
+            SyncError::Critical(format!("CBOR codec failed encoding message, {err}"))
+        })
+    }
+
+    /// Decompresses the given chunk and appends the result to the decompressed bytes.
+    ///
+    /// Fails if the decompressed bytes which were not decoded yet would exceed the
+    /// [`MAX_DECOMPRESSED_FRAME_SIZE`].
+    fn decompress(&mut self, chunk: &[u8]) -> Result<(), SyncError> {
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => self.decoder.insert(
+                zstd::stream::write::Decoder::new(LimitedBuffer::default()).map_err(|err| {
+                    SyncError::Critical(format!("zstd failed initialising, {err}"))
+                })?,
+            ),
+        };
+        decoder.get_mut().limit =
+            MAX_DECOMPRESSED_FRAME_SIZE.saturating_sub(self.decompressed.len());
+        decoder
+            .write_all(chunk)
+            .and_then(|_| decoder.flush())
+            .map_err(|err| {
+                SyncError::InvalidEncoding(format!("invalid zstd compression, {err}"))
+            })?;
+        self.decompressed
+            .extend_from_slice(&std::mem::take(&mut decoder.get_mut().bytes));
+        Ok(())
+    }
+}
+
+/// Buffer for decompressed bytes which refuses to grow beyond a limit.
+#[cfg(feature = "compression")]
+#[derive(Default)]
+struct LimitedBuffer {
+    bytes: Vec<u8>,
+    limit: usize,
+}
+
+#[cfg(feature = "compression")]
+impl Write for LimitedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.bytes.len() + buf.len() > self.limit {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("frame exceeds maximum size of {MAX_DECOMPRESSED_FRAME_SIZE} bytes"),
+            ));
         }
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Clone for ZstdContext {
+    /// Returns a fresh context, compression state can not be shared between streams.
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "compression")]
+impl std::fmt::Debug for ZstdContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZstdContext").finish_non_exhaustive()
     }
 }
 
@@ -226,4 +451,66 @@ mod tests {
         let message = stream.next().await;
         assert_eq!(message, Some(Ok("hello".into())));
     }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_frames() {
+        use tokio_util::bytes::BytesMut;
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let messages: Vec<String> = (0..1000)
+            .map(|i| format!("Hello, Sloth! This is message #{i}"))
+            .collect();
+
+        // Compression state is kept per direction, that is why sending and receiving end use
+        // their own codec.
+        let mut codec = CborCodec::<String>::new();
+        let mut compressed_tx = CborCodec::<String>::new();
+        let mut compressed_rx = CborCodec::<String>::new();
+        compressed_tx.enable_compression();
+        compressed_rx.enable_compression();
+
+        let mut uncompressed = BytesMut::new();
+        let mut compressed = BytesMut::new();
+        for message in &messages {
+            codec.encode(message.clone(), &mut uncompressed).unwrap();
+            compressed_tx
+                .encode(message.clone(), &mut compressed)
+                .unwrap();
+        }
+
+        // Similar messages are smaller when compressed.
+        assert!(compressed.len() < uncompressed.len());
+
+        let mut decoded = Vec::new();
+        while let Some(message) = compressed_rx.decode(&mut compressed).unwrap() {
+            decoded.push(message);
+        }
+        assert_eq!(decoded, messages);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn oversized_compressed_frame() {
+        use tokio_util::bytes::BytesMut;
+        use tokio_util::codec::{Decoder, Encoder};
+
+        use crate::SyncError;
+
+        use super::MAX_DECOMPRESSED_FRAME_SIZE;
+
+        let mut compressed_tx = CborCodec::<String>::new();
+        let mut compressed_rx = CborCodec::<String>::new();
+        compressed_tx.enable_compression();
+        compressed_rx.enable_compression();
+
+        // The message compresses to a tiny chunk but expands beyond the maximum frame size.
+        let message = "a".repeat(MAX_DECOMPRESSED_FRAME_SIZE);
+        let mut compressed = BytesMut::new();
+        compressed_tx.encode(message, &mut compressed).unwrap();
+        assert!(compressed.len() < 1024 * 1024);
+
+        let result = compressed_rx.decode(&mut compressed);
+        assert!(matches!(result, Err(SyncError::InvalidEncoding(_))));
+    }
 }
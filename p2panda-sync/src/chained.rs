@@ -0,0 +1,726 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Combinator to run a handshake protocol followed by a separate data protocol in one session.
+//!
+//! Access control and the exchange of the topic query are often independent of the way the
+//! actual data is synced. `ChainedProtocol` allows implementing both concerns as separate
+//! `SyncProtocol` implementations: the "handshake" protocol establishes the topic query and
+//! validates access, after it completed successfully the session is handed over to the "data"
+//! protocol.
+//!
+//! All bytes written during the handshake phase are wrapped in length-prefixed frames, followed by
+//! an empty frame marking the end of the phase. This makes sure that the handshake protocol never
+//! consumes bytes which were already sent by the data protocol of the remote peer, independent of
+//! how it buffers the underlying stream.
+//!
+//! ```text
+//! Handshake phase        Data phase
+//!
+//! [len][bytes] ... [0]   [bytes] ...
+//! ```
+use std::fmt::Debug;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Sink};
+
+use crate::{FromSync, SyncError, SyncProtocol, TopicQuery};
+
+/// Size of the length prefix of each frame sent during the handshake phase.
+const FRAME_HEADER_SIZE: usize = 4;
+
+/// Sync protocol running a handshake protocol `H` first and then a data protocol `D`.
+///
+/// The handshake protocol is required to send a `FromSync::HandshakeSuccess` message to the
+/// application layer when access was granted, the topic query contained in this message is then
+/// used to initiate the data protocol. Further `HandshakeSuccess` messages of the data protocol
+/// are not forwarded to the application layer, if they contain a different topic query than the
+/// one established during the handshake the session is aborted.
+///
+/// The handshake protocol needs to flush its messages before waiting for a response of the remote
+/// peer, as they are only sent over the wire in whole frames.
+#[derive(Debug)]
+pub struct ChainedProtocol<H, D> {
+    handshake: Arc<H>,
+    data: Arc<D>,
+}
+
+impl<H, D> ChainedProtocol<H, D> {
+    pub fn new(handshake: H, data: D) -> Self {
+        Self {
+            handshake: Arc::new(handshake),
+            data: Arc::new(data),
+        }
+    }
+}
+
+#[async_trait]
+impl<'a, T, H, D> SyncProtocol<'a, T> for ChainedProtocol<H, D>
+where
+    T: TopicQuery + 'a,
+    H: for<'b> SyncProtocol<'b, T> + 'a,
+    D: for<'b> SyncProtocol<'b, T> + 'a,
+{
+    fn name(&self) -> &'static str {
+        self.data.name()
+    }
+
+    async fn initiate(
+        self: Arc<Self>,
+        topic_query: T,
+        tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+        rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+        app_tx: Box<&'a mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin)>,
+    ) -> Result<(), SyncError> {
+        let (tx, rx, app_tx) = (*tx, *rx, *app_tx);
+
+        let mut handshake_tx = PhaseWriter::new(&mut *tx);
+        let mut handshake_rx = PhaseReader::new(&mut *rx);
+        let mut handshake_app_tx = HandshakeSink::new(&mut *app_tx);
+        self.handshake
+            .clone()
+            .initiate(
+                topic_query,
+                Box::new(&mut handshake_tx),
+                Box::new(&mut handshake_rx),
+                Box::new(&mut handshake_app_tx),
+            )
+            .await
+            .map_err(|err| phase_error("handshake", err))?;
+        let topic_query = handshake_app_tx.topic_query()?;
+        handshake_tx.finish().await?;
+        handshake_rx.finish().await?;
+
+        let mut data_app_tx = DataSink::new(app_tx, &topic_query);
+        self.data
+            .clone()
+            .initiate(
+                topic_query.clone(),
+                Box::new(tx),
+                Box::new(rx),
+                Box::new(&mut data_app_tx),
+            )
+            .await
+            .map_err(|err| phase_error("data", err))
+    }
+
+    async fn accept(
+        self: Arc<Self>,
+        tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+        rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+        app_tx: Box<&'a mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin)>,
+    ) -> Result<(), SyncError> {
+        let (tx, rx, app_tx) = (*tx, *rx, *app_tx);
+
+        let mut handshake_tx = PhaseWriter::new(&mut *tx);
+        let mut handshake_rx = PhaseReader::new(&mut *rx);
+        let mut handshake_app_tx = HandshakeSink::new(&mut *app_tx);
+        self.handshake
+            .clone()
+            .accept(
+                Box::new(&mut handshake_tx),
+                Box::new(&mut handshake_rx),
+                Box::new(&mut handshake_app_tx),
+            )
+            .await
+            .map_err(|err| phase_error("handshake", err))?;
+        let topic_query = handshake_app_tx.topic_query()?;
+        handshake_tx.finish().await?;
+        handshake_rx.finish().await?;
+
+        let mut data_app_tx = DataSink::new(app_tx, &topic_query);
+        self.data
+            .clone()
+            .accept(Box::new(tx), Box::new(rx), Box::new(&mut data_app_tx))
+            .await
+            .map_err(|err| phase_error("data", err))
+    }
+}
+
+/// Adds the phase the error occurred in to its message.
+fn phase_error(phase: &str, err: SyncError) -> SyncError {
+    match err {
+        SyncError::UnexpectedBehaviour(msg) => {
+            SyncError::UnexpectedBehaviour(format!("{phase} phase: {msg}"))
+        }
+        SyncError::InvalidEncoding(msg) => {
+            SyncError::InvalidEncoding(format!("{phase} phase: {msg}"))
+        }
+        SyncError::Critical(msg) => SyncError::Critical(format!("{phase} phase: {msg}")),
+    }
+}
+
+/// Writer for the handshake phase, sending all bytes written between two flushes as one frame.
+struct PhaseWriter<'b> {
+    inner: &'b mut (dyn AsyncWrite + Send + Unpin),
+    buf: Vec<u8>,
+    frame: Vec<u8>,
+    written: usize,
+}
+
+impl<'b> PhaseWriter<'b> {
+    fn new(inner: &'b mut (dyn AsyncWrite + Send + Unpin)) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            frame: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Sends all remaining bytes and marks the end of the handshake phase with an empty frame.
+    async fn finish(mut self) -> Result<(), SyncError> {
+        self.flush().await?;
+        self.inner.write_all(&[0; FRAME_HEADER_SIZE]).await?;
+        self.inner.flush().await?;
+        Ok(())
+    }
+}
+
+impl AsyncWrite for PhaseWriter<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // Bytes might have been written while a previous frame was still pending.
+        while !this.frame.is_empty() || !this.buf.is_empty() {
+            if this.frame.is_empty() {
+                let len = u32::try_from(this.buf.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "handshake frame too large")
+                })?;
+                this.frame.extend_from_slice(&len.to_be_bytes());
+                this.frame.append(&mut this.buf);
+            }
+
+            while this.written < this.frame.len() {
+                let n =
+                    ready!(Pin::new(&mut *this.inner).poll_write(cx, &this.frame[this.written..]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                this.written += n;
+            }
+            this.frame.clear();
+            this.written = 0;
+        }
+
+        Pin::new(&mut *this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // The underlying stream is still used by the data protocol and can't be closed here.
+        self.poll_flush(cx)
+    }
+}
+
+/// Reader for the handshake phase, returning the bytes of the remote peer's frames until the end
+/// of their handshake phase.
+///
+/// Never reads beyond the current frame, so all bytes of the data phase stay in the underlying
+/// stream.
+struct PhaseReader<'b> {
+    inner: &'b mut (dyn AsyncRead + Send + Unpin),
+    header: [u8; FRAME_HEADER_SIZE],
+    header_read: usize,
+    remaining: usize,
+    finished: bool,
+}
+
+impl<'b> PhaseReader<'b> {
+    fn new(inner: &'b mut (dyn AsyncRead + Send + Unpin)) -> Self {
+        Self {
+            inner,
+            header: [0; FRAME_HEADER_SIZE],
+            header_read: 0,
+            remaining: 0,
+            finished: false,
+        }
+    }
+
+    /// Skips all bytes the handshake protocol didn't consume until the end of the remote peer's
+    /// handshake phase.
+    async fn finish(mut self) -> Result<(), SyncError> {
+        let mut buf = [0; 1024];
+        loop {
+            match self.read(&mut buf).await {
+                Ok(0) => return Ok(()),
+                Ok(_) => (),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(SyncError::UnexpectedBehaviour(
+                        "remote peer closed stream during handshake".into(),
+                    ))
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl AsyncRead for PhaseReader<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.finished || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            if this.remaining > 0 {
+                let len = buf.len().min(this.remaining);
+                let n = ready!(Pin::new(&mut *this.inner).poll_read(cx, &mut buf[..len]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                this.remaining -= n;
+                return Poll::Ready(Ok(n));
+            }
+
+            let n = ready!(
+                Pin::new(&mut *this.inner).poll_read(cx, &mut this.header[this.header_read..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            this.header_read += n;
+
+            if this.header_read == FRAME_HEADER_SIZE {
+                this.header_read = 0;
+                // An empty frame marks the end of the remote peer's handshake phase.
+                match u32::from_be_bytes(this.header) {
+                    0 => this.finished = true,
+                    len => this.remaining = len as usize,
+                }
+            }
+        }
+    }
+}
+
+/// Forwards all messages of the handshake protocol to the application layer and remembers the
+/// topic query it established.
+struct HandshakeSink<'b, T>
+where
+    T: TopicQuery,
+{
+    inner: &'b mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin),
+    topic_query: Option<T>,
+}
+
+// The topic query is never pinned.
+impl<T> Unpin for HandshakeSink<'_, T> where T: TopicQuery {}
+
+impl<'b, T> HandshakeSink<'b, T>
+where
+    T: TopicQuery,
+{
+    fn new(inner: &'b mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin)) -> Self {
+        Self {
+            inner,
+            topic_query: None,
+        }
+    }
+
+    fn topic_query(&mut self) -> Result<T, SyncError> {
+        self.topic_query.take().ok_or_else(|| {
+            SyncError::Critical("handshake protocol did not establish a topic query".into())
+        })
+    }
+}
+
+impl<T> Sink<FromSync<T>> for HandshakeSink<'_, T>
+where
+    T: TopicQuery,
+{
+    type Error = SyncError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.get_mut().inner).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: FromSync<T>) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        if let FromSync::HandshakeSuccess(topic_query) = &item {
+            this.topic_query = Some(topic_query.clone());
+        }
+        Pin::new(&mut *this.inner).start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.get_mut().inner).poll_close(cx)
+    }
+}
+
+/// Forwards the data of the data protocol to the application layer.
+///
+/// The handshake was already announced to the application layer, so the data protocol's
+/// `HandshakeSuccess` message is only checked against the established topic query.
+struct DataSink<'b, 'c, T>
+where
+    T: TopicQuery,
+{
+    inner: &'b mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin),
+    topic_query: &'c T,
+}
+
+impl<'b, 'c, T> DataSink<'b, 'c, T>
+where
+    T: TopicQuery,
+{
+    fn new(
+        inner: &'b mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin),
+        topic_query: &'c T,
+    ) -> Self {
+        Self { inner, topic_query }
+    }
+}
+
+impl<T> Sink<FromSync<T>> for DataSink<'_, '_, T>
+where
+    T: TopicQuery,
+{
+    type Error = SyncError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.get_mut().inner).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: FromSync<T>) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        match item {
+            FromSync::HandshakeSuccess(topic_query) => {
+                if &topic_query != this.topic_query {
+                    return Err(SyncError::UnexpectedBehaviour(format!(
+                        "topic query {topic_query:?} differs from the one established during handshake"
+                    )));
+                }
+                Ok(())
+            }
+            data => Pin::new(&mut *this.inner).start_send(data),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(all(test, feature = "log-sync"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use futures::{AsyncRead, AsyncWrite, Sink, SinkExt, StreamExt};
+    use p2panda_core::{Body, Hash, Header, PrivateKey, PublicKey};
+    use p2panda_store::{MemoryStore, OperationStore};
+    use serde::{Deserialize, Serialize};
+    use tokio::sync::mpsc;
+    use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+    use tokio_util::sync::PollSender;
+
+    use crate::cbor::{into_cbor_sink, into_cbor_stream};
+    use crate::log_sync::{LogSyncProtocol, TopicLogMap};
+    use crate::{FromSync, SyncError, SyncProtocol, TopicQuery};
+
+    use super::ChainedProtocol;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct TestTopic(String);
+
+    impl TopicQuery for TestTopic {}
+
+    #[derive(Clone, Debug)]
+    struct TestTopicMap(HashMap<TestTopic, HashMap<PublicKey, Vec<u64>>>);
+
+    #[async_trait]
+    impl TopicLogMap<TestTopic, u64> for TestTopicMap {
+        async fn get(&self, topic_query: &TestTopic) -> Option<HashMap<PublicKey, Vec<u64>>> {
+            self.0.get(topic_query).cloned()
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    enum HandshakeMessage<T> {
+        Topic(T),
+        Accepted,
+        Rejected,
+    }
+
+    /// Handshake where the accepting peer grants or denies access to every topic query.
+    #[derive(Debug)]
+    struct AccessHandshake {
+        allowed: bool,
+    }
+
+    #[async_trait]
+    impl<'a> SyncProtocol<'a, TestTopic> for AccessHandshake {
+        fn name(&self) -> &'static str {
+            "access-handshake"
+        }
+
+        async fn initiate(
+            self: Arc<Self>,
+            topic_query: TestTopic,
+            tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+            rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+            mut app_tx: Box<
+                &'a mut (dyn Sink<FromSync<TestTopic>, Error = SyncError> + Send + Unpin),
+            >,
+        ) -> Result<(), SyncError> {
+            let mut sink = into_cbor_sink(tx);
+            let mut stream = into_cbor_stream(rx);
+
+            sink.send(HandshakeMessage::Topic(topic_query.clone()))
+                .await?;
+
+            match stream.next().await {
+                Some(Ok(HandshakeMessage::<TestTopic>::Accepted)) => {
+                    app_tx.send(FromSync::HandshakeSuccess(topic_query)).await?;
+                    Ok(())
+                }
+                Some(Ok(HandshakeMessage::Rejected)) => {
+                    Err(SyncError::UnexpectedBehaviour("access denied".into()))
+                }
+                Some(Err(err)) => Err(err),
+                _ => Err(SyncError::UnexpectedBehaviour(
+                    "unexpected handshake message".into(),
+                )),
+            }
+        }
+
+        async fn accept(
+            self: Arc<Self>,
+            tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+            rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+            mut app_tx: Box<
+                &'a mut (dyn Sink<FromSync<TestTopic>, Error = SyncError> + Send + Unpin),
+            >,
+        ) -> Result<(), SyncError> {
+            let mut sink = into_cbor_sink(tx);
+            let mut stream = into_cbor_stream(rx);
+
+            let Some(Ok(HandshakeMessage::Topic(topic_query))) = stream.next().await else {
+                return Err(SyncError::UnexpectedBehaviour(
+                    "expected topic query".into(),
+                ));
+            };
+
+            if !self.allowed {
+                sink.send(HandshakeMessage::<TestTopic>::Rejected).await?;
+                return Err(SyncError::UnexpectedBehaviour("access denied".into()));
+            }
+
+            sink.send(HandshakeMessage::<TestTopic>::Accepted).await?;
+            app_tx.send(FromSync::HandshakeSuccess(topic_query)).await?;
+            Ok(())
+        }
+    }
+
+    fn create_operation(
+        private_key: &PrivateKey,
+        body: &Body,
+        seq_num: u64,
+        backlink: Option<Hash>,
+    ) -> (Hash, Header, Vec<u8>) {
+        let mut header = Header {
+            version: 1,
+            public_key: private_key.public_key(),
+            signature: None,
+            payload_size: body.size(),
+            payload_hash: Some(body.hash()),
+            timestamp: seq_num * 100,
+            seq_num,
+            backlink,
+            previous: vec![],
+            extensions: None,
+        };
+        header.sign(private_key);
+        let header_bytes = header.to_bytes();
+        (header.hash(), header, header_bytes)
+    }
+
+    type TestProtocol =
+        ChainedProtocol<AccessHandshake, LogSyncProtocol<TestTopicMap, u64, (), MemoryStore<u64>>>;
+
+    /// Runs a chained sync session between both peers and returns their results and the messages
+    /// they sent to the application layer.
+    async fn run_session(
+        topic_query: TestTopic,
+        protocol_a: TestProtocol,
+        protocol_b: TestProtocol,
+    ) -> (
+        (Result<(), SyncError>, Vec<FromSync<TestTopic>>),
+        (Result<(), SyncError>, Vec<FromSync<TestTopic>>),
+    ) {
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (peer_b_read, peer_b_write) = tokio::io::split(peer_b);
+
+        let (peer_a_app_tx, mut peer_a_app_rx) = mpsc::channel(128);
+        let handle_a = tokio::spawn(async move {
+            let mut sink = PollSender::new(peer_a_app_tx)
+                .sink_map_err(|err| SyncError::Critical(err.to_string()));
+            Arc::new(protocol_a)
+                .initiate(
+                    topic_query,
+                    Box::new(&mut peer_a_write.compat_write()),
+                    Box::new(&mut peer_a_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+        });
+
+        let (peer_b_app_tx, mut peer_b_app_rx) = mpsc::channel(128);
+        let handle_b = tokio::spawn(async move {
+            let mut sink = PollSender::new(peer_b_app_tx)
+                .sink_map_err(|err| SyncError::Critical(err.to_string()));
+            Arc::new(protocol_b)
+                .accept(
+                    Box::new(&mut peer_b_write.compat_write()),
+                    Box::new(&mut peer_b_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+        });
+
+        let (result_a, result_b) = tokio::join!(handle_a, handle_b);
+
+        let mut peer_a_messages = Vec::new();
+        while let Ok(message) = peer_a_app_rx.try_recv() {
+            peer_a_messages.push(message);
+        }
+        let mut peer_b_messages = Vec::new();
+        while let Ok(message) = peer_b_app_rx.try_recv() {
+            peer_b_messages.push(message);
+        }
+
+        (
+            (result_a.unwrap(), peer_a_messages),
+            (result_b.unwrap(), peer_b_messages),
+        )
+    }
+
+    #[tokio::test]
+    async fn handshake_then_log_sync() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = TestTopic("messages".into());
+        let topic_map = TestTopicMap(HashMap::from([(
+            topic_query.clone(),
+            HashMap::from([(private_key.public_key(), vec![log_id])]),
+        )]));
+
+        // Peer b holds two operations which peer a doesn't know about yet.
+        let mut store_b = MemoryStore::default();
+        let body = Body::new("Hello, Sloth!".as_bytes());
+        let (hash_0, header_0, header_bytes_0) = create_operation(&private_key, &body, 0, None);
+        let (hash_1, header_1, header_bytes_1) =
+            create_operation(&private_key, &body, 1, Some(hash_0));
+        store_b
+            .insert_operation(hash_0, &header_0, Some(&body), &header_bytes_0, &log_id)
+            .await
+            .unwrap();
+        store_b
+            .insert_operation(hash_1, &header_1, Some(&body), &header_bytes_1, &log_id)
+            .await
+            .unwrap();
+
+        let protocol_a = ChainedProtocol::new(
+            AccessHandshake { allowed: true },
+            LogSyncProtocol::new(topic_map.clone(), MemoryStore::default()),
+        );
+        let protocol_b = ChainedProtocol::new(
+            AccessHandshake { allowed: true },
+            LogSyncProtocol::new(topic_map, store_b),
+        );
+
+        let ((result_a, messages_a), (result_b, messages_b)) =
+            run_session(topic_query.clone(), protocol_a, protocol_b).await;
+        assert_eq!(result_a, Ok(()));
+        assert_eq!(result_b, Ok(()));
+
+        // The handshake is announced once, followed by the data of the log sync phase.
+        assert_eq!(
+            messages_a,
+            vec![
+                FromSync::HandshakeSuccess(topic_query.clone()),
+                FromSync::Data {
+                    header: header_bytes_0,
+                    payload: Some(body.to_bytes()),
+                },
+                FromSync::Data {
+                    header: header_bytes_1,
+                    payload: Some(body.to_bytes()),
+                },
+            ]
+        );
+        assert_eq!(messages_b, vec![FromSync::HandshakeSuccess(topic_query)]);
+    }
+
+    #[tokio::test]
+    async fn rejected_handshake_skips_data_phase() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = TestTopic("messages".into());
+        let topic_map = TestTopicMap(HashMap::from([(
+            topic_query.clone(),
+            HashMap::from([(private_key.public_key(), vec![log_id])]),
+        )]));
+
+        let mut store_b = MemoryStore::default();
+        let body = Body::new("Hello, Sloth!".as_bytes());
+        let (hash_0, header_0, header_bytes_0) = create_operation(&private_key, &body, 0, None);
+        store_b
+            .insert_operation(hash_0, &header_0, Some(&body), &header_bytes_0, &log_id)
+            .await
+            .unwrap();
+
+        let protocol_a = ChainedProtocol::new(
+            AccessHandshake { allowed: true },
+            LogSyncProtocol::new(topic_map.clone(), MemoryStore::default()),
+        );
+        let protocol_b = ChainedProtocol::new(
+            AccessHandshake { allowed: false },
+            LogSyncProtocol::new(topic_map, store_b),
+        );
+
+        let ((result_a, messages_a), (result_b, messages_b)) =
+            run_session(topic_query, protocol_a, protocol_b).await;
+        assert_eq!(
+            result_a,
+            Err(SyncError::UnexpectedBehaviour(
+                "handshake phase: access denied".into()
+            ))
+        );
+        assert_eq!(
+            result_b,
+            Err(SyncError::UnexpectedBehaviour(
+                "handshake phase: access denied".into()
+            ))
+        );
+        assert!(messages_a.is_empty());
+        assert!(messages_b.is_empty());
+    }
+}
@@ -16,8 +16,11 @@
 //! encode wire messages in CBOR.
 #[cfg(feature = "cbor")]
 pub mod cbor;
+pub mod chained;
 #[cfg(feature = "log-sync")]
 pub mod log_sync;
+#[cfg(feature = "set-reconciliation")]
+pub mod set_reconciliation;
 
 use std::fmt::Debug;
 use std::hash::Hash;
@@ -17,22 +17,56 @@
 //!
 //! To find out which logs to send matching the given "topic query" a `TopicLogMap` is provided. This
 //! interface aids the sync protocol in deciding which logs to transfer for each given topic.
+//!
+//! The initiating peer can optionally be configured with "resume" log heights, for example when
+//! it already received operations in an earlier, interrupted session which did not reach the
+//! store yet. These are sent in an additional "have_range" field of the "Have" message and the
+//! accepting peer will only send operations with a sequence number greater than the given heights.
+//! Peers not knowing about this field ignore it and send operations based on the regular log
+//! heights instead.
+//!
+//! With the `compression` feature enabled, the initiating peer can optionally propose to compress
+//! all further messages of the session. The proposal is sent in an additional "compression" field
+//! of the "Have" message. If the accepting peer supports compression as well it answers with a
+//! "Compression" message before sending any data, otherwise the session continues uncompressed.
+//!
+//! The initiating peer can also request "header-only" sync in an additional "headers_only" field of
+//! its "Have" message, for example when payloads are fetched lazily later on via `p2panda-blobs`.
+//! The accepting peer then sends all operations without their payloads. Peers not knowing about
+//! this field send payloads as usual.
+//!
+//! The initiating peer announces the version of the protocol it speaks in an additional "version"
+//! field of its first "Have" message. The accepting peer checks if it supports this version and
+//! aborts the session with an "incompatible protocol version" error otherwise, instead of failing
+//! later on messages it can't decode. Messages without a "version" field are sent by peers which
+//! don't know about versioning yet, they speak version 1 of the protocol.
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
 use futures::{stream, AsyncRead, AsyncWrite, Sink, SinkExt, StreamExt};
 use p2panda_core::{Extensions, PublicKey};
-use p2panda_store::{LogId, LogStore};
+use p2panda_store::{LogId, LogStore, OperationStore};
+use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize};
 
-use crate::cbor::{into_cbor_sink, into_cbor_stream};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tokio_util::compat::{FuturesAsyncReadCompatExt, FuturesAsyncWriteCompatExt};
+
+use crate::cbor::{CborCodec, MessageObserver};
 use crate::{FromSync, SyncError, SyncProtocol, TopicQuery};
 
 type SeqNum = u64;
 
+/// Version of the protocol spoken by this implementation.
+const PROTOCOL_VERSION: u8 = 1;
+
+/// Version assumed for peers not sending a "version" field.
+const LEGACY_PROTOCOL_VERSION: u8 = 1;
+
 type LogHeights<T> = Vec<(T, SeqNum)>;
 
 type Logs<T> = HashMap<PublicKey, Vec<T>>;
@@ -87,19 +121,207 @@ enum Message<T, L = String> {
     Have(T, Vec<(PublicKey, LogHeights<L>)>),
     Data(Vec<u8>, Option<Vec<u8>>),
     Done,
+    /// Confirmation that all further messages of this session are compressed.
+    ///
+    /// This message is only sent by the accepting peer if the initiating peer proposed
+    /// compression and both have it enabled.
+    Compression,
+}
+
+/// Message as it is sent over the wire, including optional fields extending the protocol.
+///
+/// The message is encoded exactly like `Message`, with optional fields added next to the "type"
+/// and "value" fields. Peers which don't know about an optional field ignore it, so it can be sent
+/// without breaking sync sessions with them.
+#[derive(Debug, Clone)]
+struct WireMessage<T, L = String> {
+    message: Message<T, L>,
+
+    /// "Resume" log heights of the initiating peer, sent along with its "have" message.
+    have_range: Option<Vec<(PublicKey, LogHeights<L>)>>,
+
+    /// Proposal of the initiating peer to compress the session, sent along with its "have"
+    /// message.
+    compression: bool,
+
+    /// Protocol version of the initiating peer, sent along with its "have" message.
+    version: Option<u8>,
+
+    /// Request of the initiating peer to only receive operation headers without payloads, sent
+    /// along with its "have" message.
+    headers_only: bool,
+}
+
+impl<T, L> Serialize for WireMessage<T, L>
+where
+    T: Serialize,
+    L: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut len = match self.message {
+            Message::Done | Message::Compression => 1,
+            _ => 2,
+        };
+        if self.have_range.is_some() {
+            len += 1;
+        }
+        if self.compression {
+            len += 1;
+        }
+        if self.version.is_some() {
+            len += 1;
+        }
+        if self.headers_only {
+            len += 1;
+        }
+
+        let mut map = serializer.serialize_map(Some(len))?;
+        match &self.message {
+            Message::Have(topic_query, log_heights) => {
+                map.serialize_entry("type", "Have")?;
+                map.serialize_entry("value", &(topic_query, log_heights))?;
+            }
+            Message::Data(header, body) => {
+                map.serialize_entry("type", "Data")?;
+                map.serialize_entry("value", &(header, body))?;
+            }
+            Message::Done => {
+                map.serialize_entry("type", "Done")?;
+            }
+            Message::Compression => {
+                map.serialize_entry("type", "Compression")?;
+            }
+        }
+        if let Some(have_range) = &self.have_range {
+            map.serialize_entry("have_range", have_range)?;
+        }
+        if self.compression {
+            map.serialize_entry("compression", &true)?;
+        }
+        if let Some(version) = &self.version {
+            map.serialize_entry("version", version)?;
+        }
+        if self.headers_only {
+            map.serialize_entry("headers_only", &true)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, T, L> Deserialize<'de> for WireMessage<T, L>
+where
+    T: Deserialize<'de>,
+    L: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct WireMessageVisitor<T, L>(PhantomData<(T, L)>);
+
+        impl<'de, T, L> Visitor<'de> for WireMessageVisitor<T, L>
+        where
+            T: Deserialize<'de>,
+            L: Deserialize<'de>,
+        {
+            type Value = WireMessage<T, L>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("sync message")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                // The "type" field is always encoded first, it tells us how to decode the value.
+                let message_type = match map.next_key::<String>()? {
+                    Some(key) if key == "type" => map.next_value::<String>()?,
+                    _ => return Err(de::Error::missing_field("type")),
+                };
+
+                let mut message = match message_type.as_str() {
+                    "Done" => Some(Message::Done),
+                    "Compression" => Some(Message::Compression),
+                    "Have" | "Data" => None,
+                    unknown => {
+                        return Err(de::Error::unknown_variant(
+                            unknown,
+                            &["Have", "Data", "Done", "Compression"],
+                        ))
+                    }
+                };
+                let mut have_range = None;
+                let mut compression = false;
+                let mut version = None;
+                let mut headers_only = false;
+
+                while let Some(key) = map.next_key::<String>()? {
+                    match (key.as_str(), message_type.as_str()) {
+                        ("value", "Have") => {
+                            let (topic_query, log_heights) = map.next_value()?;
+                            message = Some(Message::Have(topic_query, log_heights));
+                        }
+                        ("value", "Data") => {
+                            let (header, body) = map.next_value()?;
+                            message = Some(Message::Data(header, body));
+                        }
+                        ("have_range", _) => have_range = map.next_value()?,
+                        ("compression", _) => compression = map.next_value()?,
+                        ("version", _) => version = map.next_value()?,
+                        ("headers_only", _) => headers_only = map.next_value()?,
+                        // Ignore fields we don't know about.
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                let message = message.ok_or_else(|| de::Error::missing_field("value"))?;
+                Ok(WireMessage {
+                    message,
+                    have_range,
+                    compression,
+                    version,
+                    headers_only,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(WireMessageVisitor(PhantomData))
+    }
+}
+
+impl<T, L> From<Message<T, L>> for WireMessage<T, L> {
+    fn from(message: Message<T, L>) -> Self {
+        Self {
+            message,
+            have_range: None,
+            compression: false,
+            version: None,
+            headers_only: false,
+        }
+    }
 }
 
 /// Efficient sync protocol for append-only log data types.
 #[derive(Clone, Debug)]
-pub struct LogSyncProtocol<TM, L, E, S: LogStore<L, E>> {
+pub struct LogSyncProtocol<TM, L, E, S: OperationStore<L, E>> {
     topic_map: TM,
     store: S,
+    resume_log_heights: Arc<Mutex<HashMap<PublicKey, LogHeights<L>>>>,
+    compression: bool,
+    headers_only: bool,
+    observer: Option<MessageObserver>,
     _marker: PhantomData<(L, E)>,
 }
 
 impl<TM, L, E, S> LogSyncProtocol<TM, L, E, S>
 where
-    S: LogStore<L, E>,
+    S: OperationStore<L, E>,
 {
     /// Returns a new sync protocol instance, configured with a store and `TopicLogMap` implementation
     /// which associates the to-be-synced logs with a given topic.
@@ -107,9 +329,105 @@ where
         Self {
             topic_map,
             store,
+            resume_log_heights: Arc::new(Mutex::new(HashMap::new())),
+            compression: false,
+            headers_only: false,
+            observer: None,
             _marker: PhantomData {},
         }
     }
+
+    /// Enables compression of sync messages with zstd.
+    ///
+    /// Compression is negotiated with the remote peer at the beginning of each session and only
+    /// used if both peers have enabled it, otherwise messages are sent uncompressed.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Requests only operation headers without payloads in sync sessions initiated by us.
+    ///
+    /// This is useful for "off-chain" data types where payloads are fetched lazily later on. All
+    /// operations are forwarded to the application layer with an empty payload then.
+    pub fn with_headers_only(mut self, enabled: bool) -> Self {
+        self.headers_only = enabled;
+        self
+    }
+
+    /// Passes the CBOR bytes of all messages sent and received during sync sessions to the given
+    /// observer.
+    ///
+    /// This is intended for logging and debugging only, messages can't be modified by the observer.
+    pub fn with_observer(mut self, observer: MessageObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Sets log heights from which the next sync session initiated by us should resume.
+    ///
+    /// The remote peer will only send operations with a sequence number greater than the given
+    /// height per log, even if our local store holds less operations. This avoids re-transmitting
+    /// data after an interrupted session. If the store is further ahead for a log, the height of
+    /// the store is used instead.
+    ///
+    /// Resume heights are only used for one session: they are removed as soon as a session over
+    /// a topic query containing the log was initiated, all later sessions use the log heights of
+    /// the store again.
+    pub fn set_resume_log_heights(&self, log_heights: HashMap<PublicKey, Vec<(L, SeqNum)>>) {
+        let mut resume_log_heights = self
+            .resume_log_heights
+            .lock()
+            .expect("lock is not poisoned");
+        *resume_log_heights = log_heights;
+    }
+
+    /// Returns a codec for the wire messages of this protocol.
+    fn codec<T>(&self) -> CborCodec<WireMessage<T, L>> {
+        let codec = CborCodec::new();
+        match &self.observer {
+            Some(observer) => codec.with_observer(observer.clone()),
+            None => codec,
+        }
+    }
+
+    /// Takes a snapshot of the store which a sync session reads from.
+    async fn snapshot(&self) -> Result<S::Snapshot, SyncError> {
+        self.store
+            .snapshot()
+            .await
+            .map_err(|err| SyncError::Critical(format!("could not take snapshot of store, {err}")))
+    }
+
+    /// Removes and returns the resume log heights for the given logs.
+    fn take_resume_log_heights(&self, logs: &Logs<L>) -> HashMap<PublicKey, LogHeights<L>>
+    where
+        L: LogId,
+    {
+        let mut resume_log_heights = self
+            .resume_log_heights
+            .lock()
+            .expect("lock is not poisoned");
+
+        let mut taken = HashMap::new();
+        for (public_key, log_ids) in logs {
+            let Some(log_heights) = resume_log_heights.get_mut(public_key) else {
+                continue;
+            };
+            let (matching, remaining) = log_heights
+                .drain(..)
+                .partition(|(log_id, _)| log_ids.contains(log_id));
+            *log_heights = remaining;
+            if log_heights.is_empty() {
+                resume_log_heights.remove(public_key);
+            }
+            if !matching.is_empty() {
+                taken.insert(*public_key, matching);
+            }
+        }
+        taken
+    }
 }
 
 // Bidirectional log sync protocol.
@@ -119,6 +437,7 @@ where
 // [ Initiator ]        [ Acceptor ]
 // -------------        ------------
 //       have ->        -> have
+// compression <-       <- compression  (optional)
 //       data <-        <- data
 //       done <-        <- done
 //       have <-        <- have
@@ -132,7 +451,8 @@ where
     TM: TopicLogMap<T, L>,
     L: LogId + Send + Sync + for<'de> Deserialize<'de> + Serialize + 'a,
     E: Extensions + Send + Sync + 'a,
-    S: Debug + Sync + LogStore<L, E>,
+    S: Debug + Sync + OperationStore<L, E>,
+    S::Snapshot: Sync,
 {
     fn name(&self) -> &'static str {
         "p2panda-log-sync-v1"
@@ -148,18 +468,39 @@ where
         let mut sync_done_received = false;
         let mut sync_done_sent = false;
 
-        let mut sink = into_cbor_sink(tx);
-        let mut stream = into_cbor_stream(rx);
+        let mut sink = FramedWrite::new(tx.compat_write(), self.codec::<T>());
+        let mut stream = FramedRead::new(rx.compat(), self.codec::<T>());
 
-        // Retrieve the local log heights for all logs matching the topic query.
-        let local_log_heights =
-            local_log_heights(&self.store, &self.topic_map, &topic_query).await?;
+        // Read from the same point-in-time view of the store for the whole session.
+        let store = self.snapshot().await?;
 
-        // Send our `Have` message to the remote peer.
-        sink.send(Message::<T, L>::Have(
-            topic_query.clone(),
-            local_log_heights.clone(),
-        ))
+        // Retrieve the local log heights for all logs matching the topic query.
+        let local_log_heights = local_log_heights(&store, &self.topic_map, &topic_query).await?;
+
+        // Send our `Have` message to the remote peer, including the heights we want to resume
+        // from if any were given for the logs of this topic query.
+        let have_range = match self.topic_map.get(&topic_query).await {
+            Some(logs) => {
+                let resume_log_heights = self.take_resume_log_heights(&logs);
+                if resume_log_heights.is_empty() {
+                    None
+                } else {
+                    let mut have_range = local_log_heights.clone();
+                    merge_log_heights(&mut have_range, &resume_log_heights);
+                    Some(have_range)
+                }
+            }
+            None => None,
+        };
+        // Propose compressing all further messages, the remote peer confirms it before sending any
+        // data if it supports compression as well.
+        sink.send(WireMessage {
+            message: Message::<T, L>::Have(topic_query.clone(), local_log_heights.clone()),
+            have_range,
+            compression: self.compression,
+            version: Some(PROTOCOL_VERSION),
+            headers_only: self.headers_only,
+        })
         .await?;
 
         // Announce the topic query of the sync session to the app layer.
@@ -168,8 +509,12 @@ where
             .await?;
 
         // Consume messages arriving on the receive stream.
+        let mut compression_proposed = self.compression;
         while let Some(result) = stream.next().await {
-            let message: Message<T, L> = result?;
+            let message: Message<T, L> = result?.message;
+
+            // Compression can only be confirmed with the first message of the remote peer.
+            let compression_expected = std::mem::take(&mut compression_proposed);
 
             match message {
                 Message::Data(header, payload) => {
@@ -179,6 +524,19 @@ where
                 Message::Done => {
                     sync_done_received = true;
                 }
+                Message::Compression => {
+                    if !compression_expected {
+                        return Err(SyncError::UnexpectedBehaviour(
+                            "unexpected \"compression\" message received".to_string(),
+                        ));
+                    }
+
+                    #[cfg(feature = "compression")]
+                    {
+                        sink.encoder_mut().enable_compression();
+                        stream.decoder_mut().enable_compression();
+                    }
+                }
                 Message::Have(remote_topic_query, remote_log_heights) => {
                     if !sync_done_received {
                         return Err(SyncError::UnexpectedBehaviour(
@@ -205,13 +563,15 @@ where
 
                     // Retrieve and send all messages needed by the remote peer.
                     let messages: Vec<Message<T, L>> =
-                        messages_needed_by_remote(&self.store, &logs, remote_log_heights_map)
+                        messages_needed_by_remote(&store, &logs, remote_log_heights_map, false)
                             .await?;
-                    sink.send_all(&mut stream::iter(messages.into_iter().map(Ok)))
-                        .await?;
+                    sink.send_all(&mut stream::iter(
+                        messages.into_iter().map(|message| Ok(message.into())),
+                    ))
+                    .await?;
 
                     // Signal to the remote peer that we have finished sending data.
-                    sink.send(Message::Done).await?;
+                    sink.send(Message::Done.into()).await?;
                     sync_done_sent = true;
                 }
             };
@@ -237,13 +597,48 @@ where
         let mut sync_done_sent = false;
         let mut sync_done_received = false;
 
-        let mut sink = into_cbor_sink(tx);
-        let mut stream = into_cbor_stream(rx);
+        let mut sink = FramedWrite::new(tx.compat_write(), self.codec::<T>());
+        let mut stream = FramedRead::new(rx.compat(), self.codec::<T>());
+
+        // Read from the same point-in-time view of the store for the whole session.
+        let store = self.snapshot().await?;
 
         while let Some(result) = stream.next().await {
-            let message: Message<T, L> = result?;
+            let WireMessage {
+                message,
+                have_range,
+                compression,
+                version,
+                headers_only,
+            } = result?;
             match message {
+                Message::Compression => {
+                    // Compression is only ever confirmed by the accepting peer.
+                    return Err(SyncError::UnexpectedBehaviour(
+                        "unexpected \"compression\" message received".to_string(),
+                    ));
+                }
                 Message::Have(topic_query, remote_log_heights) => {
+                    // Abort early if we can't speak the protocol version of the remote peer.
+                    let version = version.unwrap_or(LEGACY_PROTOCOL_VERSION);
+                    if version != PROTOCOL_VERSION {
+                        return Err(SyncError::UnexpectedBehaviour(format!(
+                            "incompatible protocol version {version}"
+                        )));
+                    }
+
+                    // Confirm compression if both peers support it and compress all further
+                    // messages.
+                    if compression && self.compression {
+                        sink.send(Message::Compression.into()).await?;
+
+                        #[cfg(feature = "compression")]
+                        {
+                            sink.encoder_mut().enable_compression();
+                            stream.decoder_mut().enable_compression();
+                        }
+                    }
+
                     // Signal that the "handshake" phase of this protocol is complete as we
                     // received the topic query.
                     app_tx
@@ -257,29 +652,39 @@ where
                         )));
                     };
 
+                    // The remote peer might want to resume from log heights it didn't persist yet.
+                    let remote_log_heights = have_range.unwrap_or(remote_log_heights);
                     let remote_log_heights_map: HashMap<PublicKey, Vec<(L, u64)>> =
-                        remote_log_heights.clone().into_iter().collect();
+                        remote_log_heights.into_iter().collect();
+
+                    // Retrieve and send all messages needed by the remote peer, leaving out all
+                    // payloads if the remote peer only requested headers.
+                    let messages: Vec<Message<T, L>> = messages_needed_by_remote(
+                        &store,
+                        &logs,
+                        remote_log_heights_map,
+                        headers_only,
+                    )
+                    .await?;
 
-                    // Retrieve and send all messages needed by the remote peer.
-                    let messages: Vec<Message<T, L>> =
-                        messages_needed_by_remote(&self.store, &logs, remote_log_heights_map)
-                            .await?;
-                    sink.send_all(&mut stream::iter(messages.into_iter().map(Ok)))
-                        .await?;
+                    sink.send_all(&mut stream::iter(
+                        messages.into_iter().map(|message| Ok(message.into())),
+                    ))
+                    .await?;
 
                     // Signal to the remote peer that we have finished sending data.
-                    sink.send(Message::Done).await?;
+                    sink.send(Message::Done.into()).await?;
                     sync_done_sent = true;
 
                     // Retrieve the local log heights for all logs matching the topic query.
                     let local_log_heights =
-                        local_log_heights(&self.store, &self.topic_map, &topic_query).await?;
+                        local_log_heights(&store, &self.topic_map, &topic_query).await?;
 
                     // Send our `Have` message to the remote peer.
-                    sink.send(Message::<T, L>::Have(
-                        topic_query.clone(),
-                        local_log_heights.clone(),
-                    ))
+                    sink.send(
+                        Message::<T, L>::Have(topic_query.clone(), local_log_heights.clone())
+                            .into(),
+                    )
                     .await?;
                 }
                 Message::Data(header, payload) => {
@@ -344,27 +749,73 @@ where
     Ok(local_log_heights)
 }
 
+/// Raise the log heights retrieved from the store to the given resume log heights, if they are
+/// higher.
+///
+/// Resume heights of authors which are not part of the topic query are ignored.
+fn merge_log_heights<L>(
+    log_heights: &mut [(PublicKey, LogHeights<L>)],
+    resume_log_heights: &HashMap<PublicKey, LogHeights<L>>,
+) where
+    L: LogId,
+{
+    for (public_key, log_heights) in log_heights.iter_mut() {
+        let Some(resume_log_heights) = resume_log_heights.get(public_key) else {
+            continue;
+        };
+
+        for (resume_log_id, resume_seq_num) in resume_log_heights {
+            match log_heights
+                .iter_mut()
+                .find(|(log_id, _)| log_id == resume_log_id)
+            {
+                Some((_, seq_num)) => {
+                    *seq_num = (*seq_num).max(*resume_seq_num);
+                }
+                None => log_heights.push((resume_log_id.clone(), *resume_seq_num)),
+            }
+        }
+    }
+}
+
 /// Return all messages needed by a remote peer for the given log id and format them as data
 /// messages for transport over the wire.
+///
+/// Payloads are not loaded from the store when the remote peer only requested headers.
 async fn remote_needs<T, L, E>(
-    store: &impl LogStore<L, E>,
+    store: &(impl LogStore<L, E> + Sync),
     log_id: &L,
     public_key: &PublicKey,
     from: SeqNum,
+    headers_only: bool,
 ) -> Result<Vec<Message<T, L>>, SyncError>
 where
+    L: Sync,
     E: Extensions + Send + Sync,
 {
-    let log = store
-        .get_raw_log(public_key, log_id, Some(from))
-        .await
-        .map_err(|err| SyncError::Critical(format!("could not retrieve log from store, {err}")))?;
-
-    let messages = log
-        .unwrap_or_default()
-        .into_iter()
-        .map(|(header, payload)| Message::Data(header, payload))
-        .collect();
+    let messages = if headers_only {
+        store
+            .get_raw_log_headers(public_key, log_id, Some(from))
+            .await
+            .map_err(|err| {
+                SyncError::Critical(format!("could not retrieve log from store, {err}"))
+            })?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|header| Message::Data(header, None))
+            .collect()
+    } else {
+        store
+            .get_raw_log(public_key, log_id, Some(from))
+            .await
+            .map_err(|err| {
+                SyncError::Critical(format!("could not retrieve log from store, {err}"))
+            })?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(header, payload)| Message::Data(header, payload))
+            .collect()
+    };
 
     Ok(messages)
 }
@@ -372,12 +823,13 @@ where
 /// Compare the local log heights with the remote log heights for all given logs and return all
 /// messages needed by the remote peer.
 async fn messages_needed_by_remote<T, L, E>(
-    store: &impl LogStore<L, E>,
+    store: &(impl LogStore<L, E> + Sync),
     logs: &Logs<L>,
     remote_log_heights_map: HashMap<PublicKey, Vec<(L, u64)>>,
+    headers_only: bool,
 ) -> Result<Vec<Message<T, L>>, SyncError>
 where
-    L: LogId,
+    L: LogId + Sync,
     E: Extensions + Send + Sync,
 {
     // Now that the topic query has been translated into a collection of logs we want to
@@ -421,7 +873,8 @@ where
 
             if remote_needs_from <= log_height {
                 let messages: Vec<Message<T, L>> =
-                    remote_needs(store, log_id, public_key, remote_needs_from).await?;
+                    remote_needs(store, log_id, public_key, remote_needs_from, headers_only)
+                        .await?;
                 for message in messages {
                     messages_for_remote.push(message);
                 }
@@ -439,7 +892,7 @@ mod tests {
 
     use async_trait::async_trait;
     use futures::SinkExt;
-    use p2panda_core::{Body, Hash, Header, PrivateKey};
+    use p2panda_core::{validate_header, Body, Hash, Header, PrivateKey, PublicKey};
     use p2panda_store::{MemoryStore, OperationStore};
     use serde::{Deserialize, Serialize};
     use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf};
@@ -447,9 +900,10 @@ mod tests {
     use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
     use tokio_util::sync::PollSender;
 
+    use crate::cbor::{Direction, MessageObserver};
     use crate::{FromSync, SyncError, SyncProtocol, TopicQuery};
 
-    use super::{LogSyncProtocol, Logs, Message, TopicLogMap};
+    use super::{LogSyncProtocol, Logs, Message, TopicLogMap, WireMessage, PROTOCOL_VERSION};
 
     impl<T, L> Message<T, L>
     where
@@ -524,19 +978,27 @@ mod tests {
 
     async fn assert_message_bytes(
         mut rx: ReadHalf<DuplexStream>,
-        messages: Vec<Message<LogHeightTopic, u8>>,
+        messages: Vec<WireMessage<LogHeightTopic, u8>>,
     ) {
         let mut buf = Vec::new();
         rx.read_to_end(&mut buf).await.unwrap();
         assert_eq!(
             buf,
             messages.iter().fold(Vec::new(), |mut acc, message| {
-                acc.extend(message.to_bytes());
+                acc.extend(p2panda_core::cbor::encode_cbor(message).unwrap());
                 acc
             })
         );
     }
 
+    /// Returns the message with the protocol version attached, as sent by initiating peers.
+    fn versioned<T, L>(message: Message<T, L>) -> WireMessage<T, L> {
+        WireMessage {
+            version: Some(PROTOCOL_VERSION),
+            ..message.into()
+        }
+    }
+
     fn to_bytes(messages: Vec<Message<LogHeightTopic>>) -> Vec<u8> {
         messages.iter().fold(Vec::new(), |mut acc, message| {
             acc.extend(message.to_bytes());
@@ -583,7 +1045,10 @@ mod tests {
         // Assert that peer a sent peer b the expected messages
         assert_message_bytes(
             peer_b_read,
-            vec![Message::Done, Message::Have(topic_query.clone(), vec![])],
+            vec![
+                Message::Done.into(),
+                Message::Have(topic_query.clone(), vec![]).into(),
+            ],
         )
         .await;
 
@@ -637,7 +1102,10 @@ mod tests {
         // Assert that peer a sent peer b the expected messages
         assert_message_bytes(
             peer_b_read,
-            vec![Message::Have(topic_query.clone(), vec![]), Message::Done],
+            vec![
+                versioned(Message::Have(topic_query.clone(), vec![])),
+                Message::Done.into(),
+            ],
         )
         .await;
 
@@ -721,7 +1189,11 @@ mod tests {
                 vec![(private_key.public_key(), vec![(0, 2)])],
             ),
         ];
-        assert_message_bytes(peer_b_read, messages).await;
+        assert_message_bytes(
+            peer_b_read,
+            messages.into_iter().map(WireMessage::from).collect(),
+        )
+        .await;
 
         // Assert that peer a sent the expected messages on it's app channel
         let mut messages = Vec::new();
@@ -788,11 +1260,11 @@ mod tests {
         assert_message_bytes(
             peer_b_read,
             vec![
-                Message::Have(
+                versioned(Message::Have(
                     topic_query.clone(),
                     vec![(private_key.public_key(), vec![])],
-                ),
-                Message::Done,
+                )),
+                Message::Done.into(),
             ],
         )
         .await;
@@ -820,6 +1292,89 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn session_reads_from_snapshot() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = LogHeightTopic::new("messages");
+        let logs = HashMap::from([(private_key.public_key(), vec![log_id])]);
+
+        let mut store = MemoryStore::<u64>::new();
+
+        let body = Body::new("Hello, Sloth!".as_bytes());
+        let (hash_0, header_0, header_bytes_0) = create_operation(&private_key, &body, 0, 0, None);
+        let (hash_1, header_1, header_bytes_1) =
+            create_operation(&private_key, &body, 1, 100, Some(hash_0));
+
+        store
+            .insert_operation(hash_0, &header_0, Some(&body), &header_bytes_0, &log_id)
+            .await
+            .unwrap();
+
+        // Duplex streams which simulate both ends of a bi-directional network connection
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (mut peer_b_read, mut peer_b_write) = tokio::io::split(peer_b);
+
+        // Channel for sending messages out of a running sync session
+        let (app_tx, _app_rx) = mpsc::channel(128);
+
+        // Initiate a sync session on peer a, it waits for peer b after sending it's "have"
+        // message
+        let mut topic_map = LogHeightTopicMap::new();
+        topic_map.insert(&topic_query, logs);
+        let protocol = Arc::new(LogSyncProtocol::new(topic_map, store.clone()));
+        let handle = {
+            let topic_query = topic_query.clone();
+            tokio::spawn(async move {
+                let mut sink = PollSender::new(app_tx)
+                    .sink_map_err(|err| SyncError::Critical(err.to_string()));
+                protocol
+                    .initiate(
+                        topic_query,
+                        Box::new(&mut peer_a_write.compat_write()),
+                        Box::new(&mut peer_a_read.compat()),
+                        Box::new(&mut sink),
+                    )
+                    .await
+            })
+        };
+
+        let have_bytes = p2panda_core::cbor::encode_cbor(&versioned(Message::<_, u64>::Have(
+            topic_query.clone(),
+            vec![(private_key.public_key(), vec![(log_id, 0)])],
+        )))
+        .unwrap();
+        let mut buf = vec![0; have_bytes.len()];
+        peer_b_read.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, have_bytes);
+
+        // Insert another operation while the session is running
+        store
+            .insert_operation(hash_1, &header_1, Some(&body), &header_bytes_1, &log_id)
+            .await
+            .unwrap();
+
+        peer_b_write
+            .write_all(&to_bytes(vec![
+                Message::Done,
+                Message::Have(topic_query.clone(), vec![]),
+            ]))
+            .await
+            .unwrap();
+        handle.await.unwrap().unwrap();
+
+        // Peer a only sends the operation which was in the store when the session started
+        assert_message_bytes(
+            peer_b_read,
+            vec![
+                Message::Data(header_bytes_0, Some(body.to_bytes())).into(),
+                Message::Done.into(),
+            ],
+        )
+        .await;
+    }
+
     #[tokio::test]
     async fn e2e_sync_where_one_peer_has_data() {
         let private_key = PrivateKey::new();
@@ -1230,4 +1785,544 @@ mod tests {
         peer_a_app_rx.recv_many(&mut peer_a_messages, 10).await;
         assert_eq!(peer_a_messages, peer_a_expected_messages);
     }
+
+    #[tokio::test]
+    async fn e2e_sync_resume_from_log_heights() {
+        // Scenario: peer B holds 1000 operations of a log. Peer A persisted the first 250 of them
+        // and received another 250 in an interrupted session which did not reach the store yet.
+        //
+        // Expectation: peer A resumes sync from the given log height and only receives the
+        // remaining 500 operations.
+
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = LogHeightTopic::new("messages");
+        let logs = HashMap::from([(private_key.public_key(), vec![log_id])]);
+
+        let body = Body::new("Hello, Sloth!".as_bytes());
+
+        let mut store_1 = MemoryStore::default();
+        let mut store_2 = MemoryStore::default();
+
+        let mut backlink = None;
+        for seq_num in 0..1000 {
+            let (hash, header, header_bytes) =
+                create_operation(&private_key, &body, seq_num, seq_num * 100, backlink);
+            if seq_num < 250 {
+                store_1
+                    .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                    .await
+                    .unwrap();
+            }
+            store_2
+                .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                .await
+                .unwrap();
+            backlink = Some(hash);
+        }
+
+        // Construct log height protocols for both peers, peer a resumes from seq num 499
+        let mut topic_map = LogHeightTopicMap::new();
+        topic_map.insert(&topic_query, logs);
+        let peer_a_protocol = Arc::new(LogSyncProtocol::new(topic_map.clone(), store_1));
+        peer_a_protocol.set_resume_log_heights(HashMap::from([(
+            private_key.public_key(),
+            vec![(log_id, 499)],
+        )]));
+        let resume_log_heights = peer_a_protocol.resume_log_heights.clone();
+        let peer_b_protocol = Arc::new(LogSyncProtocol::new(topic_map, store_2));
+
+        // Duplex streams which simulate both ends of a bi-directional network connection
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (peer_b_read, peer_b_write) = tokio::io::split(peer_b);
+
+        // Spawn a task which opens a sync session from peer a runs it to completion
+        let (peer_a_app_tx, mut peer_a_app_rx) = mpsc::channel(1024);
+        let mut sink =
+            PollSender::new(peer_a_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let topic_clone = topic_query.clone();
+        let handle_1 = tokio::spawn(async move {
+            peer_a_protocol
+                .initiate(
+                    topic_clone,
+                    Box::new(&mut peer_a_write.compat_write()),
+                    Box::new(&mut peer_a_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        // Spawn a task which accepts a sync session on peer b runs it to completion
+        let (peer_b_app_tx, mut peer_b_app_rx) = mpsc::channel(1024);
+        let mut sink =
+            PollSender::new(peer_b_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let handle_2 = tokio::spawn(async move {
+            peer_b_protocol
+                .accept(
+                    Box::new(&mut peer_b_write.compat_write()),
+                    Box::new(&mut peer_b_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        // Wait for both to complete
+        let (_, _) = tokio::join!(handle_1, handle_2);
+
+        // Peer a should only receive the operations it didn't have yet.
+        let mut peer_a_messages = Vec::new();
+        peer_a_app_rx.recv_many(&mut peer_a_messages, 2000).await;
+        assert_eq!(peer_a_messages.len(), 501);
+        assert_eq!(
+            peer_a_messages[0],
+            FromSync::HandshakeSuccess(topic_query.clone())
+        );
+        for (index, message) in peer_a_messages[1..].iter().enumerate() {
+            let FromSync::Data { header, .. } = message else {
+                panic!("expected data message");
+            };
+            let header = Header::try_from(header.as_slice()).unwrap();
+            assert_eq!(header.seq_num, 500 + index as u64);
+        }
+
+        // Peer b already has everything.
+        let mut peer_b_messages = Vec::new();
+        peer_b_app_rx.recv_many(&mut peer_b_messages, 10).await;
+        assert_eq!(
+            peer_b_messages,
+            vec![FromSync::HandshakeSuccess(topic_query)]
+        );
+
+        // Resume heights are only used for one session.
+        assert!(resume_log_heights.lock().unwrap().is_empty());
+    }
+
+    /// Accepts a sync session where the initiating peer sends a "have" message announcing the
+    /// given protocol version.
+    async fn accept_with_version(
+        version: Option<u8>,
+    ) -> (Result<(), SyncError>, Vec<FromSync<LogHeightTopic>>) {
+        let topic_query = LogHeightTopic::new("messages");
+        let mut topic_map = LogHeightTopicMap::new();
+        topic_map.insert(&topic_query, HashMap::new());
+        let protocol = Arc::new(LogSyncProtocol::new(topic_map, MemoryStore::<u64>::new()));
+
+        // Duplex streams which simulate both ends of a bi-directional network connection
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (_peer_b_read, mut peer_b_write) = tokio::io::split(peer_b);
+
+        let mut message_bytes = p2panda_core::cbor::encode_cbor(&WireMessage {
+            version,
+            ..Message::<LogHeightTopic, u64>::Have(topic_query, vec![]).into()
+        })
+        .unwrap();
+        message_bytes.extend(Message::<LogHeightTopic, u64>::Done.to_bytes());
+        peer_b_write.write_all(&message_bytes[..]).await.unwrap();
+
+        let (app_tx, mut app_rx) = mpsc::channel(128);
+        let mut sink =
+            PollSender::new(app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let result = protocol
+            .accept(
+                Box::new(&mut peer_a_write.compat_write()),
+                Box::new(&mut peer_a_read.compat()),
+                Box::new(&mut sink),
+            )
+            .await;
+        drop(sink);
+
+        let mut messages = Vec::new();
+        while let Some(message) = app_rx.recv().await {
+            messages.push(message);
+        }
+        (result, messages)
+    }
+
+    #[tokio::test]
+    async fn protocol_version_negotiation() {
+        let topic_query = LogHeightTopic::new("messages");
+
+        // Peers speaking the same version proceed with sync.
+        let (result, messages) = accept_with_version(Some(PROTOCOL_VERSION)).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            messages,
+            vec![FromSync::HandshakeSuccess(topic_query.clone())]
+        );
+
+        // Peers not sending a version speak the first version of the protocol.
+        let (result, messages) = accept_with_version(None).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(messages, vec![FromSync::HandshakeSuccess(topic_query)]);
+
+        // Sessions with peers speaking an unknown version are aborted before the handshake
+        // completes.
+        let (result, messages) = accept_with_version(Some(PROTOCOL_VERSION + 1)).await;
+        assert_eq!(
+            result,
+            Err(SyncError::UnexpectedBehaviour(format!(
+                "incompatible protocol version {}",
+                PROTOCOL_VERSION + 1
+            )))
+        );
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn optional_fields_ignored_by_older_peers() {
+        let public_key = PrivateKey::new().public_key();
+        let topic_query = LogHeightTopic::new("messages");
+
+        let bytes = p2panda_core::cbor::encode_cbor(&WireMessage {
+            message: Message::<LogHeightTopic, u64>::Have(
+                topic_query.clone(),
+                vec![(public_key, vec![(0, 249)])],
+            ),
+            have_range: Some(vec![(public_key, vec![(0, 499)])]),
+            compression: true,
+            version: Some(PROTOCOL_VERSION),
+            headers_only: true,
+        })
+        .unwrap();
+
+        // Peers not knowing about resume heights, compression, versions or header-only sync decode
+        // a regular "have" message.
+        #[derive(Deserialize)]
+        #[serde(tag = "type", content = "value")]
+        enum PreviousMessage {
+            Have(LogHeightTopic, Vec<(PublicKey, Vec<(u64, u64)>)>),
+        }
+
+        let message: PreviousMessage = p2panda_core::cbor::decode_cbor(&bytes[..]).unwrap();
+        let PreviousMessage::Have(received_topic_query, log_heights) = message;
+        assert_eq!(received_topic_query, topic_query);
+        assert_eq!(log_heights, vec![(public_key, vec![(0, 249)])]);
+    }
+
+    #[cfg(feature = "compression")]
+    async fn sync_with_compression(
+        store: MemoryStore<u64>,
+        topic_map: LogHeightTopicMap<LogHeightTopic>,
+        topic_query: LogHeightTopic,
+        initiator_compression: bool,
+        acceptor_compression: bool,
+    ) -> Vec<FromSync<LogHeightTopic>> {
+        let peer_a_protocol = Arc::new(
+            LogSyncProtocol::new(topic_map.clone(), MemoryStore::<u64>::new())
+                .with_compression(initiator_compression),
+        );
+        let peer_b_protocol =
+            Arc::new(LogSyncProtocol::new(topic_map, store).with_compression(acceptor_compression));
+
+        // Duplex streams which simulate both ends of a bi-directional network connection
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (peer_b_read, peer_b_write) = tokio::io::split(peer_b);
+
+        let (peer_a_app_tx, mut peer_a_app_rx) = mpsc::channel(2048);
+        let mut sink =
+            PollSender::new(peer_a_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let handle_1 = tokio::spawn(async move {
+            peer_a_protocol
+                .initiate(
+                    topic_query,
+                    Box::new(&mut peer_a_write.compat_write()),
+                    Box::new(&mut peer_a_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (peer_b_app_tx, _peer_b_app_rx) = mpsc::channel(128);
+        let mut sink =
+            PollSender::new(peer_b_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let handle_2 = tokio::spawn(async move {
+            peer_b_protocol
+                .accept(
+                    Box::new(&mut peer_b_write.compat_write()),
+                    Box::new(&mut peer_b_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (result_1, result_2) = tokio::join!(handle_1, handle_2);
+        result_1.unwrap();
+        result_2.unwrap();
+
+        let mut peer_a_messages = Vec::new();
+        peer_a_app_rx.recv_many(&mut peer_a_messages, 2048).await;
+        peer_a_messages
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn e2e_sync_with_compression() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = LogHeightTopic::new("messages");
+        let logs = HashMap::from([(private_key.public_key(), vec![log_id])]);
+
+        let mut topic_map = LogHeightTopicMap::new();
+        topic_map.insert(&topic_query, logs);
+
+        // Populate a store with 1000 text operations.
+        let mut store = MemoryStore::<u64>::new();
+        let mut backlink = None;
+        for seq_num in 0..1000 {
+            let body = Body::new(format!("Hello, Sloth! This is message #{seq_num}").as_bytes());
+            let (hash, header, header_bytes) =
+                create_operation(&private_key, &body, seq_num, seq_num * 100, backlink);
+            store
+                .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                .await
+                .unwrap();
+            backlink = Some(hash);
+        }
+
+        let uncompressed = sync_with_compression(
+            store.clone(),
+            topic_map.clone(),
+            topic_query.clone(),
+            false,
+            false,
+        )
+        .await;
+        assert_eq!(uncompressed.len(), 1001);
+
+        // Both peers agree on compression.
+        let compressed = sync_with_compression(
+            store.clone(),
+            topic_map.clone(),
+            topic_query.clone(),
+            true,
+            true,
+        )
+        .await;
+        assert_eq!(compressed, uncompressed);
+
+        // Accepting peer does not support compression, we transparently fall back.
+        let fallback = sync_with_compression(store, topic_map, topic_query, true, false).await;
+        assert_eq!(fallback, uncompressed);
+    }
+
+    type ObservedMessages = Arc<std::sync::Mutex<Vec<(Direction, Vec<u8>)>>>;
+
+    #[tokio::test]
+    async fn e2e_sync_with_observer() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = LogHeightTopic::new("messages");
+        let logs = HashMap::from([(private_key.public_key(), vec![log_id])]);
+
+        let mut topic_map = LogHeightTopicMap::new();
+        topic_map.insert(&topic_query, logs);
+
+        // Peer b holds two operations which peer a doesn't know about yet.
+        let mut store = MemoryStore::<u64>::new();
+        let body = Body::new("Hello, Sloth!".as_bytes());
+        let (hash_0, header_0, header_bytes_0) = create_operation(&private_key, &body, 0, 0, None);
+        let (hash_1, header_1, header_bytes_1) =
+            create_operation(&private_key, &body, 1, 100, Some(hash_0));
+        store
+            .insert_operation(hash_0, &header_0, Some(&body), &header_bytes_0, &log_id)
+            .await
+            .unwrap();
+        store
+            .insert_operation(hash_1, &header_1, Some(&body), &header_bytes_1, &log_id)
+            .await
+            .unwrap();
+
+        // Both peers record all messages passing their codecs.
+        let observed_a = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let observed_b = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let observer = |observed: ObservedMessages| {
+            MessageObserver::new(move |direction, bytes| {
+                observed.lock().unwrap().push((direction, bytes.to_vec()))
+            })
+        };
+        let peer_a_protocol = Arc::new(
+            LogSyncProtocol::new(topic_map.clone(), MemoryStore::<u64>::new())
+                .with_observer(observer(observed_a.clone())),
+        );
+        let peer_b_protocol = Arc::new(
+            LogSyncProtocol::new(topic_map, store).with_observer(observer(observed_b.clone())),
+        );
+
+        // Duplex streams which simulate both ends of a bi-directional network connection
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (peer_b_read, peer_b_write) = tokio::io::split(peer_b);
+
+        let (peer_a_app_tx, _peer_a_app_rx) = mpsc::channel(128);
+        let mut sink =
+            PollSender::new(peer_a_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let topic_query_clone = topic_query.clone();
+        let handle_1 = tokio::spawn(async move {
+            peer_a_protocol
+                .initiate(
+                    topic_query_clone,
+                    Box::new(&mut peer_a_write.compat_write()),
+                    Box::new(&mut peer_a_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (peer_b_app_tx, _peer_b_app_rx) = mpsc::channel(128);
+        let mut sink =
+            PollSender::new(peer_b_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let handle_2 = tokio::spawn(async move {
+            peer_b_protocol
+                .accept(
+                    Box::new(&mut peer_b_write.compat_write()),
+                    Box::new(&mut peer_b_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (result_1, result_2) = tokio::join!(handle_1, handle_2);
+        result_1.unwrap();
+        result_2.unwrap();
+
+        let messages = |observed: &ObservedMessages, direction: Direction| {
+            observed
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(observed_direction, _)| *observed_direction == direction)
+                .map(|(_, bytes)| bytes.clone())
+                .collect::<Vec<Vec<u8>>>()
+        };
+
+        let peer_a_sent: Vec<WireMessage<LogHeightTopic, u64>> = vec![
+            versioned(Message::Have(
+                topic_query.clone(),
+                vec![(private_key.public_key(), vec![])],
+            )),
+            Message::Done.into(),
+        ];
+        let peer_b_sent: Vec<Message<LogHeightTopic, u64>> = vec![
+            Message::Data(header_bytes_0, Some(body.to_bytes())),
+            Message::Data(header_bytes_1, Some(body.to_bytes())),
+            Message::Done,
+            Message::Have(
+                topic_query,
+                vec![(private_key.public_key(), vec![(log_id, 1)])],
+            ),
+        ];
+        let peer_a_sent: Vec<Vec<u8>> = peer_a_sent
+            .iter()
+            .map(|message| p2panda_core::cbor::encode_cbor(message).unwrap())
+            .collect();
+        let peer_b_sent: Vec<Vec<u8>> = peer_b_sent.iter().map(Message::to_bytes).collect();
+
+        assert_eq!(messages(&observed_a, Direction::Outbound), peer_a_sent);
+        assert_eq!(messages(&observed_a, Direction::Inbound), peer_b_sent);
+        assert_eq!(messages(&observed_b, Direction::Outbound), peer_b_sent);
+        assert_eq!(messages(&observed_b, Direction::Inbound), peer_a_sent);
+    }
+
+    #[tokio::test]
+    async fn e2e_sync_headers_only() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = LogHeightTopic::new("messages");
+        let logs = HashMap::from([(private_key.public_key(), vec![log_id])]);
+
+        let mut topic_map = LogHeightTopicMap::new();
+        topic_map.insert(&topic_query, logs);
+
+        // Peer b holds three operations with payloads.
+        let mut store = MemoryStore::<u64>::new();
+        let body = Body::new("Hello, Sloth!".as_bytes());
+        let mut backlink = None;
+        let mut expected_headers = Vec::new();
+        for seq_num in 0..3 {
+            let (hash, header, header_bytes) =
+                create_operation(&private_key, &body, seq_num, seq_num * 100, backlink);
+            store
+                .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                .await
+                .unwrap();
+            expected_headers.push(header_bytes);
+            backlink = Some(hash);
+        }
+
+        // Peer a only requests headers.
+        let peer_a_protocol = Arc::new(
+            LogSyncProtocol::new(topic_map.clone(), MemoryStore::<u64>::new())
+                .with_headers_only(true),
+        );
+        let peer_b_protocol = Arc::new(LogSyncProtocol::new(topic_map, store));
+
+        // Duplex streams which simulate both ends of a bi-directional network connection
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (peer_b_read, peer_b_write) = tokio::io::split(peer_b);
+
+        let (peer_a_app_tx, mut peer_a_app_rx) = mpsc::channel(128);
+        let mut sink =
+            PollSender::new(peer_a_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let topic_query_clone = topic_query.clone();
+        let handle_1 = tokio::spawn(async move {
+            peer_a_protocol
+                .initiate(
+                    topic_query_clone,
+                    Box::new(&mut peer_a_write.compat_write()),
+                    Box::new(&mut peer_a_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (peer_b_app_tx, _peer_b_app_rx) = mpsc::channel(128);
+        let mut sink =
+            PollSender::new(peer_b_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let handle_2 = tokio::spawn(async move {
+            peer_b_protocol
+                .accept(
+                    Box::new(&mut peer_b_write.compat_write()),
+                    Box::new(&mut peer_b_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (result_1, result_2) = tokio::join!(handle_1, handle_2);
+        result_1.unwrap();
+        result_2.unwrap();
+
+        let mut peer_a_messages = Vec::new();
+        peer_a_app_rx.recv_many(&mut peer_a_messages, 10).await;
+        assert_eq!(
+            peer_a_messages.remove(0),
+            FromSync::HandshakeSuccess(topic_query)
+        );
+
+        // All headers arrived without their payloads and are still valid.
+        let mut received_headers = Vec::new();
+        for message in peer_a_messages {
+            let FromSync::Data { header, payload } = message else {
+                panic!("unexpected message {message:?}");
+            };
+            assert_eq!(payload, None);
+            let decoded = Header::try_from(&header[..]).unwrap();
+            validate_header(&decoded).unwrap();
+            assert_eq!(decoded.payload_hash, Some(body.hash()));
+            received_headers.push(header);
+        }
+        assert_eq!(received_headers, expected_headers);
+    }
 }
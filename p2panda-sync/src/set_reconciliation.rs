@@ -0,0 +1,637 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Range-based set reconciliation sync protocol for sparse log data types.
+//!
+//! Log-height based sync assumes that peers hold a prefix of each log. When peers hold different,
+//! overlapping subsets of operations instead, for example after pruning or partial replication,
+//! comparing log heights leads to re-sending operations the remote peer already knows about.
+//!
+//! This protocol treats all operations matching a topic query as a set of operation hashes and
+//! reconciles it with the set of the remote peer, similar to [Negentropy]:
+//!
+//! 1. All hashes are ordered and the resulting key space is split into ranges. For each range a
+//!    "fingerprint" over all contained hashes is sent to the remote peer.
+//! 2. The remote peer compares the fingerprints with the ones of its own ranges. Ranges with equal
+//!    fingerprints hold the same operations and are skipped. Differing ranges are split further
+//!    and new fingerprints sent back, until a range is small enough to send the full list of
+//!    hashes it contains.
+//! 3. A peer receiving a list of hashes knows the exact difference of that range: it sends the
+//!    operations the remote peer is missing and requests the ones it is missing itself.
+//!
+//! Peers take turns until no unresolved ranges are left, only the genuine difference of both sets
+//! is transferred.
+//!
+//! To find out which logs to reconcile for a given "topic query" the `TopicLogMap` of the log-height
+//! based sync protocol is used.
+//!
+//! [Negentropy]: https://logperiodic.com/rbsr.html
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite, Sink, SinkExt, Stream, StreamExt};
+use p2panda_core::{Extensions, Hash};
+use p2panda_store::{LogId, LogStore};
+use serde::{Deserialize, Serialize};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tokio_util::compat::{FuturesAsyncReadCompatExt, FuturesAsyncWriteCompatExt};
+
+use crate::cbor::CborCodec;
+use crate::log_sync::TopicLogMap;
+use crate::{FromSync, SyncError, SyncProtocol, TopicQuery};
+
+/// Ranges with up to this number of operations are sent as a list of hashes instead of being
+/// split further.
+const ID_LIST_THRESHOLD: usize = 32;
+
+/// Number of sub-ranges a range with differing fingerprints is split into.
+const BRANCHING_FACTOR: usize = 16;
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+enum Message<T> {
+    /// Topic query of the session, sent by the initiating peer.
+    Handshake(T),
+
+    /// Operation the remote peer is missing.
+    Data(Vec<u8>, Option<Vec<u8>>),
+
+    /// Ends the turn of a peer, containing all ranges which still need to be reconciled and the
+    /// hashes of operations the peer is missing.
+    ///
+    /// A turn without any ranges and wanted operations ends the session.
+    Reconcile { ranges: Vec<Range>, want: Vec<Hash> },
+}
+
+/// Range of the key space, reaching from the upper bound of the previous range (or the start of
+/// the key space) to the given upper bound (or the end of the key space).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Range {
+    /// Exclusive upper bound of this range, `None` if it reaches until the end of the key space.
+    upper_bound: Option<Hash>,
+    mode: Mode,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Mode {
+    /// Range is already reconciled.
+    Skip,
+
+    /// Fingerprint over all hashes in this range.
+    Fingerprint(Hash),
+
+    /// All hashes in this range.
+    IdList(Vec<Hash>),
+}
+
+/// Operation which is part of the reconciled set.
+#[derive(Debug)]
+struct Item {
+    hash: Hash,
+    header: Vec<u8>,
+    payload: Option<Vec<u8>>,
+}
+
+/// Operations of one peer, ordered by their hash.
+#[derive(Debug)]
+struct ItemSet(Vec<Item>);
+
+impl ItemSet {
+    fn new(mut items: Vec<Item>) -> Self {
+        items.sort_by(|a, b| a.hash.as_bytes().cmp(b.hash.as_bytes()));
+        items.dedup_by(|a, b| a.hash == b.hash);
+        Self(items)
+    }
+
+    fn get(&self, hash: &Hash) -> Option<&Item> {
+        self.0
+            .binary_search_by(|item| item.hash.as_bytes().cmp(hash.as_bytes()))
+            .ok()
+            .map(|index| &self.0[index])
+    }
+
+    /// Returns all items with hashes between the given lower (inclusive) and upper (exclusive)
+    /// bound.
+    fn range(&self, lower_bound: Option<&Hash>, upper_bound: Option<&Hash>) -> &[Item] {
+        let position = |bound: &Hash| {
+            self.0
+                .partition_point(|item| item.hash.as_bytes() < bound.as_bytes())
+        };
+        let start = lower_bound.map_or(0, position);
+        let end = upper_bound.map_or(self.0.len(), position);
+        &self.0[start..end.max(start)]
+    }
+
+    /// Ranges covering the whole key space to start reconciliation with.
+    fn initial_ranges(&self) -> Vec<Range> {
+        split_range(&self.0, None)
+    }
+
+    /// Compares the ranges of the remote peer with our own.
+    fn reconcile(&self, ranges: Vec<Range>) -> Result<Difference<'_>, SyncError> {
+        let mut response = Vec::new();
+        let mut missing_remote = Vec::new();
+        let mut missing_local = Vec::new();
+
+        let mut lower_bound: Option<Hash> = None;
+        for (index, range) in ranges.iter().enumerate() {
+            // Ranges need to be ordered and only the last one can reach until the end of the key
+            // space.
+            match (&lower_bound, &range.upper_bound) {
+                (Some(lower), Some(upper)) if upper.as_bytes() <= lower.as_bytes() => {
+                    return Err(SyncError::UnexpectedBehaviour(
+                        "received unordered ranges".into(),
+                    ));
+                }
+                (_, None) if index != ranges.len() - 1 => {
+                    return Err(SyncError::UnexpectedBehaviour(
+                        "received unordered ranges".into(),
+                    ));
+                }
+                _ => (),
+            }
+
+            let items = self.range(lower_bound.as_ref(), range.upper_bound.as_ref());
+            match &range.mode {
+                Mode::Skip => push_range(&mut response, range.upper_bound, Mode::Skip),
+                Mode::Fingerprint(remote_fingerprint) => {
+                    if fingerprint(items) == *remote_fingerprint {
+                        push_range(&mut response, range.upper_bound, Mode::Skip);
+                    } else {
+                        for sub_range in split_range(items, range.upper_bound) {
+                            push_range(&mut response, sub_range.upper_bound, sub_range.mode);
+                        }
+                    }
+                }
+                Mode::IdList(remote_hashes) => {
+                    let remote_set: HashSet<&Hash> = remote_hashes.iter().collect();
+                    let local_set: HashSet<&Hash> = items.iter().map(|item| &item.hash).collect();
+                    missing_remote
+                        .extend(items.iter().filter(|item| !remote_set.contains(&item.hash)));
+                    missing_local.extend(
+                        remote_hashes
+                            .iter()
+                            .filter(|hash| !local_set.contains(hash)),
+                    );
+                    push_range(&mut response, range.upper_bound, Mode::Skip);
+                }
+            }
+
+            lower_bound = range.upper_bound;
+        }
+
+        // Skipped ranges at the end don't need to be sent.
+        while matches!(
+            response.last(),
+            Some(Range {
+                mode: Mode::Skip,
+                ..
+            })
+        ) {
+            response.pop();
+        }
+
+        Ok(Difference {
+            ranges: response,
+            missing_remote,
+            missing_local,
+        })
+    }
+}
+
+/// Result of comparing the ranges of the remote peer with our own.
+struct Difference<'s> {
+    /// Ranges which still need to be reconciled, to be sent back to the remote peer.
+    ranges: Vec<Range>,
+
+    /// Items the remote peer is missing.
+    missing_remote: Vec<&'s Item>,
+
+    /// Hashes of items we are missing.
+    missing_local: Vec<Hash>,
+}
+
+/// Adds a range to the response, merging consecutive skipped ranges.
+fn push_range(ranges: &mut Vec<Range>, upper_bound: Option<Hash>, mode: Mode) {
+    if let (Mode::Skip, Some(last)) = (&mode, ranges.last_mut()) {
+        if last.mode == Mode::Skip {
+            last.upper_bound = upper_bound;
+            return;
+        }
+    }
+    ranges.push(Range { upper_bound, mode });
+}
+
+/// Splits the items of a range into sub-ranges, sending small ranges as a list of hashes.
+fn split_range(items: &[Item], upper_bound: Option<Hash>) -> Vec<Range> {
+    if items.len() <= ID_LIST_THRESHOLD {
+        return vec![Range {
+            upper_bound,
+            mode: Mode::IdList(items.iter().map(|item| item.hash).collect()),
+        }];
+    }
+
+    let chunk_size = items.len().div_ceil(BRANCHING_FACTOR);
+    let chunks: Vec<&[Item]> = items.chunks(chunk_size).collect();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| Range {
+            upper_bound: match chunks.get(index + 1) {
+                Some(next_chunk) => Some(next_chunk[0].hash),
+                None => upper_bound,
+            },
+            mode: Mode::Fingerprint(fingerprint(chunk)),
+        })
+        .collect()
+}
+
+/// Fingerprint over the hashes of all given items.
+fn fingerprint(items: &[Item]) -> Hash {
+    let mut combined = [0; 32];
+    for item in items {
+        for (byte, hash_byte) in combined.iter_mut().zip(item.hash.as_bytes()) {
+            *byte ^= hash_byte;
+        }
+    }
+    let mut bytes = combined.to_vec();
+    bytes.extend_from_slice(&(items.len() as u64).to_be_bytes());
+    Hash::new(bytes)
+}
+
+/// Set reconciliation sync protocol for log data types where peers hold overlapping subsets of
+/// operations.
+#[derive(Clone, Debug)]
+pub struct SetReconciliationProtocol<TM, L, E, S: LogStore<L, E>> {
+    topic_map: TM,
+    store: S,
+    _marker: PhantomData<(L, E)>,
+}
+
+impl<TM, L, E, S> SetReconciliationProtocol<TM, L, E, S>
+where
+    S: LogStore<L, E>,
+{
+    /// Returns a new sync protocol instance, configured with a store and `TopicLogMap`
+    /// implementation which associates the to-be-synced logs with a given topic.
+    pub fn new(topic_map: TM, store: S) -> Self {
+        Self {
+            topic_map,
+            store,
+            _marker: PhantomData {},
+        }
+    }
+}
+
+impl<TM, L, E, S> SetReconciliationProtocol<TM, L, E, S>
+where
+    L: LogId,
+    E: Extensions + Send + Sync,
+    S: LogStore<L, E>,
+{
+    /// Loads all operations of the logs matching the topic query.
+    async fn item_set<T>(&self, topic_query: &T) -> Result<ItemSet, SyncError>
+    where
+        T: TopicQuery,
+        TM: TopicLogMap<T, L>,
+    {
+        let Some(logs) = self.topic_map.get(topic_query).await else {
+            return Err(SyncError::UnexpectedBehaviour(format!(
+                "unsupported topic query {topic_query:?} requested from remote peer"
+            )));
+        };
+
+        let mut items = Vec::new();
+        for (public_key, log_ids) in logs {
+            for log_id in log_ids {
+                let log = self
+                    .store
+                    .get_raw_log(&public_key, &log_id, None)
+                    .await
+                    .map_err(|err| {
+                        SyncError::Critical(format!("could not retrieve log from store, {err}"))
+                    })?;
+                items.extend(
+                    log.unwrap_or_default()
+                        .into_iter()
+                        .map(|(header, payload)| Item {
+                            hash: Hash::new(&header),
+                            header,
+                            payload,
+                        }),
+                );
+            }
+        }
+
+        Ok(ItemSet::new(items))
+    }
+}
+
+#[async_trait]
+impl<'a, T, TM, L, E, S> SyncProtocol<'a, T> for SetReconciliationProtocol<TM, L, E, S>
+where
+    T: TopicQuery,
+    TM: TopicLogMap<T, L>,
+    L: LogId + Send + Sync + 'a,
+    E: Extensions + Send + Sync + 'a,
+    S: Debug + Sync + LogStore<L, E>,
+{
+    fn name(&self) -> &'static str {
+        "p2panda-set-reconciliation-v1"
+    }
+
+    async fn initiate(
+        self: Arc<Self>,
+        topic_query: T,
+        tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+        rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+        mut app_tx: Box<&'a mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin)>,
+    ) -> Result<(), SyncError> {
+        let mut sink = FramedWrite::new(tx.compat_write(), CborCodec::<Message<T>>::new());
+        let mut stream = FramedRead::new(rx.compat(), CborCodec::<Message<T>>::new());
+
+        let item_set = self.item_set(&topic_query).await?;
+
+        sink.send(Message::Handshake(topic_query.clone())).await?;
+        app_tx
+            .send(FromSync::HandshakeSuccess(topic_query.clone()))
+            .await?;
+
+        // Start reconciliation with the ranges covering our whole set.
+        sink.send(Message::Reconcile {
+            ranges: item_set.initial_ranges(),
+            want: vec![],
+        })
+        .await?;
+
+        reconcile(&item_set, &mut sink, &mut stream, *app_tx).await
+    }
+
+    async fn accept(
+        self: Arc<Self>,
+        tx: Box<&'a mut (dyn AsyncWrite + Send + Unpin)>,
+        rx: Box<&'a mut (dyn AsyncRead + Send + Unpin)>,
+        mut app_tx: Box<&'a mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin)>,
+    ) -> Result<(), SyncError> {
+        let mut sink = FramedWrite::new(tx.compat_write(), CborCodec::<Message<T>>::new());
+        let mut stream = FramedRead::new(rx.compat(), CborCodec::<Message<T>>::new());
+
+        let topic_query = match stream.next().await {
+            Some(Ok(Message::Handshake(topic_query))) => topic_query,
+            Some(Ok(_)) => {
+                return Err(SyncError::UnexpectedBehaviour(
+                    "expected \"handshake\" message".into(),
+                ))
+            }
+            Some(Err(err)) => return Err(err),
+            None => {
+                return Err(SyncError::UnexpectedBehaviour(
+                    "remote peer closed stream before handshake".into(),
+                ))
+            }
+        };
+
+        let item_set = self.item_set(&topic_query).await?;
+        app_tx.send(FromSync::HandshakeSuccess(topic_query)).await?;
+
+        reconcile(&item_set, &mut sink, &mut stream, *app_tx).await
+    }
+}
+
+/// Takes turns with the remote peer until all ranges are reconciled.
+async fn reconcile<T, W, R>(
+    item_set: &ItemSet,
+    sink: &mut W,
+    stream: &mut R,
+    app_tx: &mut (dyn Sink<FromSync<T>, Error = SyncError> + Send + Unpin),
+) -> Result<(), SyncError>
+where
+    T: TopicQuery,
+    W: Sink<Message<T>, Error = SyncError> + Unpin,
+    R: Stream<Item = Result<Message<T>, SyncError>> + Unpin,
+{
+    while let Some(result) = stream.next().await {
+        match result? {
+            Message::Data(header, payload) => {
+                // Forward data received from the remote to the app layer.
+                app_tx.send(FromSync::Data { header, payload }).await?;
+            }
+            Message::Reconcile { ranges, want } => {
+                // The remote peer ended the session.
+                if ranges.is_empty() && want.is_empty() {
+                    break;
+                }
+
+                // Send all operations the remote peer asked for.
+                for hash in want {
+                    let Some(item) = item_set.get(&hash) else {
+                        return Err(SyncError::UnexpectedBehaviour(format!(
+                            "remote peer requested unknown operation {hash}"
+                        )));
+                    };
+                    sink.feed(Message::Data(item.header.clone(), item.payload.clone()))
+                        .await?;
+                }
+
+                let Difference {
+                    ranges,
+                    missing_remote,
+                    missing_local: want,
+                } = item_set.reconcile(ranges)?;
+                for item in missing_remote {
+                    sink.feed(Message::Data(item.header.clone(), item.payload.clone()))
+                        .await?;
+                }
+
+                // Sending a turn without ranges and wanted operations ends the session.
+                let done = ranges.is_empty() && want.is_empty();
+                sink.send(Message::Reconcile { ranges, want }).await?;
+                if done {
+                    break;
+                }
+            }
+            Message::Handshake(_) => {
+                return Err(SyncError::UnexpectedBehaviour(
+                    "unexpected \"handshake\" message received".into(),
+                ));
+            }
+        }
+    }
+
+    // Flush all bytes so that no messages are lost.
+    sink.flush().await?;
+    app_tx.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use futures::SinkExt;
+    use p2panda_core::{Body, Hash, Header, PrivateKey, PublicKey};
+    use p2panda_store::{MemoryStore, OperationStore};
+    use serde::{Deserialize, Serialize};
+    use tokio::sync::mpsc;
+    use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+    use tokio_util::sync::PollSender;
+
+    use crate::log_sync::TopicLogMap;
+    use crate::{FromSync, SyncError, SyncProtocol, TopicQuery};
+
+    use super::SetReconciliationProtocol;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct TestTopic(String);
+
+    impl TopicQuery for TestTopic {}
+
+    #[derive(Clone, Debug)]
+    struct TestTopicMap(HashMap<TestTopic, HashMap<PublicKey, Vec<u64>>>);
+
+    #[async_trait]
+    impl TopicLogMap<TestTopic, u64> for TestTopicMap {
+        async fn get(&self, topic_query: &TestTopic) -> Option<HashMap<PublicKey, Vec<u64>>> {
+            self.0.get(topic_query).cloned()
+        }
+    }
+
+    fn create_operation(
+        private_key: &PrivateKey,
+        body: &Body,
+        seq_num: u64,
+        backlink: Option<Hash>,
+    ) -> (Hash, Header, Vec<u8>) {
+        let mut header = Header {
+            version: 1,
+            public_key: private_key.public_key(),
+            signature: None,
+            payload_size: body.size(),
+            payload_hash: Some(body.hash()),
+            timestamp: seq_num,
+            seq_num,
+            backlink,
+            previous: vec![],
+            extensions: None,
+        };
+        header.sign(private_key);
+        let header_bytes = header.to_bytes();
+        (header.hash(), header, header_bytes)
+    }
+
+    #[tokio::test]
+    async fn e2e_sync_overlapping_sets() {
+        let private_key = PrivateKey::new();
+        let log_id = 0;
+        let topic_query = TestTopic("messages".into());
+        let topic_map = TestTopicMap(HashMap::from([(
+            topic_query.clone(),
+            HashMap::from([(private_key.public_key(), vec![log_id])]),
+        )]));
+
+        // Peer a holds operations 0 to 999 of a log, peer b holds operations 200 to 1199. Both
+        // hold 1000 operations, 800 of them in common.
+        let mut store_a = MemoryStore::<u64>::new();
+        let mut store_b = MemoryStore::<u64>::new();
+        let mut only_a = HashSet::new();
+        let mut only_b = HashSet::new();
+        let mut backlink = None;
+        for seq_num in 0..1200 {
+            let body = Body::new(format!("Hello, Sloth! #{seq_num}").as_bytes());
+            let (hash, header, header_bytes) =
+                create_operation(&private_key, &body, seq_num, backlink);
+            if seq_num < 1000 {
+                store_a
+                    .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                    .await
+                    .unwrap();
+            }
+            if seq_num >= 200 {
+                store_b
+                    .insert_operation(hash, &header, Some(&body), &header_bytes, &log_id)
+                    .await
+                    .unwrap();
+            }
+            match seq_num {
+                0..200 => only_a.insert(hash),
+                1000.. => only_b.insert(hash),
+                _ => true,
+            };
+            backlink = Some(hash);
+        }
+
+        let peer_a_protocol = Arc::new(SetReconciliationProtocol::new(topic_map.clone(), store_a));
+        let peer_b_protocol = Arc::new(SetReconciliationProtocol::new(topic_map, store_b));
+
+        // Duplex streams which simulate both ends of a bi-directional network connection
+        let (peer_a, peer_b) = tokio::io::duplex(64 * 1024);
+        let (peer_a_read, peer_a_write) = tokio::io::split(peer_a);
+        let (peer_b_read, peer_b_write) = tokio::io::split(peer_b);
+
+        let (peer_a_app_tx, mut peer_a_app_rx) = mpsc::channel(2048);
+        let mut sink =
+            PollSender::new(peer_a_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let topic_query_clone = topic_query.clone();
+        let handle_1 = tokio::spawn(async move {
+            peer_a_protocol
+                .initiate(
+                    topic_query_clone,
+                    Box::new(&mut peer_a_write.compat_write()),
+                    Box::new(&mut peer_a_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (peer_b_app_tx, mut peer_b_app_rx) = mpsc::channel(2048);
+        let mut sink =
+            PollSender::new(peer_b_app_tx).sink_map_err(|err| SyncError::Critical(err.to_string()));
+        let handle_2 = tokio::spawn(async move {
+            peer_b_protocol
+                .accept(
+                    Box::new(&mut peer_b_write.compat_write()),
+                    Box::new(&mut peer_b_read.compat()),
+                    Box::new(&mut sink),
+                )
+                .await
+                .unwrap();
+        });
+
+        let (result_1, result_2) = tokio::join!(handle_1, handle_2);
+        result_1.unwrap();
+        result_2.unwrap();
+
+        let mut peer_a_messages = Vec::new();
+        peer_a_app_rx.recv_many(&mut peer_a_messages, 2048).await;
+        let mut peer_b_messages = Vec::new();
+        peer_b_app_rx.recv_many(&mut peer_b_messages, 2048).await;
+
+        assert_eq!(
+            peer_a_messages[0],
+            FromSync::HandshakeSuccess(topic_query.clone())
+        );
+        assert_eq!(peer_b_messages[0], FromSync::HandshakeSuccess(topic_query));
+
+        let received_hashes = |messages: &[FromSync<TestTopic>]| -> HashSet<Hash> {
+            messages
+                .iter()
+                .map(|message| match message {
+                    FromSync::Data { header, .. } => Hash::new(header),
+                    _ => panic!("unexpected message {message:?}"),
+                })
+                .collect()
+        };
+
+        // Only the genuine difference of 400 operations was transferred.
+        assert_eq!(peer_a_messages.len() - 1 + peer_b_messages.len() - 1, 400);
+        assert_eq!(received_hashes(&peer_a_messages[1..]), only_b);
+        assert_eq!(received_hashes(&peer_b_messages[1..]), only_a);
+    }
+}